@@ -0,0 +1,339 @@
+use crate::config::Config;
+use crate::initialization::{
+    ensure_database_is_present, ensure_directories, ensure_required_tools,
+    initialize_area_upload_service, initialize_cid_db, initialize_claims_db,
+    initialize_country_service, initialize_extraction_service, initialize_storage_service,
+    initialize_whosonfirst_db, validate_config, DatabaseDownload, InitializationResult,
+    ToolInstall,
+};
+use crate::services::{
+    BackpressureService, CatalogService, DatabaseService, EventService, EvictionService,
+    GossipService, PauseService, StorageBackend,
+};
+use crate::types::PhaseTimings;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use super::{NodeRunner, Notifiers, SystemdNotifier, TuiState};
+
+/// Assembles a node the way the `anynode` binary does, so another program can embed one.
+/// Components left unset are built from the config, set ones are used as they are.
+pub struct AnyNodeBuilder {
+    config: Config,
+    download: DatabaseDownload,
+    tool_install: ToolInstall,
+    skip_extract: bool,
+    fresh: bool,
+    force: bool,
+    connect_peers: Vec<String>,
+    whosonfirst_db: Option<Arc<DatabaseService>>,
+    cid_db: Option<Arc<DatabaseService>>,
+    storage_service: Option<Arc<dyn StorageBackend>>,
+    events: Option<Arc<EventService>>,
+    pause: Option<Arc<PauseService>>,
+    notifiers: Option<Notifiers>,
+    systemd: SystemdNotifier,
+    tui: Option<TuiState>,
+}
+
+/// A node assembled by `AnyNodeBuilder`. The runner holds the services, the shared ones are
+/// kept here too so the caller can start the background tasks it wants next to the run.
+pub struct AnyNode {
+    pub config: Arc<Config>,
+    pub whosonfirst_db: Arc<DatabaseService>,
+    pub cid_db: Arc<DatabaseService>,
+    pub storage_service: Arc<dyn StorageBackend>,
+    pub events: Arc<EventService>,
+    pub pause: Arc<PauseService>,
+    /// Set when GOSSIP_PEERS is, not started yet
+    pub gossip: Option<Arc<GossipService>>,
+    pub runner: NodeRunner,
+}
+
+impl AnyNodeBuilder {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            download: DatabaseDownload::Never,
+            tool_install: ToolInstall::Never,
+            skip_extract: false,
+            fresh: false,
+            force: false,
+            connect_peers: Vec::new(),
+            whosonfirst_db: None,
+            cid_db: None,
+            storage_service: None,
+            events: None,
+            pause: None,
+            notifiers: None,
+            systemd: SystemdNotifier::default(),
+            tui: None,
+        }
+    }
+
+    /// What to do when the WhosOnFirst database is missing, fail by default
+    pub fn with_database_download(mut self, download: DatabaseDownload) -> Self {
+        self.download = download;
+        self
+    }
+
+    /// What to do when pmtiles is missing, report it by default
+    pub fn with_tool_install(mut self, tool_install: ToolInstall) -> Self {
+        self.tool_install = tool_install;
+        self
+    }
+
+    /// Upload existing extracts only
+    pub fn with_skip_extract(mut self, skip_extract: bool) -> Self {
+        self.skip_extract = skip_extract;
+        self
+    }
+
+    /// Discard the checkpoint of an interrupted run instead of resuming it
+    pub fn with_fresh(mut self, fresh: bool) -> Self {
+        self.fresh = fresh;
+        self
+    }
+
+    /// Run even when the PLAN_SAMPLES estimate exceeds the storage quota
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Peers to dial as soon as the storage node is up
+    pub fn with_connect_peers(mut self, connect_peers: Vec<String>) -> Self {
+        self.connect_peers = connect_peers;
+        self
+    }
+
+    /// Use an open WhosOnFirst database, its download and path check are skipped
+    pub fn with_whosonfirst_db(mut self, whosonfirst_db: Arc<DatabaseService>) -> Self {
+        self.whosonfirst_db = Some(whosonfirst_db);
+        self
+    }
+
+    /// Use an open CID database, created with its CID tables
+    pub fn with_cid_db(mut self, cid_db: Arc<DatabaseService>) -> Self {
+        self.cid_db = Some(cid_db);
+        self
+    }
+
+    /// Use a storage service created by the caller, its node is started by the run
+    pub fn with_storage_service(mut self, storage_service: Arc<dyn StorageBackend>) -> Self {
+        self.storage_service = Some(storage_service);
+        self
+    }
+
+    /// Publish pipeline events on a bus the caller already listens to
+    pub fn with_events(mut self, events: Arc<EventService>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Pause and resume the run through a switch the caller holds
+    pub fn with_pause(mut self, pause: Arc<PauseService>) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    /// Channels run milestones are reported on, those of the config by default
+    pub fn with_notifiers(mut self, notifiers: Notifiers) -> Self {
+        self.notifiers = Some(notifiers);
+        self
+    }
+
+    /// Service manager startup and the run report their phases to
+    pub fn with_systemd(mut self, systemd: SystemdNotifier) -> Self {
+        self.systemd = systemd;
+        self
+    }
+
+    /// Monitor the run on a terminal dashboard instead of the status spinner
+    pub fn with_tui(mut self, state: TuiState) -> Self {
+        self.tui = Some(state);
+        self
+    }
+
+    /// Check the tools and databases, open the services and wire them into a runner. Nothing
+    /// is started, the storage node starts with the run.
+    pub async fn build(self) -> InitializationResult<AnyNode> {
+        let mut config = self.config;
+        self.systemd.status("Checking tools and databases");
+
+        if let Err(e) = ensure_required_tools(&mut config, self.tool_install).await {
+            error!("Failed to ensure required tools: {}", e);
+            return Err(e);
+        }
+
+        let timings = match &self.whosonfirst_db {
+            Some(_) => PhaseTimings::new(),
+            None => match ensure_database_is_present(&mut config, self.download).await {
+                Ok(timings) => timings,
+                Err(e) => {
+                    error!("Failed to ensure database is present: {}", e);
+                    return Err(e);
+                }
+            },
+        };
+        let timings = Arc::new(Mutex::new(timings));
+        let config = Arc::new(config);
+
+        if self.whosonfirst_db.is_none() {
+            if let Err(e) = validate_config(&config) {
+                error!("Configuration validation failed: {}", e);
+                return Err(e);
+            }
+        }
+
+        ensure_directories(&config).await?;
+
+        self.systemd.status("Initializing services");
+
+        let whosonfirst_db = match self.whosonfirst_db {
+            Some(db) => db,
+            None => initialize_whosonfirst_db(&config).await?,
+        };
+        let cid_db = match self.cid_db {
+            Some(db) => db,
+            None => initialize_cid_db(&config).await?,
+        };
+        let checkpoint = match self.fresh {
+            true => {
+                cid_db.clear_run_checkpoint().await?;
+                None
+            }
+            false => cid_db.get_run_checkpoint().await?,
+        };
+        if let Some(checkpoint) = &checkpoint {
+            info!("Resuming interrupted run: {}", checkpoint);
+        }
+        let claims = initialize_claims_db(&config).await?;
+        let country_service = initialize_country_service(&config, whosonfirst_db.clone());
+        let storage_service = match self.storage_service {
+            Some(storage_service) => storage_service,
+            None => {
+                initialize_storage_service(
+                    &config,
+                    None,
+                    None,
+                    config.bootstrap_nodes.clone(),
+                    None,
+                    None,
+                )
+                .await?
+            }
+        };
+
+        let events = self.events.unwrap_or_else(|| Arc::new(EventService::new()));
+        let pause = self.pause.unwrap_or_else(|| Arc::new(PauseService::new()));
+        let gossip = match config.gossip_peers.is_empty() {
+            true => None,
+            false => Some(Arc::new(GossipService::new(cid_db.clone(), &config))),
+        };
+        let backpressure = Arc::new(BackpressureService::new(&config));
+
+        let extraction_service = initialize_extraction_service(
+            &config,
+            whosonfirst_db.clone(),
+            cid_db.clone(),
+            events.clone(),
+        )?
+        .with_pause(pause.clone())
+        .with_backpressure(backpressure.clone())
+        .with_timings(timings.clone())
+        .with_checkpoint(
+            checkpoint
+                .as_ref()
+                .map(|checkpoint| checkpoint.extracted_countries.clone())
+                .unwrap_or_default(),
+        );
+        let extraction_service = match claims {
+            Some(claims) => extraction_service.with_claims(claims),
+            None => extraction_service,
+        };
+        let extraction_service = match config.trusted_manifests.is_empty() {
+            true => extraction_service,
+            false => extraction_service.with_catalog(Arc::new(CatalogService::new(
+                storage_service.clone(),
+                config.trusted_manifests.clone(),
+                config.encryption_key.as_ref().map(|key| key.id()),
+            ))),
+        };
+        let extraction_service = match config.areas_dir_max_size {
+            Some(max_size) => extraction_service.with_eviction(Arc::new(EvictionService::new(
+                config.areas_dir.clone(),
+                max_size,
+                cid_db.clone(),
+            ))),
+            None => extraction_service,
+        };
+        if !self.skip_extract {
+            if let Err(e) = extraction_service.validate_planet_source().await {
+                error!("Planet source validation failed: {}", e);
+                return Err(e.into());
+            }
+        }
+        let upload_service = initialize_area_upload_service(
+            cid_db.clone(),
+            whosonfirst_db.clone(),
+            storage_service.clone(),
+            &config,
+            config.area_ids.clone(),
+            events.clone(),
+        )?
+        .with_pause(pause.clone())
+        .with_backpressure(backpressure)
+        .with_timings(timings)
+        .with_checkpoint(
+            checkpoint
+                .as_ref()
+                .map(|checkpoint| checkpoint.uploaded_countries.clone())
+                .unwrap_or_default(),
+        );
+
+        if !config.area_ids.is_empty() {
+            info!("Processing {} specific area IDs", config.area_ids.len());
+        } else {
+            info!("Retrieving list of all countries...");
+            let countries = country_service
+                .get_countries_to_process(&config.target_countries)
+                .await;
+            info!("Processing {} countries", countries.len());
+        }
+
+        let runner = NodeRunner::new(
+            config.clone(),
+            storage_service.clone(),
+            extraction_service,
+            upload_service,
+            country_service,
+            config.area_ids.clone(),
+            self.skip_extract,
+        )
+        .with_connect_peers(self.connect_peers)
+        .with_force(self.force)
+        .with_resumed_phase(checkpoint.map(|checkpoint| checkpoint.phase))
+        .with_notifiers(
+            self.notifiers
+                .unwrap_or_else(|| Notifiers::from_config(&config)),
+        )
+        .with_systemd(self.systemd);
+        let runner = match self.tui {
+            Some(state) => runner.with_tui(state, events.clone()),
+            None => runner,
+        };
+
+        Ok(AnyNode {
+            config,
+            whosonfirst_db,
+            cid_db,
+            storage_service,
+            events,
+            pause,
+            gossip,
+            runner,
+        })
+    }
+}
@@ -0,0 +1,191 @@
+use crate::services::{DatabaseService, EventService, GossipService, PauseService};
+use crate::types::{AreaInfo, BoundingBox, CidAnnouncement};
+use axum::extract::{FromRef, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{error, info, warn};
+
+/// Databases `GET /areas` is answered from
+#[derive(Clone)]
+pub struct AreaCatalog {
+    pub cid_db: Arc<DatabaseService>,
+    pub whosonfirst_db: Arc<DatabaseService>,
+}
+
+#[derive(Clone)]
+struct ServerState {
+    events: Arc<EventService>,
+    pause: Arc<PauseService>,
+    gossip: Option<Arc<GossipService>>,
+    catalog: Option<AreaCatalog>,
+}
+
+impl FromRef<ServerState> for Arc<EventService> {
+    fn from_ref(state: &ServerState) -> Self {
+        state.events.clone()
+    }
+}
+
+impl FromRef<ServerState> for Arc<PauseService> {
+    fn from_ref(state: &ServerState) -> Self {
+        state.pause.clone()
+    }
+}
+
+impl FromRef<ServerState> for Option<Arc<GossipService>> {
+    fn from_ref(state: &ServerState) -> Self {
+        state.gossip.clone()
+    }
+}
+
+impl FromRef<ServerState> for Option<AreaCatalog> {
+    fn from_ref(state: &ServerState) -> Self {
+        state.catalog.clone()
+    }
+}
+
+/// Pipeline state returned by the control endpoints
+#[derive(Debug, Serialize)]
+struct PipelineState {
+    paused: bool,
+}
+
+/// Query of `GET /areas`, `bbox=min_lon,min_lat,max_lon,max_lat`
+#[derive(Debug, Deserialize)]
+struct AreasQuery {
+    bbox: String,
+}
+
+/// Serve pipeline events as Server-Sent Events on `GET /events`, with `POST /pause`,
+/// `POST /resume` and `GET /state` to control the pipeline, `POST /announcements` to
+/// receive other nodes' uploads when gossip is enabled, and `GET /areas?bbox=` to find the
+/// uploaded areas covering a viewport when the databases are given
+pub async fn start_events_server(
+    addr: SocketAddr,
+    events: Arc<EventService>,
+    pause: Arc<PauseService>,
+    gossip: Option<Arc<GossipService>>,
+    catalog: Option<AreaCatalog>,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Serving pipeline events on http://{}/events", listener.local_addr()?);
+
+    let app = Router::new()
+        .route("/events", get(stream_events))
+        .route("/state", get(pipeline_state))
+        .route("/pause", post(pause_pipeline))
+        .route("/resume", post(resume_pipeline))
+        .route("/announcements", post(receive_announcement))
+        .route("/areas", get(areas_in_bbox))
+        .with_state(ServerState {
+            events,
+            pause,
+            gossip,
+            catalog,
+        });
+
+    Ok(tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            error!("Events server stopped: {}", e);
+        }
+    }))
+}
+
+async fn pipeline_state(State(pause): State<Arc<PauseService>>) -> Json<PipelineState> {
+    Json(PipelineState {
+        paused: pause.is_paused(),
+    })
+}
+
+async fn pause_pipeline(State(pause): State<Arc<PauseService>>) -> Json<PipelineState> {
+    pause.pause();
+    pipeline_state(State(pause)).await
+}
+
+async fn resume_pipeline(State(pause): State<Arc<PauseService>>) -> Json<PipelineState> {
+    pause.resume();
+    pipeline_state(State(pause)).await
+}
+
+async fn receive_announcement(
+    State(gossip): State<Option<Arc<GossipService>>>,
+    Json(announcement): Json<CidAnnouncement>,
+) -> StatusCode {
+    let Some(gossip) = gossip else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    match gossip.receive(announcement).await {
+        Ok(_) => StatusCode::ACCEPTED,
+        Err(e) => {
+            warn!("Failed to record an announcement: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+async fn areas_in_bbox(
+    State(catalog): State<Option<AreaCatalog>>,
+    Query(query): Query<AreasQuery>,
+) -> Result<Json<Vec<AreaInfo>>, (StatusCode, String)> {
+    let Some(catalog) = catalog else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "No area catalog on this node".to_string(),
+        ));
+    };
+    let bbox = query
+        .bbox
+        .parse::<BoundingBox>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    catalog
+        .cid_db
+        .get_localities_in_bbox(
+            &catalog.whosonfirst_db,
+            bbox.min_longitude,
+            bbox.min_latitude,
+            bbox.max_longitude,
+            bbox.max_latitude,
+        )
+        .await
+        .map(Json)
+        .map_err(|e| {
+            warn!("Failed to look up areas in {}: {}", bbox, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+        })
+}
+
+async fn stream_events(
+    State(events): State<Arc<EventService>>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = events.subscribe();
+
+    let stream = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => {
+                    let sse_event = Event::default()
+                        .event(event.name())
+                        .json_data(&event)
+                        .unwrap_or_else(|_| Event::default().event(event.name()));
+                    return Some((Ok(sse_event), receiver));
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Events subscriber lagged, {} events dropped", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
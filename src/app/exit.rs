@@ -0,0 +1,183 @@
+use crate::commands::CommandError;
+use crate::config::ConfigError;
+use crate::initialization::InitializationError;
+use crate::services::{AreaUploadError, DatabaseError, ExtractionError, StorageError};
+use crate::utils::CmdError;
+use std::error::Error;
+use std::process::ExitCode;
+
+use super::ApplicationError;
+
+/// Process exit codes, so a supervisor can tell a failure worth retrying from one that
+/// needs someone to look at it. 2 is left to clap, which exits with it on usage errors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success,
+    /// Anything not covered below, e.g. a database or filesystem error
+    Failure,
+    /// The configuration or the command line is invalid, retrying will not help
+    ConfigError,
+    /// A required external tool is not installed
+    MissingTools,
+    /// The storage node failed to start or stopped responding
+    StorageNodeFailure,
+    /// Extraction did not finish, areas extracted before the failure were uploaded
+    ExtractionIncomplete,
+    /// Uploads failed, the areas are retried by the next run
+    UploadFailure,
+    /// A check command such as verify or doctor found problems
+    ChecksFailed,
+    /// The run was cancelled before it finished, as by Ctrl+C
+    Cancelled,
+}
+
+impl ExitStatus {
+    pub fn code(self) -> u8 {
+        match self {
+            Self::Success => 0,
+            Self::Failure => 1,
+            Self::ConfigError => 3,
+            Self::MissingTools => 4,
+            Self::StorageNodeFailure => 5,
+            Self::ExtractionIncomplete => 6,
+            Self::UploadFailure => 7,
+            Self::ChecksFailed => 8,
+            Self::Cancelled => 130,
+        }
+    }
+
+    /// Status for an error that reached `main`, by the error types it can be
+    pub fn from_error(error: &(dyn Error + 'static)) -> Self {
+        if let Some(e) = error.downcast_ref::<CommandError>() {
+            e.exit_status()
+        } else if let Some(e) = error.downcast_ref::<ApplicationError>() {
+            e.exit_status()
+        } else if let Some(e) = error.downcast_ref::<InitializationError>() {
+            e.exit_status()
+        } else if error.is::<ConfigError>() {
+            Self::ConfigError
+        } else if error.is::<StorageError>() {
+            Self::StorageNodeFailure
+        } else {
+            Self::Failure
+        }
+    }
+}
+
+impl From<ExitStatus> for ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        ExitCode::from(status.code())
+    }
+}
+
+impl ApplicationError {
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            Self::StorageError(_) | Self::UploadError(AreaUploadError::StorageError(_)) => {
+                ExitStatus::StorageNodeFailure
+            }
+            Self::UploadError(_) => ExitStatus::UploadFailure,
+            Self::ExtractionError(_) => ExitStatus::ExtractionIncomplete,
+            Self::QuotaExceeded(_, _) => ExitStatus::ConfigError,
+            Self::DatabaseError(_) | Self::IoError(_) | Self::CompressError(_) => {
+                ExitStatus::Failure
+            }
+        }
+    }
+}
+
+impl InitializationError {
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            Self::ConfigError(_) | Self::DirectoryNotFound(_) | Self::DatabaseMissing => {
+                ExitStatus::ConfigError
+            }
+            Self::DatabaseError(
+                DatabaseError::MissingSprTable
+                | DatabaseError::MissingSprColumns(_)
+                | DatabaseError::MissingPlacetypes(_),
+            ) => ExitStatus::ConfigError,
+            Self::CmdError(CmdError::CommandNotFound(_) | CmdError::UnsupportedVersion { .. }) => {
+                ExitStatus::MissingTools
+            }
+            Self::StorageError(_) => ExitStatus::StorageNodeFailure,
+            Self::ExtractionError(ExtractionError::PlanetSourceInvalid(_, _)) => {
+                ExitStatus::ConfigError
+            }
+            Self::ExtractionError(_) => ExitStatus::ExtractionIncomplete,
+            Self::DatabaseError(_)
+            | Self::IoError(_)
+            | Self::DownloadError(_)
+            | Self::CmdError(_) => ExitStatus::Failure,
+        }
+    }
+}
+
+impl CommandError {
+    pub fn exit_status(&self) -> ExitStatus {
+        match self {
+            Self::ConfigError(_)
+            | Self::AreaIdsError(_)
+            | Self::BoundingBoxError(_)
+            | Self::GeoJsonError(_) => ExitStatus::ConfigError,
+            Self::InitializationError(e) => e.exit_status(),
+            Self::StorageError(_) | Self::UploadError(AreaUploadError::StorageError(_)) => {
+                ExitStatus::StorageNodeFailure
+            }
+            Self::UploadError(_) => ExitStatus::UploadFailure,
+            Self::ExtractionError(_) => ExitStatus::ExtractionIncomplete,
+            Self::ChecksFailed(_) => ExitStatus::ChecksFailed,
+            Self::DatabaseError(_)
+            | Self::IoError(_)
+            | Self::ExportError(_)
+            | Self::IdentityError(_)
+            | Self::BackupError(_) => ExitStatus::Failure,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boxed_errors_keep_their_status() {
+        let error: Box<dyn Error> = Box::new(InitializationError::CmdError(
+            CmdError::CommandNotFound("pmtiles".to_string()),
+        ));
+        assert_eq!(ExitStatus::from_error(error.as_ref()), ExitStatus::MissingTools);
+
+        let error: Box<dyn Error> = Box::new(InitializationError::ExtractionError(
+            ExtractionError::PlanetSourceInvalid(
+                "https://example.com/planet.pmtiles".to_string(),
+                "Not a PMTiles file".to_string(),
+            ),
+        ));
+        assert_eq!(ExitStatus::from_error(error.as_ref()), ExitStatus::ConfigError);
+
+        let error: Box<dyn Error> = Box::new(CommandError::ChecksFailed(2));
+        assert_eq!(ExitStatus::from_error(error.as_ref()), ExitStatus::ChecksFailed);
+
+        let error: Box<dyn Error> = Box::new(std::io::Error::other("disk full"));
+        assert_eq!(ExitStatus::from_error(error.as_ref()), ExitStatus::Failure);
+    }
+
+    #[test]
+    fn codes_are_distinct_and_skip_the_usage_code() {
+        let statuses = [
+            ExitStatus::Success,
+            ExitStatus::Failure,
+            ExitStatus::ConfigError,
+            ExitStatus::MissingTools,
+            ExitStatus::StorageNodeFailure,
+            ExitStatus::ExtractionIncomplete,
+            ExitStatus::UploadFailure,
+            ExitStatus::ChecksFailed,
+            ExitStatus::Cancelled,
+        ];
+        let codes: std::collections::HashSet<u8> =
+            statuses.iter().map(|status| status.code()).collect();
+        assert_eq!(codes.len(), statuses.len());
+        assert!(!codes.contains(&2));
+    }
+}
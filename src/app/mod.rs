@@ -1,5 +1,15 @@
+pub mod builder;
+pub mod events_server;
+pub mod exit;
 pub mod monitor;
+pub mod notifier;
+pub mod progress;
+pub mod report;
 pub mod runner;
+pub mod snapshot;
+pub mod spr_file;
+pub mod systemd;
+pub mod tui;
 
 use thiserror::Error;
 
@@ -15,8 +25,23 @@ pub enum ApplicationError {
     StorageError(#[from] crate::services::StorageError),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("Compression error: {0}")]
+    CompressError(#[from] crate::utils::CompressError),
+    #[error("The run needs {0} of storage quota, {1} is available, rerun with --force to start it anyway")]
+    QuotaExceeded(String, String),
 }
 
 pub type ApplicationResult<T> = Result<T, ApplicationError>;
 
-pub use runner::NodeRunner;
+pub use builder::{AnyNode, AnyNodeBuilder};
+pub use events_server::{start_events_server, AreaCatalog};
+pub use exit::ExitStatus;
+pub use monitor::{start_node_supervisor, start_peer_watch};
+pub use notifier::{Notifier, Notifiers, NotifyError};
+pub use progress::{start_progress_events, ProgressEvent};
+pub use report::write_run_report;
+pub use runner::{display_node_info, wait_for_shutdown_signal, NodeRunner, RunHandle, RunProgress};
+pub use snapshot::start_db_snapshot_publisher;
+pub use spr_file::start_spr_file_writer;
+pub use systemd::SystemdNotifier;
+pub use tui::{TuiLogLayer, TuiState};
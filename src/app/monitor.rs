@@ -1,8 +1,23 @@
-use crate::services::{StorageService, StorageStatus};
+use crate::app::notifier::Notifiers;
+use crate::services::{StorageBackend, StorageStatus, StorageUsage};
+use crate::types::{Notification, UploadProgress};
+use crate::utils::format_bytes;
 use indicatif::{ProgressBar, ProgressStyle};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 use tokio::time::interval;
+use tracing::{error, info, warn};
+
+/// Time between two health checks of the storage node supervisor
+const SUPERVISOR_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// Consecutive failed health checks after which the node is restarted
+const SUPERVISOR_MAX_FAILED_CHECKS: u32 = 3;
+/// Delay before the first restart, doubled for each restart that does not bring the node back
+const RESTART_BACKOFF_BASE: Duration = Duration::from_secs(5);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(300);
+/// Time between two looks at the discovery table of the peer watch
+const PEER_CHECK_INTERVAL: Duration = Duration::from_secs(30);
 
 pub fn create_node_status_progress_bar() -> ProgressBar {
     let pb = ProgressBar::new_spinner();
@@ -16,10 +31,13 @@ pub fn create_node_status_progress_bar() -> ProgressBar {
 }
 
 pub async fn monitor_node_status(
-    storage_service: Arc<StorageService>,
+    storage_service: Arc<dyn StorageBackend>,
     progress_bar: ProgressBar,
+    warn_thresholds: Vec<u8>,
+    upload_progress: Arc<Mutex<UploadProgress>>,
 ) {
     let mut tick = interval(Duration::from_secs(2));
+    let mut usage_alerts = UsageAlerts::new(warn_thresholds);
 
     loop {
         tick.tick().await;
@@ -29,10 +47,19 @@ pub async fn monitor_node_status(
         match storage_service.get_node_info().await {
             Ok(node_info) => {
                 let status_str = format_status(&status);
-                progress_bar.set_message(format!(
+                let mut message = format!(
                     "Status: {} | Discovery: {} nodes",
                     status_str, node_info.discovery_node_count
-                ));
+                );
+                if let Some(usage) = node_info.storage_usage {
+                    usage_alerts.check(&usage);
+                    message.push_str(&format!(" | Storage: {}", format_usage(&usage)));
+                }
+                let upload_progress = upload_progress.lock().await;
+                if upload_progress.remaining_files() > 0 {
+                    message.push_str(&format!(" | Upload: {}", upload_progress));
+                }
+                progress_bar.set_message(message);
             }
             Err(_) => {
                 let status_str = format_status(&status);
@@ -42,6 +69,257 @@ pub async fn monitor_node_status(
     }
 }
 
+/// Restart the storage node when it reports `Error` or fails several health checks in a
+/// row, backing off exponentially while restarts do not bring it back. A node that is not
+/// started, or was stopped on purpose, is left alone. Uploads wait while the node is down.
+pub fn start_node_supervisor(storage_service: Arc<dyn StorageBackend>) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = interval(SUPERVISOR_CHECK_INTERVAL);
+        let mut failed_checks = 0;
+        let mut backoff = RestartBackoff::new(RESTART_BACKOFF_BASE, RESTART_BACKOFF_MAX);
+
+        loop {
+            tick.tick().await;
+
+            match storage_service.get_status().await {
+                StorageStatus::Error => {}
+                StorageStatus::Connected => match storage_service.check_health().await {
+                    Ok(()) => {
+                        failed_checks = 0;
+                        backoff.reset();
+                        continue;
+                    }
+                    Err(e) => {
+                        failed_checks += 1;
+                        warn!(
+                            "Storage node health check {}/{} failed: {}",
+                            failed_checks, SUPERVISOR_MAX_FAILED_CHECKS, e
+                        );
+                        if failed_checks < SUPERVISOR_MAX_FAILED_CHECKS {
+                            continue;
+                        }
+                    }
+                },
+                _ => {
+                    failed_checks = 0;
+                    continue;
+                }
+            }
+
+            let delay = backoff.next_delay();
+            warn!("Restarting the storage node in {}s", delay.as_secs());
+            tokio::time::sleep(delay).await;
+            failed_checks = 0;
+            match storage_service.restart_node().await {
+                Ok(()) => info!("Storage node restarted"),
+                Err(e) => error!("Failed to restart the storage node: {}", e),
+            }
+        }
+    })
+}
+
+/// Warn and notify when the discovery table stays empty for `alert_after`, then dial
+/// `targets`, the bootstrap records and configured peers, again every `alert_after` until
+/// peers show up. Nothing is checked while the node is not started.
+pub fn start_peer_watch(
+    storage_service: Arc<dyn StorageBackend>,
+    targets: Vec<String>,
+    alert_after: Duration,
+    notifiers: Notifiers,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = interval(PEER_CHECK_INTERVAL);
+        let mut watch = EmptyNetworkWatch::new(alert_after);
+
+        loop {
+            tick.tick().await;
+
+            let node_count = match storage_service.get_status().await {
+                StorageStatus::Connected => match storage_service.get_node_info().await {
+                    Ok(node_info) => node_info.discovery_node_count,
+                    Err(_) => continue,
+                },
+                _ => {
+                    watch.reset();
+                    continue;
+                }
+            };
+
+            match watch.observe(node_count, Instant::now()) {
+                PeerWatchAction::None => continue,
+                PeerWatchAction::Recovered => {
+                    info!("Discovery table has {} nodes again", node_count);
+                    continue;
+                }
+                PeerWatchAction::Alert => {
+                    warn!(
+                        "!!! No peers in the discovery table for {}s, this node is serving \
+                         nothing to the network. Check STORAGE_BOOTSTRAP_NODES and the \
+                         node's reachability !!!",
+                        alert_after.as_secs()
+                    );
+                    notifiers
+                        .notify(Notification::NoPeers {
+                            secs: alert_after.as_secs(),
+                        })
+                        .await;
+                }
+                PeerWatchAction::Redial => {}
+            }
+
+            if targets.is_empty() {
+                warn!("No bootstrap nodes or peers configured to dial");
+                continue;
+            }
+            info!("Re-dialing {} bootstrap nodes and peers", targets.len());
+            for target in &targets {
+                match storage_service.connect_peer(target).await {
+                    Ok(peer_id) => info!("Connected to peer {}", peer_id),
+                    Err(e) => warn!("Failed to connect to peer: {}", e),
+                }
+            }
+        }
+    })
+}
+
+#[derive(Debug, PartialEq)]
+enum PeerWatchAction {
+    None,
+    /// The table has been empty for the whole alert period, alert and dial
+    Alert,
+    /// Another alert period went by without peers, dial again
+    Redial,
+    /// Peers showed up after an alert
+    Recovered,
+}
+
+/// Tracks how long the discovery table has been empty
+struct EmptyNetworkWatch {
+    alert_after: Duration,
+    empty_since: Option<Instant>,
+    alerted: bool,
+    dialed_at: Option<Instant>,
+}
+
+impl EmptyNetworkWatch {
+    fn new(alert_after: Duration) -> Self {
+        Self {
+            alert_after,
+            empty_since: None,
+            alerted: false,
+            dialed_at: None,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.empty_since = None;
+        self.alerted = false;
+        self.dialed_at = None;
+    }
+
+    fn observe(&mut self, node_count: usize, now: Instant) -> PeerWatchAction {
+        if node_count > 0 {
+            let recovered = self.alerted;
+            self.reset();
+            return match recovered {
+                true => PeerWatchAction::Recovered,
+                false => PeerWatchAction::None,
+            };
+        }
+
+        let empty_since = *self.empty_since.get_or_insert(now);
+        if now.duration_since(empty_since) < self.alert_after {
+            return PeerWatchAction::None;
+        }
+        let due = self
+            .dialed_at
+            .is_none_or(|dialed_at| now.duration_since(dialed_at) >= self.alert_after);
+        if !due {
+            return PeerWatchAction::None;
+        }
+        self.dialed_at = Some(now);
+        match std::mem::replace(&mut self.alerted, true) {
+            false => PeerWatchAction::Alert,
+            true => PeerWatchAction::Redial,
+        }
+    }
+}
+
+/// Exponential delay between restarts, reset once the node is healthy again
+struct RestartBackoff {
+    base: Duration,
+    max: Duration,
+    restarts: u32,
+}
+
+impl RestartBackoff {
+    fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            restarts: 0,
+        }
+    }
+
+    fn next_delay(&mut self) -> Duration {
+        let delay = self
+            .base
+            .saturating_mul(2u32.saturating_pow(self.restarts))
+            .min(self.max);
+        self.restarts = self.restarts.saturating_add(1);
+        delay
+    }
+
+    fn reset(&mut self) {
+        self.restarts = 0;
+    }
+}
+
+/// Render usage as `used / quota (percent)`
+pub fn format_usage(usage: &StorageUsage) -> String {
+    format!(
+        "{} / {} ({:.0}%)",
+        format_bytes(usage.used_bytes + usage.reserved_bytes),
+        format_bytes(usage.quota_bytes),
+        usage.used_percent()
+    )
+}
+
+/// Warns once each time usage climbs past a threshold, re-arming when it drops back
+pub(crate) struct UsageAlerts {
+    thresholds: Vec<u8>,
+    crossed: usize,
+}
+
+impl UsageAlerts {
+    pub(crate) fn new(thresholds: Vec<u8>) -> Self {
+        Self {
+            thresholds,
+            crossed: 0,
+        }
+    }
+
+    pub(crate) fn check(&mut self, usage: &StorageUsage) {
+        let percent = usage.used_percent();
+        let crossed = self
+            .thresholds
+            .iter()
+            .take_while(|t| percent >= f64::from(**t))
+            .count();
+
+        if crossed > self.crossed {
+            warn!(
+                "Storage usage passed {}% of quota: {}",
+                self.thresholds[crossed - 1],
+                format_usage(usage)
+            );
+        } else if crossed < self.crossed {
+            info!("Storage usage back under {}% of quota", self.thresholds[self.crossed - 1]);
+        }
+        self.crossed = crossed;
+    }
+}
+
 pub fn format_status(status: &StorageStatus) -> &'static str {
     match status {
         StorageStatus::Disconnected => "Disconnected",
@@ -51,3 +329,45 @@ pub fn format_status(status: &StorageStatus) -> &'static str {
         StorageStatus::Error => "Error",
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restart_backoff_doubles_up_to_the_max_and_resets() {
+        let mut backoff = RestartBackoff::new(Duration::from_secs(5), Duration::from_secs(30));
+        let delays: Vec<_> = (0..5).map(|_| backoff.next_delay().as_secs()).collect();
+        assert_eq!(delays, [5, 10, 20, 30, 30]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_secs(5));
+    }
+
+    #[test]
+    fn empty_network_alerts_once_then_redials_each_period() {
+        let mut watch = EmptyNetworkWatch::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(watch.observe(0, at(0)), PeerWatchAction::None);
+        assert_eq!(watch.observe(0, at(30)), PeerWatchAction::None);
+        assert_eq!(watch.observe(0, at(60)), PeerWatchAction::Alert);
+        assert_eq!(watch.observe(0, at(90)), PeerWatchAction::None);
+        assert_eq!(watch.observe(0, at(120)), PeerWatchAction::Redial);
+        assert_eq!(watch.observe(2, at(150)), PeerWatchAction::Recovered);
+        assert_eq!(watch.observe(2, at(180)), PeerWatchAction::None);
+    }
+
+    #[test]
+    fn peers_before_the_alert_period_restart_it() {
+        let mut watch = EmptyNetworkWatch::new(Duration::from_secs(60));
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(watch.observe(0, at(0)), PeerWatchAction::None);
+        assert_eq!(watch.observe(1, at(30)), PeerWatchAction::None);
+        assert_eq!(watch.observe(0, at(60)), PeerWatchAction::None);
+        assert_eq!(watch.observe(0, at(120)), PeerWatchAction::Alert);
+    }
+}
@@ -0,0 +1,231 @@
+use crate::config::Config;
+use crate::types::{Notification, NotifierKind};
+use crate::utils::{send_email, Email, SmtpError, SmtpServer};
+use futures::future::BoxFuture;
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{info, warn};
+
+/// How long a webhook receiver gets to answer
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Error, Debug)]
+pub enum NotifyError {
+    #[error("Webhook error: {0}")]
+    WebhookError(#[from] reqwest::Error),
+    #[error("Email error: {0}")]
+    EmailError(#[from] SmtpError),
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+/// Channel run milestones are reported on
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn notify<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), NotifyError>>;
+}
+
+/// Writes milestones to the log
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn name(&self) -> &'static str {
+        "log"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), NotifyError>> {
+        Box::pin(async move {
+            info!("{}", notification);
+            Ok(())
+        })
+    }
+}
+
+/// POSTs each milestone as JSON, tagged with its `event` name like the events stream
+pub struct WebhookNotifier {
+    client: reqwest::Client,
+    url: String,
+}
+
+impl WebhookNotifier {
+    pub fn new(url: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), NotifyError>> {
+        Box::pin(async move {
+            self.client
+                .post(&self.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(serde_json::to_vec(notification)?)
+                .timeout(WEBHOOK_TIMEOUT)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Mails each milestone to a fixed list of recipients
+pub struct EmailNotifier {
+    server: SmtpServer,
+    from: String,
+    to: Vec<String>,
+}
+
+impl EmailNotifier {
+    pub fn new(server: SmtpServer, from: String, to: Vec<String>) -> Self {
+        Self { server, from, to }
+    }
+}
+
+impl Notifier for EmailNotifier {
+    fn name(&self) -> &'static str {
+        "email"
+    }
+
+    fn notify<'a>(
+        &'a self,
+        notification: &'a Notification,
+    ) -> BoxFuture<'a, Result<(), NotifyError>> {
+        Box::pin(async move {
+            let subject = notification.subject();
+            let body = notification.to_string();
+            let email = Email {
+                from: &self.from,
+                to: &self.to,
+                subject: &subject,
+                body: &body,
+            };
+            send_email(&self.server, &email).await?;
+            Ok(())
+        })
+    }
+}
+
+/// Every configured notifier. A notifier that fails is logged and never stops the others
+/// or the run.
+#[derive(Default)]
+pub struct Notifiers {
+    notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Notifiers {
+    pub fn new(notifiers: Vec<Box<dyn Notifier>>) -> Self {
+        Self { notifiers }
+    }
+
+    /// The notifiers selected in NOTIFIERS. `Config::from_env` makes sure each one has the
+    /// settings it needs.
+    pub fn from_config(config: &Config) -> Self {
+        let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+        for kind in &config.notifiers {
+            match kind {
+                NotifierKind::Log => notifiers.push(Box::new(LogNotifier)),
+                NotifierKind::Webhook => {
+                    if let Some(url) = &config.notify_webhook_url {
+                        notifiers.push(Box::new(WebhookNotifier::new(url.clone())));
+                    }
+                }
+                NotifierKind::Email => {
+                    if let (Some(server), Some(from)) =
+                        (&config.notify_smtp_server, &config.notify_email_from)
+                    {
+                        notifiers.push(Box::new(EmailNotifier::new(
+                            server.clone(),
+                            from.clone(),
+                            config.notify_email_to.clone(),
+                        )));
+                    }
+                }
+            }
+        }
+        Self::new(notifiers)
+    }
+
+    pub async fn notify(&self, notification: Notification) {
+        let results = futures::future::join_all(
+            self.notifiers.iter().map(|notifier| notifier.notify(&notification)),
+        )
+        .await;
+
+        for (notifier, result) in self.notifiers.iter().zip(results) {
+            if let Err(e) = result {
+                warn!("Failed to send {} notification: {}", notifier.name(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    struct Recorder {
+        received: Arc<Mutex<Vec<String>>>,
+        fail: bool,
+    }
+
+    impl Notifier for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn notify<'a>(
+            &'a self,
+            notification: &'a Notification,
+        ) -> BoxFuture<'a, Result<(), NotifyError>> {
+            Box::pin(async move {
+                self.received.lock().unwrap().push(notification.subject());
+                match self.fail {
+                    true => Err(serde_json::from_str::<u8>("x").unwrap_err().into()),
+                    false => Ok(()),
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn a_failing_notifier_does_not_stop_the_others() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let notifiers = Notifiers::new(vec![
+            Box::new(Recorder {
+                received: received.clone(),
+                fail: true,
+            }),
+            Box::new(Recorder {
+                received: received.clone(),
+                fail: false,
+            }),
+        ]);
+
+        notifiers
+            .notify(Notification::RunFailed {
+                error: "disk full".to_string(),
+            })
+            .await;
+
+        assert_eq!(received.lock().unwrap().len(), 2);
+    }
+}
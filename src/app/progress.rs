@@ -0,0 +1,240 @@
+use crate::services::EventService;
+use crate::types::{PipelineEvent, PipelineStage};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+/// One line of the `--progress-events` stream. Every field is always present, `null` when
+/// it does not apply, so consumers can rely on the shape.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct ProgressEvent {
+    /// Name of the pipeline event behind this line, e.g. `area_extracted`
+    pub event: &'static str,
+    pub phase: PipelineStage,
+    pub country: String,
+    /// WhosOnFirst ID of the area, `null` for country-level events
+    pub locality_id: Option<u32>,
+    /// Share of the country's areas done in this phase, `null` while the total is unknown,
+    /// e.g. for areas selected by ID or uploads started during extraction
+    pub percent: Option<f64>,
+}
+
+/// Areas done out of the total of each country and phase under way
+#[derive(Debug, Default)]
+struct ProgressTracker {
+    countries: HashMap<(String, PipelineStage), (u64, u64)>,
+}
+
+impl ProgressTracker {
+    fn apply(&mut self, event: &PipelineEvent) -> ProgressEvent {
+        let (phase, country_code, locality_id) = match event {
+            PipelineEvent::CountryStarted {
+                country_code,
+                stage,
+                area_count,
+                completed,
+            } => {
+                self.countries
+                    .insert((country_code.clone(), *stage), (*area_count, *completed));
+                (*stage, country_code, None)
+            }
+            PipelineEvent::AreaExtracted {
+                country_code,
+                area_id,
+            } => {
+                self.advance(country_code, PipelineStage::Extraction);
+                (PipelineStage::Extraction, country_code, Some(*area_id))
+            }
+            PipelineEvent::AreaSkipped {
+                country_code,
+                area_id,
+                ..
+            } => (PipelineStage::Extraction, country_code, Some(*area_id)),
+            PipelineEvent::UploadStarted {
+                country_code,
+                area_id,
+                ..
+            } => (PipelineStage::Upload, country_code, Some(*area_id)),
+            PipelineEvent::AreaUploaded {
+                country_code,
+                area_id,
+                ..
+            }
+            | PipelineEvent::UploadFailed {
+                country_code,
+                area_id,
+                ..
+            } => {
+                self.advance(country_code, PipelineStage::Upload);
+                (PipelineStage::Upload, country_code, Some(*area_id))
+            }
+            PipelineEvent::CountryCompleted {
+                country_code,
+                stage,
+            } => {
+                self.countries.remove(&(country_code.clone(), *stage));
+                return ProgressEvent {
+                    event: event.name(),
+                    phase: *stage,
+                    country: country_code.clone(),
+                    locality_id: None,
+                    percent: Some(100.0),
+                };
+            }
+        };
+
+        ProgressEvent {
+            event: event.name(),
+            phase,
+            country: country_code.clone(),
+            locality_id,
+            percent: self.percent(country_code, phase),
+        }
+    }
+
+    fn advance(&mut self, country_code: &str, phase: PipelineStage) {
+        if let Some((_, done)) = self.countries.get_mut(&(country_code.to_string(), phase)) {
+            *done += 1;
+        }
+    }
+
+    /// Rounded to one decimal, capped at 100 since retried areas may be counted twice
+    fn percent(&self, country_code: &str, phase: PipelineStage) -> Option<f64> {
+        let (total, done) = self.countries.get(&(country_code.to_string(), phase))?;
+        if *total == 0 {
+            return Some(100.0);
+        }
+        let percent = (*done).min(*total) as f64 / *total as f64 * 100.0;
+        Some((percent * 10.0).round() / 10.0)
+    }
+}
+
+/// Write each pipeline event as a line of JSON to `target`, stdout when `None`. The target
+/// may be a named pipe, opening it waits for a reader. Stops when the target stops taking
+/// lines, e.g. once the reader of a pipe goes away.
+pub fn start_progress_events(
+    events: &EventService,
+    target: Option<PathBuf>,
+) -> tokio::task::JoinHandle<()> {
+    // Subscribe right away so events emitted before the task first runs are not missed
+    let mut receiver = events.subscribe();
+
+    tokio::spawn(async move {
+        let mut output: Box<dyn AsyncWrite + Unpin + Send> = match &target {
+            None => Box::new(tokio::io::stdout()),
+            Some(path) => {
+                let file = tokio::fs::OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)
+                    .await;
+                match file {
+                    Ok(file) => {
+                        info!("Writing progress events to {}", path.display());
+                        Box::new(file)
+                    }
+                    Err(e) => {
+                        warn!(
+                            "Failed to open {} for progress events: {}",
+                            path.display(),
+                            e
+                        );
+                        return;
+                    }
+                }
+            }
+        };
+
+        let mut tracker = ProgressTracker::default();
+        loop {
+            let event = match receiver.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("Progress events lagged, {} events dropped", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let progress = tracker.apply(&event);
+            let Ok(mut line) = serde_json::to_vec(&progress) else {
+                continue;
+            };
+            line.push(b'\n');
+            if let Err(e) = write_line(&mut output, &line).await {
+                warn!("Stopped writing progress events: {}", e);
+                return;
+            }
+        }
+    })
+}
+
+async fn write_line(
+    output: &mut (dyn AsyncWrite + Unpin + Send),
+    line: &[u8],
+) -> std::io::Result<()> {
+    output.write_all(line).await?;
+    output.flush().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percent_follows_the_areas_done_in_each_phase() {
+        let mut tracker = ProgressTracker::default();
+        let started = tracker.apply(&PipelineEvent::CountryStarted {
+            country_code: "FR".to_string(),
+            stage: PipelineStage::Extraction,
+            area_count: 4,
+            completed: 1,
+        });
+        assert_eq!(started.percent, Some(25.0));
+        assert_eq!(started.locality_id, None);
+
+        let extracted = tracker.apply(&PipelineEvent::AreaExtracted {
+            country_code: "FR".to_string(),
+            area_id: 7,
+        });
+        assert_eq!(extracted.event, "area_extracted");
+        assert_eq!(extracted.locality_id, Some(7));
+        assert_eq!(extracted.percent, Some(50.0));
+
+        let uploaded = tracker.apply(&PipelineEvent::AreaUploaded {
+            country_code: "FR".to_string(),
+            area_id: 7,
+            cid: "cid".to_string(),
+            file_size: 10,
+        });
+        assert_eq!(uploaded.phase, PipelineStage::Upload);
+        assert_eq!(uploaded.percent, None);
+
+        let completed = tracker.apply(&PipelineEvent::CountryCompleted {
+            country_code: "FR".to_string(),
+            stage: PipelineStage::Extraction,
+        });
+        assert_eq!(completed.percent, Some(100.0));
+    }
+
+    #[test]
+    fn lines_keep_every_field() {
+        let mut tracker = ProgressTracker::default();
+        let event = tracker.apply(&PipelineEvent::UploadStarted {
+            country_code: "DE".to_string(),
+            area_id: 3,
+            file_size: 10,
+        });
+        let json = serde_json::to_value(&event).unwrap();
+
+        assert_eq!(json["event"], "upload_started");
+        assert_eq!(json["phase"], "upload");
+        assert_eq!(json["country"], "DE");
+        assert_eq!(json["locality_id"], 3);
+        assert!(json["percent"].is_null());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::types::{CountryUsage, PhaseTimings, UploadStats};
+use serde::Serialize;
+use std::path::Path;
+
+/// Upload totals and phase timings for a run alongside the storage each country occupies
+#[derive(Serialize)]
+struct RunReport<'a> {
+    generated_at: String,
+    uploaded: u64,
+    reused: u64,
+    failed: u64,
+    bytes_uploaded: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lifetime: Option<LifetimeReport<'a>>,
+    timings: &'a PhaseTimings,
+    countries: &'a [CountryUsage],
+}
+
+#[derive(Serialize)]
+struct LifetimeReport<'a> {
+    runs: u64,
+    #[serde(flatten)]
+    stats: &'a UploadStats,
+}
+
+/// Write the run report as JSON, through a temporary file so readers never see a partial report
+pub async fn write_run_report(
+    path: &Path,
+    stats: &UploadStats,
+    lifetime: Option<&(u64, UploadStats)>,
+    timings: &PhaseTimings,
+    countries: &[CountryUsage],
+) -> std::io::Result<()> {
+    let report = RunReport {
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        uploaded: stats.total_uploaded,
+        reused: stats.total_reused,
+        failed: stats.total_failed,
+        bytes_uploaded: stats.total_bytes_uploaded,
+        lifetime: lifetime.map(|(runs, stats)| LifetimeReport { runs: *runs, stats }),
+        timings,
+        countries,
+    };
+    let json = serde_json::to_string_pretty(&report)?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    tokio::fs::write(&temp_path, json).await?;
+    tokio::fs::rename(&temp_path, path).await
+}
@@ -1,28 +1,46 @@
-use crate::app::monitor::{create_node_status_progress_bar, monitor_node_status};
+use crate::app::monitor::{create_node_status_progress_bar, format_usage, monitor_node_status};
+use crate::app::exit::ExitStatus;
+use crate::app::notifier::Notifiers;
+use crate::app::systemd::SystemdNotifier;
+use crate::app::tui::{monitor_dashboard, TuiState};
+use crate::app::report::write_run_report;
 use crate::config::Config;
 use crate::initialization::print_final_stats;
 use crate::services::{
-    AreaUploadService, CountryService, ExtractionService, StorageService,
+    AreaUploadService, CountryService, EventService, ExtractionError, ExtractionService,
+    StorageBackend,
 };
+use crate::types::{CompletedExtract, Notification, RunPhase, UploadStats};
+use crate::utils::format_bytes;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
 use tracing::{error, info, warn};
 
-use super::ApplicationResult;
+use super::{ApplicationError, ApplicationResult};
 
 pub struct NodeRunner {
     config: Arc<Config>,
-    storage_service: Arc<StorageService>,
+    storage_service: Arc<dyn StorageBackend>,
     extraction_service: ExtractionService,
     upload_service: AreaUploadService,
     country_service: CountryService,
     area_ids: Vec<u32>,
     skip_extract: bool,
+    /// Run even when the planned run exceeds the storage quota
+    force: bool,
+    connect_peers: Vec<String>,
+    /// Phase of the interrupted run this one resumes
+    resumed_phase: Option<RunPhase>,
+    notifiers: Notifiers,
+    systemd: SystemdNotifier,
+    tui: Option<(TuiState, Arc<EventService>)>,
 }
 
 impl NodeRunner {
     pub fn new(
         config: Arc<Config>,
-        storage_service: Arc<StorageService>,
+        storage_service: Arc<dyn StorageBackend>,
         extraction_service: ExtractionService,
         upload_service: AreaUploadService,
         country_service: CountryService,
@@ -37,96 +55,289 @@ impl NodeRunner {
             country_service,
             area_ids,
             skip_extract,
+            force: false,
+            connect_peers: Vec::new(),
+            resumed_phase: None,
+            notifiers: Notifiers::default(),
+            systemd: SystemdNotifier::default(),
+            tui: None,
         }
     }
 
-    pub async fn run(&self) -> ApplicationResult<()> {
+    /// Peers to dial as soon as the storage node is up
+    pub fn with_connect_peers(mut self, connect_peers: Vec<String>) -> Self {
+        self.connect_peers = connect_peers;
+        self
+    }
+
+    /// Run even when the PLAN_SAMPLES estimate exceeds the storage quota
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Resume an interrupted run, extraction is skipped when it had reached the upload phase
+    pub fn with_resumed_phase(mut self, phase: Option<RunPhase>) -> Self {
+        self.resumed_phase = phase;
+        self
+    }
+
+    /// Channels run milestones are reported on
+    pub fn with_notifiers(mut self, notifiers: Notifiers) -> Self {
+        self.notifiers = notifiers;
+        self
+    }
+
+    /// Service manager the run reports its phases to
+    pub fn with_systemd(mut self, systemd: SystemdNotifier) -> Self {
+        self.systemd = systemd;
+        self
+    }
+
+    /// Monitor the run on a terminal dashboard fed by `events` instead of the status spinner
+    pub fn with_tui(mut self, state: TuiState, events: Arc<EventService>) -> Self {
+        self.tui = Some((state, events));
+        self
+    }
+
+    /// Run the pipeline once. A run that went through but left work undone returns the
+    /// status saying what, extraction that stopped early or uploads that failed.
+    pub async fn run(&self) -> ApplicationResult<ExitStatus> {
+        let result = self.run_pipeline().await;
+        if let Err(e) = &result {
+            self.notifiers
+                .notify(Notification::RunFailed {
+                    error: e.to_string(),
+                })
+                .await;
+        }
+        result
+    }
+
+    /// Run the pipeline in the background. As with `run`, the storage node keeps serving once
+    /// the run is done, until `StorageBackend::stop_node`. A cancelled run stops it.
+    pub fn spawn(self) -> RunHandle {
+        let runner = Arc::new(self);
+        let (cancel, mut cancelled) = watch::channel(false);
+
+        let task = tokio::spawn({
+            let runner = runner.clone();
+            async move {
+                tokio::select! {
+                    result = runner.run() => result,
+                    // The sender lives in the handle, a dropped handle leaves the run going.
+                    // The borrowed value is dropped right away, it is not Send.
+                    Ok(()) = async { cancelled.wait_for(|cancelled| *cancelled).await.map(drop) } => {
+                        info!("Run cancelled, the next run resumes from its checkpoint");
+                        if let Err(e) = runner.shutdown().await {
+                            warn!("Failed to stop the storage node: {}", e);
+                        }
+                        Ok(ExitStatus::Cancelled)
+                    }
+                }
+            }
+        });
+
+        RunHandle {
+            runner,
+            cancel,
+            task,
+        }
+    }
+
+    async fn run_pipeline(&self) -> ApplicationResult<ExitStatus> {
+        let extracting = !self.skip_extract && self.resumed_phase != Some(RunPhase::Upload);
+        if extracting && self.config.plan_samples > 0 && self.area_ids.is_empty() {
+            self.plan().await?;
+        }
+
         info!("Starting storage node...");
+        self.systemd.status("Starting storage node");
         self.storage_service.start_node().await?;
         info!("Storage node started successfully");
+        self.systemd.attach_storage(self.storage_service.clone());
+        self.systemd.ready();
+        let peer_id = match self.storage_service.get_node_info().await {
+            Ok(node_info) => node_info.peer_id,
+            Err(_) => None,
+        };
+        self.notifiers
+            .notify(Notification::NodeStarted { peer_id })
+            .await;
 
-        if !self.skip_extract {
-            info!("Extracting PMTiles from planet file...");
-            if !self.area_ids.is_empty() {
-                info!("Processing {} specific area IDs", self.area_ids.len());
-                if let Err(e) = self
-                    .extraction_service
-                    .extract_areas_by_ids(&self.area_ids)
-                    .await
-                {
-                    error!("Failed to extract PMTiles: {}", e);
-                    warn!("Continuing with existing PMTiles if available...");
-                }
-            } else {
-                let countries = self
-                    .country_service
-                    .get_countries_to_process(&self.config.target_countries);
-                info!("Processing {} countries", countries.len());
-                if let Err(e) = self.extraction_service.extract_areas(&countries).await {
-                    error!("Failed to extract PMTiles: {}", e);
-                    warn!("Continuing with existing PMTiles if available...");
-                }
+        for target in &self.connect_peers {
+            match self.storage_service.connect_peer(target).await {
+                Ok(peer_id) => info!("Connected to peer {}", peer_id),
+                Err(e) => warn!("Failed to connect to peer: {}", e),
             }
-        } else {
+        }
+
+        let mut extraction_failed = false;
+        if self.skip_extract {
             info!("Skipping PMTiles extraction (--no-extract flag set)");
+        } else if self.resumed_phase == Some(RunPhase::Upload) {
+            info!("Skipping PMTiles extraction, the interrupted run had finished it");
+        } else {
+            info!("Extracting PMTiles from planet file, uploading extracts as they complete...");
+            self.systemd.status("Extracting and uploading areas");
+            // Extraction is held back at the high watermark, so the channel never holds more
+            let (sender, receiver) = mpsc::channel(self.config.upload_backlog_high);
+            let (result, streamed) = tokio::join!(
+                self.extract(sender),
+                self.upload_service.process_extracts(receiver)
+            );
+            if let Err(e) = streamed {
+                warn!(
+                    "Uploads stopped during extraction, the rest wait for the final pass: {}",
+                    e
+                );
+            }
+            if let Err(e) = &result {
+                error!("Failed to extract PMTiles: {}", e);
+                warn!("Continuing with existing PMTiles if available...");
+                extraction_failed = true;
+            }
+            self.notifiers
+                .notify(Notification::ExtractionFinished {
+                    error: result.err().map(|e| e.to_string()),
+                })
+                .await;
         }
 
         info!("Uploading areas to storage...");
+        self.systemd.status("Uploading areas");
         self.upload_service.process_areas().await?;
 
         let stats = self.upload_service.get_stats().await;
-        print_final_stats(&stats);
-
-        self.display_node_info().await;
+        let lifetime = match self.upload_service.record_run().await {
+            Ok(lifetime) => Some(lifetime),
+            Err(e) => {
+                warn!("Failed to record run statistics: {}", e);
+                None
+            }
+        };
+        let timings = self.upload_service.get_timings().await;
+        print_final_stats(&stats, lifetime.as_ref(), &timings);
+        self.notifiers
+            .notify(Notification::UploadsFinished {
+                stats: stats.clone(),
+            })
+            .await;
 
-        Ok(())
-    }
+        match self.upload_service.publish_index().await {
+            Ok(Some(index)) => {
+                info!(
+                    "Dataset root CID: {} ({} countries, {} areas)",
+                    index.root_cid, index.country_count, index.area_count
+                );
+                self.notifiers
+                    .notify(Notification::IndexPublished {
+                        root_cid: index.root_cid,
+                        country_count: index.country_count,
+                        area_count: index.area_count,
+                    })
+                    .await;
+            }
+            Ok(None) => info!("Nothing uploaded yet, no dataset index published"),
+            Err(e) => warn!("Failed to publish the dataset index: {}", e),
+        }
 
-    async fn display_node_info(&self) {
-        match self.storage_service.get_node_info().await {
-            Ok(node_info) => {
-                info!("Storage node is now running and serving files to the network...");
-                if let Some(peer_id) = node_info.peer_id {
-                    info!("Peer ID: {}", peer_id);
-                }
-                if !node_info.addresses.is_empty() {
-                    info!("Node Addresses:");
-                    for addr in &node_info.addresses {
-                        info!("  {}", addr);
+        if let Some(path) = &self.config.report_file {
+            match self.upload_service.get_bytes_by_country().await {
+                Ok(countries) => {
+                    let report =
+                        write_run_report(path, &stats, lifetime.as_ref(), &timings, &countries);
+                    match report.await {
+                        Ok(()) => info!("Wrote run report to {}", path.display()),
+                        Err(e) => warn!("Failed to write run report to {}: {}", path.display(), e),
                     }
                 }
-                if !node_info.announce_addresses.is_empty() {
-                    info!("Announce Addresses:");
-                    for addr in &node_info.announce_addresses {
-                        info!("  {}", addr);
-                    }
-                }
-                if let Some(spr) = node_info.spr {
-                    info!("Signed Peer Record:\n  {}", spr);
-                }
-                info!("Discovery table nodes: {}", node_info.discovery_node_count);
-                if node_info.discovery_node_count > 0 {
-                    info!("Successfully connected to the network via bootstrap nodes");
-                } else {
-                    warn!("No peers in discovery table - bootstrap may have failed");
-                }
-                if let Some(version) = node_info.version {
-                    info!("Storage version: {}", version);
-                }
+                Err(e) => warn!("Failed to compute per-country storage: {}", e),
             }
-            Err(e) => {
-                info!("Storage node is now running and serving files to the network...");
-                warn!("Failed to get node info: {}", e);
+        }
+
+        display_node_info(self.storage_service.as_ref()).await;
+        self.systemd.status("Serving");
+
+        if extraction_failed {
+            Ok(ExitStatus::ExtractionIncomplete)
+        } else if stats.total_failed > 0 {
+            Ok(ExitStatus::UploadFailure)
+        } else {
+            Ok(ExitStatus::Success)
+        }
+    }
+
+    /// Sample the target countries and print what the run is expected to take, refusing to
+    /// start a run over the storage quota unless forced
+    async fn plan(&self) -> ApplicationResult<()> {
+        let countries = self
+            .country_service
+            .get_countries_to_process(&self.config.target_countries)
+            .await;
+        info!(
+            "Planning the run from {} sample extracts per country...",
+            self.config.plan_samples
+        );
+        self.systemd.status("Planning the run");
+        let plan = self
+            .extraction_service
+            .plan_countries(&countries, self.config.plan_samples)
+            .await?;
+        println!("{}", plan);
+
+        if plan.exceeds_quota() {
+            if !self.force {
+                return Err(ApplicationError::QuotaExceeded(
+                    format_bytes(plan.quota_bytes()),
+                    format_bytes(plan.storage_quota),
+                ));
             }
+            warn!("The run is expected to exceed the storage quota, starting it anyway (--force)");
+        }
+        Ok(())
+    }
+
+    /// Extract the target areas, handing each extract to the upload stage through `sender`.
+    /// The channel closes once extraction returns and its service is dropped.
+    async fn extract(
+        &self,
+        sender: mpsc::Sender<CompletedExtract>,
+    ) -> Result<(), ExtractionError> {
+        let extraction_service = self.extraction_service.clone().with_handoff(sender);
+        if !self.area_ids.is_empty() {
+            info!("Processing {} specific area IDs", self.area_ids.len());
+            extraction_service.extract_areas_by_ids(&self.area_ids).await
+        } else {
+            let countries = self
+                .country_service
+                .get_countries_to_process(&self.config.target_countries)
+                .await;
+            info!("Processing {} countries", countries.len());
+            extraction_service.extract_areas(&countries).await
         }
     }
 
     pub fn start_monitoring(&self) -> tokio::task::JoinHandle<()> {
-        let progress_bar = create_node_status_progress_bar();
         let storage_service = self.storage_service.clone();
+        let warn_thresholds = self.config.storage_warn_thresholds.clone();
+        let upload_progress = self.upload_service.progress();
+
+        if let Some((state, events)) = self.tui.clone() {
+            return tokio::spawn(monitor_dashboard(
+                state,
+                storage_service,
+                events,
+                warn_thresholds,
+                upload_progress,
+            ));
+        }
+
+        let progress_bar = create_node_status_progress_bar();
 
         tokio::spawn(async move {
-            monitor_node_status(storage_service, progress_bar).await;
+            monitor_node_status(storage_service, progress_bar, warn_thresholds, upload_progress)
+                .await;
         })
     }
 
@@ -137,3 +348,119 @@ impl NodeRunner {
         Ok(())
     }
 }
+
+/// Where a spawned run is, see `RunHandle::progress`
+#[derive(Debug, Clone)]
+pub struct RunProgress {
+    pub stats: UploadStats,
+    /// Extracts waiting for upload, 0 before uploads start
+    pub remaining_files: u64,
+    pub remaining_bytes: u64,
+    pub eta: Option<Duration>,
+}
+
+/// A run started by `NodeRunner::spawn`. Per-area progress is published on the event bus
+/// the runner was built with.
+pub struct RunHandle {
+    runner: Arc<NodeRunner>,
+    cancel: watch::Sender<bool>,
+    task: tokio::task::JoinHandle<ApplicationResult<ExitStatus>>,
+}
+
+impl RunHandle {
+    pub async fn progress(&self) -> RunProgress {
+        let stats = self.runner.upload_service.get_stats().await;
+        let progress = self.runner.upload_service.progress();
+        let progress = progress.lock().await;
+        RunProgress {
+            stats,
+            remaining_files: progress.remaining_files(),
+            remaining_bytes: progress.remaining_bytes(),
+            eta: progress.eta(),
+        }
+    }
+
+    /// Stop the run at its next step and the storage node with it. Work done so far is kept
+    /// and the next run resumes from the checkpoint. Does nothing once the run is done.
+    pub fn cancel(&self) {
+        self.cancel.send_replace(true);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+
+    /// Wait for the run to end, `ExitStatus::Cancelled` when it was cancelled
+    pub async fn await_completion(self) -> ApplicationResult<ExitStatus> {
+        match self.task.await {
+            Ok(result) => result,
+            Err(e) => Err(std::io::Error::other(format!("run task failed: {}", e)).into()),
+        }
+    }
+}
+
+/// Log the node's identity, addresses, peers and storage use once it is serving
+pub async fn display_node_info(storage_service: &dyn StorageBackend) {
+    match storage_service.get_node_info().await {
+        Ok(node_info) => {
+            info!("Storage node is now running and serving files to the network...");
+            if let Some(peer_id) = node_info.peer_id {
+                info!("Peer ID: {}", peer_id);
+            }
+            if !node_info.addresses.is_empty() {
+                info!("Node Addresses:");
+                for addr in &node_info.addresses {
+                    info!("  {}", addr);
+                }
+            }
+            if !node_info.announce_addresses.is_empty() {
+                info!("Announce Addresses:");
+                for addr in &node_info.announce_addresses {
+                    info!("  {}", addr);
+                }
+            }
+            if let Some(spr) = node_info.spr {
+                info!("Signed Peer Record:\n  {}", spr);
+            }
+            info!("Discovery table nodes: {}", node_info.discovery_node_count);
+            for peer in &node_info.peers {
+                info!(
+                    "  Peer {} {}{}",
+                    peer.peer_id,
+                    peer.address.as_deref().unwrap_or("-"),
+                    if peer.seen { "" } else { " (not seen)" }
+                );
+            }
+            if node_info.discovery_node_count > 0 {
+                info!("Successfully connected to the network via bootstrap nodes");
+            } else {
+                warn!("No peers in discovery table - bootstrap may have failed");
+            }
+            if let Some(usage) = &node_info.storage_usage {
+                info!("Storage usage: {}", format_usage(usage));
+            }
+            if let Some(version) = node_info.version {
+                info!("Storage version: {}", version);
+            }
+        }
+        Err(e) => {
+            info!("Storage node is now running and serving files to the network...");
+            warn!("Failed to get node info: {}", e);
+        }
+    }
+}
+
+/// Resolve on Ctrl+C or SIGTERM
+pub async fn wait_for_shutdown_signal() {
+    let mut sig_term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("Failed to setup SIGTERM handler");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {
+            info!("Received Ctrl+C, shutting down gracefully...");
+        }
+        _ = sig_term.recv() => {
+            info!("Received termination signal, shutting down gracefully...");
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use crate::config::Config;
+use crate::services::{DatabaseService, StorageBackend};
+use crate::utils::{compress_file, format_bytes};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::time::interval;
+use tracing::{info, warn};
+
+use super::ApplicationResult;
+
+/// Upload a snapshot of the CID database every `DB_SNAPSHOT_INTERVAL_SECS` and record its
+/// CID, so a node that lost its disk can be rebuilt from the network. The first snapshot is
+/// taken one interval after the start, once the node had time to connect.
+pub fn start_db_snapshot_publisher(
+    cid_db: Arc<DatabaseService>,
+    storage_service: Arc<dyn StorageBackend>,
+    config: Arc<Config>,
+) -> Option<tokio::task::JoinHandle<()>> {
+    let period = config.db_snapshot_interval?;
+
+    Some(tokio::spawn(async move {
+        let mut tick = interval(period);
+        tick.tick().await;
+
+        loop {
+            tick.tick().await;
+
+            if let Err(e) = publish_snapshot(&cid_db, storage_service.as_ref(), &config).await {
+                warn!("Failed to publish a CID database snapshot: {}", e);
+            }
+        }
+    }))
+}
+
+/// Back the database up next to itself, compress and upload the copy, then record its CID.
+/// A snapshot whose CID matches the previous one is not recorded again.
+async fn publish_snapshot(
+    cid_db: &DatabaseService,
+    storage_service: &dyn StorageBackend,
+    config: &Config,
+) -> ApplicationResult<()> {
+    let snapshot_path = snapshot_path(&config.cid_db_path);
+    if snapshot_path.exists() {
+        tokio::fs::remove_file(&snapshot_path).await?;
+    }
+
+    let result = async {
+        cid_db.backup_to(&snapshot_path).await?;
+        let payload = compress_file(
+            &snapshot_path,
+            config.db_snapshot_compression,
+            &config.zstd_cmd,
+        )
+        .await?;
+
+        let upload = storage_service.upload_file(&payload).await;
+        if payload != snapshot_path {
+            let _ = tokio::fs::remove_file(&payload).await;
+        }
+        ApplicationResult::Ok(upload?)
+    }
+    .await;
+    let _ = tokio::fs::remove_file(&snapshot_path).await;
+    let upload = result?;
+
+    let latest = cid_db.get_latest_db_snapshot().await?;
+    if latest.is_some_and(|snapshot| snapshot.cid == upload.cid) {
+        return Ok(());
+    }
+
+    cid_db
+        .record_db_snapshot(&upload.cid, upload.size, config.db_snapshot_compression)
+        .await?;
+    info!(
+        "Published CID database snapshot {} ({})",
+        upload.cid,
+        format_bytes(upload.size)
+    );
+    Ok(())
+}
+
+/// `<cid_db>.snapshot`, on the database's volume so the copy does not fill another one
+fn snapshot_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".snapshot");
+    PathBuf::from(path)
+}
@@ -0,0 +1,84 @@
+use crate::services::StorageBackend;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::interval;
+use tracing::{info, warn};
+
+const SPR_FILE_REFRESH_SECS: u64 = 10;
+
+/// Node record published for orchestration tooling
+#[derive(Debug, PartialEq, Serialize)]
+struct NodeRecord {
+    peer_id: Option<String>,
+    spr: String,
+    addresses: Vec<String>,
+    announce_addresses: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct NodeRecordFile<'a> {
+    #[serde(flatten)]
+    record: &'a NodeRecord,
+    updated_at: String,
+}
+
+/// Keep `path` up to date with the node's signed peer record and addresses. The file is
+/// written once the node reports an SPR and rewritten whenever the record changes.
+pub fn start_spr_file_writer(
+    storage_service: Arc<dyn StorageBackend>,
+    path: PathBuf,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut tick = interval(Duration::from_secs(SPR_FILE_REFRESH_SECS));
+        let mut written: Option<NodeRecord> = None;
+
+        loop {
+            tick.tick().await;
+
+            let Ok(node_info) = storage_service.get_node_info().await else {
+                continue;
+            };
+            let Some(spr) = node_info.spr else {
+                continue;
+            };
+
+            let record = NodeRecord {
+                peer_id: node_info.peer_id,
+                spr,
+                addresses: node_info.addresses,
+                announce_addresses: node_info.announce_addresses,
+            };
+            if written.as_ref() == Some(&record) {
+                continue;
+            }
+
+            match write_record(&path, &record).await {
+                Ok(()) => {
+                    info!("Wrote signed peer record to {}", path.display());
+                    written = Some(record);
+                }
+                Err(e) => warn!("Failed to write signed peer record to {}: {}", path.display(), e),
+            }
+        }
+    })
+}
+
+/// Write through a temporary file so readers never see a partial record
+async fn write_record(path: &Path, record: &NodeRecord) -> std::io::Result<()> {
+    let file = NodeRecordFile {
+        record,
+        updated_at: chrono::Utc::now().to_rfc3339(),
+    };
+    let json = serde_json::to_string_pretty(&file)?;
+
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    tokio::fs::write(&temp_path, json).await?;
+    tokio::fs::rename(&temp_path, path).await
+}
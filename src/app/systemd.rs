@@ -0,0 +1,205 @@
+use crate::app::monitor::{format_status, format_usage};
+use crate::services::StorageBackend;
+use std::os::unix::net::UnixDatagram;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// Reports state to the service manager through $NOTIFY_SOCKET (sd_notify). Every call is
+/// a no-op when the process was not started by systemd with `Type=notify`. Downloads and
+/// the first extraction run before READY=1, so units want `TimeoutStartSec=infinity`, and
+/// `WatchdogSec=` to have a hung storage node restarted.
+#[derive(Clone, Default)]
+pub struct SystemdNotifier {
+    inner: Option<Arc<Inner>>,
+}
+
+struct Inner {
+    socket: UnixDatagram,
+    address: std::os::unix::net::SocketAddr,
+    /// Half of WATCHDOG_USEC, `None` when the watchdog is off
+    watchdog_interval: Option<Duration>,
+    /// Pipeline phase shown in STATUS, storage state is appended to it
+    phase: Mutex<String>,
+    storage: OnceLock<Arc<dyn StorageBackend>>,
+}
+
+impl SystemdNotifier {
+    pub fn from_env() -> Self {
+        let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+            return Self::default();
+        };
+
+        let address = match notify_address(&path.to_string_lossy()) {
+            Ok(address) => address,
+            Err(e) => {
+                warn!("Ignoring NOTIFY_SOCKET {:?}: {}", path, e);
+                return Self::default();
+            }
+        };
+        let socket = match UnixDatagram::unbound() {
+            Ok(socket) => socket,
+            Err(e) => {
+                warn!("Failed to open the systemd notification socket: {}", e);
+                return Self::default();
+            }
+        };
+
+        Self {
+            inner: Some(Arc::new(Inner {
+                socket,
+                address,
+                watchdog_interval: watchdog_interval(),
+                phase: Mutex::new(String::new()),
+                storage: OnceLock::new(),
+            })),
+        }
+    }
+
+    /// Startup is complete, `systemctl start` returns
+    pub fn ready(&self) {
+        self.send("READY=1");
+    }
+
+    pub fn stopping(&self) {
+        self.send("STOPPING=1");
+    }
+
+    /// Show `phase` in `systemctl status`, followed by the storage state once a node is attached
+    pub fn status(&self, phase: &str) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        *inner.phase.lock().unwrap_or_else(|e| e.into_inner()) = phase.to_string();
+        self.send(&format!("STATUS={}", phase));
+    }
+
+    /// Storage node whose health gates watchdog pings and whose state is shown in STATUS
+    pub fn attach_storage(&self, storage: Arc<dyn StorageBackend>) {
+        if let Some(inner) = &self.inner {
+            let _ = inner.storage.set(storage);
+        }
+    }
+
+    /// Ping the watchdog every half period while the storage node answers in time, so a
+    /// hung node gets the service restarted. Returns `None` when the watchdog is off.
+    pub fn start_watchdog(&self) -> Option<tokio::task::JoinHandle<()>> {
+        let inner = self.inner.clone()?;
+        let interval = inner.watchdog_interval?;
+        let notifier = self.clone();
+
+        Some(tokio::spawn(async move {
+            let mut tick = tokio::time::interval(interval);
+            loop {
+                tick.tick().await;
+
+                let Some(storage) = inner.storage.get() else {
+                    // Still initializing, nothing to check yet
+                    notifier.send("WATCHDOG=1");
+                    continue;
+                };
+
+                match tokio::time::timeout(interval, storage_summary(storage.as_ref())).await {
+                    Ok(summary) => {
+                        let phase = inner.phase.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                        let status = match phase.is_empty() {
+                            true => summary,
+                            false => format!("{} | {}", phase, summary),
+                        };
+                        notifier.send(&format!("STATUS={}\nWATCHDOG=1", status));
+                    }
+                    Err(_) => warn!(
+                        "Storage node did not answer within {:?}, skipping watchdog ping",
+                        interval
+                    ),
+                }
+            }
+        }))
+    }
+
+    fn send(&self, state: &str) {
+        let Some(inner) = &self.inner else {
+            return;
+        };
+        if let Err(e) = inner.socket.send_to_addr(state.as_bytes(), &inner.address) {
+            debug!("Failed to notify systemd: {}", e);
+        }
+    }
+}
+
+async fn storage_summary(storage: &dyn StorageBackend) -> String {
+    let status = storage.get_status().await;
+    match storage.get_node_info().await {
+        Ok(node_info) => {
+            let mut summary = format!(
+                "Storage: {}, {} discovery nodes",
+                format_status(&status),
+                node_info.discovery_node_count
+            );
+            if let Some(usage) = node_info.storage_usage {
+                summary.push_str(&format!(", {}", format_usage(&usage)));
+            }
+            summary
+        }
+        Err(_) => format!("Storage: {}", format_status(&status)),
+    }
+}
+
+/// Socket address of NOTIFY_SOCKET, a path or an abstract name starting with `@`
+fn notify_address(path: &str) -> std::io::Result<std::os::unix::net::SocketAddr> {
+    #[cfg(target_os = "linux")]
+    if let Some(name) = path.strip_prefix('@') {
+        use std::os::linux::net::SocketAddrExt;
+        return std::os::unix::net::SocketAddr::from_abstract_name(name);
+    }
+    std::os::unix::net::SocketAddr::from_pathname(path)
+}
+
+/// Half the watchdog period from WATCHDOG_USEC, when it is meant for this process
+fn watchdog_interval() -> Option<Duration> {
+    let pid_matches = std::env::var("WATCHDOG_PID")
+        .ok()
+        .and_then(|pid| pid.parse::<u32>().ok())
+        .is_none_or(|pid| pid == std::process::id());
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+
+    (pid_matches && usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_notifier_does_nothing() {
+        let notifier = SystemdNotifier::default();
+        notifier.ready();
+        notifier.status("Uploading");
+        assert!(notifier.start_watchdog().is_none());
+    }
+
+    #[test]
+    fn sends_states_to_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notify");
+        let receiver = UnixDatagram::bind(&path).unwrap();
+
+        let notifier = SystemdNotifier {
+            inner: Some(Arc::new(Inner {
+                socket: UnixDatagram::unbound().unwrap(),
+                address: notify_address(&path.to_string_lossy()).unwrap(),
+                watchdog_interval: None,
+                phase: Mutex::new(String::new()),
+                storage: OnceLock::new(),
+            })),
+        };
+        notifier.status("Extracting areas");
+        notifier.ready();
+
+        let mut buffer = [0; 256];
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"STATUS=Extracting areas");
+        let len = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"READY=1");
+    }
+}
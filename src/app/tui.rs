@@ -0,0 +1,497 @@
+use crate::app::monitor::{
+    create_node_status_progress_bar, format_status, format_usage, monitor_node_status, UsageAlerts,
+};
+use crate::services::{EventService, NodeInfo, StorageBackend};
+use crate::types::{PipelineEvent, PipelineStage, UploadProgress};
+use crate::utils::format_bytes;
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::cursor::{Hide, Show};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{IsTerminal, Stdout};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::field::{Field, Visit};
+use tracing::{Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// How many warnings and errors the dashboard keeps
+const RECENT_ERRORS: usize = 8;
+
+/// Upload rows shown before the rest are summed up
+const CURRENT_UPLOADS: usize = 6;
+
+/// What the terminal dashboard shows, fed by pipeline events and warning/error logs
+#[derive(Clone, Default)]
+pub struct TuiState {
+    dashboard: Arc<Mutex<Dashboard>>,
+    /// The dashboard owns the terminal, plain log output is held back
+    active: Arc<AtomicBool>,
+}
+
+#[derive(Default)]
+struct Dashboard {
+    countries: BTreeMap<String, CountryProgress>,
+    /// Size of each area being uploaded, by country and area ID
+    uploading: BTreeMap<(String, u32), u64>,
+    errors: VecDeque<String>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct CountryProgress {
+    extracted: u64,
+    uploaded: u64,
+    failed: u64,
+    skipped: u64,
+    extraction_done: bool,
+    upload_done: bool,
+}
+
+impl TuiState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True while the dashboard is drawn, log output to the terminal should be hidden
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn apply(&self, event: &PipelineEvent) {
+        let mut dashboard = self.dashboard.lock().unwrap_or_else(|e| e.into_inner());
+        let dashboard = &mut *dashboard;
+        match event {
+            PipelineEvent::CountryStarted { .. } => {}
+            PipelineEvent::AreaExtracted { country_code, .. } => {
+                dashboard.country(country_code).extracted += 1;
+            }
+            PipelineEvent::UploadStarted {
+                country_code,
+                area_id,
+                file_size,
+            } => {
+                dashboard
+                    .uploading
+                    .insert((country_code.clone(), *area_id), *file_size);
+            }
+            PipelineEvent::AreaUploaded {
+                country_code,
+                area_id,
+                ..
+            } => {
+                dashboard
+                    .uploading
+                    .remove(&(country_code.clone(), *area_id));
+                dashboard.country(country_code).uploaded += 1;
+            }
+            PipelineEvent::UploadFailed {
+                country_code,
+                area_id,
+                ..
+            } => {
+                dashboard
+                    .uploading
+                    .remove(&(country_code.clone(), *area_id));
+                dashboard.country(country_code).failed += 1;
+            }
+            PipelineEvent::AreaSkipped { country_code, .. } => {
+                dashboard.country(country_code).skipped += 1;
+            }
+            PipelineEvent::CountryCompleted {
+                country_code,
+                stage,
+            } => {
+                let country = dashboard.country(country_code);
+                match stage {
+                    PipelineStage::Extraction => country.extraction_done = true,
+                    PipelineStage::Upload => country.upload_done = true,
+                }
+            }
+        }
+    }
+
+    fn record_log(&self, line: String) {
+        let mut dashboard = self.dashboard.lock().unwrap_or_else(|e| e.into_inner());
+        if dashboard.errors.len() == RECENT_ERRORS {
+            dashboard.errors.pop_front();
+        }
+        dashboard.errors.push_back(line);
+    }
+}
+
+impl Dashboard {
+    fn country(&mut self, country_code: &str) -> &mut CountryProgress {
+        self.countries.entry(country_code.to_string()).or_default()
+    }
+}
+
+/// Collects warnings and errors for the dashboard's recent errors panel
+pub struct TuiLogLayer {
+    state: TuiState,
+}
+
+impl TuiLogLayer {
+    pub fn new(state: TuiState) -> Self {
+        Self { state }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for TuiLogLayer {
+    fn on_event(&self, event: &tracing::Event<'_>, _ctx: Context<'_, S>) {
+        let level = *event.metadata().level();
+        if level > Level::WARN {
+            return;
+        }
+
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        self.state.record_log(format!(
+            "{} {:<5} {}",
+            chrono::Local::now().format("%H:%M:%S"),
+            level,
+            message.0
+        ));
+    }
+}
+
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+/// Alternate screen the dashboard draws on, the previous terminal content comes back when
+/// it is dropped, including when the monitoring task is aborted. The terminal stays out of
+/// raw mode so Ctrl-C still stops the node.
+struct Screen {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+    active: Arc<AtomicBool>,
+}
+
+impl Screen {
+    fn enter(active: Arc<AtomicBool>) -> std::io::Result<Self> {
+        let mut stdout = std::io::stdout();
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        let terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+        active.store(true, Ordering::Relaxed);
+        Ok(Self { terminal, active })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(self.terminal.backend_mut(), Show, LeaveAlternateScreen);
+        self.active.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Draw the dashboard every second until the task is aborted. Falls back to the status
+/// spinner when stdout is not a terminal.
+pub async fn monitor_dashboard(
+    state: TuiState,
+    storage_service: Arc<dyn StorageBackend>,
+    events: Arc<EventService>,
+    warn_thresholds: Vec<u8>,
+    upload_progress: Arc<tokio::sync::Mutex<UploadProgress>>,
+) {
+    let mut receiver = events.subscribe();
+    let screen = match std::io::stdout().is_terminal() {
+        true => Screen::enter(state.active.clone()),
+        false => Err(std::io::Error::other("stdout is not a terminal")),
+    };
+    let mut screen = match screen {
+        Ok(screen) => screen,
+        Err(e) => {
+            tracing::warn!(
+                "Terminal dashboard unavailable, showing the status line: {}",
+                e
+            );
+            let progress_bar = create_node_status_progress_bar();
+            monitor_node_status(
+                storage_service,
+                progress_bar,
+                warn_thresholds,
+                upload_progress,
+            )
+            .await;
+            return;
+        }
+    };
+
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+    let mut usage_alerts = UsageAlerts::new(warn_thresholds);
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => match event {
+                Ok(event) => state.apply(&event),
+                Err(RecvError::Lagged(skipped)) => {
+                    tracing::warn!("Dashboard lagged, {} events dropped", skipped);
+                }
+                Err(RecvError::Closed) => return,
+            },
+            _ = tick.tick() => {
+                let status = storage_service.get_status().await;
+                let node_info = storage_service.get_node_info().await.ok();
+                if let Some(usage) = node_info.as_ref().and_then(|info| info.storage_usage) {
+                    usage_alerts.check(&usage);
+                }
+
+                let mut header = vec![format!("Storage node: {}", format_status(&status))];
+                if let Some(info) = &node_info {
+                    header.push(node_summary(info));
+                }
+                let progress = upload_progress.lock().await;
+                if progress.remaining_files() > 0 {
+                    header.push(format!("Upload: {}", progress));
+                }
+                drop(progress);
+
+                let dashboard = state.dashboard.lock().unwrap_or_else(|e| e.into_inner());
+                let drawn = screen
+                    .terminal
+                    .draw(|frame| render(frame, &dashboard, &header));
+                drop(dashboard);
+                if drawn.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+fn node_summary(info: &NodeInfo) -> String {
+    let mut summary = format!(
+        "Peers: {} connected, {} discovery nodes",
+        info.peers.len(),
+        info.discovery_node_count
+    );
+    if let Some(usage) = &info.storage_usage {
+        summary.push_str(&format!(" | Storage: {}", format_usage(usage)));
+    }
+    summary
+}
+
+/// Lay the panels out on the frame. Countries get the rows left over by the other panels.
+fn render(frame: &mut Frame, dashboard: &Dashboard, header: &[String]) {
+    let uploads_shown = dashboard.uploading.len().min(CURRENT_UPLOADS + 1);
+    let [top, countries, uploads, errors] = Layout::vertical([
+        Constraint::Length(header.len() as u16 + 1),
+        Constraint::Min(3),
+        Constraint::Length(uploads_shown as u16 + 1),
+        Constraint::Length(dashboard.errors.len() as u16 + 1),
+    ])
+    .areas(frame.area());
+
+    let mut lines = vec![Line::from("AnyNode".bold())];
+    lines.extend(header.iter().map(|line| Line::from(line.as_str())));
+    frame.render_widget(Paragraph::new(lines), top);
+
+    render_countries(frame, dashboard, countries);
+
+    let mut lines: Vec<Line> = dashboard
+        .uploading
+        .iter()
+        .take(CURRENT_UPLOADS)
+        .map(|((country_code, area_id), size)| {
+            Line::from(format!(
+                "{} {:>10}  {}",
+                country_code,
+                area_id,
+                format_bytes(*size)
+            ))
+        })
+        .collect();
+    if dashboard.uploading.len() > CURRENT_UPLOADS {
+        lines.push(Line::from(more(
+            dashboard.uploading.len() - CURRENT_UPLOADS,
+        )));
+    }
+    frame.render_widget(
+        Paragraph::new(lines).block(section("Current uploads", dashboard.uploading.len())),
+        uploads,
+    );
+
+    let lines: Vec<Line> = dashboard
+        .errors
+        .iter()
+        .map(|line| Line::from(line.as_str()))
+        .collect();
+    frame.render_widget(
+        Paragraph::new(lines).block(section("Recent errors", dashboard.errors.len())),
+        errors,
+    );
+}
+
+fn render_countries(frame: &mut Frame, dashboard: &Dashboard, area: Rect) {
+    // The section title and the column names take two rows
+    let rows = usize::from(area.height.saturating_sub(2));
+    let hidden = dashboard.countries.len().saturating_sub(rows);
+    let shown = match hidden {
+        0 => rows,
+        // The last row sums up the hidden countries instead
+        _ => rows.saturating_sub(1),
+    };
+
+    let table_rows: Vec<Row> = dashboard
+        .countries
+        .iter()
+        .take(shown)
+        .map(|(country_code, progress)| {
+            Row::new([
+                country_code.clone(),
+                format!("{:>9}", progress.extracted),
+                format!("{:>9}", progress.uploaded),
+                format!("{:>7}", progress.failed),
+                format!("{:>7}", progress.skipped),
+                stage(progress).to_string(),
+            ])
+        })
+        .collect();
+
+    let table = Table::new(
+        table_rows,
+        [
+            Constraint::Length(4),
+            Constraint::Length(9),
+            Constraint::Length(9),
+            Constraint::Length(7),
+            Constraint::Length(7),
+            Constraint::Min(5),
+        ],
+    )
+    .header(
+        Row::new([
+            "Code",
+            "Extracted",
+            " Uploaded",
+            "  Failed",
+            " Skipped",
+            "Stage",
+        ])
+        .style(Style::new().bold()),
+    )
+    .block(section("Countries", dashboard.countries.len()));
+
+    match hidden {
+        0 => frame.render_widget(table, area),
+        _ => {
+            let [table_area, more_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(area);
+            frame.render_widget(table, table_area);
+            let line = Line::from(more(dashboard.countries.len() - shown));
+            frame.render_widget(Paragraph::new(line), more_area);
+        }
+    }
+}
+
+/// Block titled `title (count)` over a panel
+fn section(title: &str, count: usize) -> Block<'static> {
+    Block::new()
+        .borders(Borders::TOP)
+        .title(format!("{} ({})", title, count).bold().underlined())
+}
+
+fn more(count: usize) -> String {
+    format!("... {} more", count)
+}
+
+fn stage(progress: &CountryProgress) -> &'static str {
+    match (progress.extraction_done, progress.upload_done) {
+        (_, true) => "done",
+        (true, false) => "uploading",
+        (false, _) => "extracting",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_update_countries_and_current_uploads() {
+        let state = TuiState::new();
+        state.apply(&PipelineEvent::AreaExtracted {
+            country_code: "FR".to_string(),
+            area_id: 1,
+        });
+        state.apply(&PipelineEvent::UploadStarted {
+            country_code: "FR".to_string(),
+            area_id: 1,
+            file_size: 2048,
+        });
+        state.apply(&PipelineEvent::UploadStarted {
+            country_code: "FR".to_string(),
+            area_id: 2,
+            file_size: 4096,
+        });
+        state.apply(&PipelineEvent::AreaUploaded {
+            country_code: "FR".to_string(),
+            area_id: 1,
+            cid: "cid".to_string(),
+            file_size: 2048,
+        });
+        state.apply(&PipelineEvent::CountryCompleted {
+            country_code: "FR".to_string(),
+            stage: PipelineStage::Extraction,
+        });
+
+        let dashboard = state.dashboard.lock().unwrap();
+        let france = dashboard.countries["FR"];
+        assert_eq!((france.extracted, france.uploaded), (1, 1));
+        assert_eq!(stage(&france), "uploading");
+        assert_eq!(dashboard.uploading.len(), 1);
+        assert!(dashboard.uploading.contains_key(&("FR".to_string(), 2)));
+    }
+
+    #[test]
+    fn keeps_only_the_most_recent_errors() {
+        let state = TuiState::new();
+        for i in 0..RECENT_ERRORS + 2 {
+            state.record_log(format!("error {}", i));
+        }
+
+        let dashboard = state.dashboard.lock().unwrap();
+        assert_eq!(dashboard.errors.len(), RECENT_ERRORS);
+        assert_eq!(dashboard.errors.front().unwrap(), "error 2");
+    }
+
+    #[test]
+    fn render_fits_the_terminal() {
+        let mut dashboard = Dashboard::default();
+        for code in ["DE", "ES", "FR", "IT", "NL", "PT"] {
+            dashboard.country(code).extracted = 3;
+        }
+        dashboard.errors.push_back("x".repeat(200));
+
+        let mut terminal = Terminal::new(ratatui::backend::TestBackend::new(40, 10)).unwrap();
+        terminal
+            .draw(|frame| render(frame, &dashboard, &["Storage node: Connected".to_string()]))
+            .unwrap();
+
+        let buffer = terminal.backend().buffer();
+        let rows: Vec<String> = (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect();
+        assert!(rows.iter().any(|row| row.contains("Countries (6)")));
+        assert!(rows.iter().any(|row| row.contains("... 4 more")));
+        assert!(rows.iter().any(|row| row.contains("Recent errors (1)")));
+    }
+}
@@ -1,4 +1,7 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use clap_complete::Shell;
+use crate::types::{BoundingBox, ExtractionMode, ListenAddr, Shard, SprUri};
+use crate::utils::{read_area_ids_file, AreaIdsError};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -6,6 +9,9 @@ use std::path::PathBuf;
 #[command(author = "Xavier Saliniere <bonjour@xaviers.sh>")]
 #[command(version = "0.1.0")]
 #[command(about = "Extract PMTiles map data and upload to decentralized storage", long_about = None)]
+#[command(after_help = "Exit codes: 0 success, 1 other failure, 2 usage error, 3 configuration \
+error, 4 missing tools, 5 storage node failure, 6 extraction incomplete, 7 upload failure, \
+8 checks failed, 130 cancelled")]
 pub struct Cli {
     #[arg(long, help = "Run in non-interactive mode (no prompts)")]
     pub non_interactive: bool,
@@ -13,9 +19,24 @@ pub struct Cli {
     #[arg(long, help = "Skip downloading planet files")]
     pub no_download: bool,
 
+    #[arg(
+        long,
+        help = "Download pmtiles into TOOLS_DIR without asking when PMTILES_CMD is missing"
+    )]
+    pub auto_install: bool,
+
     #[arg(long, help = "Skip extracting PMTiles from planet files")]
     pub no_extract: bool,
 
+    #[arg(
+        long,
+        help = "Ignore the checkpoint left by an interrupted run and process every country again"
+    )]
+    pub fresh: bool,
+
+    #[arg(long, help = "Run even when the PLAN_SAMPLES estimate exceeds the storage quota")]
+    pub force: bool,
+
     #[arg(
         long,
         help = "Port for the Storage node (overrides STORAGE_DISCOVERY_PORT env var)"
@@ -43,12 +64,28 @@ pub struct Cli {
     #[arg(short, long, help = "Quiet mode (minimal output)")]
     pub quiet: bool,
 
+    #[arg(
+        long,
+        help = "Monitor the run on a terminal dashboard, log output is hidden while it is shown"
+    )]
+    pub tui: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = "-",
+        conflicts_with = "tui",
+        help = "Write progress as newline-delimited JSON events (phase, country, locality_id, percent) to stdout, or to a file or named pipe"
+    )]
+    pub progress_events: Option<PathBuf>,
+
     #[arg(
         long,
         value_name = "SPR_URI",
         help = "Bootstrap node SPR URI (can be repeated for multiple nodes)"
     )]
-    pub bootstrap: Vec<String>,
+    pub bootstrap: Vec<SprUri>,
 
     #[arg(
         long,
@@ -60,9 +97,17 @@ pub struct Cli {
     #[arg(
         long,
         value_name = "ADDRS",
+        value_delimiter = ',',
         help = "Listen addresses (comma-separated multi-addresses, overrides STORAGE_LISTEN_ADDRS env var)"
     )]
-    pub listen_addrs: Option<String>,
+    pub listen_addrs: Option<Vec<ListenAddr>>,
+
+    #[arg(
+        long,
+        value_name = "SPR_OR_MULTIADDR",
+        help = "Peer to dial once the node has started, as an SPR URI or a multiaddress ending in /p2p/<peer-id> (can be repeated)"
+    )]
+    pub connect: Vec<String>,
 
     #[arg(
         long,
@@ -70,6 +115,283 @@ pub struct Cli {
         help = "Comma-separated area IDs to extract (overrides AREA_IDS and TARGET_COUNTRIES env vars)"
     )]
     pub area_ids: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with = "area_ids",
+        help = "File of area IDs to extract, separated by commas or newlines with # comments (overrides AREA_IDS and TARGET_COUNTRIES env vars)"
+    )]
+    pub area_ids_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "Extract and upload per-area extracts (areas), one whole-country extract (country), both, or one extract per grid cell (grid) (overrides EXTRACTION_MODE)"
+    )]
+    pub mode: Option<ExtractionMode>,
+
+    #[arg(
+        long,
+        value_name = "INDEX/COUNT",
+        help = "Only handle the target countries hashed to this shard of a fleet, e.g. 2/5 (overrides SHARD env var)"
+    )]
+    pub shard: Option<Shard>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect the configuration
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// Run end-to-end diagnostics on a tiny sample workload
+    Doctor,
+    /// Exercise each subsystem with tiny synthetic inputs, without a WhosOnFirst database or
+    /// planet, and print a pass/fail checklist for deployment checks
+    SelfTest,
+    /// Extract areas into AREAS_DIR without starting a storage node or uploading, e.g. on a
+    /// dedicated extraction machine
+    Extract {
+        #[arg(
+            long,
+            value_name = "CODES",
+            value_delimiter = ',',
+            help = "Comma-separated countries to extract (overrides TARGET_COUNTRIES, can be repeated)"
+        )]
+        country: Vec<String>,
+        #[arg(
+            long,
+            value_name = "IDS",
+            value_delimiter = ',',
+            conflicts_with = "country",
+            help = "Comma-separated area IDs to extract (overrides AREA_IDS)"
+        )]
+        area_ids: Vec<u32>,
+    },
+    /// Extract a one-off region of the planet that is no WhosOnFirst place, e.g. for a demo,
+    /// recorded as a custom area under a synthetic ID
+    ExtractBbox {
+        #[arg(
+            long,
+            value_name = "MIN_LON,MIN_LAT,MAX_LON,MAX_LAT",
+            allow_hyphen_values = true,
+            help = "Region to extract in degrees"
+        )]
+        bbox: BoundingBox,
+        #[arg(long, help = "Start the storage node and upload the extract")]
+        upload: bool,
+    },
+    /// Extract each polygon of a GeoJSON file as a custom region named after its feature,
+    /// e.g. to publish the service areas of an organisation
+    ExtractGeojson {
+        #[arg(value_name = "FILE")]
+        path: PathBuf,
+        #[arg(
+            long,
+            help = "Keep only the tiles of each polygon instead of every tile of its bounding box"
+        )]
+        clip: bool,
+        #[arg(long, help = "Start the storage node and upload the extracts")]
+        upload: bool,
+    },
+    /// Start the node briefly and list the known peers in its discovery table. These
+    /// are not necessarily connected.
+    Peers {
+        #[arg(long, value_name = "SECS", default_value_t = 10, help = "Seconds to wait for discovery")]
+        wait: u64,
+        #[arg(long, help = "Print the peers as JSON")]
+        json: bool,
+    },
+    /// Start the storage node and serve already uploaded content, without the WhosOnFirst
+    /// database, the planet file or extraction
+    Serve,
+    /// Show uploaded areas, run history and storage used per country
+    Status {
+        #[arg(long, help = "Print the status as JSON")]
+        json: bool,
+    },
+    /// Show the areas of each country and how many are extracted and uploaded
+    Countries {
+        #[arg(long, help = "Include countries without any areas")]
+        all: bool,
+        #[arg(long, help = "Print the counts as JSON")]
+        json: bool,
+    },
+    /// List a country's uploaded areas with their CIDs, one page at a time
+    List {
+        #[arg(long, value_name = "CODE", help = "Country whose areas are listed")]
+        country: String,
+        #[arg(long, value_name = "N", default_value_t = 1, help = "Page to show, from 1")]
+        page: u32,
+        #[arg(long, value_name = "N", default_value_t = 50, help = "Areas per page")]
+        limit: u32,
+        #[arg(long, help = "Print the page as JSON")]
+        json: bool,
+    },
+    /// List the uploaded areas whose bounding box intersects a viewport, with their CIDs
+    Areas {
+        #[arg(
+            long,
+            value_name = "MIN_LON,MIN_LAT,MAX_LON,MAX_LAT",
+            allow_hyphen_values = true,
+            help = "Viewport in degrees, a min_lon above max_lon crosses the antimeridian"
+        )]
+        bbox: BoundingBox,
+        #[arg(long, help = "Print the areas as JSON")]
+        json: bool,
+    },
+    /// Write the uploaded areas with their bounding boxes, CIDs, sizes and times to a
+    /// GeoParquet file, e.g. to join the catalog with other datasets in DuckDB or Spark
+    Export {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        #[arg(long, value_name = "CODE", help = "Only export areas of this country")]
+        country: Option<String>,
+    },
+    /// Check that every mapped CID is still held by the local node
+    Verify {
+        #[arg(long, value_name = "CODE", help = "Only verify areas of this country")]
+        country: Option<String>,
+        #[arg(long, help = "Read content back and compare it with the local extract")]
+        rehash: bool,
+        #[arg(
+            long,
+            help = "Re-upload missing content from the local extract when present, or pin it \
+                    from the network"
+        )]
+        repair: bool,
+        #[arg(long, help = "Print the summary and findings as JSON once done")]
+        json: bool,
+    },
+    /// Cross-reference WhosOnFirst areas, extracts on disk and CID mappings
+    Audit {
+        #[arg(long, value_name = "CODE", help = "Only audit this country")]
+        country: Option<String>,
+        #[arg(long, help = "List the area IDs behind every count")]
+        list: bool,
+    },
+    /// Download stored content by CID to spot-check it, from the local repo or the network
+    Fetch {
+        #[arg(value_name = "CID")]
+        cid: String,
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
+    /// Upload the extracts already on disk, e.g. copied from an extraction machine, without
+    /// downloading the WhosOnFirst database or extracting
+    Upload {
+        #[arg(
+            long,
+            value_name = "PATH",
+            help = "Directory of per-country extracts to upload (overrides AREAS_DIR)"
+        )]
+        dir: Option<PathBuf>,
+    },
+    /// Upload again the areas whose uploads failed after every attempt
+    RetryFailed,
+    /// Maintain the node's SQLite databases
+    Db {
+        #[command(subcommand)]
+        action: DbCommand,
+    },
+    /// Manage the local content-hash index of uploaded extracts
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+    /// List or remove the content held in the node's repo
+    Store {
+        #[command(subcommand)]
+        action: StoreCommand,
+    },
+    /// Show, export or import the node's peer identity
+    Identity {
+        #[command(subcommand)]
+        action: Option<IdentityCommand>,
+    },
+    /// Print the completion script of a shell, e.g.
+    /// `anynode completions bash > /etc/bash_completion.d/anynode`
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: Shell,
+    },
+    /// Print the man page, or write the pages of every subcommand to a directory
+    Man {
+        #[arg(long, value_name = "DIR", help = "Write one page per command into this directory")]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdentityCommand {
+    /// Print the peer ID, addresses and signed peer record (default)
+    Show,
+    /// Copy the node's private key to a file
+    Export {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+    },
+    /// Install a private key as the node's identity
+    Import {
+        #[arg(value_name = "FILE")]
+        file: PathBuf,
+        #[arg(long, help = "Replace an existing key")]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Check the CID database's integrity, refresh its statistics and compact it. Run it
+    /// with the node stopped.
+    Maintain {
+        #[arg(long, help = "Also maintain the WhosOnFirst database")]
+        whosonfirst: bool,
+    },
+    /// Copy the CID database to a file. Safe while the node is running.
+    Backup {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+        #[arg(long, help = "Replace an existing file")]
+        force: bool,
+    },
+    /// Replace the CID database with a backup or a fetched snapshot, keeping the current
+    /// one as .pre-restore. Run it with the node stopped.
+    Restore {
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CacheCommand {
+    /// Drop entries whose file is gone and whose CID no longer backs any area
+    Gc {
+        #[arg(long, help = "List the entries that would be removed without removing them")]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StoreCommand {
+    /// List the CIDs in the repo with their sizes
+    Ls,
+    /// Remove content from the repo
+    Rm {
+        #[arg(value_name = "CID", required = true)]
+        cids: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Check paths, URLs, tools, ports and bootstrap records without starting the node
+    Validate,
 }
 
 impl Cli {
@@ -77,6 +399,13 @@ impl Cli {
         Self::parse()
     }
 
+    /// Where `--progress-events` writes, `None` for stdout
+    pub fn get_progress_events_target(&self) -> Option<Option<PathBuf>> {
+        self.progress_events
+            .as_ref()
+            .map(|path| (path.as_os_str() != "-").then(|| path.clone()))
+    }
+
     pub fn get_port(&self, env_port: Option<u16>) -> Option<u16> {
         self.port.or(env_port)
     }
@@ -89,6 +418,10 @@ impl Cli {
         self.non_interactive
     }
 
+    pub fn should_auto_install(&self) -> bool {
+        self.auto_install
+    }
+
     pub fn should_skip_download(&self) -> bool {
         self.no_download
     }
@@ -107,7 +440,7 @@ impl Cli {
         }
     }
 
-    pub fn get_bootstrap_nodes(&self, env_nodes: Vec<String>) -> Vec<String> {
+    pub fn get_bootstrap_nodes(&self, env_nodes: Vec<SprUri>) -> Vec<SprUri> {
         if !self.bootstrap.is_empty() {
             self.bootstrap.clone()
         } else {
@@ -119,27 +452,30 @@ impl Cli {
         self.nat.clone().unwrap_or(env_nat)
     }
 
-    pub fn get_listen_addrs(&self, env_addrs: Vec<String>) -> Vec<String> {
-        if let Some(addrs) = &self.listen_addrs {
-            addrs
-                .split(',')
-                .map(|s| s.trim().to_string())
-                .filter(|s| !s.is_empty())
-                .collect()
-        } else {
-            env_addrs
-        }
+    pub fn get_listen_addrs(&self, env_addrs: Vec<ListenAddr>) -> Vec<ListenAddr> {
+        self.listen_addrs.clone().unwrap_or(env_addrs)
+    }
+
+    pub fn get_shard(&self, env_shard: Option<Shard>) -> Option<Shard> {
+        self.shard.or(env_shard)
     }
 
-    pub fn get_area_ids(&self, env_ids: Vec<u32>) -> Vec<u32> {
+    pub fn get_extraction_mode(&self, env_mode: ExtractionMode) -> ExtractionMode {
+        self.mode.unwrap_or(env_mode)
+    }
+
+    pub fn get_area_ids(&self, env_ids: Vec<u32>) -> Result<Vec<u32>, AreaIdsError> {
         if let Some(ids) = &self.area_ids {
-            ids.split(',')
+            Ok(ids
+                .split(',')
                 .map(|s| s.trim())
                 .filter(|s| !s.is_empty())
                 .filter_map(|s| s.parse::<u32>().ok())
-                .collect()
+                .collect())
+        } else if let Some(path) = &self.area_ids_file {
+            read_area_ids_file(path)
         } else {
-            env_ids
+            Ok(env_ids)
         }
     }
 }
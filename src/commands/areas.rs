@@ -0,0 +1,68 @@
+use crate::config::Config;
+use crate::initialization::open_whosonfirst_db;
+use crate::services::DatabaseService;
+use crate::types::{AreaInfo, BoundingBox};
+use crate::utils::format_bytes;
+use serde::Serialize;
+
+use super::list::truncate;
+use super::{print_json, CommandResult};
+
+/// Areas printed by `anynode areas --json`
+#[derive(Serialize)]
+struct AreasInBbox<'a> {
+    bbox: &'a BoundingBox,
+    areas: &'a [AreaInfo],
+}
+
+/// Print the uploaded areas whose bounding box intersects `bbox` with their CIDs, read from
+/// the CID and WhosOnFirst databases without starting the node
+pub async fn areas_command(bbox: &BoundingBox, json: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+
+    let areas = match config.cid_db_path.exists() && config.whosonfirst_db_present() {
+        true => {
+            let cid_db = DatabaseService::new(&config.cid_db_path.to_string_lossy(), false).await?;
+            let whosonfirst_db = open_whosonfirst_db(&config).await?;
+            cid_db
+                .get_localities_in_bbox(
+                    &whosonfirst_db,
+                    bbox.min_longitude,
+                    bbox.min_latitude,
+                    bbox.max_longitude,
+                    bbox.max_latitude,
+                )
+                .await?
+        }
+        false => Vec::new(),
+    };
+
+    if json {
+        return print_json(&AreasInBbox {
+            bbox,
+            areas: &areas,
+        });
+    }
+    if areas.is_empty() {
+        println!("No uploaded area intersects {}", bbox);
+        return Ok(());
+    }
+
+    println!(
+        "{:<10}  {:<30}  {:<7}  {:<8}  {:>10}  CID",
+        "AREA", "NAME", "COUNTRY", "TYPE", "SIZE"
+    );
+    for info in &areas {
+        println!(
+            "{:<10}  {:<30}  {:<7}  {:<8}  {:>10}  {}",
+            info.area.id,
+            truncate(&info.area.name, 30),
+            info.area.country,
+            info.area.placetype,
+            format_bytes(info.file_size),
+            info.cid
+        );
+    }
+    println!("{} areas intersect {}", areas.len(), bbox);
+    Ok(())
+}
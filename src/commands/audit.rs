@@ -0,0 +1,293 @@
+use crate::config::Config;
+use crate::initialization::{initialize_cid_db, initialize_whosonfirst_db};
+use crate::services::CountryService;
+use crate::types::AREA_PARTS_EXTENSION;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::CommandResult;
+
+/// Options of `anynode audit`
+pub struct AuditOptions<'a> {
+    pub country: Option<&'a str>,
+    pub list: bool,
+}
+
+/// Where the three records of a country's areas disagree
+#[derive(Debug, Default, PartialEq)]
+pub struct CountryAudit {
+    /// Areas extraction would pick up that have neither a file nor a CID
+    pub not_extracted: Vec<u32>,
+    /// Extracts or parts directories of known areas without a CID
+    pub not_uploaded: Vec<u32>,
+    /// CIDs whose extract is no longer on disk
+    pub missing_files: Vec<u32>,
+    /// Files named after IDs that are not areas of this country
+    pub orphans: Vec<u32>,
+}
+
+impl CountryAudit {
+    /// Compare the areas extraction would pick up (`expected`), every area of the country
+    /// (`known`), the area IDs found on disk and those with a CID mapping
+    pub fn reconcile(
+        expected: &BTreeSet<u32>,
+        known: &HashSet<u32>,
+        on_disk: &BTreeSet<u32>,
+        mapped: &BTreeSet<u32>,
+    ) -> Self {
+        Self {
+            not_extracted: expected
+                .iter()
+                .filter(|id| !on_disk.contains(id) && !mapped.contains(id))
+                .copied()
+                .collect(),
+            not_uploaded: on_disk
+                .iter()
+                .filter(|id| known.contains(id) && !mapped.contains(id))
+                .copied()
+                .collect(),
+            missing_files: mapped.difference(on_disk).copied().collect(),
+            orphans: on_disk.iter().filter(|id| !known.contains(id)).copied().collect(),
+        }
+    }
+
+    fn is_clean(&self) -> bool {
+        self.not_extracted.is_empty()
+            && self.not_uploaded.is_empty()
+            && self.missing_files.is_empty()
+            && self.orphans.is_empty()
+    }
+}
+
+/// Cross-reference the WhosOnFirst areas, the extracts in the areas directory and the CID
+/// mappings, and report where they disagree. Nothing is changed.
+pub async fn audit_command(options: AuditOptions<'_>) -> CommandResult<()> {
+    let config = Arc::new(Config::load()?);
+    let whosonfirst_db = initialize_whosonfirst_db(&config).await?;
+    let cid_db = initialize_cid_db(&config).await?;
+
+    let only = options.country.map(str::to_ascii_uppercase);
+    let targets: Vec<String> = match &only {
+        Some(country) => vec![country.clone()],
+        None => {
+            CountryService::new(whosonfirst_db.clone(), config.clone())
+                .get_countries_to_process(&config.target_countries)
+                .await
+        }
+    };
+
+    let mut mapped: BTreeMap<String, BTreeSet<u32>> = BTreeMap::new();
+    for mapping in cid_db.get_cid_mappings(only.as_deref()).await? {
+        mapped
+            .entry(mapping.country_code)
+            .or_default()
+            .insert(mapping.area_id);
+    }
+
+    let mut on_disk = scan_areas_dir(&config.areas_dir)?;
+    if let Some(country) = &only {
+        on_disk.retain(|code, _| code == country);
+    }
+
+    let countries: BTreeSet<String> = targets
+        .iter()
+        .cloned()
+        .chain(mapped.keys().cloned())
+        .chain(on_disk.keys().cloned())
+        .collect();
+
+    let (min, max) = (config.min_bbox_area_km2, config.max_bbox_area_km2);
+    let empty = BTreeSet::new();
+    let mut audits = Vec::new();
+    for country in countries {
+        let areas = whosonfirst_db.get_country_areas(&country, None).await?;
+        let known: HashSet<u32> = areas.iter().map(|area| area.id as u32).collect();
+
+        let expected: BTreeSet<u32> = if targets.contains(&country) {
+            let eligible = match config.min_population {
+                Some(_) => whosonfirst_db
+                    .get_country_areas(&country, config.min_population)
+                    .await?,
+                None => areas,
+            };
+            eligible
+                .iter()
+                .filter(|area| area.bbox_size_violation(min, max).is_none())
+                .map(|area| area.id as u32)
+                .collect()
+        } else {
+            BTreeSet::new()
+        };
+
+        let mut audit = CountryAudit::reconcile(
+            &expected,
+            &known,
+            on_disk.get(&country).unwrap_or(&empty),
+            mapped.get(&country).unwrap_or(&empty),
+        );
+        // Uploaded extracts are expected to be gone under a deleting retention policy or
+        // once evicted
+        if config.deletes_uploaded_extracts() {
+            audit.missing_files.clear();
+        }
+        if !audit.is_clean() {
+            audits.push((country, audit));
+        }
+    }
+
+    if audits.is_empty() {
+        println!("Database, areas directory and CID mappings agree");
+        return Ok(());
+    }
+
+    println!(
+        "{:<8}  {:>14}  {:>13}  {:>13}  {:>8}",
+        "COUNTRY", "NOT EXTRACTED", "NOT UPLOADED", "MISSING FILE", "ORPHANS"
+    );
+    for (country, audit) in &audits {
+        println!(
+            "{:<8}  {:>14}  {:>13}  {:>13}  {:>8}",
+            country,
+            audit.not_extracted.len(),
+            audit.not_uploaded.len(),
+            audit.missing_files.len(),
+            audit.orphans.len()
+        );
+    }
+
+    let total = |count: fn(&CountryAudit) -> usize| -> usize {
+        audits.iter().map(|(_, audit)| count(audit)).sum()
+    };
+    println!();
+    println!(
+        "{} areas never extracted, {} files never uploaded, {} mappings without a file, \
+         {} orphan files",
+        total(|a| a.not_extracted.len()),
+        total(|a| a.not_uploaded.len()),
+        total(|a| a.missing_files.len()),
+        total(|a| a.orphans.len())
+    );
+
+    if options.list {
+        print_ids("Never extracted", &audits, |a| &a.not_extracted);
+        print_ids("Never uploaded", &audits, |a| &a.not_uploaded);
+        print_ids("Mapped without a file", &audits, |a| &a.missing_files);
+        print_ids("Orphan files", &audits, |a| &a.orphans);
+    }
+
+    Ok(())
+}
+
+fn print_ids(
+    title: &str,
+    audits: &[(String, CountryAudit)],
+    ids: fn(&CountryAudit) -> &Vec<u32>,
+) {
+    if audits.iter().all(|(_, audit)| ids(audit).is_empty()) {
+        return;
+    }
+
+    println!();
+    println!("{}:", title);
+    for (country, audit) in audits {
+        for id in ids(audit) {
+            println!("  {}/{}", country, id);
+        }
+    }
+}
+
+/// Area IDs per country directory, from `<id>.pmtiles` extracts and `<id>.parts` directories
+pub(super) fn scan_areas_dir(areas_dir: &Path) -> std::io::Result<BTreeMap<String, BTreeSet<u32>>> {
+    let mut areas = BTreeMap::new();
+    if !areas_dir.exists() {
+        return Ok(areas);
+    }
+
+    for country_entry in std::fs::read_dir(areas_dir)? {
+        let country_path = country_entry?.path();
+        let Some(country_code) = country_path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if !country_path.is_dir() {
+            continue;
+        }
+
+        let mut ids = BTreeSet::new();
+        for entry in std::fs::read_dir(&country_path)? {
+            let path = entry?.path();
+            let is_extract =
+                path.is_file() && path.extension().is_some_and(|ext| ext == "pmtiles");
+            let is_parts_dir =
+                path.is_dir() && path.extension().is_some_and(|ext| ext == AREA_PARTS_EXTENSION);
+            if !is_extract && !is_parts_dir {
+                continue;
+            }
+
+            if let Some(id) = path
+                .file_stem()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.parse().ok())
+            {
+                ids.insert(id);
+            }
+        }
+        areas.insert(country_code.to_string(), ids);
+    }
+
+    Ok(areas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[u32]) -> BTreeSet<u32> {
+        ids.iter().copied().collect()
+    }
+
+    #[test]
+    fn reconcile_sorts_areas_into_each_discrepancy() {
+        // 1 done, 2 not uploaded, 3 uploaded then deleted, 4 never extracted,
+        // 5 filtered out of extraction but known, 9 unknown
+        let expected = ids(&[1, 2, 3, 4]);
+        let known: HashSet<u32> = [1, 2, 3, 4, 5].into_iter().collect();
+        let on_disk = ids(&[1, 2, 5, 9]);
+        let mapped = ids(&[1, 3]);
+
+        let audit = CountryAudit::reconcile(&expected, &known, &on_disk, &mapped);
+        assert_eq!(
+            audit,
+            CountryAudit {
+                not_extracted: vec![4],
+                not_uploaded: vec![2, 5],
+                missing_files: vec![3],
+                orphans: vec![9],
+            }
+        );
+    }
+
+    #[test]
+    fn reconcile_of_a_consistent_country_is_clean() {
+        let expected = ids(&[1, 2]);
+        let known: HashSet<u32> = [1, 2].into_iter().collect();
+
+        let audit = CountryAudit::reconcile(&expected, &known, &expected, &expected);
+        assert!(audit.is_clean());
+    }
+
+    #[test]
+    fn scan_reads_extracts_and_parts_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let country = dir.path().join("FR");
+        std::fs::create_dir_all(country.join("7.parts")).unwrap();
+        std::fs::write(country.join("3.pmtiles"), b"").unwrap();
+        std::fs::write(country.join("notes.txt"), b"").unwrap();
+        std::fs::write(country.join("abc.pmtiles"), b"").unwrap();
+        std::fs::write(dir.path().join("stray.pmtiles"), b"").unwrap();
+
+        let scanned = scan_areas_dir(dir.path()).unwrap();
+        assert_eq!(scanned.len(), 1);
+        assert_eq!(scanned["FR"], ids(&[3, 7]));
+    }
+}
@@ -0,0 +1,47 @@
+use crate::config::Config;
+use crate::services::DatabaseService;
+use crate::utils::format_bytes;
+use std::path::Path;
+
+use super::CommandResult;
+
+/// Trim the extract cache. Entries are kept while their file still exists or their CID
+/// still backs an area, since either can be matched by a later run.
+pub async fn cache_gc_command(dry_run: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+
+    let path = &config.cid_db_path;
+    if !path.exists() {
+        println!("No cache yet ({} does not exist)", path.display());
+        return Ok(());
+    }
+
+    let db = DatabaseService::new(&path.to_string_lossy(), true).await?;
+    let removable: Vec<_> = db
+        .get_unreferenced_cache_entries()
+        .await?
+        .into_iter()
+        .filter(|(_, path, _)| !Path::new(path).exists())
+        .collect();
+
+    if removable.is_empty() {
+        println!("Nothing to remove");
+        return Ok(());
+    }
+
+    let bytes: u64 = removable.iter().map(|(_, _, size)| size).sum();
+    if dry_run {
+        for (hash, path, size) in &removable {
+            println!("{}  {:>12}  {}", &hash[..16], format_bytes(*size), path);
+        }
+        println!();
+        println!("Would remove {} entries ({})", removable.len(), format_bytes(bytes));
+        return Ok(());
+    }
+
+    let hashes: Vec<String> = removable.into_iter().map(|(hash, _, _)| hash).collect();
+    db.delete_cache_entries(&hashes).await?;
+    println!("Removed {} entries ({})", hashes.len(), format_bytes(bytes));
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use crate::cli::Cli;
+use clap::CommandFactory;
+use clap_complete::Shell;
+use std::path::Path;
+
+use super::CommandResult;
+
+/// Print the completion script of `shell` on stdout. Generated from the CLI definition at
+/// runtime, so packagers can install scripts that match the binary they ship.
+pub fn completions_command(shell: Shell) -> CommandResult<()> {
+    let mut command = Cli::command();
+    let name = command.get_name().to_string();
+    clap_complete::generate(shell, &mut command, name, &mut std::io::stdout());
+    Ok(())
+}
+
+/// Print the `anynode(1)` man page on stdout, or with `dir`, write one page per command and
+/// subcommand there, e.g. `anynode-db-backup.1`
+pub fn man_command(dir: Option<&Path>) -> CommandResult<()> {
+    let command = Cli::command();
+    match dir {
+        None => clap_mangen::Man::new(command).render(&mut std::io::stdout())?,
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            clap_mangen::generate_to(command, dir)?;
+            println!("Wrote man pages to {}", dir.display());
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,285 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::types::{Compression, Transport};
+use crate::utils::{
+    abbreviate, ensure_pmtiles_version, format_bytes, http_client, is_tool_available,
+    parse_s3_location, presign_url, probe_remote_file,
+};
+use reqwest::header::HeaderMap;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::path::Path;
+
+use super::{CheckReport, CommandError, CommandResult};
+
+/// Validity of the presigned URL used to probe an S3 planet source
+const S3_PROBE_EXPIRY_SECS: u64 = 60;
+
+/// Load the configuration and check everything a run depends on without starting the node
+pub async fn validate_config_command(cli: &Cli) -> CommandResult<()> {
+    let mut report = CheckReport::new();
+
+    let config = match Config::load() {
+        Ok(config) => {
+            report.pass("configuration", "loaded");
+            config
+        }
+        Err(e) => {
+            report.fail("configuration", e.to_string());
+            report.print();
+            return Err(CommandError::ChecksFailed(report.failure_count()));
+        }
+    };
+
+    check_paths(&config, &mut report);
+    check_urls(&config, &mut report).await;
+    check_tools(&config, &mut report).await;
+    check_ports(&config, cli, &mut report);
+    check_bootstrap_nodes(&config, cli, &mut report);
+
+    report.print();
+
+    match report.failure_count() {
+        0 => Ok(()),
+        failures => Err(CommandError::ChecksFailed(failures)),
+    }
+}
+
+fn check_paths(config: &Config, report: &mut CheckReport) {
+    if config.whosonfirst_db_present() {
+        report.pass(
+            "whosonfirst database",
+            config.whosonfirst_db_path.display().to_string(),
+        );
+    } else {
+        report.warn(
+            "whosonfirst database",
+            format!(
+                "{} missing, it will be downloaded on the next run",
+                config.whosonfirst_db_path.display()
+            ),
+        );
+    }
+
+    match config.cid_db_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => {
+            check_directory(report, "cid database directory", parent)
+        }
+        _ => report.pass("cid database directory", "current directory"),
+    }
+    check_directory(report, "areas directory", &config.areas_dir);
+    check_directory(report, "storage data directory", &config.storage_data_dir);
+
+    match config.planet_pmtiles_location.as_deref() {
+        None => report.warn("planet location", "not configured, extraction will fail"),
+        Some(location) if location.contains("://") => {}
+        Some(location) => {
+            if Path::new(location).is_file() {
+                report.pass("planet location", location);
+            } else {
+                report.fail("planet location", format!("{} not found", location));
+            }
+        }
+    }
+}
+
+/// A directory passes if it exists and is writable, or if it can be created under an
+/// existing writable ancestor
+fn check_directory(report: &mut CheckReport, name: &str, path: &Path) {
+    if path.exists() {
+        if !path.is_dir() {
+            report.fail(name, format!("{} is not a directory", path.display()));
+        } else if !is_writable(path) {
+            report.fail(name, format!("{} is not writable", path.display()));
+        } else {
+            report.pass(name, path.display().to_string());
+        }
+        return;
+    }
+
+    match path.ancestors().skip(1).find(|p| p.exists()) {
+        Some(ancestor) if ancestor.is_dir() && is_writable(ancestor) => {
+            report.pass(name, format!("{} (will be created)", path.display()))
+        }
+        Some(ancestor) => report.fail(
+            name,
+            format!(
+                "{} cannot be created under {}",
+                path.display(),
+                ancestor.display()
+            ),
+        ),
+        None => report.pass(name, format!("{} (will be created)", path.display())),
+    }
+}
+
+/// Whether this process can create files in `dir`. Permission bits alone miss root,
+/// ACLs, read-only mounts and ownership, so a probe file is created and removed instead.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".anynode-write-test-{}", uuid::Uuid::new_v4()));
+    match std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+    {
+        Ok(_) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+async fn check_urls(config: &Config, report: &mut CheckReport) {
+    let mirror_client =
+        match http_client(config.proxy_url.as_deref(), &config.whosonfirst_db_headers) {
+            Ok(client) => client,
+            Err(e) => {
+                report.fail("proxy", e.to_string());
+                return;
+            }
+        };
+
+    // Mirrors only matter while the database still has to be downloaded
+    let database_present = config.whosonfirst_db_present();
+    for url in &config.whosonfirst_db_urls {
+        match probe_remote_file(&mirror_client, url).await {
+            Ok(info) => report.pass("whosonfirst mirror", describe_remote(url, info.content_length)),
+            Err(e) if database_present => {
+                report.warn("whosonfirst mirror", format!("{}: {}", url, e))
+            }
+            Err(e) => report.fail("whosonfirst mirror", format!("{}: {}", url, e)),
+        }
+    }
+
+    let Some(location) = config.planet_pmtiles_location.as_deref() else {
+        return;
+    };
+
+    // Presigned S3 URLs carry their own authorization, planet headers are for http(s) only
+    let (url, headers) = if location.starts_with("s3://") {
+        let Some(credentials) = config.s3_credentials.as_ref() else {
+            report.fail(
+                "planet location",
+                "s3:// location requires AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+            );
+            return;
+        };
+        let presigned = parse_s3_location(location).and_then(|(bucket, key)| {
+            presign_url(credentials, "HEAD", &bucket, &key, S3_PROBE_EXPIRY_SECS)
+        });
+        match presigned {
+            Ok(url) => (url, HeaderMap::new()),
+            Err(e) => {
+                report.fail("planet location", format!("{}: {}", location, e));
+                return;
+            }
+        }
+    } else if location.starts_with("http://") || location.starts_with("https://") {
+        (location.to_string(), config.planet_pmtiles_headers.clone())
+    } else {
+        return;
+    };
+
+    let client = match http_client(config.proxy_url.as_deref(), &headers) {
+        Ok(client) => client,
+        Err(e) => {
+            report.fail("planet location", e.to_string());
+            return;
+        }
+    };
+    match probe_remote_file(&client, &url).await {
+        Ok(info) if !info.accepts_ranges => report.warn(
+            "planet location",
+            format!(
+                "{} does not advertise range requests, extraction may fail",
+                location
+            ),
+        ),
+        Ok(info) => report.pass("planet location", describe_remote(location, info.content_length)),
+        Err(e) => report.fail("planet location", format!("{}: {}", location, e)),
+    }
+}
+
+fn describe_remote(location: &str, content_length: Option<u64>) -> String {
+    match content_length {
+        Some(size) => format!("{} ({})", location, format_bytes(size)),
+        None => location.to_string(),
+    }
+}
+
+async fn check_tools(config: &Config, report: &mut CheckReport) {
+    let mut tools = vec![&config.pmtiles_cmd];
+    if config.upload_compression == Compression::Zstd {
+        tools.push(&config.zstd_cmd);
+    }
+
+    for tool in tools {
+        if is_tool_available(tool).await {
+            report.pass("tool", tool.as_str());
+        } else {
+            report.fail("tool", format!("{} not found or not runnable", tool));
+            if tool == &config.pmtiles_cmd {
+                return;
+            }
+        }
+    }
+
+    match ensure_pmtiles_version(&config.pmtiles_cmd).await {
+        Ok(Some(version)) => report.pass("pmtiles version", version.to_string()),
+        Ok(None) => report.warn(
+            "pmtiles version",
+            format!("{} prints no version", config.pmtiles_cmd),
+        ),
+        Err(e) => report.fail("pmtiles version", e.to_string()),
+    }
+}
+
+fn check_ports(config: &Config, cli: &Cli, report: &mut CheckReport) {
+    let discovery_port = cli
+        .get_port(Some(config.discovery_port))
+        .unwrap_or(config.discovery_port);
+    match UdpSocket::bind(("0.0.0.0", discovery_port)) {
+        Ok(_) => report.pass("discovery port", format!("udp/{}", discovery_port)),
+        Err(e) => report.fail("discovery port", format!("udp/{}: {}", discovery_port, e)),
+    }
+
+    for addr in cli.get_listen_addrs(config.listen_addrs.clone()) {
+        if addr.port() == 0 {
+            report.pass("listen address", format!("{} (random port)", addr));
+            continue;
+        }
+
+        let socket = SocketAddr::new(addr.ip(), addr.port());
+        let bound = match addr.transport() {
+            Transport::Tcp => TcpListener::bind(socket).map(drop),
+            Transport::Udp => UdpSocket::bind(socket).map(drop),
+        };
+        match bound {
+            Ok(()) => report.pass("listen address", addr.to_string()),
+            Err(e) => report.fail("listen address", format!("{}: {}", addr, e)),
+        }
+    }
+
+    if let Some(addr) = config.events_listen_addr {
+        match TcpListener::bind(addr) {
+            Ok(_) => report.pass("events address", addr.to_string()),
+            Err(e) => report.fail("events address", format!("{}: {}", addr, e)),
+        }
+    }
+}
+
+fn check_bootstrap_nodes(config: &Config, cli: &Cli, report: &mut CheckReport) {
+    let nodes = cli.get_bootstrap_nodes(config.bootstrap_nodes.clone());
+    if nodes.is_empty() {
+        report.warn("bootstrap nodes", "none configured, the node may not find peers");
+        return;
+    }
+
+    // Syntax is checked when the configuration loads, so every node here decoded fine
+    for node in nodes {
+        report.pass(
+            "bootstrap node",
+            format!("{} ({})", abbreviate(node.as_str()), node.peer_id()),
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use crate::config::Config;
+use crate::initialization::{initialize_cid_db, initialize_whosonfirst_db};
+use crate::services::CountryService;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::audit::scan_areas_dir;
+use super::{print_json, CommandResult};
+
+/// Counts printed by `anynode countries --json`
+#[derive(Serialize)]
+struct CountriesReport {
+    countries: Vec<CountryProgress>,
+    total_areas: u64,
+    total_extracted: u64,
+    total_uploaded: u64,
+}
+
+#[derive(Serialize)]
+struct CountryProgress {
+    country_code: String,
+    areas: u64,
+    extracted: u64,
+    uploaded: u64,
+}
+
+/// Print the areas the WhosOnFirst database holds per country next to how many are
+/// extracted in the areas directory and uploaded, without starting the node
+pub async fn countries_command(all: bool, json: bool) -> CommandResult<()> {
+    let config = Arc::new(Config::load()?);
+    let whosonfirst_db = initialize_whosonfirst_db(&config).await?;
+    let cid_db = initialize_cid_db(&config).await?;
+
+    let countries = CountryService::new(whosonfirst_db, config.clone())
+        .get_all_countries()
+        .await?;
+    let extracted = scan_areas_dir(&config.areas_dir)?;
+    let uploaded: HashMap<String, u64> = cid_db
+        .get_bytes_by_country()
+        .await?
+        .into_iter()
+        .map(|usage| (usage.country_code, usage.area_count))
+        .collect();
+
+    let rows: Vec<CountryProgress> = countries
+        .iter()
+        .map(|country| CountryProgress {
+            country_code: country.country_code.clone(),
+            areas: country.area_count as u64,
+            extracted: extracted
+                .get(&country.country_code)
+                .map_or(0, |ids| ids.len() as u64),
+            uploaded: uploaded.get(&country.country_code).copied().unwrap_or(0),
+        })
+        .filter(|row| all || row.areas > 0 || row.extracted > 0 || row.uploaded > 0)
+        .collect();
+    let total_areas: u64 = rows.iter().map(|row| row.areas).sum();
+    let total_extracted: u64 = rows.iter().map(|row| row.extracted).sum();
+    let total_uploaded: u64 = rows.iter().map(|row| row.uploaded).sum();
+
+    if json {
+        return print_json(&CountriesReport {
+            countries: rows,
+            total_areas,
+            total_extracted,
+            total_uploaded,
+        });
+    }
+
+    println!(
+        "{:<8}  {:>8}  {:>10}  {:>9}",
+        "COUNTRY", "AREAS", "EXTRACTED", "UPLOADED"
+    );
+    for row in &rows {
+        println!(
+            "{:<8}  {:>8}  {:>10}  {:>9}",
+            row.country_code, row.areas, row.extracted, row.uploaded
+        );
+    }
+
+    println!();
+    println!(
+        "{} areas, {} extracted, {} uploaded",
+        total_areas, total_extracted, total_uploaded
+    );
+    Ok(())
+}
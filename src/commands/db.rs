@@ -0,0 +1,189 @@
+use crate::config::Config;
+use crate::services::{whosonfirst_bundle_files, DatabaseService};
+use crate::utils::{decompress_file, detect_compression, format_bytes};
+use std::path::{Path, PathBuf};
+
+use super::{CommandError, CommandResult};
+
+/// Problems listed per database before the rest are only counted
+const MAX_PROBLEMS_SHOWN: usize = 20;
+
+/// Check, re-analyze and compact the CID database, and the WhosOnFirst database with
+/// `whosonfirst`. A database that fails its integrity check is left untouched. The node
+/// should be stopped, compacting needs the database to itself.
+pub async fn db_maintain_command(whosonfirst: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+
+    let mut databases = vec![("CID database", config.cid_db_path.clone())];
+    if whosonfirst && config.whosonfirst_bundle {
+        for path in whosonfirst_bundle_files(&config.whosonfirst_db_path)? {
+            databases.push(("WhosOnFirst database", path));
+        }
+    } else if whosonfirst {
+        databases.push(("WhosOnFirst database", config.whosonfirst_db_path.clone()));
+    }
+
+    let mut failed = 0;
+    for (name, path) in databases {
+        if !maintain_database(name, &path).await? {
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        return Err(CommandError::ChecksFailed(failed));
+    }
+    Ok(())
+}
+
+/// Returns false when the database failed its integrity check
+async fn maintain_database(name: &str, path: &Path) -> CommandResult<bool> {
+    if !path.exists() {
+        println!("{}: {} does not exist, skipped", name, path.display());
+        return Ok(true);
+    }
+
+    println!("{} ({})", name, path.display());
+    let size_before = std::fs::metadata(path)?.len();
+    let db = DatabaseService::new(&path.to_string_lossy(), false).await?;
+
+    let problems = db.integrity_check().await?;
+    if !problems.is_empty() {
+        println!("  Integrity check: {} problems", problems.len());
+        for problem in problems.iter().take(MAX_PROBLEMS_SHOWN) {
+            println!("    {}", problem);
+        }
+        if problems.len() > MAX_PROBLEMS_SHOWN {
+            println!("    ... and {} more", problems.len() - MAX_PROBLEMS_SHOWN);
+        }
+        println!("  Not compacted, restore a backup or rebuild the database");
+        return Ok(false);
+    }
+    println!("  Integrity check: ok");
+
+    db.compact().await?;
+    let size_after = std::fs::metadata(path)?.len();
+    println!(
+        "  Size: {} -> {} ({} reclaimed)",
+        format_bytes(size_before),
+        format_bytes(size_after),
+        format_bytes(size_before.saturating_sub(size_after))
+    );
+
+    Ok(true)
+}
+
+/// Copy the CID database to `path` with SQLite's online backup API. Safe while the node runs,
+/// the copy is a consistent snapshot. The copy is integrity checked before reporting success.
+pub async fn db_backup_command(path: &Path, force: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+
+    if !config.cid_db_path.exists() {
+        return Err(CommandError::BackupError(format!(
+            "no CID database at {}",
+            config.cid_db_path.display()
+        )));
+    }
+    if path.exists() {
+        if !force {
+            return Err(CommandError::BackupError(format!(
+                "{} already exists, pass --force to replace it",
+                path.display()
+            )));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let db = DatabaseService::new(&config.cid_db_path.to_string_lossy(), false).await?;
+    db.backup_to(path).await?;
+
+    let backup = DatabaseService::new(&path.to_string_lossy(), false).await?;
+    let problems = backup.integrity_check().await?;
+    if !problems.is_empty() {
+        return Err(CommandError::BackupError(format!(
+            "the backup at {} failed its integrity check: {}",
+            path.display(),
+            problems[0]
+        )));
+    }
+
+    println!(
+        "Backed up {} to {} ({})",
+        config.cid_db_path.display(),
+        path.display(),
+        format_bytes(std::fs::metadata(path)?.len())
+    );
+    Ok(())
+}
+
+/// Replace the CID database with the backup at `path`, or with a snapshot fetched from
+/// storage, which is decompressed first. The backup must pass its integrity check and hold
+/// CID mappings. The current database is first saved next to itself with a `.pre-restore`
+/// suffix. Run it with the node stopped, a running node keeps its own view.
+pub async fn db_restore_command(path: &Path) -> CommandResult<()> {
+    let config = Config::load()?;
+
+    if !path.exists() {
+        return Err(CommandError::BackupError(format!(
+            "no backup at {}",
+            path.display()
+        )));
+    }
+
+    let compression = detect_compression(path)?;
+    if compression.is_none() {
+        return restore_database(&config.cid_db_path, path).await;
+    }
+
+    let decompressed = suffixed(&config.cid_db_path, ".restore");
+    decompress_file(path, &decompressed, compression, &config.zstd_cmd)
+        .await
+        .map_err(|e| CommandError::BackupError(format!("{}: {}", path.display(), e)))?;
+    let result = restore_database(&config.cid_db_path, &decompressed).await;
+    let _ = std::fs::remove_file(&decompressed);
+    result
+}
+
+async fn restore_database(db_path: &Path, backup_path: &Path) -> CommandResult<()> {
+    let backup = DatabaseService::new(&backup_path.to_string_lossy(), false).await?;
+    let problems = backup.integrity_check().await?;
+    if !problems.is_empty() {
+        return Err(CommandError::BackupError(format!(
+            "the backup at {} failed its integrity check: {}",
+            backup_path.display(),
+            problems[0]
+        )));
+    }
+    if !backup.has_cid_tables().await? {
+        return Err(CommandError::BackupError(format!(
+            "{} is not a CID database backup",
+            backup_path.display()
+        )));
+    }
+    drop(backup);
+
+    let db = DatabaseService::new(&db_path.to_string_lossy(), false).await?;
+    if db.has_cid_tables().await? {
+        let previous = suffixed(db_path, ".pre-restore");
+        if previous.exists() {
+            std::fs::remove_file(&previous)?;
+        }
+        db.backup_to(&previous).await?;
+        println!("Saved the current database to {}", previous.display());
+    }
+
+    db.restore_from(backup_path).await?;
+    println!(
+        "Restored {} ({})",
+        db_path.display(),
+        format_bytes(std::fs::metadata(db_path)?.len())
+    );
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its file name
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut suffixed = path.as_os_str().to_owned();
+    suffixed.push(suffix);
+    PathBuf::from(suffixed)
+}
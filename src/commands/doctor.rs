@@ -0,0 +1,257 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::open_whosonfirst_db;
+use crate::services::{
+    DatabaseError, DatabaseService, EventService, ExtractionService, REQUIRED_PLACETYPES,
+};
+use crate::utils::format_bytes;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Instant;
+
+use super::{storage_service_for, CheckReport, CommandError, CommandResult};
+
+/// Run the pipeline end to end on a single tiny workload, reporting each step. Everything
+/// is written to a scratch directory that is removed afterwards.
+pub async fn doctor_command(cli: &Cli) -> CommandResult<()> {
+    let mut report = CheckReport::new();
+
+    let config = match Config::load() {
+        Ok(config) => {
+            report.pass("configuration", "loaded");
+            Arc::new(config)
+        }
+        Err(e) => {
+            report.fail("configuration", e.to_string());
+            report.print();
+            return Err(CommandError::ChecksFailed(report.failure_count()));
+        }
+    };
+
+    let work_dir =
+        std::env::temp_dir().join(format!("anynode-doctor-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&work_dir).await?;
+
+    let whosonfirst_db = check_whosonfirst_db(&config, &mut report).await;
+    check_cid_db(&config, &mut report).await;
+    match whosonfirst_db {
+        Some(db) => check_sample_extraction(&config, db, &work_dir, &mut report).await,
+        None => report.warn("sample extraction", "skipped, WhosOnFirst database unavailable"),
+    }
+    check_storage_node(cli, &config, &work_dir, &mut report).await;
+
+    let _ = tokio::fs::remove_dir_all(&work_dir).await;
+
+    report.print();
+
+    match report.failure_count() {
+        0 => Ok(()),
+        failures => Err(CommandError::ChecksFailed(failures)),
+    }
+}
+
+async fn check_whosonfirst_db(
+    config: &Config,
+    report: &mut CheckReport,
+) -> Option<Arc<DatabaseService>> {
+    let path = &config.whosonfirst_db_path;
+    if !config.whosonfirst_db_present() {
+        report.fail("whosonfirst database", format!("{} not found", path.display()));
+        return None;
+    }
+
+    let db = match open_whosonfirst_db(config).await {
+        Ok(db) => db,
+        Err(e) => {
+            report.fail("whosonfirst database", format!("{}: {}", path.display(), e));
+            return None;
+        }
+    };
+    report.pass("whosonfirst database", path.display().to_string());
+
+    if let Err(e) = db.validate_whosonfirst_schema().await {
+        report.fail("spr schema", e.to_string());
+        return None;
+    }
+    report.pass("spr schema", "required columns present");
+
+    match db.missing_placetypes().await {
+        Ok(missing) if missing.is_empty() => {
+            report.pass("placetypes", REQUIRED_PLACETYPES.join(", "));
+        }
+        Ok(missing) => {
+            report.fail(
+                "placetypes",
+                format!(
+                    "no {} areas, use the WhosOnFirst admin database",
+                    missing.join(" or ")
+                ),
+            );
+            return None;
+        }
+        Err(e) => {
+            report.fail("placetypes", e.to_string());
+            return None;
+        }
+    }
+
+    if config.min_population.is_some() {
+        match db.has_population_source().await {
+            Ok(true) => report.pass("population data", "available for MIN_POPULATION"),
+            Ok(false) => {
+                let error = DatabaseError::NoPopulationSource;
+                report.fail("population data", error.to_string());
+                return None;
+            }
+            Err(e) => {
+                report.fail("population data", e.to_string());
+                return None;
+            }
+        }
+    }
+    Some(Arc::new(db))
+}
+
+async fn check_cid_db(config: &Config, report: &mut CheckReport) {
+    let path = &config.cid_db_path;
+    if !path.exists() {
+        report.pass("cid database", format!("{} (will be created)", path.display()));
+        return;
+    }
+
+    let db = match DatabaseService::new(&path.to_string_lossy(), false).await {
+        Ok(db) => db,
+        Err(e) => {
+            report.fail("cid database", format!("{}: {}", path.display(), e));
+            return;
+        }
+    };
+
+    match db.get_cid_mapping_stats().await {
+        Ok((count, countries)) => report.pass(
+            "cid database",
+            format!("{} mappings across {} countries", count, countries),
+        ),
+        Err(e) => report.fail("cid database", format!("{}: {}", path.display(), e)),
+    }
+}
+
+async fn check_sample_extraction(
+    config: &Arc<Config>,
+    whosonfirst_db: Arc<DatabaseService>,
+    work_dir: &Path,
+    report: &mut CheckReport,
+) {
+    let area = match whosonfirst_db.get_smallest_area().await {
+        Ok(Some(area)) => area,
+        Ok(None) => {
+            report.fail("sample extraction", "no extractable area in the database");
+            return;
+        }
+        Err(e) => {
+            report.fail("sample extraction", e.to_string());
+            return;
+        }
+    };
+
+    // Extraction records are kept in memory so the real CID database is left untouched
+    let scratch_db = match DatabaseService::in_memory(true).await {
+        Ok(db) => Arc::new(db),
+        Err(e) => {
+            report.fail("sample extraction", e.to_string());
+            return;
+        }
+    };
+    let extraction_service = ExtractionService::new(
+        config.clone(),
+        whosonfirst_db,
+        scratch_db,
+        Arc::new(EventService::new()),
+    );
+
+    let planet_source = match extraction_service.get_planet_source() {
+        Ok(source) => source,
+        Err(e) => {
+            report.fail("sample extraction", e.to_string());
+            return;
+        }
+    };
+    let planet_version = match extraction_service.get_planet_version(&planet_source).await {
+        Ok(version) => version,
+        Err(e) => {
+            report.fail("sample extraction", e.to_string());
+            return;
+        }
+    };
+    let (planet_source, _proxy) = match extraction_service.proxy_planet_source(planet_source).await
+    {
+        Ok(proxied) => proxied,
+        Err(e) => {
+            report.fail("sample extraction", e.to_string());
+            return;
+        }
+    };
+
+    let country_dir = work_dir.join(&area.country);
+    if let Err(e) = tokio::fs::create_dir_all(&country_dir).await {
+        report.fail("sample extraction", e.to_string());
+        return;
+    }
+
+    let started = Instant::now();
+    let result = extraction_service
+        .extract_area(&area, &planet_source, &planet_version, &country_dir)
+        .await;
+
+    match result {
+        Ok(()) => {
+            let size = std::fs::metadata(country_dir.join(format!("{}.pmtiles", area.id)))
+                .map(|m| m.len())
+                .unwrap_or(0);
+            report.pass(
+                "sample extraction",
+                format!(
+                    "{} {} ({}), {} in {:.1}s",
+                    area.placetype,
+                    area.id,
+                    area.name,
+                    format_bytes(size),
+                    started.elapsed().as_secs_f64()
+                ),
+            );
+        }
+        Err(e) => report.fail("sample extraction", e.to_string()),
+    }
+}
+
+async fn check_storage_node(cli: &Cli, config: &Config, work_dir: &Path, report: &mut CheckReport) {
+    let storage_service = match storage_service_for(cli, config, Some(work_dir.join("storage"))).await {
+        Ok(service) => service,
+        Err(e) => {
+            report.fail("storage node", e.to_string());
+            report.warn("test upload", "skipped, storage node unavailable");
+            return;
+        }
+    };
+
+    if let Err(e) = storage_service.start_node().await {
+        report.fail("storage node", e.to_string());
+        report.warn("test upload", "skipped, storage node unavailable");
+        return;
+    }
+    report.pass("storage node", "started");
+
+    let blob_path = work_dir.join("doctor-blob.txt");
+    let blob = format!("anynode doctor {}\n", uuid::Uuid::new_v4());
+    match tokio::fs::write(&blob_path, blob).await {
+        Ok(()) => match storage_service.upload_file(&blob_path).await {
+            Ok(result) => report.pass("test upload", format!("CID {}", result.cid)),
+            Err(e) => report.fail("test upload", e.to_string()),
+        },
+        Err(e) => report.fail("test upload", e.to_string()),
+    }
+
+    if let Err(e) = storage_service.stop_node().await {
+        report.fail("storage node shutdown", e.to_string());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::config::Config;
+use crate::initialization::open_whosonfirst_db;
+use crate::services::DatabaseService;
+use crate::types::{AdministrativeArea, CatalogRecord};
+use crate::utils::{encode_parquet, ColumnValues, ParquetColumn};
+use serde_json::json;
+use std::path::Path;
+
+use super::CommandResult;
+
+/// WKB geometry type of a polygon
+const WKB_POLYGON: u32 = 3;
+
+/// Write every uploaded area with its bounding box, CID, size and extraction and upload
+/// times to a GeoParquet file, read from the CID and WhosOnFirst databases without starting
+/// the node. The geometry is the bounding box the area was extracted with.
+pub async fn export_command(path: &Path, country: Option<&str>) -> CommandResult<()> {
+    let config = Config::load()?;
+    let country = country.map(str::to_uppercase);
+
+    let records = match config.cid_db_path.exists() && config.whosonfirst_db_present() {
+        true => {
+            let cid_db = DatabaseService::new(&config.cid_db_path.to_string_lossy(), false).await?;
+            let whosonfirst_db = open_whosonfirst_db(&config).await?;
+            cid_db
+                .get_catalog_records(&whosonfirst_db, country.as_deref())
+                .await?
+        }
+        false => Vec::new(),
+    };
+
+    let file = encode_parquet(
+        &geoparquet_columns(&records),
+        &[("geo", geo_metadata(&records))],
+    )?;
+    tokio::fs::write(path, file).await?;
+    println!("Exported {} areas to {}", records.len(), path.display());
+    Ok(())
+}
+
+fn geoparquet_columns(records: &[CatalogRecord]) -> Vec<ParquetColumn> {
+    let strings = |field: fn(&CatalogRecord) -> &str| {
+        ColumnValues::Utf8(
+            records
+                .iter()
+                .map(|record| Some(field(record).to_string()))
+                .collect(),
+        )
+    };
+
+    vec![
+        ParquetColumn::new(
+            "area_id",
+            ColumnValues::Int64(
+                records
+                    .iter()
+                    .map(|record| Some(record.info.area.id))
+                    .collect(),
+            ),
+        ),
+        ParquetColumn::new("name", strings(|record| &record.info.area.name)),
+        ParquetColumn::new("country", strings(|record| &record.info.area.country)),
+        ParquetColumn::new("placetype", strings(|record| &record.info.area.placetype)),
+        ParquetColumn::new("cid", strings(|record| &record.info.cid)),
+        ParquetColumn::new(
+            "file_size",
+            ColumnValues::Int64(
+                records
+                    .iter()
+                    .map(|record| Some(record.info.file_size as i64))
+                    .collect(),
+            ),
+        ),
+        ParquetColumn::new(
+            "extracted_at",
+            ColumnValues::TimestampMillis(
+                records.iter().map(|record| record.extracted_at).collect(),
+            ),
+        ),
+        ParquetColumn::new(
+            "uploaded_at",
+            ColumnValues::TimestampMillis(
+                records.iter().map(|record| record.uploaded_at).collect(),
+            ),
+        ),
+        ParquetColumn::new(
+            "geometry",
+            ColumnValues::Binary(
+                records
+                    .iter()
+                    .map(|record| Some(bbox_wkb(&record.info.area)))
+                    .collect(),
+            ),
+        ),
+    ]
+}
+
+/// The `geo` file metadata of GeoParquet 1.1, with WGS84 longitude/latitude implied by the
+/// absent CRS
+fn geo_metadata(records: &[CatalogRecord]) -> String {
+    let mut column = json!({
+        "encoding": "WKB",
+        "geometry_types": ["Polygon"],
+    });
+    if let Some(first) = records.first() {
+        let area = &first.info.area;
+        let start = [
+            area.min_longitude,
+            area.min_latitude,
+            area.max_longitude,
+            area.max_latitude,
+        ];
+        let bbox = records.iter().fold(start, |bbox, record| {
+            let area = &record.info.area;
+            [
+                bbox[0].min(area.min_longitude),
+                bbox[1].min(area.min_latitude),
+                bbox[2].max(area.max_longitude),
+                bbox[3].max(area.max_latitude),
+            ]
+        });
+        column["bbox"] = json!(bbox);
+    }
+
+    json!({
+        "version": "1.1.0",
+        "primary_column": "geometry",
+        "columns": { "geometry": column },
+    })
+    .to_string()
+}
+
+/// An area's bounding box as a little-endian WKB polygon, its ring counter-clockwise
+fn bbox_wkb(area: &AdministrativeArea) -> Vec<u8> {
+    let ring = [
+        (area.min_longitude, area.min_latitude),
+        (area.max_longitude, area.min_latitude),
+        (area.max_longitude, area.max_latitude),
+        (area.min_longitude, area.max_latitude),
+        (area.min_longitude, area.min_latitude),
+    ];
+
+    let mut wkb = vec![1];
+    wkb.extend_from_slice(&WKB_POLYGON.to_le_bytes());
+    wkb.extend_from_slice(&1u32.to_le_bytes());
+    wkb.extend_from_slice(&(ring.len() as u32).to_le_bytes());
+    for (x, y) in ring {
+        wkb.extend_from_slice(&x.to_le_bytes());
+        wkb.extend_from_slice(&y.to_le_bytes());
+    }
+    wkb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::AreaInfo;
+
+    fn record(id: i64, bbox: [f64; 4]) -> CatalogRecord {
+        let area = AdministrativeArea {
+            id,
+            name: format!("Area {}", id),
+            country: "FR".to_string(),
+            placetype: "region".to_string(),
+            latitude: 0.0,
+            longitude: 0.0,
+            min_longitude: bbox[0],
+            min_latitude: bbox[1],
+            max_longitude: bbox[2],
+            max_latitude: bbox[3],
+        };
+        CatalogRecord {
+            info: AreaInfo::new(area, 1024, format!("cid-{}", id)),
+            uploaded_at: Some(1_700_000_000_000),
+            extracted_at: None,
+        }
+    }
+
+    #[test]
+    fn bounding_boxes_are_closed_wkb_polygons() {
+        let wkb = bbox_wkb(&record(1, [7.0, 47.5, 8.2, 49.1]).info.area);
+
+        assert_eq!(wkb.len(), 1 + 4 + 4 + 4 + 5 * 16);
+        assert_eq!(&wkb[..13], &[1, 3, 0, 0, 0, 1, 0, 0, 0, 5, 0, 0, 0]);
+        assert_eq!(&wkb[13..29], &wkb[wkb.len() - 16..]);
+        assert_eq!(f64::from_le_bytes(wkb[29..37].try_into().unwrap()), 8.2);
+    }
+
+    #[test]
+    fn geo_metadata_covers_every_area() {
+        let records = [
+            record(1, [7.0, 47.5, 8.2, 49.1]),
+            record(2, [-5.1, 47.3, -1.0, 48.9]),
+        ];
+        let geo: serde_json::Value = serde_json::from_str(&geo_metadata(&records)).unwrap();
+
+        assert_eq!(geo["primary_column"], "geometry");
+        assert_eq!(geo["columns"]["geometry"]["encoding"], "WKB");
+        assert_eq!(
+            geo["columns"]["geometry"]["bbox"],
+            json!([-5.1, 47.3, 8.2, 49.1])
+        );
+        let empty: serde_json::Value = serde_json::from_str(&geo_metadata(&[])).unwrap();
+        assert!(empty["columns"]["geometry"].get("bbox").is_none());
+    }
+
+    #[test]
+    fn columns_hold_one_value_per_record() {
+        let columns = geoparquet_columns(&[record(1, [0.0, 0.0, 1.0, 1.0])]);
+        let names: Vec<_> = columns.iter().map(|column| column.name.as_str()).collect();
+
+        assert_eq!(
+            names,
+            vec![
+                "area_id",
+                "name",
+                "country",
+                "placetype",
+                "cid",
+                "file_size",
+                "extracted_at",
+                "uploaded_at",
+                "geometry"
+            ]
+        );
+        assert!(encode_parquet(&columns, &[]).is_ok());
+    }
+}
@@ -0,0 +1,81 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::{
+    ensure_database_is_present, ensure_directories, ensure_required_tools, initialize_cid_db,
+    initialize_claims_db, initialize_country_service, initialize_whosonfirst_db,
+    validate_config, DatabaseDownload, ToolInstall,
+};
+use crate::services::{EventService, ExtractionService};
+use std::sync::Arc;
+
+use super::CommandResult;
+
+/// Options of `anynode extract`
+pub struct ExtractOptions<'a> {
+    pub countries: &'a [String],
+    pub area_ids: &'a [u32],
+}
+
+/// Run the extraction phase alone, without a storage node, leaving the extracts in AREAS_DIR
+/// for a separate machine to upload
+pub async fn extract_command(cli: &Cli, options: ExtractOptions<'_>) -> CommandResult<()> {
+    let mut config = Config::load()?;
+    config.shard = cli.get_shard(config.shard);
+    config.extraction_mode = cli.get_extraction_mode(config.extraction_mode);
+
+    ensure_required_tools(&mut config, ToolInstall::from_cli(cli)).await?;
+    ensure_database_is_present(&mut config, DatabaseDownload::from_cli(cli)).await?;
+    let config = Arc::new(config);
+    validate_config(&config)?;
+    ensure_directories(&config).await?;
+
+    let whosonfirst_db = initialize_whosonfirst_db(&config).await?;
+    let cid_db = initialize_cid_db(&config).await?;
+    let extraction_service = ExtractionService::new(
+        config.clone(),
+        whosonfirst_db.clone(),
+        cid_db,
+        Arc::new(EventService::new()),
+    );
+    let extraction_service = match initialize_claims_db(&config).await? {
+        Some(claims) => extraction_service.with_claims(claims),
+        None => extraction_service,
+    };
+
+    let area_ids = match options.area_ids.is_empty() {
+        true => cli.get_area_ids(config.area_ids.clone())?,
+        false => options.area_ids.to_vec(),
+    };
+    if !area_ids.is_empty() && options.countries.is_empty() {
+        println!("Extracting {} areas...", area_ids.len());
+        extraction_service.extract_areas_by_ids(&area_ids).await?;
+        println!("Extraction finished, extracts are in {}", config.areas_dir.display());
+        return Ok(());
+    }
+
+    let targets = match options.countries.is_empty() {
+        true => config.target_countries.clone(),
+        false => options
+            .countries
+            .iter()
+            .map(|country| country.to_uppercase())
+            .collect(),
+    };
+    let countries = initialize_country_service(&config, whosonfirst_db)
+        .get_countries_to_process(&targets)
+        .await;
+    println!("Extracting {} countries...", countries.len());
+    extraction_service.extract_areas(&countries).await?;
+
+    let counts = extraction_service
+        .batch_get_pmtiles_file_count(&countries)
+        .await?;
+    let total: u32 = counts.values().sum();
+    println!(
+        "Extraction finished, {} extracts for {} countries are in {}",
+        total,
+        countries.len(),
+        config.areas_dir.display()
+    );
+    Ok(())
+}
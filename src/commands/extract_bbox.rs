@@ -0,0 +1,152 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::{
+    ensure_database_is_present, ensure_directories, ensure_required_tools, initialize_cid_db,
+    initialize_whosonfirst_db, validate_config, DatabaseDownload, ToolInstall,
+};
+use crate::services::{
+    AreaUploadService, DatabaseService, EventService, ExtractionService, PlanetSource,
+};
+use crate::types::{AdministrativeArea, BoundingBox, BoundingBoxError, CUSTOM_AREA_COUNTRY};
+use crate::utils::ThrottledProxy;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::{storage_service_for, CommandResult};
+
+/// Extract a bounding box of the planet as a custom region of the `XX` directory of
+/// AREAS_DIR, recorded under a synthetic ID so the same box keeps its ID, and with `upload`
+/// upload it like any other area
+pub async fn extract_bbox_command(
+    cli: &Cli,
+    bbox: &BoundingBox,
+    upload: bool,
+) -> CommandResult<()> {
+    // pmtiles extracts a box from west to east only
+    if bbox.crosses_antimeridian() {
+        return Err(BoundingBoxError::CrossesAntimeridian(bbox.to_string()).into());
+    }
+
+    let regions = CustomRegions::open(cli).await?;
+    let area = regions.cid_db.record_custom_area(*bbox, None).await?;
+    println!("Extracting custom region {} ({})...", area.id, bbox);
+    regions.extract(&area, None).await?;
+    println!(
+        "Extraction finished, the extract is in {}",
+        regions.country_dir.display()
+    );
+
+    if upload {
+        regions.upload(cli, &[area]).await?;
+    }
+    Ok(())
+}
+
+/// What extracting and uploading custom regions takes, shared by `extract-bbox` and
+/// `extract-geojson`
+pub(super) struct CustomRegions {
+    config: Arc<Config>,
+    pub(super) cid_db: Arc<DatabaseService>,
+    whosonfirst_db: Arc<DatabaseService>,
+    events: Arc<EventService>,
+    extraction_service: ExtractionService,
+    planet_source: PlanetSource,
+    planet_version: String,
+    /// Keeps the local planet proxy alive while regions are extracted, when one is needed
+    _planet_proxy: Option<ThrottledProxy>,
+    /// Directory of AREAS_DIR the regions are extracted to
+    pub(super) country_dir: PathBuf,
+}
+
+impl CustomRegions {
+    pub(super) async fn open(cli: &Cli) -> CommandResult<Self> {
+        let mut config = Config::load()?;
+        ensure_required_tools(&mut config, ToolInstall::from_cli(cli)).await?;
+        ensure_database_is_present(&mut config, DatabaseDownload::from_cli(cli)).await?;
+        let config = Arc::new(config);
+        validate_config(&config)?;
+        ensure_directories(&config).await?;
+
+        let whosonfirst_db = initialize_whosonfirst_db(&config).await?;
+        let cid_db = initialize_cid_db(&config).await?;
+        let events = Arc::new(EventService::new());
+        let extraction_service = ExtractionService::new(
+            config.clone(),
+            whosonfirst_db.clone(),
+            cid_db.clone(),
+            events.clone(),
+        );
+
+        let planet_source = extraction_service.get_planet_source()?;
+        let planet_version = extraction_service
+            .get_planet_version(&planet_source)
+            .await?;
+        let (planet_source, planet_proxy) = extraction_service
+            .proxy_planet_source(planet_source)
+            .await?;
+        let country_dir = config.areas_dir.join(CUSTOM_AREA_COUNTRY);
+        tokio::fs::create_dir_all(&country_dir).await?;
+
+        Ok(Self {
+            config,
+            cid_db,
+            whosonfirst_db,
+            events,
+            extraction_service,
+            planet_source,
+            planet_version,
+            _planet_proxy: planet_proxy,
+            country_dir,
+        })
+    }
+
+    /// Extract a region by its bounding box, or by the GeoJSON `region` file when given
+    pub(super) async fn extract(
+        &self,
+        area: &AdministrativeArea,
+        region: Option<&Path>,
+    ) -> CommandResult<()> {
+        self.extraction_service
+            .extract_area_clipped(
+                area,
+                region,
+                &self.planet_source,
+                &self.planet_version,
+                &self.country_dir,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Start the storage node, upload the extracts of `areas` and report their CIDs
+    pub(super) async fn upload(self, cli: &Cli, areas: &[AdministrativeArea]) -> CommandResult<()> {
+        let storage_service = storage_service_for(cli, &self.config, None).await?;
+        storage_service.start_node().await?;
+        let upload_service = AreaUploadService::new(
+            self.cid_db.clone(),
+            Some(self.whosonfirst_db),
+            storage_service.clone(),
+            self.config.clone(),
+            areas.iter().map(|area| area.id as u32).collect(),
+            self.events,
+        );
+        let result = upload_service.process_areas().await;
+        storage_service.stop_node().await?;
+        result?;
+
+        let uploaded = self
+            .cid_db
+            .get_cid_mappings(Some(CUSTOM_AREA_COUNTRY))
+            .await?;
+        for area in areas {
+            match uploaded
+                .iter()
+                .find(|upload| upload.area_id as i64 == area.id)
+            {
+                Some(upload) => println!("{} ({}) uploaded as {}", area.name, area.id, upload.cid),
+                None => println!("{} ({}) was not uploaded, see the log", area.name, area.id),
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,68 @@
+use crate::cli::Cli;
+use crate::services::ExtractionError;
+use crate::utils::read_boundaries_file;
+use std::path::Path;
+use tracing::error;
+
+use super::extract_bbox::CustomRegions;
+use super::CommandResult;
+
+/// Extract every polygon of a GeoJSON file as a custom region named after its feature, by
+/// its bounding box or with `clip` by the polygon itself, and with `upload` upload them
+pub async fn extract_geojson_command(
+    cli: &Cli,
+    path: &Path,
+    clip: bool,
+    upload: bool,
+) -> CommandResult<()> {
+    let boundaries = read_boundaries_file(path)?;
+    let regions = CustomRegions::open(cli).await?;
+
+    println!(
+        "Extracting {} regions from {}...",
+        boundaries.len(),
+        path.display()
+    );
+    let mut extracted = Vec::new();
+    for boundary in &boundaries {
+        let area = regions
+            .cid_db
+            .record_custom_area(boundary.bbox, Some(&boundary.name))
+            .await?;
+
+        let result = match clip {
+            true => {
+                let region_path = regions
+                    .country_dir
+                    .join(format!("{}.region.geojson", area.id));
+                tokio::fs::write(&region_path, boundary.to_geojson()).await?;
+                let result = regions.extract(&area, Some(&region_path)).await;
+                tokio::fs::remove_file(&region_path).await?;
+                result
+            }
+            false => regions.extract(&area, None).await,
+        };
+        match result {
+            Ok(()) => extracted.push(area),
+            Err(e) => error!("Failed to extract {} ({}): {}", area.name, area.id, e),
+        }
+    }
+    println!(
+        "Extraction finished, {} of {} regions are in {}",
+        extracted.len(),
+        boundaries.len(),
+        regions.country_dir.display()
+    );
+
+    if upload && !extracted.is_empty() {
+        regions.upload(cli, &extracted).await?;
+    }
+    if extracted.len() < boundaries.len() {
+        return Err(ExtractionError::ExtractionFailed(
+            0,
+            format!("{} regions failed", boundaries.len() - extracted.len()),
+        )
+        .into());
+    }
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::utils::format_bytes;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::path::Path;
+use tracing::{info, warn};
+
+use super::{storage_service_for, CommandResult};
+
+/// Start the node and download the content behind `cid` to `destination`, from the local
+/// repo or the network, to spot-check stored data. The content is written as stored, still
+/// compressed or encrypted when it was uploaded that way.
+pub async fn fetch_command(cli: &Cli, cid: &str, destination: &Path) -> CommandResult<()> {
+    let config = Config::load()?;
+    let storage_service = storage_service_for(cli, &config, None).await?;
+    storage_service.start_node().await?;
+
+    for target in &cli.connect {
+        match storage_service.connect_peer(target).await {
+            Ok(peer_id) => info!("Connected to peer {}", peer_id),
+            Err(e) => warn!("Failed to connect to peer: {}", e),
+        }
+    }
+
+    let progress_bar = ProgressBar::new(0);
+    progress_bar.set_style(
+        ProgressStyle::with_template("{bar:40.green} {bytes}/{total_bytes} {bytes_per_sec}")
+            .unwrap(),
+    );
+    let bar = progress_bar.clone();
+    let result = storage_service
+        .download_file_with_progress(
+            cid,
+            destination,
+            Box::new(move |downloaded, total| {
+                if let Some(total) = total {
+                    bar.set_length(total);
+                }
+                bar.set_position(downloaded);
+            }),
+        )
+        .await;
+    progress_bar.finish_and_clear();
+    storage_service.stop_node().await?;
+
+    let downloaded = result?;
+    println!(
+        "Fetched {} ({}) to {}",
+        downloaded.cid,
+        format_bytes(downloaded.size as u64),
+        destination.display()
+    );
+    Ok(())
+}
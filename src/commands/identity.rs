@@ -0,0 +1,81 @@
+use crate::cli::{Cli, IdentityCommand};
+use crate::config::Config;
+use crate::services::NODE_KEY_FILE;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use super::{storage_service_for, CommandError, CommandResult};
+
+pub async fn identity_command(cli: &Cli, action: Option<&IdentityCommand>) -> CommandResult<()> {
+    let config = Config::load()?;
+    let key_path = cli
+        .get_data_dir(Some(config.storage_data_dir.clone()))
+        .unwrap_or_else(|| config.storage_data_dir.clone())
+        .join(NODE_KEY_FILE);
+
+    match action {
+        None | Some(IdentityCommand::Show) => show_identity(cli, &config, &key_path).await,
+        Some(IdentityCommand::Export { file }) => {
+            if !key_path.is_file() {
+                return Err(CommandError::IdentityError(format!(
+                    "no node key at {}, start the node once to generate it",
+                    key_path.display()
+                )));
+            }
+            copy_key(&key_path, file)?;
+            println!("Exported node key to {}", file.display());
+            Ok(())
+        }
+        Some(IdentityCommand::Import { file, force }) => {
+            if key_path.exists() && !force {
+                return Err(CommandError::IdentityError(format!(
+                    "a node key already exists at {}, pass --force to replace it",
+                    key_path.display()
+                )));
+            }
+            if let Some(parent) = key_path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            copy_key(file, &key_path)?;
+            println!("Imported node key into {}", key_path.display());
+            Ok(())
+        }
+    }
+}
+
+/// Start the node briefly so it can report its peer ID and signed peer record
+async fn show_identity(cli: &Cli, config: &Config, key_path: &Path) -> CommandResult<()> {
+    let storage_service = storage_service_for(cli, config, None).await?;
+    storage_service.start_node().await?;
+    let node_info = storage_service.get_node_info().await;
+    storage_service.stop_node().await?;
+    let node_info = node_info?;
+
+    println!("Peer ID:  {}", node_info.peer_id.as_deref().unwrap_or("-"));
+    println!("Key file: {}", key_path.display());
+    if !node_info.addresses.is_empty() {
+        println!("Addresses:");
+        for addr in &node_info.addresses {
+            println!("  {}", addr);
+        }
+    }
+    if !node_info.announce_addresses.is_empty() {
+        println!("Announce addresses:");
+        for addr in &node_info.announce_addresses {
+            println!("  {}", addr);
+        }
+    }
+    match node_info.spr {
+        Some(spr) => println!("SPR:      {}", spr),
+        None => println!("SPR:      -"),
+    }
+
+    Ok(())
+}
+
+/// Copy a private key, restricting the copy to the owner as the node requires
+fn copy_key(from: &Path, to: &Path) -> CommandResult<()> {
+    std::fs::copy(from, to)?;
+    std::fs::set_permissions(to, std::fs::Permissions::from_mode(0o600))?;
+    Ok(())
+}
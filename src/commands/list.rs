@@ -0,0 +1,95 @@
+use crate::config::Config;
+use crate::initialization::open_whosonfirst_db;
+use crate::services::DatabaseService;
+use crate::types::{AreaInfo, PaginatedAreasResult, PaginationInfo};
+use crate::utils::format_bytes;
+use serde::Serialize;
+
+use super::{print_json, CommandResult};
+
+/// Page printed by `anynode list --json`
+#[derive(Serialize)]
+struct AreaPage<'a> {
+    country_code: &'a str,
+    areas: &'a [AreaInfo],
+    pagination: &'a PaginationInfo,
+}
+
+/// Print one page of a country's uploaded areas with their CIDs, read from the CID and
+/// WhosOnFirst databases without starting the node
+pub async fn list_command(country: &str, page: u32, limit: u32, json: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+    let country = country.to_uppercase();
+
+    for path in [&config.cid_db_path, &config.whosonfirst_db_path] {
+        if !path.exists() {
+            if json {
+                let empty = PaginatedAreasResult {
+                    areas: Vec::new(),
+                    pagination: PaginationInfo {
+                        page,
+                        limit,
+                        total: 0,
+                        total_pages: 0,
+                    },
+                };
+                return print_page(&country, &empty);
+            }
+            println!("Nothing to list ({} does not exist)", path.display());
+            return Ok(());
+        }
+    }
+
+    let cid_db = DatabaseService::new(&config.cid_db_path.to_string_lossy(), false).await?;
+    let whosonfirst_db = open_whosonfirst_db(&config).await?;
+    let result = cid_db
+        .get_areas_paginated(&whosonfirst_db, &country, page, limit)
+        .await?;
+    let pagination = &result.pagination;
+
+    if json {
+        return print_page(&country, &result);
+    }
+    if pagination.total == 0 {
+        println!("No areas of {} uploaded yet", country);
+        return Ok(());
+    }
+
+    println!(
+        "{:<10}  {:<30}  {:<8}  {:>10}  CID",
+        "AREA", "NAME", "TYPE", "SIZE"
+    );
+    for info in &result.areas {
+        println!(
+            "{:<10}  {:<30}  {:<8}  {:>10}  {}",
+            info.area.id,
+            truncate(&info.area.name, 30),
+            info.area.placetype,
+            format_bytes(info.file_size),
+            info.cid
+        );
+    }
+    println!(
+        "Page {} of {} ({} areas of {}, {} per page)",
+        pagination.page, pagination.total_pages, pagination.total, country, pagination.limit
+    );
+    Ok(())
+}
+
+fn print_page(country_code: &str, result: &PaginatedAreasResult) -> CommandResult<()> {
+    print_json(&AreaPage {
+        country_code,
+        areas: &result.areas,
+        pagination: &result.pagination,
+    })
+}
+
+/// Shorten a name to `width` characters so the columns stay aligned
+pub(super) fn truncate(name: &str, width: usize) -> String {
+    if name.chars().count() <= width {
+        return name.to_string();
+    }
+    let mut short: String = name.chars().take(width - 1).collect();
+    short.push('…');
+    short
+}
@@ -0,0 +1,190 @@
+pub mod areas;
+pub mod audit;
+pub mod cache;
+pub mod completions;
+pub mod config_validate;
+pub mod countries;
+pub mod db;
+pub mod doctor;
+pub mod export;
+pub mod extract;
+pub mod extract_bbox;
+pub mod extract_geojson;
+pub mod fetch;
+pub mod identity;
+pub mod list;
+pub mod peers;
+pub mod report;
+pub mod retry_failed;
+pub mod self_test;
+pub mod serve;
+pub mod status;
+pub mod store;
+pub mod upload;
+pub mod verify;
+
+use crate::cli::{CacheCommand, Cli, Command, ConfigCommand, DbCommand, StoreCommand};
+use crate::config::Config;
+use crate::initialization::{initialize_storage_service, InitializationResult};
+use crate::services::StorageBackend;
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CommandError {
+    #[error("Configuration error: {0}")]
+    ConfigError(#[from] crate::config::ConfigError),
+    #[error("Initialization error: {0}")]
+    InitializationError(#[from] crate::initialization::InitializationError),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] crate::services::DatabaseError),
+    #[error("Storage error: {0}")]
+    StorageError(#[from] crate::services::StorageError),
+    #[error("Extraction error: {0}")]
+    ExtractionError(#[from] crate::services::ExtractionError),
+    #[error("Upload error: {0}")]
+    UploadError(#[from] crate::services::AreaUploadError),
+    #[error("Area IDs error: {0}")]
+    AreaIdsError(#[from] crate::utils::AreaIdsError),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Bounding box error: {0}")]
+    BoundingBoxError(#[from] crate::types::BoundingBoxError),
+    #[error("GeoJSON error: {0}")]
+    GeoJsonError(#[from] crate::utils::GeoJsonError),
+    #[error("Export error: {0}")]
+    ExportError(#[from] crate::utils::ParquetError),
+    #[error("Identity error: {0}")]
+    IdentityError(String),
+    #[error("Backup error: {0}")]
+    BackupError(String),
+    #[error("{0} checks failed")]
+    ChecksFailed(usize),
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
+
+pub use areas::areas_command;
+pub use audit::{audit_command, AuditOptions};
+pub use cache::cache_gc_command;
+pub use completions::{completions_command, man_command};
+pub use config_validate::validate_config_command;
+pub use countries::countries_command;
+pub use db::{db_backup_command, db_maintain_command, db_restore_command};
+pub use doctor::doctor_command;
+pub use export::export_command;
+pub use extract::{extract_command, ExtractOptions};
+pub use extract_bbox::extract_bbox_command;
+pub use extract_geojson::extract_geojson_command;
+pub use fetch::fetch_command;
+pub use identity::identity_command;
+pub use list::list_command;
+pub use peers::peers_command;
+pub use report::{CheckReport, CheckResult, CheckStatus};
+pub use retry_failed::retry_failed_command;
+pub use self_test::self_test_command;
+pub use serve::serve_command;
+pub use status::status_command;
+pub use store::{store_ls_command, store_rm_command};
+pub use upload::upload_command;
+pub use verify::{verify_command, VerifyOptions};
+
+/// Run a subcommand to completion
+pub async fn dispatch(cli: &Cli, command: &Command) -> CommandResult<()> {
+    match command {
+        Command::Config { action } => match action {
+            ConfigCommand::Validate => validate_config_command(cli).await,
+        },
+        Command::Doctor => doctor_command(cli).await,
+        Command::SelfTest => self_test_command().await,
+        Command::Extract { country, area_ids } => {
+            let options = ExtractOptions {
+                countries: country,
+                area_ids,
+            };
+            extract_command(cli, options).await
+        }
+        Command::ExtractBbox { bbox, upload } => extract_bbox_command(cli, bbox, *upload).await,
+        Command::ExtractGeojson { path, clip, upload } => {
+            extract_geojson_command(cli, path, *clip, *upload).await
+        }
+        Command::Peers { wait, json } => peers_command(cli, *wait, *json).await,
+        Command::Serve => serve_command(cli).await,
+        Command::Status { json } => status_command(*json).await,
+        Command::Countries { all, json } => countries_command(*all, *json).await,
+        Command::List {
+            country,
+            page,
+            limit,
+            json,
+        } => list_command(country, *page, *limit, *json).await,
+        Command::Areas { bbox, json } => areas_command(bbox, *json).await,
+        Command::Export { path, country } => export_command(path, country.as_deref()).await,
+        Command::Verify {
+            country,
+            rehash,
+            repair,
+            json,
+        } => {
+            let options = VerifyOptions {
+                country: country.as_deref(),
+                rehash: *rehash,
+                repair: *repair,
+                json: *json,
+            };
+            verify_command(cli, options).await
+        }
+        Command::Audit { country, list } => {
+            let options = AuditOptions {
+                country: country.as_deref(),
+                list: *list,
+            };
+            audit_command(options).await
+        }
+        Command::Fetch { cid, path } => fetch_command(cli, cid, path).await,
+        Command::Upload { dir } => upload_command(cli, dir.as_ref()).await,
+        Command::RetryFailed => retry_failed_command(cli).await,
+        Command::Db { action } => match action {
+            DbCommand::Maintain { whosonfirst } => db_maintain_command(*whosonfirst).await,
+            DbCommand::Backup { path, force } => db_backup_command(path, *force).await,
+            DbCommand::Restore { path } => db_restore_command(path).await,
+        },
+        Command::Cache { action } => match action {
+            CacheCommand::Gc { dry_run } => cache_gc_command(*dry_run).await,
+        },
+        Command::Store { action } => match action {
+            StoreCommand::Ls => store_ls_command(cli).await,
+            StoreCommand::Rm { cids } => store_rm_command(cli, cids).await,
+        },
+        Command::Identity { action } => identity_command(cli, action.as_ref()).await,
+        Command::Completions { shell } => completions_command(*shell),
+        Command::Man { dir } => man_command(dir.as_deref()),
+    }
+}
+
+/// Print `value` as pretty JSON on stdout, for the `--json` output of informational commands.
+/// Field names are kept stable so scripts can rely on them.
+fn print_json<T: Serialize>(value: &T) -> CommandResult<()> {
+    let json = serde_json::to_string_pretty(value).map_err(std::io::Error::from)?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Create a storage node with the CLI overrides applied, optionally in another data dir
+async fn storage_service_for(
+    cli: &Cli,
+    config: &Config,
+    data_dir: Option<PathBuf>,
+) -> InitializationResult<Arc<dyn StorageBackend>> {
+    initialize_storage_service(
+        config,
+        cli.get_port(Some(config.discovery_port)),
+        data_dir.or_else(|| cli.get_data_dir(Some(config.storage_data_dir.clone()))),
+        cli.get_bootstrap_nodes(config.bootstrap_nodes.clone()),
+        Some(cli.get_nat(config.nat.clone())),
+        Some(cli.get_listen_addrs(config.listen_addrs.clone())),
+    )
+    .await
+}
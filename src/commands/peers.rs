@@ -0,0 +1,51 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use std::time::Duration;
+
+use super::{print_json, storage_service_for, CommandResult};
+
+/// Start the node, give discovery `wait_secs` to populate the routing table, then print
+/// the peers it knows about, as a JSON array with `json`. The bindings do not expose live
+/// connections, so these are known peers, not necessarily connected ones.
+pub async fn peers_command(cli: &Cli, wait_secs: u64, json: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+    let storage_service = storage_service_for(cli, &config, None).await?;
+
+    storage_service.start_node().await?;
+    if !json {
+        println!("Waiting {}s for peer discovery...", wait_secs);
+    }
+    tokio::time::sleep(Duration::from_secs(wait_secs)).await;
+
+    let peers = storage_service.list_peers().await;
+    storage_service.stop_node().await?;
+    let peers = peers?;
+
+    if json {
+        return print_json(&peers);
+    }
+
+    if peers.is_empty() {
+        println!("No peers in the discovery table");
+        return Ok(());
+    }
+
+    let id_width = peers.iter().map(|p| p.peer_id.len()).max().unwrap_or(0);
+    for peer in &peers {
+        println!(
+            "{:<width$}  {:<8}  {}",
+            peer.peer_id,
+            if peer.seen { "seen" } else { "unseen" },
+            peer.address.as_deref().unwrap_or("-"),
+            width = id_width
+        );
+    }
+    println!();
+    println!(
+        "{} known peers, {} seen",
+        peers.len(),
+        peers.iter().filter(|p| p.seen).count()
+    );
+
+    Ok(())
+}
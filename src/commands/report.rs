@@ -0,0 +1,113 @@
+use console::style;
+
+/// Outcome of a single check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+        }
+    }
+
+    /// Label in green, yellow or red, plain when stdout is not a terminal
+    pub fn styled_label(&self) -> String {
+        let label = style(self.label()).bold();
+        match self {
+            CheckStatus::Pass => label.green(),
+            CheckStatus::Warn => label.yellow(),
+            CheckStatus::Fail => label.red(),
+        }
+        .to_string()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CheckResult {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// Ordered list of check results, printed as a pass/fail table
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    results: Vec<CheckResult>,
+}
+
+impl CheckReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.results.push(CheckResult {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    pub fn pass(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.add(name, CheckStatus::Pass, detail);
+    }
+
+    pub fn warn(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.add(name, CheckStatus::Warn, detail);
+    }
+
+    pub fn fail(&mut self, name: impl Into<String>, detail: impl Into<String>) {
+        self.add(name, CheckStatus::Fail, detail);
+    }
+
+    pub fn results(&self) -> &[CheckResult] {
+        &self.results
+    }
+
+    pub fn failure_count(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|r| r.status == CheckStatus::Fail)
+            .count()
+    }
+
+    pub fn print(&self) {
+        let name_width = self
+            .results
+            .iter()
+            .map(|r| r.name.len())
+            .max()
+            .unwrap_or(0);
+
+        for result in &self.results {
+            println!(
+                "{}  {:<width$}  {}",
+                result.status.styled_label(),
+                result.name,
+                result.detail,
+                width = name_width
+            );
+        }
+
+        let failures = self.failure_count();
+        let warnings = self
+            .results
+            .iter()
+            .filter(|r| r.status == CheckStatus::Warn)
+            .count();
+        println!();
+        println!(
+            "{} checks, {} failed, {} warnings",
+            self.results.len(),
+            failures,
+            warnings
+        );
+    }
+}
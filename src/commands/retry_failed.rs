@@ -0,0 +1,45 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::{initialize_cid_db, initialize_whosonfirst_db};
+use crate::services::{AreaUploadService, EventService};
+use std::sync::Arc;
+
+use super::{storage_service_for, CommandResult};
+
+/// Start the node and upload again everything recorded as permanently failed
+pub async fn retry_failed_command(cli: &Cli) -> CommandResult<()> {
+    let config = Arc::new(Config::load()?);
+    let cid_db = initialize_cid_db(&config).await?;
+
+    let failed = cid_db.get_failed_uploads().await?;
+    if failed.is_empty() {
+        println!("No failed uploads");
+        return Ok(());
+    }
+    println!("Retrying {} failed uploads...", failed.len());
+
+    let whosonfirst_db = initialize_whosonfirst_db(&config).await?;
+    let storage_service = storage_service_for(cli, &config, None).await?;
+    storage_service.start_node().await?;
+
+    let upload_service = AreaUploadService::new(
+        cid_db.clone(),
+        Some(whosonfirst_db),
+        storage_service.clone(),
+        config.clone(),
+        Vec::new(),
+        Arc::new(EventService::new()),
+    );
+    let result = upload_service.retry_failed().await;
+    storage_service.stop_node().await?;
+    let attempted = result?;
+
+    let stats = upload_service.get_stats().await;
+    let remaining = cid_db.get_failed_uploads().await?.len();
+    println!(
+        "{} retried: {} uploaded, {} reused, {} failed again, {} left in the failed list",
+        attempted, stats.total_uploaded, stats.total_reused, stats.total_failed, remaining
+    );
+
+    Ok(())
+}
@@ -0,0 +1,322 @@
+use crate::config::Config;
+use crate::initialization::{initialize_storage_service, InitializationResult};
+use crate::services::{DatabaseService, StorageBackend};
+use crate::types::{CompletedUpload, Compression, ListenAddr};
+use crate::utils::{
+    compress_file, decompress_file, decrypt_file, encrypt_file, format_bytes, run_command,
+    write_sample_pmtiles, EncryptionKey, PmtilesHeader,
+};
+use std::path::Path;
+use std::sync::Arc;
+
+use super::{CheckReport, CommandError, CommandResult};
+
+/// Bytes of the synthetic payload pushed through compression, encryption and the storage node
+const SAMPLE_SIZE: usize = 1024;
+
+/// Where the scratch storage node listens, a random loopback port so a node already running
+/// on the host keeps its ports
+const SCRATCH_LISTEN_ADDR: &str = "/ip4/127.0.0.1/tcp/0";
+
+/// Exercise each subsystem on tiny synthetic inputs and print a pass/fail checklist. Unlike
+/// `doctor` it needs no WhosOnFirst database or planet, only the configured tools and
+/// storage node. Local files go to a scratch directory removed afterwards, also when a check
+/// fails or panics. The s3 and kubo backends store the sample remotely, so it is deleted
+/// again as a check of its own.
+pub async fn self_test_command() -> CommandResult<()> {
+    let mut report = CheckReport::new();
+
+    let config = match Config::load() {
+        Ok(config) => {
+            report.pass("configuration", "loaded");
+            config
+        }
+        Err(e) => {
+            report.fail("configuration", e.to_string());
+            report.print();
+            return Err(CommandError::ChecksFailed(report.failure_count()));
+        }
+    };
+
+    let scratch = tempfile::Builder::new()
+        .prefix("anynode-self-test-")
+        .tempdir()?;
+    let work_dir = scratch.path();
+    let sample = sample_bytes();
+    let sample_path = work_dir.join("sample.bin");
+    tokio::fs::write(&sample_path, &sample).await?;
+
+    record(&mut report, "sqlite", check_sqlite(work_dir).await);
+    let mut compressions = vec![Compression::Gzip];
+    if config.upload_compression == Compression::Zstd {
+        compressions.push(Compression::Zstd);
+    }
+    for compression in compressions {
+        let result = check_compression(&sample_path, compression, &config.zstd_cmd).await;
+        record(&mut report, format!("{} round trip", compression), result);
+    }
+    record(
+        &mut report,
+        "encryption round trip",
+        check_encryption(&sample_path).await,
+    );
+    record(
+        &mut report,
+        "pmtiles extract",
+        check_pmtiles(&config.pmtiles_cmd, work_dir).await,
+    );
+    check_storage_round_trip(&config, work_dir, &sample_path, &mut report).await;
+    drop(scratch);
+
+    report.print();
+
+    match report.failure_count() {
+        0 => Ok(()),
+        failures => Err(CommandError::ChecksFailed(failures)),
+    }
+}
+
+fn record(report: &mut CheckReport, name: impl Into<String>, result: Result<String, String>) {
+    match result {
+        Ok(detail) => report.pass(name, detail),
+        Err(detail) => report.fail(name, detail),
+    }
+}
+
+/// `SAMPLE_SIZE` bytes cycling through every byte value
+fn sample_bytes() -> Vec<u8> {
+    (0..SAMPLE_SIZE).map(|i| (i * 7 % 256) as u8).collect()
+}
+
+/// Write a CID mapping to a fresh SQLite database and read it back
+async fn check_sqlite(work_dir: &Path) -> Result<String, String> {
+    let path = work_dir.join("self-test.db");
+    let db = DatabaseService::new(&path.to_string_lossy(), true)
+        .await
+        .map_err(|e| e.to_string())?;
+    let upload = CompletedUpload::new("XX".to_string(), 1, "self-test".to_string(), 1);
+    db.batch_insert_cid_mappings(&[upload])
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mappings = db.get_cid_mappings(None).await.map_err(|e| e.to_string())?;
+    match mappings.as_slice() {
+        [mapping] if mapping.cid == "self-test" => Ok("CID mapping written and read back".into()),
+        _ => Err(format!(
+            "read back {} mappings instead of 1",
+            mappings.len()
+        )),
+    }
+}
+
+/// Compress the sample and decompress it again
+async fn check_compression(
+    sample_path: &Path,
+    compression: Compression,
+    zstd_cmd: &str,
+) -> Result<String, String> {
+    let compressed = compress_file(sample_path, compression, zstd_cmd)
+        .await
+        .map_err(|e| e.to_string())?;
+    let restored = sample_path.with_extension("restored");
+    decompress_file(&compressed, &restored, compression, zstd_cmd)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let size = tokio::fs::metadata(&compressed)
+        .await
+        .map_err(|e| e.to_string())?
+        .len();
+    compare_with_sample(&restored).await?;
+    Ok(format!(
+        "{} to {}",
+        format_bytes(SAMPLE_SIZE as u64),
+        format_bytes(size)
+    ))
+}
+
+/// Encrypt the sample with a throwaway key and decrypt it again
+async fn check_encryption(sample_path: &Path) -> Result<String, String> {
+    let key_bytes = [
+        uuid::Uuid::new_v4().into_bytes(),
+        uuid::Uuid::new_v4().into_bytes(),
+    ]
+    .concat();
+    let key: EncryptionKey = hex::encode(key_bytes)
+        .parse()
+        .map_err(|e| format!("{}", e))?;
+
+    let (encrypted, info) = encrypt_file(sample_path, &key, "self-test")
+        .await
+        .map_err(|e| e.to_string())?;
+    let restored = sample_path.with_extension("decrypted");
+    decrypt_file(&encrypted, &restored, &key, &info)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    compare_with_sample(&restored).await?;
+    Ok("AES-256-GCM with a throwaway key".into())
+}
+
+/// Extract a region of a one tile archive with pmtiles and check the result is a complete
+/// archive
+async fn check_pmtiles(pmtiles_cmd: &str, work_dir: &Path) -> Result<String, String> {
+    let source = work_dir.join("sample.pmtiles");
+    write_sample_pmtiles(&source, [-10.0, -10.0, 10.0, 10.0], SAMPLE_SIZE)
+        .map_err(|e| e.to_string())?;
+
+    let output = work_dir.join("extract.pmtiles");
+    let (source, output_arg) = (source.to_string_lossy(), output.to_string_lossy());
+    run_command(
+        pmtiles_cmd,
+        &["extract", &source, &output_arg, "--bbox=-1,-1,1,1"],
+        None,
+    )
+    .await
+    .map_err(|e| format!("{}: {}", pmtiles_cmd, e))?;
+
+    let bytes = tokio::fs::read(&output).await.map_err(|e| e.to_string())?;
+    let header = PmtilesHeader::parse(&bytes).map_err(|e| e.to_string())?;
+    header
+        .check_size(bytes.len() as u64)
+        .map_err(|e| e.to_string())?;
+    Ok(format!(
+        "{} archive extracted",
+        format_bytes(bytes.len() as u64)
+    ))
+}
+
+/// Start a storage node in the scratch directory, upload the sample and fetch it back
+async fn check_storage_round_trip(
+    config: &Config,
+    work_dir: &Path,
+    sample_path: &Path,
+    report: &mut CheckReport,
+) {
+    let storage_service = match scratch_storage_service(config, work_dir).await {
+        Ok(service) => service,
+        Err(e) => {
+            report.fail("storage node", e.to_string());
+            return;
+        }
+    };
+    if let Err(e) = storage_service.start_node().await {
+        report.fail("storage node", e.to_string());
+        return;
+    }
+    report.pass("storage node", "started");
+
+    check_upload_round_trip(storage_service.as_ref(), work_dir, sample_path, report).await;
+
+    if let Err(e) = storage_service.stop_node().await {
+        report.fail("storage node shutdown", e.to_string());
+    }
+}
+
+/// Upload the sample, fetch it back and delete it, so a remote backend keeps no trace of it
+async fn check_upload_round_trip(
+    storage_service: &dyn StorageBackend,
+    work_dir: &Path,
+    sample_path: &Path,
+    report: &mut CheckReport,
+) {
+    let upload = match storage_service.upload_file(sample_path).await {
+        Ok(upload) => upload,
+        Err(e) => {
+            report.fail("upload", e.to_string());
+            return;
+        }
+    };
+    report.pass(
+        "upload",
+        format!("{} as {}", format_bytes(upload.size), upload.cid),
+    );
+
+    let restored = work_dir.join("downloaded.bin");
+    let result = match storage_service.download_file(&upload.cid, &restored).await {
+        Ok(_) => compare_with_sample(&restored).await,
+        Err(e) => Err(e.to_string()),
+    };
+    record(
+        report,
+        "download",
+        result.map(|()| "content matches".into()),
+    );
+
+    let result = storage_service.delete(&upload.cid).await;
+    record(
+        report,
+        "delete",
+        result
+            .map(|()| format!("{} removed", upload.cid))
+            .map_err(|e| e.to_string()),
+    );
+}
+
+/// Storage node of the configured backend with its data in `work_dir`. A Logos node gets a
+/// random discovery port, listens on loopback only and joins no network, so it runs next to
+/// the node of a deployed host instead of clashing with its ports.
+async fn scratch_storage_service(
+    config: &Config,
+    work_dir: &Path,
+) -> InitializationResult<Arc<dyn StorageBackend>> {
+    let listen_addr: ListenAddr = SCRATCH_LISTEN_ADDR
+        .parse()
+        .map_err(|e| std::io::Error::other(format!("{}", e)))?;
+    initialize_storage_service(
+        config,
+        Some(0),
+        Some(work_dir.join("storage")),
+        Vec::new(),
+        Some("none".to_string()),
+        Some(vec![listen_addr]),
+    )
+    .await
+}
+
+async fn compare_with_sample(path: &Path) -> Result<(), String> {
+    let bytes = tokio::fs::read(path).await.map_err(|e| e.to_string())?;
+    match bytes == sample_bytes() {
+        true => Ok(()),
+        false => Err(format!("{} differs from the sample", path.display())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn local_subsystems_pass_on_the_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let sample_path = dir.path().join("sample.bin");
+        std::fs::write(&sample_path, sample_bytes()).unwrap();
+
+        assert!(check_sqlite(dir.path()).await.is_ok());
+        assert!(check_compression(&sample_path, Compression::Gzip, "zstd")
+            .await
+            .is_ok());
+        assert!(check_encryption(&sample_path).await.is_ok());
+
+        let pmtiles_cmd = crate::testing::write_fake_pmtiles_cmd(dir.path()).unwrap();
+        let result = check_pmtiles(&pmtiles_cmd.to_string_lossy(), dir.path()).await;
+        assert!(result.is_ok(), "{:?}", result);
+    }
+
+    #[tokio::test]
+    async fn upload_round_trip_deletes_the_sample() {
+        let dir = tempfile::tempdir().unwrap();
+        let sample_path = dir.path().join("sample.bin");
+        std::fs::write(&sample_path, sample_bytes()).unwrap();
+        let storage = crate::testing::MockStorageBackend::new();
+        storage.start_node().await.unwrap();
+
+        let mut report = CheckReport::new();
+        check_upload_round_trip(&storage, dir.path(), &sample_path, &mut report).await;
+
+        assert_eq!(report.failure_count(), 0);
+        let uploads = storage.uploads();
+        assert_eq!(uploads.len(), 1);
+        assert!(storage.content(&uploads[0]).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use crate::app::monitor::{create_node_status_progress_bar, monitor_node_status};
+use crate::app::{
+    display_node_info, start_events_server, start_node_supervisor, start_peer_watch,
+    start_spr_file_writer, wait_for_shutdown_signal, Notifiers, SystemdNotifier,
+};
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::initialize_cid_db;
+use crate::services::{EventService, GossipService, PauseService};
+use crate::types::UploadProgress;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+use super::{storage_service_for, CommandResult};
+
+/// Start the storage node and serve the content already uploaded until stopped. The
+/// WhosOnFirst database, the planet file and the extraction tools are not needed.
+pub async fn serve_command(cli: &Cli) -> CommandResult<()> {
+    let config = Arc::new(Config::load()?);
+
+    let systemd = SystemdNotifier::from_env();
+    let watchdog_handle = systemd.start_watchdog();
+    systemd.status("Starting storage node");
+
+    let storage_service = storage_service_for(cli, &config, None).await?;
+    storage_service.start_node().await?;
+    systemd.attach_storage(storage_service.clone());
+    systemd.ready();
+
+    for target in &cli.connect {
+        match storage_service.connect_peer(target).await {
+            Ok(peer_id) => info!("Connected to peer {}", peer_id),
+            Err(e) => warn!("Failed to connect to peer: {}", e),
+        }
+    }
+
+    // Serving nodes still take part in gossip, so they learn what the fleet holds
+    let gossip = match config.gossip_peers.is_empty() {
+        true => None,
+        false => {
+            let cid_db = initialize_cid_db(&config).await?;
+            Some(Arc::new(GossipService::new(cid_db, &config)))
+        }
+    };
+    let events = Arc::new(EventService::new());
+    let events_handle = match config.events_listen_addr {
+        Some(addr) => Some(
+            start_events_server(addr, events, Arc::new(PauseService::new()), gossip, None).await?,
+        ),
+        None => None,
+    };
+    let spr_file_handle = config
+        .spr_file
+        .clone()
+        .map(|path| start_spr_file_writer(storage_service.clone(), path));
+
+    display_node_info(storage_service.as_ref()).await;
+    systemd.status("Serving");
+
+    let monitor_handle = tokio::spawn(monitor_node_status(
+        storage_service.clone(),
+        create_node_status_progress_bar(),
+        config.storage_warn_thresholds.clone(),
+        Arc::new(Mutex::new(UploadProgress::new())),
+    ));
+    let supervisor_handle = start_node_supervisor(storage_service.clone());
+    let peer_watch_handle = config.no_peers_alert_after.map(|alert_after| {
+        let targets = cli
+            .get_bootstrap_nodes(config.bootstrap_nodes.clone())
+            .iter()
+            .map(|spr| spr.to_string())
+            .chain(cli.connect.iter().cloned())
+            .collect();
+        start_peer_watch(
+            storage_service.clone(),
+            targets,
+            alert_after,
+            Notifiers::from_config(&config),
+        )
+    });
+
+    info!("Press Ctrl+C to stop the node gracefully");
+    wait_for_shutdown_signal().await;
+
+    systemd.stopping();
+    monitor_handle.abort();
+    supervisor_handle.abort();
+    for handle in [watchdog_handle, events_handle, spr_file_handle, peer_watch_handle]
+        .into_iter()
+        .flatten()
+    {
+        handle.abort();
+    }
+
+    info!("Stopping storage node...");
+    storage_service.stop_node().await?;
+    info!("Storage node stopped successfully");
+    Ok(())
+}
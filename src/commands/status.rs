@@ -0,0 +1,246 @@
+use crate::config::Config;
+use crate::services::DatabaseService;
+use crate::types::{
+    CountryUsage, DbSnapshot, OversizedExtract, PublishedIndex, RunStats, UploadStats,
+};
+use crate::utils::format_bytes;
+use serde::Serialize;
+
+use super::{print_json, CommandResult};
+
+const RECENT_RUNS: u32 = 5;
+
+/// Largest oversized extracts listed, the JSON report has all of them
+const OVERSIZED_SHOWN: usize = 10;
+
+/// Everything `anynode status` shows, printed as is with `--json`
+#[derive(Serialize)]
+struct StatusReport {
+    area_count: u64,
+    country_count: usize,
+    bytes_stored: u64,
+    storage_quota: u64,
+    published_index: Option<PublishedIndex>,
+    db_snapshot: Option<DbSnapshot>,
+    peer_area_count: u64,
+    peer_node_count: u64,
+    failed_upload_count: usize,
+    oversized_extracts: Vec<OversizedExtract>,
+    lifetime: Option<LifetimeStats>,
+    recent_runs: Vec<RunStats>,
+    countries: Vec<CountryUsage>,
+    /// Run statistics are kept but predate reuse counting, until the next run migrates them
+    #[serde(skip)]
+    run_history_pending: bool,
+}
+
+#[derive(Serialize)]
+struct LifetimeStats {
+    runs: u64,
+    #[serde(flatten)]
+    stats: UploadStats,
+}
+
+impl StatusReport {
+    fn new(countries: Vec<CountryUsage>, storage_quota: u64) -> Self {
+        Self {
+            area_count: countries.iter().map(|c| c.area_count).sum(),
+            country_count: countries.len(),
+            bytes_stored: countries.iter().map(|c| c.total_bytes).sum(),
+            storage_quota,
+            published_index: None,
+            db_snapshot: None,
+            peer_area_count: 0,
+            peer_node_count: 0,
+            failed_upload_count: 0,
+            oversized_extracts: Vec::new(),
+            lifetime: None,
+            recent_runs: Vec::new(),
+            countries,
+            run_history_pending: false,
+        }
+    }
+}
+
+/// Print how many areas have been uploaded, the history of recent runs and how much of the
+/// quota each country takes, read from the CID database without starting the node
+pub async fn status_command(json: bool) -> CommandResult<()> {
+    let config = Config::load()?;
+
+    let path = &config.cid_db_path;
+    if !path.exists() {
+        if json {
+            return print_json(&StatusReport::new(Vec::new(), config.storage_quota));
+        }
+        println!("No uploads recorded yet ({} does not exist)", path.display());
+        return Ok(());
+    }
+
+    let db = DatabaseService::new(&path.to_string_lossy(), false).await?;
+    let report = read_status(&db, config.storage_quota).await?;
+
+    if json {
+        return print_json(&report);
+    }
+    print_status(&report);
+    Ok(())
+}
+
+/// Gather the status from the CID database. Tables added by later versions may be missing
+/// from older databases, their parts of the status are left empty.
+async fn read_status(db: &DatabaseService, storage_quota: u64) -> CommandResult<StatusReport> {
+    let mut report = StatusReport::new(db.get_bytes_by_country().await?, storage_quota);
+
+    if !db.get_table_columns("published_indexes").await?.is_empty() {
+        report.published_index = db.get_latest_published_index().await?;
+    }
+
+    if !db.get_table_columns("db_snapshots").await?.is_empty() {
+        report.db_snapshot = db.get_latest_db_snapshot().await?;
+    }
+
+    if !db.get_table_columns("peer_cids").await?.is_empty() {
+        (report.peer_area_count, report.peer_node_count) = db.count_peer_cids().await?;
+    }
+
+    if !db.get_table_columns("failed_uploads").await?.is_empty() {
+        report.failed_upload_count = db.get_failed_uploads().await?.len();
+    }
+
+    if !db.get_table_columns("oversized_extracts").await?.is_empty() {
+        report.oversized_extracts = db.get_oversized_extracts().await?;
+    }
+
+    // Databases created before run statistics were recorded have no run_stats table, and
+    // those from before reuse was counted are migrated by the next run
+    let run_columns = db.get_table_columns("run_stats").await?;
+    if run_columns.iter().any(|c| c == "reused") {
+        let (runs, stats) = db.get_lifetime_stats().await?;
+        if runs > 0 {
+            report.lifetime = Some(LifetimeStats { runs, stats });
+            report.recent_runs = db.get_recent_runs(RECENT_RUNS).await?;
+        }
+    } else if !run_columns.is_empty() {
+        report.run_history_pending = true;
+    }
+
+    Ok(report)
+}
+
+fn print_status(report: &StatusReport) {
+    let quota = report.storage_quota;
+    println!(
+        "{} areas across {} countries, {} of {} quota ({:.1}%)",
+        report.area_count,
+        report.country_count,
+        format_bytes(report.bytes_stored),
+        format_bytes(quota),
+        percent(report.bytes_stored, quota)
+    );
+
+    if let Some(index) = &report.published_index {
+        println!(
+            "Dataset root {} published {} ({} countries, {} areas)",
+            index.root_cid, index.published_at, index.country_count, index.area_count
+        );
+    }
+
+    if let Some(snapshot) = &report.db_snapshot {
+        println!(
+            "Database snapshot {} uploaded {} ({})",
+            snapshot.cid,
+            snapshot.created_at,
+            format_bytes(snapshot.size)
+        );
+    }
+
+    if report.peer_area_count > 0 {
+        println!(
+            "{} areas announced by {} other nodes",
+            report.peer_area_count, report.peer_node_count
+        );
+    }
+
+    if report.failed_upload_count > 0 {
+        println!(
+            "{} uploads failed after every attempt, run `anynode retry-failed` to retry them",
+            report.failed_upload_count
+        );
+    }
+
+    if !report.oversized_extracts.is_empty() {
+        println!(
+            "{} areas skipped, their extracts exceed MAX_OUTPUT_SIZE:",
+            report.oversized_extracts.len()
+        );
+        for extract in report.oversized_extracts.iter().take(OVERSIZED_SHOWN) {
+            println!(
+                "  {} {}  {}",
+                extract.country_code,
+                extract.area_id,
+                format_bytes(extract.file_size)
+            );
+        }
+    }
+
+    if let Some(lifetime) = &report.lifetime {
+        print_run_history(lifetime, &report.recent_runs);
+    } else if report.run_history_pending {
+        println!("Run history is shown again after the next upload run");
+    }
+
+    if report.countries.is_empty() {
+        return;
+    }
+
+    println!();
+    println!(
+        "{:<8}  {:>8}  {:>12}  {:>8}  {:>8}",
+        "COUNTRY", "AREAS", "SIZE", "SHARE", "QUOTA"
+    );
+    for country in &report.countries {
+        println!(
+            "{:<8}  {:>8}  {:>12}  {:>7.1}%  {:>7.1}%",
+            country.country_code,
+            country.area_count,
+            format_bytes(country.total_bytes),
+            percent(country.total_bytes, report.bytes_stored),
+            percent(country.total_bytes, quota)
+        );
+    }
+}
+
+fn print_run_history(lifetime: &LifetimeStats, recent_runs: &[RunStats]) {
+    println!(
+        "Lifetime: {} uploaded, {} reused, {} failed, {} over {} runs",
+        lifetime.stats.total_uploaded,
+        lifetime.stats.total_reused,
+        lifetime.stats.total_failed,
+        format_bytes(lifetime.stats.total_bytes_uploaded),
+        lifetime.runs
+    );
+
+    println!();
+    println!(
+        "{:<20}  {:>8}  {:>8}  {:>8}  {:>12}",
+        "RUN FINISHED", "UPLOADED", "REUSED", "FAILED", "SIZE"
+    );
+    for run in recent_runs {
+        println!(
+            "{:<20}  {:>8}  {:>8}  {:>8}  {:>12}",
+            run.finished_at,
+            run.stats.total_uploaded,
+            run.stats.total_reused,
+            run.stats.total_failed,
+            format_bytes(run.stats.total_bytes_uploaded)
+        );
+    }
+}
+
+fn percent(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64 * 100.0
+    }
+}
@@ -0,0 +1,69 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::utils::format_bytes;
+
+use super::{storage_service_for, CommandResult};
+
+/// Start the node and print every dataset in its repo, largest first
+pub async fn store_ls_command(cli: &Cli) -> CommandResult<()> {
+    let config = Config::load()?;
+    let storage_service = storage_service_for(cli, &config, None).await?;
+
+    storage_service.start_node().await?;
+    let content = storage_service.list_local_content().await;
+    storage_service.stop_node().await?;
+    let mut content = content?;
+
+    if content.is_empty() {
+        println!("The repo holds no content");
+        return Ok(());
+    }
+
+    content.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.cid.cmp(&b.cid)));
+    let cid_width = content.iter().map(|c| c.cid.len()).max().unwrap_or(0);
+    for entry in &content {
+        println!(
+            "{:<width$}  {:>12}  {}",
+            entry.cid,
+            format_bytes(entry.size),
+            entry.filename.as_deref().unwrap_or("-"),
+            width = cid_width
+        );
+    }
+
+    let bytes: u64 = content.iter().map(|c| c.size).sum();
+    println!();
+    println!("{} datasets, {}", content.len(), format_bytes(bytes));
+
+    Ok(())
+}
+
+/// Start the node and remove `cids` from its repo. Every CID is attempted; the command
+/// fails with the last error when any removal did.
+pub async fn store_rm_command(cli: &Cli, cids: &[String]) -> CommandResult<()> {
+    let config = Config::load()?;
+    let storage_service = storage_service_for(cli, &config, None).await?;
+
+    storage_service.start_node().await?;
+    let mut removed = 0;
+    let mut last_error = None;
+    for cid in cids {
+        match storage_service.delete(cid).await {
+            Ok(()) => {
+                println!("Removed {}", cid);
+                removed += 1;
+            }
+            Err(e) => {
+                eprintln!("Failed to remove {}: {}", cid, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    storage_service.stop_node().await?;
+
+    println!("Removed {} of {} CIDs", removed, cids.len());
+    match last_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
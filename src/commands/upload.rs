@@ -0,0 +1,89 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::{initialize_cid_db, initialize_whosonfirst_db, print_final_stats};
+use crate::services::{AreaUploadService, EventService};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tracing::warn;
+
+use super::{storage_service_for, CommandResult};
+
+/// Start the node and upload the extracts already in AREAS_DIR, or in `dir`, without
+/// downloading the WhosOnFirst database or extracting anything
+pub async fn upload_command(cli: &Cli, dir: Option<&PathBuf>) -> CommandResult<()> {
+    let mut config = Config::load()?;
+    if let Some(dir) = dir {
+        config.areas_dir = dir.clone();
+    }
+    let config = Arc::new(config);
+
+    if !config.areas_dir.is_dir() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("Areas directory not found: {}", config.areas_dir.display()),
+        )
+        .into());
+    }
+
+    let cid_db = initialize_cid_db(&config).await?;
+    // The database only vets area IDs and describes them, extracts copied from an
+    // extraction machine are uploaded without it
+    let whosonfirst_db = match config.whosonfirst_db_present() {
+        true => Some(initialize_whosonfirst_db(&config).await?),
+        false => {
+            warn!(
+                "WhosOnFirst database {} not found, uploading every extract without metadata",
+                config.whosonfirst_db_path.display()
+            );
+            None
+        }
+    };
+
+    let area_ids = cli.get_area_ids(config.area_ids.clone())?;
+
+    let storage_service = storage_service_for(cli, &config, None).await?;
+    storage_service.start_node().await?;
+
+    let upload_service = AreaUploadService::new(
+        cid_db,
+        whosonfirst_db,
+        storage_service.clone(),
+        config.clone(),
+        area_ids,
+        Arc::new(EventService::new()),
+    );
+    println!("Uploading extracts from {}...", config.areas_dir.display());
+    let result = upload_service.process_areas().await;
+    if result.is_err() {
+        storage_service.stop_node().await?;
+    }
+    result?;
+
+    let stats = upload_service.get_stats().await;
+    let lifetime = match upload_service.record_run().await {
+        Ok(lifetime) => Some(lifetime),
+        Err(e) => {
+            warn!("Failed to record run statistics: {}", e);
+            None
+        }
+    };
+    let timings = upload_service.get_timings().await;
+    print_final_stats(&stats, lifetime.as_ref(), &timings);
+
+    let index = upload_service.publish_index().await;
+    storage_service.stop_node().await?;
+    match index {
+        Ok(Some(index)) => println!(
+            "Dataset root CID: {} ({} countries, {} areas)",
+            index.root_cid, index.country_count, index.area_count
+        ),
+        Ok(None) => println!("Nothing uploaded yet, no dataset index published"),
+        Err(e) => warn!("Failed to publish the dataset index: {}", e),
+    }
+
+    println!(
+        "{} uploaded, {} reused, {} failed",
+        stats.total_uploaded, stats.total_reused, stats.total_failed
+    );
+    Ok(())
+}
@@ -0,0 +1,539 @@
+use crate::cli::Cli;
+use crate::config::Config;
+use crate::initialization::initialize_cid_db;
+use crate::services::{DatabaseService, StorageBackend};
+use crate::types::{
+    area_parts_dir, AreaPartUpload, CompletedUpload, Compression, SplitAreaManifest,
+    SPLIT_AREA_INDEX,
+};
+use crate::utils::{
+    payload_cache_key, prepare_payload, sha256_file, EncryptionInfo, EncryptionKey,
+};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use super::{print_json, storage_service_for, CommandError, CommandResult};
+
+/// Options of `anynode verify`
+pub struct VerifyOptions<'a> {
+    pub country: Option<&'a str>,
+    pub rehash: bool,
+    pub repair: bool,
+    /// Print the summary and every finding as JSON once done instead of as they come
+    pub json: bool,
+}
+
+/// Summary printed by `anynode verify --json`
+#[derive(Serialize)]
+struct VerifyReport {
+    areas: usize,
+    ok: usize,
+    repaired: usize,
+    problems: usize,
+    findings: Vec<Finding>,
+}
+
+/// Problem found, or repair made, on a CID
+#[derive(Serialize)]
+struct Finding {
+    /// `<country>/<area_id>`, followed by `part <n>` for parts of split areas
+    area: String,
+    message: String,
+}
+
+/// Check that every mapped CID, and every part CID of split areas, is still held by the
+/// local node. Missing content can be re-uploaded from the local extract, or pinned from the
+/// network when there is none, e.g. after restoring a database snapshot. `rehash`
+/// compares stored content against it, or against the recorded size for compressed and
+/// encrypted uploads.
+pub async fn verify_command(cli: &Cli, options: VerifyOptions<'_>) -> CommandResult<()> {
+    let config = Config::load()?;
+    let cid_db = initialize_cid_db(&config).await?;
+
+    let country = options.country.map(str::to_ascii_uppercase);
+    let mappings = cid_db.get_cid_mappings(country.as_deref()).await?;
+    if mappings.is_empty() {
+        if options.json {
+            return print_json(&VerifyReport {
+                areas: 0,
+                ok: 0,
+                repaired: 0,
+                problems: 0,
+                findings: Vec::new(),
+            });
+        }
+        println!("No CID mappings to verify");
+        return Ok(());
+    }
+    if !options.json {
+        println!("Verifying {} areas...", mappings.len());
+    }
+
+    let storage_service = storage_service_for(cli, &config, None).await?;
+    storage_service.start_node().await?;
+
+    let scratch_dir =
+        std::env::temp_dir().join(format!("anynode-verify-{}", uuid::Uuid::new_v4()));
+    tokio::fs::create_dir_all(&scratch_dir).await?;
+
+    let verifier = Verifier {
+        storage: storage_service.as_ref(),
+        cid_db: &cid_db,
+        options: &options,
+        scratch_dir: &scratch_dir,
+        zstd_cmd: &config.zstd_cmd,
+        encryption_key: config.encryption_key.as_ref(),
+        findings: Mutex::new(Vec::new()),
+    };
+
+    let (mut ok, mut repaired, mut problems) = (0, 0, 0);
+    let mut result = Ok(());
+    for mapping in &mappings {
+        let country_dir = config.areas_dir.join(&mapping.country_code);
+        let outcome = match cid_db
+            .get_area_parts(&mapping.country_code, mapping.area_id)
+            .await
+        {
+            Ok(parts) if parts.is_empty() => verifier.verify_extract(mapping, &country_dir).await,
+            Ok(parts) => verifier.verify_split(mapping, parts, &country_dir).await,
+            Err(e) => Err(e.into()),
+        };
+
+        match outcome {
+            Ok(Outcome::Ok) => ok += 1,
+            Ok(Outcome::Repaired) => repaired += 1,
+            Ok(Outcome::Problem) => problems += 1,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+    }
+
+    let _ = tokio::fs::remove_dir_all(&scratch_dir).await;
+    storage_service.stop_node().await?;
+    result?;
+
+    if options.json {
+        print_json(&VerifyReport {
+            areas: mappings.len(),
+            ok,
+            repaired,
+            problems,
+            findings: verifier.findings.into_inner().unwrap_or_default(),
+        })?;
+    } else {
+        println!();
+        println!(
+            "{} ok, {} repaired, {} problems out of {} areas",
+            ok,
+            repaired,
+            problems,
+            mappings.len()
+        );
+    }
+
+    match problems {
+        0 => Ok(()),
+        problems => Err(CommandError::ChecksFailed(problems)),
+    }
+}
+
+enum Outcome {
+    Ok,
+    Repaired,
+    Problem,
+}
+
+/// State of one stored CID
+enum Check {
+    Present,
+    Missing,
+    Problem(String),
+}
+
+struct Verifier<'a> {
+    storage: &'a dyn StorageBackend,
+    cid_db: &'a DatabaseService,
+    options: &'a VerifyOptions<'a>,
+    scratch_dir: &'a Path,
+    zstd_cmd: &'a str,
+    encryption_key: Option<&'a EncryptionKey>,
+    /// Collected for `--json`, printed right away otherwise
+    findings: Mutex<Vec<Finding>>,
+}
+
+/// Content uploaded again in place of a lost CID
+struct Reupload {
+    cid: String,
+    size: u64,
+    encryption: Option<EncryptionInfo>,
+}
+
+impl Verifier<'_> {
+    fn report(&self, area: &str, message: String) {
+        if !self.options.json {
+            println!("{}: {}", area, message);
+            return;
+        }
+        if let Ok(mut findings) = self.findings.lock() {
+            findings.push(Finding {
+                area: area.to_string(),
+                message,
+            });
+        }
+    }
+
+    async fn verify_extract(
+        &self,
+        mapping: &CompletedUpload,
+        country_dir: &Path,
+    ) -> CommandResult<Outcome> {
+        let label = label(mapping);
+        let local_file = Some(country_dir.join(format!("{}.pmtiles", mapping.area_id)))
+            .filter(|path| path.is_file());
+
+        let compared = local_file.as_deref().filter(|_| is_plain(mapping));
+        match self.check(&mapping.cid, compared, mapping.file_size).await {
+            Check::Present => Ok(Outcome::Ok),
+            Check::Problem(problem) => {
+                self.report(&label, format!("{} {}", mapping.cid, problem));
+                Ok(Outcome::Problem)
+            }
+            Check::Missing => {
+                let Some(path) = self.repair_source(&label, &mapping.cid, local_file) else {
+                    return Ok(match self.pin_from_network(&label, &mapping.cid).await {
+                        true => Outcome::Repaired,
+                        false => Outcome::Problem,
+                    });
+                };
+                let Some(reupload) = self
+                    .reupload(
+                        &label,
+                        &mapping.cid,
+                        &path,
+                        mapping.compression,
+                        mapping.encryption.as_ref(),
+                    )
+                    .await?
+                else {
+                    return Ok(Outcome::Problem);
+                };
+                let mut repaired = CompletedUpload::new(
+                    mapping.country_code.clone(),
+                    mapping.area_id,
+                    reupload.cid,
+                    reupload.size,
+                );
+                repaired.compression = mapping.compression;
+                repaired.encryption = reupload.encryption;
+                self.cid_db.batch_insert_cid_mappings(&[repaired]).await?;
+                Ok(Outcome::Repaired)
+            }
+        }
+    }
+
+    /// Check every part of a split area, then its index. The index is uploaded again when
+    /// it is missing or when a part it lists had to be re-uploaded under a new CID.
+    async fn verify_split(
+        &self,
+        mapping: &CompletedUpload,
+        parts: Vec<AreaPartUpload>,
+        country_dir: &Path,
+    ) -> CommandResult<Outcome> {
+        let parts_dir = area_parts_dir(country_dir, mapping.area_id as i64);
+        let mut problem = false;
+        let mut parts_repaired = false;
+
+        for part in parts {
+            let label = format!("{} part {}", label(mapping), part.part.index);
+            let local_file =
+                Some(parts_dir.join(part.part.file_name())).filter(|path| path.is_file());
+
+            let compared = local_file
+                .as_deref()
+                .filter(|_| part.compression.is_none() && part.encryption.is_none());
+            match self.check(&part.cid, compared, part.file_size).await {
+                Check::Present => {}
+                Check::Problem(description) => {
+                    self.report(&label, format!("{} {}", part.cid, description));
+                    problem = true;
+                }
+                Check::Missing => {
+                    let repaired = match self.repair_source(&label, &part.cid, local_file) {
+                        Some(path) => {
+                            self.reupload(
+                                &label,
+                                &part.cid,
+                                &path,
+                                part.compression,
+                                part.encryption.as_ref(),
+                            )
+                            .await?
+                        }
+                        None => None,
+                    };
+                    match repaired {
+                        Some(reupload) => {
+                            let upload = AreaPartUpload {
+                                part: part.part,
+                                cid: reupload.cid,
+                                file_size: reupload.size,
+                                compression: part.compression,
+                                encryption: reupload.encryption,
+                            };
+                            self.cid_db
+                                .record_area_part(&mapping.country_code, mapping.area_id, &upload)
+                                .await?;
+                            parts_repaired = true;
+                        }
+                        None => {
+                            if !self.pin_from_network(&label, &part.cid).await {
+                                problem = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let label = label(mapping);
+        let local_index = Some(parts_dir.join(SPLIT_AREA_INDEX))
+            .filter(|path| path.is_file() && mapping.encryption.is_none());
+        let index_missing = match self.check(&mapping.cid, local_index.as_deref(), 0).await {
+            Check::Present => false,
+            Check::Missing => {
+                self.report(&label, format!("index {} missing", mapping.cid));
+                true
+            }
+            Check::Problem(description) => {
+                self.report(&label, format!("index {} {}", mapping.cid, description));
+                return Ok(Outcome::Problem);
+            }
+        };
+
+        if problem {
+            // An index rebuilt now would still list parts that are gone
+            if index_missing || parts_repaired {
+                self.report(
+                    &label,
+                    "index not rebuilt while parts are missing".to_string(),
+                );
+            }
+            return Ok(Outcome::Problem);
+        }
+        if !index_missing && !parts_repaired {
+            return Ok(Outcome::Ok);
+        }
+        if index_missing && !parts_repaired && self.pin_from_network(&label, &mapping.cid).await {
+            return Ok(Outcome::Repaired);
+        }
+        if !self.options.repair {
+            self.report(&label, "index needs to be rebuilt (--repair)".to_string());
+            return Ok(Outcome::Problem);
+        }
+        if !parts_dir.is_dir() {
+            self.report(
+                &label,
+                "no local parts directory to rebuild the index in".to_string(),
+            );
+            return Ok(Outcome::Problem);
+        }
+
+        let parts = self
+            .cid_db
+            .get_area_parts(&mapping.country_code, mapping.area_id)
+            .await?;
+        let file_size = parts.iter().map(|p| p.file_size).sum();
+        let manifest = SplitAreaManifest {
+            area_id: mapping.area_id,
+            country_code: mapping.country_code.clone(),
+            parts,
+        };
+        let index_path = parts_dir.join(SPLIT_AREA_INDEX);
+        let index = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+        tokio::fs::write(&index_path, index).await?;
+
+        let Some(reupload) = self
+            .reupload(
+                &label,
+                &mapping.cid,
+                &index_path,
+                Compression::None,
+                mapping.encryption.as_ref(),
+            )
+            .await?
+        else {
+            return Ok(Outcome::Problem);
+        };
+        let mut repaired = CompletedUpload::new(
+            mapping.country_code.clone(),
+            mapping.area_id,
+            reupload.cid,
+            file_size,
+        );
+        repaired.encryption = reupload.encryption;
+        self.cid_db.batch_insert_cid_mappings(&[repaired]).await?;
+        Ok(Outcome::Repaired)
+    }
+
+    /// Whether the node still holds `cid` and, with `rehash`, whether it matches the local
+    /// file or the recorded size (`expected_size` of 0 skips the size comparison)
+    async fn check(&self, cid: &str, local_file: Option<&Path>, expected_size: u64) -> Check {
+        match self.storage.has_content(cid).await {
+            Ok(true) => {}
+            Ok(false) => return Check::Missing,
+            Err(e) => return Check::Problem(format!("check failed: {}", e)),
+        }
+
+        if !self.options.rehash {
+            return Check::Present;
+        }
+
+        match self.rehash_matches(cid, local_file, expected_size).await {
+            Ok(true) => Check::Present,
+            Ok(false) => Check::Problem("content does not match".to_string()),
+            Err(e) => Check::Problem(format!("could not be read back: {}", e)),
+        }
+    }
+
+    /// Local file to repair missing content from, reporting why there is none. Without a
+    /// local file, repairs fall back to `pin_from_network`.
+    fn repair_source(
+        &self,
+        label: &str,
+        cid: &str,
+        local_file: Option<PathBuf>,
+    ) -> Option<PathBuf> {
+        match (local_file, self.options.repair) {
+            (Some(path), true) => Some(path),
+            (Some(_), false) => {
+                self.report(label, format!("{} missing, local file available (--repair)", cid));
+                None
+            }
+            (None, true) => None,
+            (None, false) => {
+                self.report(
+                    label,
+                    format!(
+                        "{} missing, no local file, may be pinned from the network (--repair)",
+                        cid
+                    ),
+                );
+                None
+            }
+        }
+    }
+
+    /// Fetch missing content from other nodes into the local repo, with `repair`. The CID
+    /// stays the same, so nothing needs to be recorded again.
+    async fn pin_from_network(&self, label: &str, cid: &str) -> bool {
+        if !self.options.repair {
+            return false;
+        }
+        match self.storage.pin_content(cid).await {
+            Ok(_) => {
+                self.report(label, format!("{} missing, pinned from the network", cid));
+                true
+            }
+            Err(e) => {
+                self.report(label, format!("{} missing, not found on the network: {}", cid, e));
+                false
+            }
+        }
+    }
+
+    /// Upload `path` again with the codec and key of its first upload and point its extract
+    /// cache entry at the new CID, so later runs do not hand back the lost one. `None` when
+    /// the upload failed or the content was encrypted with a key that is not configured.
+    async fn reupload(
+        &self,
+        label: &str,
+        old_cid: &str,
+        path: &Path,
+        compression: Compression,
+        encryption: Option<&EncryptionInfo>,
+    ) -> CommandResult<Option<Reupload>> {
+        let key = match (encryption, self.encryption_key) {
+            (None, _) => None,
+            (Some(info), Some(key)) if info.key_id == key.id() => Some(key),
+            (Some(info), _) => {
+                self.report(
+                    label,
+                    format!(
+                        "{} missing, encrypted with key {} which is not configured",
+                        old_cid, info.key_id
+                    ),
+                );
+                return Ok(None);
+            }
+        };
+
+        let cache_key = payload_cache_key(&sha256_file(path).await?, compression, key);
+        let payload =
+            match prepare_payload(path, compression, key, &cache_key, self.zstd_cmd).await {
+                Ok(payload) => payload,
+                Err(e) => {
+                    self.report(label, format!("{} missing, {}", old_cid, e));
+                    return Ok(None);
+                }
+            };
+        let result = self.storage.upload_file(&payload.path).await;
+        let encryption = payload.encryption.clone();
+        payload.remove().await;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                self.report(label, format!("{} missing, re-upload failed: {}", old_cid, e));
+                return Ok(None);
+            }
+        };
+        self.report(label, format!("{} missing, re-uploaded as {}", old_cid, result.cid));
+
+        self.cid_db
+            .insert_cache_entry(&cache_key, &result.cid, result.size, &path.to_string_lossy())
+            .await?;
+
+        Ok(Some(Reupload {
+            cid: result.cid,
+            size: result.size,
+            encryption,
+        }))
+    }
+
+    /// Read the stored content back and compare it with the local file, or with the
+    /// recorded size when the local file is gone
+    async fn rehash_matches(
+        &self,
+        cid: &str,
+        local_file: Option<&Path>,
+        expected_size: u64,
+    ) -> CommandResult<bool> {
+        let downloaded = self.scratch_dir.join(cid);
+        let result = self.storage.download_local_file(cid, &downloaded).await;
+        let matches = match (result, local_file) {
+            (Ok(_), Some(local_file)) => {
+                sha256_file(&downloaded).await? == sha256_file(local_file).await?
+            }
+            (Ok(result), None) => expected_size == 0 || result.size as u64 == expected_size,
+            (Err(e), _) => {
+                let _ = tokio::fs::remove_file(&downloaded).await;
+                return Err(e.into());
+            }
+        };
+
+        tokio::fs::remove_file(&downloaded).await?;
+        Ok(matches)
+    }
+}
+
+/// Whether the stored content is the local extract byte for byte
+fn is_plain(mapping: &CompletedUpload) -> bool {
+    mapping.compression.is_none() && mapping.encryption.is_none()
+}
+
+fn label(mapping: &CompletedUpload) -> String {
+    format!("{}/{}", mapping.country_code, mapping.area_id)
+}
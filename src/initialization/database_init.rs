@@ -1,5 +1,5 @@
 use crate::config::Config;
-use crate::services::DatabaseService;
+use crate::services::{ClaimService, DatabaseError, DatabaseService};
 use std::sync::Arc;
 use tracing::info;
 
@@ -8,16 +8,36 @@ use super::InitializationResult;
 pub async fn initialize_whosonfirst_db(config: &Config) -> InitializationResult<Arc<DatabaseService>> {
     info!("Initializing WhosOnFirst database at {:?}", config.whosonfirst_db_path);
 
-    let db = DatabaseService::new(
-        config.whosonfirst_db_path.to_str().unwrap(),
-        false, // Don't create CID tables for WhosOnFirst DB
-    )
-    .await?;
+    let db = open_whosonfirst_db(config).await?;
+    db.validate_whosonfirst_schema().await?;
+
+    // Fail before any work starts rather than on the first country
+    if config.min_population.is_some() && !db.has_population_source().await? {
+        return Err(crate::services::DatabaseError::NoPopulationSource.into());
+    }
+    // Viewport lookups of the events server are answered from it
+    db.create_bbox_index().await?;
 
     info!("WhosOnFirst database initialized successfully");
     Ok(Arc::new(db))
 }
 
+/// Open the WhosOnFirst database, one file or a bundle of the target countries' databases
+pub async fn open_whosonfirst_db(config: &Config) -> Result<DatabaseService, DatabaseError> {
+    if config.whosonfirst_bundle {
+        return DatabaseService::open_whosonfirst_bundle(
+            &config.whosonfirst_db_path,
+            &config.bundle_countries(),
+        )
+        .await;
+    }
+    DatabaseService::new(
+        config.whosonfirst_db_path.to_str().unwrap(),
+        false, // Don't create CID tables for WhosOnFirst DB
+    )
+    .await
+}
+
 pub async fn initialize_cid_db(config: &Config) -> InitializationResult<Arc<DatabaseService>> {
     info!("Initializing CID mappings database at {:?}", config.cid_db_path);
 
@@ -34,3 +54,18 @@ pub async fn initialize_cid_db(config: &Config) -> InitializationResult<Arc<Data
     info!("CID mappings database initialized successfully");
     Ok(Arc::new(db))
 }
+
+/// Open the claims database shared with cooperating nodes, `None` when CLAIMS_DB_PATH is unset
+pub async fn initialize_claims_db(
+    config: &Config,
+) -> InitializationResult<Option<Arc<ClaimService>>> {
+    let Some(path) = &config.claims_db_path else {
+        return Ok(None);
+    };
+    info!("Opening work claims database at {:?} as {}", path, config.node_id);
+
+    let claims = ClaimService::open(path, config.node_id.clone(), config.claim_lease).await?;
+
+    info!("Work claims database opened successfully");
+    Ok(Some(Arc::new(claims)))
+}
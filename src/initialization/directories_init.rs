@@ -13,7 +13,7 @@ pub async fn ensure_directories(config: &Config) -> InitializationResult<()> {
     }
 
     if let Some(planet_location) = &config.planet_pmtiles_location {
-        if !planet_location.starts_with("http://") && !planet_location.starts_with("https://") {
+        if !planet_location.contains("://") {
             if let Some(parent) = PathBuf::from(planet_location).parent() {
                 if !parent.exists() {
                     tokio::fs::create_dir_all(parent).await?;
@@ -1,38 +1,126 @@
 use crate::config::Config;
-use crate::utils::{download_file_with_progress, run_command};
+use crate::services::{whosonfirst_bundle_file_name, DatabaseError, DatabaseService};
+use crate::types::PhaseTimings;
+use crate::utils::{
+    decompress_bzip2_file, download_bzip2_decompressed, download_file_with_progress, format_bytes,
+    http_client, RateLimiter,
+};
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 use tracing::{info, warn};
 
 use super::{InitializationError, InitializationResult};
 
+/// What to do when the WhosOnFirst database is missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseDownload {
+    /// Download it without asking
+    Auto,
+    /// Ask on the terminal first
+    Ask,
+    /// Fail with `DatabaseMissing`
+    Never,
+}
+
+impl DatabaseDownload {
+    /// `--skip-download` asks first, or fails with `--non-interactive`
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        if !cli.should_skip_download() {
+            Self::Auto
+        } else if !cli.is_non_interactive() {
+            Self::Ask
+        } else {
+            Self::Never
+        }
+    }
+}
+
+/// Integrity check problems quoted in the error, `quick_check` reports up to a hundred
+const MAX_REPORTED_PROBLEMS: usize = 3;
+
+/// WhosOnFirst distribution holding the administrative placetypes areas are extracted from
+const ADMIN_DATABASE_FILE: &str = "whosonfirst-data-admin-latest.db";
+
+/// Download and decompress the WhosOnFirst database when it is missing, returning how long
+/// each step took. A database without the placetypes the node needs, e.g. a postalcode or
+/// venue distribution, is swapped for the admin one in `config`.
 pub async fn ensure_database_is_present(
+    config: &mut Config,
+    download: DatabaseDownload,
+) -> InitializationResult<PhaseTimings> {
+    if config.whosonfirst_bundle {
+        return ensure_bundle_is_present(config, download).await;
+    }
+
+    let mut timings = ensure_single_database(config, download).await?;
+    if select_admin_database(config).await? {
+        let admin_timings = ensure_single_database(config, download).await?;
+        timings.download = timings.download.or(admin_timings.download);
+        timings.decompress = timings.decompress.or(admin_timings.decompress);
+    }
+    Ok(timings)
+}
+
+/// Point `config` at the admin distribution, next to the configured database and on the same
+/// mirrors, when the configured one lacks `REQUIRED_PLACETYPES`. Returns whether it did.
+async fn select_admin_database(config: &mut Config) -> InitializationResult<bool> {
+    let db = DatabaseService::new(&config.whosonfirst_db_path.to_string_lossy(), false).await?;
+    db.validate_whosonfirst_schema().await?;
+    let missing = db.missing_placetypes().await?;
+    if missing.is_empty() {
+        return Ok(false);
+    }
+
+    let missing: Vec<String> = missing.iter().map(|p| p.to_string()).collect();
+    let admin_path = config
+        .whosonfirst_db_path
+        .with_file_name(ADMIN_DATABASE_FILE);
+    if admin_path == config.whosonfirst_db_path {
+        return Err(DatabaseError::MissingPlacetypes(missing).into());
+    }
+
+    warn!(
+        "{} has no {} areas, using the admin database {} instead",
+        config.whosonfirst_db_path.display(),
+        missing.join(" or "),
+        admin_path.display()
+    );
+    config.whosonfirst_db_path = admin_path;
+    config.whosonfirst_db_urls = sibling_urls(&config.whosonfirst_db_urls, ADMIN_DATABASE_FILE);
+    Ok(true)
+}
+
+async fn ensure_single_database(
     config: &Config,
-    cli: &crate::cli::Cli,
-) -> InitializationResult<()> {
+    download: DatabaseDownload,
+) -> InitializationResult<PhaseTimings> {
     let database_path = &config.whosonfirst_db_path;
     let compressed_path = format!("{}.bz2", database_path.display());
+    let mut timings = PhaseTimings::new();
 
     if database_path.exists() {
         info!("WhosOnFirst database already present.");
-        return Ok(());
+        return Ok(timings);
     }
 
     if Path::new(&compressed_path).exists() {
         info!("Compressed database found, decompressing...");
-        decompress_database(&config.bzip2_cmd, &compressed_path).await?;
-        return Ok(());
+        let started = Instant::now();
+        decompress_database(&compressed_path).await?;
+        timings.decompress = Some(started.elapsed());
+        return Ok(timings);
     }
 
     info!("WhosOnFirst database not found.");
 
-    if !cli.should_skip_download() {
+    if download == DatabaseDownload::Auto {
         info!("Auto-downloading WhosOnFirst database...");
-        download_and_decompress_database(config, &compressed_path).await?;
-        return Ok(());
+        download_and_decompress_database(config, database_path, &mut timings).await?;
+        return Ok(timings);
     }
 
-    if !cli.is_non_interactive() {
+    if download == DatabaseDownload::Ask {
         print!("Do you want to download the WhosOnFirst database? This may take a while. (y/n) ");
         io::stdout().flush()?;
 
@@ -40,8 +128,8 @@ pub async fn ensure_database_is_present(
         io::stdin().read_line(&mut input)?;
 
         if input.trim().to_lowercase() == "y" {
-            download_and_decompress_database(config, &compressed_path).await?;
-            return Ok(());
+            download_and_decompress_database(config, database_path, &mut timings).await?;
+            return Ok(timings);
         }
     }
 
@@ -49,31 +137,198 @@ pub async fn ensure_database_is_present(
     Err(InitializationError::DatabaseMissing)
 }
 
+/// Download the per-country databases of the target countries missing from the bundle
+/// directory. They are fetched next to the global database on each mirror, under the name
+/// WhosOnFirst gives them.
+async fn ensure_bundle_is_present(
+    config: &Config,
+    download: DatabaseDownload,
+) -> InitializationResult<PhaseTimings> {
+    let dir = &config.whosonfirst_db_path;
+    let mut timings = PhaseTimings::new();
+
+    let countries = config.bundle_countries();
+    if countries.is_empty() {
+        if config.whosonfirst_db_present() {
+            return Ok(timings);
+        }
+        warn!(
+            "{} holds no country database, set TARGET_COUNTRIES to download them",
+            dir.display()
+        );
+        return Err(InitializationError::DatabaseMissing);
+    }
+
+    let mut missing = Vec::new();
+    for country in &countries {
+        let database_path = dir.join(whosonfirst_bundle_file_name(country));
+        let compressed_path = format!("{}.bz2", database_path.display());
+        if database_path.exists() {
+            continue;
+        }
+        if Path::new(&compressed_path).exists() {
+            info!("Compressed {} database found, decompressing...", country);
+            let started = Instant::now();
+            decompress_database(&compressed_path).await?;
+            *timings.decompress.get_or_insert_default() += started.elapsed();
+            continue;
+        }
+        missing.push(country.as_str());
+    }
+    if missing.is_empty() {
+        info!("WhosOnFirst country databases already present.");
+        return Ok(timings);
+    }
+
+    info!(
+        "WhosOnFirst databases not found for: {}",
+        missing.join(", ")
+    );
+    let confirmed = match download {
+        DatabaseDownload::Auto => true,
+        DatabaseDownload::Ask => {
+            print!(
+                "Do you want to download the WhosOnFirst databases of {}? (y/n) ",
+                missing.join(", ")
+            );
+            io::stdout().flush()?;
+
+            let mut input = String::new();
+            io::stdin().read_line(&mut input)?;
+            input.trim().to_lowercase() == "y"
+        }
+        DatabaseDownload::Never => false,
+    };
+    if !confirmed {
+        info!("Database download skipped.");
+        return Err(InitializationError::DatabaseMissing);
+    }
+
+    for country in missing {
+        let file_name = whosonfirst_bundle_file_name(country);
+        let database_path = dir.join(&file_name);
+        let urls = sibling_urls(&config.whosonfirst_db_urls, &file_name);
+        info!("Downloading the {} WhosOnFirst database...", country);
+        download_and_decompress(config, &urls, &database_path, &mut timings).await?;
+    }
+    Ok(timings)
+}
+
+/// Mirror URLs of another WhosOnFirst database, next to the configured one on each mirror
+fn sibling_urls(mirrors: &[String], file_name: &str) -> Vec<String> {
+    mirrors
+        .iter()
+        .filter_map(|url| url.rsplit_once('/'))
+        .map(|(base, _)| format!("{}/{}.bz2", base, file_name))
+        .collect()
+}
+
 async fn download_and_decompress_database(
     config: &Config,
-    compressed_path: &str,
+    database_path: &Path,
+    timings: &mut PhaseTimings,
 ) -> InitializationResult<()> {
-    if let Some(parent) = Path::new(compressed_path).parent() {
+    download_and_decompress(config, &config.whosonfirst_db_urls, database_path, timings).await
+}
+
+/// Download a `.bz2` database and decompress it. Over one connection it is decompressed on
+/// the fly, the compressed file is never written and there is no separate decompression
+/// phase. Over more it is downloaded in parallel ranges, then decompressed.
+async fn download_and_decompress(
+    config: &Config,
+    urls: &[String],
+    database_path: &Path,
+    timings: &mut PhaseTimings,
+) -> InitializationResult<()> {
+    if let Some(parent) = database_path.parent() {
         tokio::fs::create_dir_all(parent).await?;
     }
 
+    let started = Instant::now();
+    let rate_limiter = config.download_rate_limit.map(RateLimiter::new);
+    let client = http_client(config.proxy_url.as_deref(), &config.whosonfirst_db_headers)?;
+    if config.download_connections == 1 {
+        info!("Downloading and decompressing WhosOnFirst database...");
+        download_bzip2_decompressed(&client, urls, database_path, rate_limiter.as_ref()).await?;
+        *timings.download.get_or_insert_default() += started.elapsed();
+        info!("Database downloaded and decompressed successfully!");
+        return check_database(database_path).await;
+    }
+
     info!("Downloading WhosOnFirst database...");
-    download_file_with_progress(&config.whosonfirst_db_url, Path::new(compressed_path)).await?;
+    let compressed_path = format!("{}.bz2", database_path.display());
+    download_file_with_progress(
+        &client,
+        urls,
+        Path::new(&compressed_path),
+        rate_limiter.as_ref(),
+        config.download_connections,
+    )
+    .await?;
+    *timings.download.get_or_insert_default() += started.elapsed();
     info!("Database download completed!");
 
     info!("Decompressing database...");
-    decompress_database(&config.bzip2_cmd, compressed_path).await?;
+    let started = Instant::now();
+    decompress_database(&compressed_path).await?;
+    *timings.decompress.get_or_insert_default() += started.elapsed();
     info!("Database decompressed successfully!");
 
     Ok(())
 }
 
-async fn decompress_database(bzip2_cmd: &str, compressed_path: &str) -> InitializationResult<()> {
-    let output = run_command(bzip2_cmd, &["-dv", compressed_path], None).await?;
+/// Decompress `<database>.bz2` next to itself, then delete it and check the database
+async fn decompress_database(compressed_path: &str) -> InitializationResult<()> {
+    let compressed_path = PathBuf::from(compressed_path);
+    let database_path = compressed_path.with_extension("");
+    let output = database_path.clone();
+    let size = tokio::task::spawn_blocking(move || {
+        let written = decompress_bzip2_file(&compressed_path, &output)?;
+        std::fs::remove_file(&compressed_path)?;
+        Ok::<_, io::Error>(written)
+    })
+    .await
+    .map_err(io::Error::other)??;
+    info!("Decompressed database to {}", format_bytes(size));
+    check_database(&database_path).await
+}
 
-    if !output.stderr.is_empty() {
-        warn!("Decompression output: {}", output.stderr);
+/// Check a database just decompressed, so a corrupted download fails here rather than as
+/// query errors in the middle of a run. A corrupted one is deleted for the next run to
+/// download it again.
+async fn check_database(database_path: &Path) -> InitializationResult<()> {
+    info!("Checking the integrity of {}...", database_path.display());
+    let problems = match DatabaseService::new(&database_path.to_string_lossy(), false).await {
+        Ok(db) => db.check_integrity().await?,
+        Err(DatabaseError::RusqliteError(e)) => vec![e.to_string()],
+        Err(e) => return Err(e.into()),
+    };
+    if problems.is_empty() {
+        info!("Database integrity check passed");
+        return Ok(());
     }
 
-    Ok(())
+    tokio::fs::remove_file(database_path).await?;
+    let problems: Vec<String> = problems.into_iter().take(MAX_REPORTED_PROBLEMS).collect();
+    Err(
+        DatabaseError::CorruptDatabase(database_path.display().to_string(), problems.join("; "))
+            .into(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn country_databases_are_fetched_next_to_the_global_one() {
+        let mirrors = vec![
+            "https://data.geocode.earth/wof/dist/sqlite/whosonfirst-data-admin-latest.db.bz2"
+                .to_string(),
+        ];
+        assert_eq!(
+            sibling_urls(&mirrors, &whosonfirst_bundle_file_name("FR")),
+            vec!["https://data.geocode.earth/wof/dist/sqlite/whosonfirst-data-admin-fr-latest.db.bz2"]
+        );
+    }
 }
@@ -1,15 +1,19 @@
 use crate::config::Config;
 use crate::services::{
-    AreaUploadService, CountryService, DatabaseService, ExtractionService, StorageService,
+    AreaUploadService, CountryService, DatabaseService, EventService, ExtractionService,
+    KuboBackend, LocalBackend, S3Backend, StorageBackend,
 };
-use crate::types::UploadStats;
+use crate::types::{ListenAddr, PhaseTimings, SprUri, StorageBackendKind, UploadStats};
 use std::path::PathBuf;
 use std::sync::Arc;
-use tracing::info;
+use tracing::{info, warn};
 
-pub fn initialize_country_service() -> CountryService {
+pub fn initialize_country_service(
+    config: &Arc<Config>,
+    whosonfirst_db: Arc<DatabaseService>,
+) -> CountryService {
     info!("Initializing country service");
-    let country_service = CountryService::new();
+    let country_service = CountryService::new(whosonfirst_db, config.clone());
     info!("Country service initialized successfully");
     country_service
 }
@@ -18,12 +22,39 @@ pub async fn initialize_storage_service(
     config: &Config,
     port_override: Option<u16>,
     data_dir_override: Option<PathBuf>,
-    bootstrap_nodes: Vec<String>,
+    bootstrap_nodes: Vec<SprUri>,
     nat_override: Option<String>,
-    listen_addrs_override: Option<Vec<String>>,
-) -> super::InitializationResult<Arc<StorageService>> {
+    listen_addrs_override: Option<Vec<ListenAddr>>,
+) -> super::InitializationResult<Arc<dyn StorageBackend>> {
     info!("Initializing storage service");
 
+    match config.storage_backend {
+        StorageBackendKind::Logos => {}
+        StorageBackendKind::Kubo => {
+            info!("Using the Kubo RPC API at {}", config.kubo_api_url);
+            return Ok(Arc::new(KuboBackend::new(&config.kubo_api_url)));
+        }
+        StorageBackendKind::S3 => {
+            let (Some(bucket), Some(credentials)) = (&config.s3_bucket, &config.s3_credentials)
+            else {
+                return Err(crate::services::StorageError::NodeCreation(
+                    "the s3 backend needs S3_BUCKET and AWS credentials".to_string(),
+                )
+                .into());
+            };
+            info!("Using bucket s3://{}/{}", bucket, config.s3_prefix);
+            return Ok(Arc::new(S3Backend::new(
+                credentials.clone(),
+                bucket,
+                &config.s3_prefix,
+            )));
+        }
+        StorageBackendKind::Local => {
+            let root = data_dir_override.unwrap_or_else(|| config.storage_data_dir.clone());
+            return Ok(Arc::new(LocalBackend::new(&root, config.storage_quota)));
+        }
+    }
+
     let port = port_override.unwrap_or(config.discovery_port);
     let data_dir = data_dir_override.unwrap_or_else(|| config.storage_data_dir.clone());
     let nat = nat_override.unwrap_or_else(|| config.nat.clone());
@@ -34,30 +65,53 @@ pub async fn initialize_storage_service(
     }
 
     info!("Using NAT configuration: {}", nat);
-    info!("Using listen addresses: {:?}", listen_addrs);
-
-    let storage_service = StorageService::new(
-        &data_dir,
-        config.storage_quota,
-        port,
-        config.max_peers,
-        bootstrap_nodes,
-        nat,
-        listen_addrs,
-    )
-    .await?;
-
-    info!("Storage service initialized successfully");
-    Ok(Arc::new(storage_service))
+    info!(
+        "Using listen addresses: {}",
+        listen_addrs
+            .iter()
+            .map(|a| a.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    #[cfg(feature = "storage-bindings")]
+    {
+        let storage_service = crate::services::StorageService::new(
+            &data_dir,
+            config.storage_quota,
+            port,
+            config.max_peers,
+            bootstrap_nodes,
+            nat,
+            listen_addrs,
+        )
+        .await?;
+
+        info!("Storage service initialized successfully");
+        Ok(Arc::new(storage_service))
+    }
+
+    #[cfg(not(feature = "storage-bindings"))]
+    {
+        let _ = (data_dir, port, bootstrap_nodes, nat, listen_addrs);
+        Err(crate::services::StorageError::NodeCreation(
+            "built without the storage-bindings feature, set STORAGE_BACKEND to another backend"
+                .to_string(),
+        )
+        .into())
+    }
 }
 
 pub fn initialize_extraction_service(
     config: &Arc<Config>,
     whosonfirst_db: Arc<DatabaseService>,
+    cid_db: Arc<DatabaseService>,
+    events: Arc<EventService>,
 ) -> super::InitializationResult<ExtractionService> {
     info!("Initializing extraction service");
 
-    let extraction_service = ExtractionService::new(config.clone(), whosonfirst_db);
+    let extraction_service =
+        ExtractionService::new(config.clone(), whosonfirst_db, cid_db, events);
 
     info!("Extraction service initialized successfully");
     Ok(extraction_service)
@@ -66,19 +120,20 @@ pub fn initialize_extraction_service(
 pub fn initialize_area_upload_service(
     cid_db: Arc<DatabaseService>,
     whosonfirst_db: Arc<DatabaseService>,
-    storage: Arc<StorageService>,
-    config: &Config,
+    storage: Arc<dyn StorageBackend>,
+    config: &Arc<Config>,
     area_ids: Vec<u32>,
+    events: Arc<EventService>,
 ) -> super::InitializationResult<AreaUploadService> {
     info!("Initializing area upload service");
 
     let upload_service = AreaUploadService::new(
         cid_db,
-        whosonfirst_db,
+        Some(whosonfirst_db),
         storage,
-        config.areas_dir.clone(),
-        config.target_countries.clone(),
+        config.clone(),
         area_ids,
+        events,
     );
 
     info!("Area upload service initialized successfully");
@@ -91,10 +146,63 @@ pub fn print_startup_info(config: &Config, cli: &crate::cli::Cli) {
     info!("CID Mappings DB: {:?}", config.cid_db_path);
     info!("Areas Dir: {:?}", config.areas_dir);
     info!("Planet PMTiles: {:?}", config.planet_pmtiles_location);
+    info!("Storage Backend: {}", config.storage_backend);
     info!("Storage Port: {}", config.discovery_port);
     info!("Storage Data Dir: {:?}", config.storage_data_dir);
     info!("Max Concurrent Extractions: {}", config.max_concurrent_extractions);
+    info!(
+        "Uploads: {} to {} concurrent, batches of {}, queue capacity {}",
+        config.min_concurrent_uploads,
+        config.max_concurrent_uploads,
+        config.upload_batch_size,
+        config.upload_queue_capacity
+    );
+    info!(
+        "Upload Backlog Watermarks: {} high, {} low",
+        config.upload_backlog_high, config.upload_backlog_low
+    );
     info!("Target Countries: {:?}", config.target_countries);
+    info!("Country Priority: {}", config.country_priority);
+    info!("Extraction Mode: {}", config.extraction_mode);
+    if let Some(shard) = config.shard {
+        info!("Shard: {}", shard);
+    }
+    if !config.gossip_peers.is_empty() {
+        info!("Gossip Peers: {:?}, {} hops", config.gossip_peers, config.gossip_ttl);
+        if config.events_listen_addr.is_none() {
+            warn!("EVENTS_LISTEN_ADDR is unset, announcements from other nodes are not received");
+        }
+    }
+    if !config.trusted_manifests.is_empty() {
+        info!("Trusted Manifests: {:?}", config.trusted_manifests);
+    }
+    if let Some(path) = &config.claims_db_path {
+        info!(
+            "Work Claims: {:?} as {}, {}s lease",
+            path,
+            config.node_id,
+            config.claim_lease.as_secs()
+        );
+    }
+    if let Some(min_population) = config.min_population {
+        info!("Min Population: {}", min_population);
+    }
+    if config.min_bbox_area_km2.is_some() || config.max_bbox_area_km2.is_some() {
+        info!(
+            "BBox Area Limits: {:?} - {:?} km²",
+            config.min_bbox_area_km2, config.max_bbox_area_km2
+        );
+    }
+    info!("Upload Windows: {}", config.upload_schedule);
+    info!("Retention Policy: {}", config.retention_policy);
+    info!("Upload Compression: {}", config.upload_compression);
+    if let Some(key) = &config.encryption_key {
+        info!("Upload Encryption: AES-256-GCM with key {}", key.id());
+    }
+    if !config.notifiers.is_empty() {
+        let notifiers: Vec<String> = config.notifiers.iter().map(|n| n.to_string()).collect();
+        info!("Notifiers: {}", notifiers.join(", "));
+    }
     info!("Non-Interactive: {}", cli.is_non_interactive());
     info!("Skip Download: {}", cli.should_skip_download());
     info!("Skip Extract: {}", cli.should_skip_extract());
@@ -102,10 +210,29 @@ pub fn print_startup_info(config: &Config, cli: &crate::cli::Cli) {
     info!("========================");
 }
 
-pub fn print_final_stats(stats: &UploadStats) {
+pub fn print_final_stats(
+    stats: &UploadStats,
+    lifetime: Option<&(u64, UploadStats)>,
+    timings: &PhaseTimings,
+) {
     info!("=== Final Statistics ===");
     info!("Total Uploaded: {}", stats.total_uploaded);
+    info!("Total Reused: {}", stats.total_reused);
     info!("Total Failed: {}", stats.total_failed);
     info!("Total Bytes: {} bytes", stats.total_bytes_uploaded);
+    if let Some((runs, totals)) = lifetime {
+        info!("--- Lifetime ({} runs) ---", runs);
+        info!("Total Uploaded: {}", totals.total_uploaded);
+        info!("Total Reused: {}", totals.total_reused);
+        info!("Total Failed: {}", totals.total_failed);
+        info!("Total Bytes: {} bytes", totals.total_bytes_uploaded);
+    }
+    let timings = timings.summary();
+    if !timings.is_empty() {
+        info!("--- Phase Timings ---");
+        for line in timings {
+            info!("{}", line);
+        }
+    }
     info!("========================");
 }
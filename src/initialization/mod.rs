@@ -31,12 +31,14 @@ pub enum InitializationError {
 
 pub type InitializationResult<T> = Result<T, InitializationError>;
 
-pub use database_init::{initialize_cid_db, initialize_whosonfirst_db};
+pub use database_init::{
+    initialize_cid_db, initialize_claims_db, initialize_whosonfirst_db, open_whosonfirst_db,
+};
 pub use directories_init::ensure_directories;
-pub use download_init::ensure_database_is_present;
+pub use download_init::{ensure_database_is_present, DatabaseDownload};
 pub use init::{
     initialize_country_service, initialize_extraction_service, initialize_area_upload_service,
     initialize_storage_service, print_final_stats, print_startup_info,
 };
-pub use tools_init::ensure_required_tools;
+pub use tools_init::{ensure_required_tools, ToolInstall};
 pub use validation_init::validate_config;
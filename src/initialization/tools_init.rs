@@ -1,11 +1,261 @@
 use crate::config::Config;
-use tracing::info;
+use crate::types::Compression;
+use crate::utils::{download_file_with_progress, http_client, is_tool_available, run_command};
+use reqwest::header::HeaderMap;
+use std::fs::{File, Permissions};
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tracing::{info, warn};
 
 use super::InitializationResult;
 
-pub async fn ensure_required_tools(config: &Config) -> InitializationResult<()> {
+/// go-pmtiles release installed into TOOLS_DIR when pmtiles is missing
+const PMTILES_RELEASE: &str = "1.22.1";
+
+/// What to do when pmtiles is missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToolInstall {
+    /// Download it without asking
+    Auto,
+    /// Ask on the terminal first
+    Ask,
+    /// Report it as missing
+    Never,
+}
+
+impl ToolInstall {
+    /// `--auto-install` downloads without asking, `--non-interactive` never downloads
+    pub fn from_cli(cli: &crate::cli::Cli) -> Self {
+        if cli.should_auto_install() {
+            Self::Auto
+        } else if !cli.is_non_interactive() {
+            Self::Ask
+        } else {
+            Self::Never
+        }
+    }
+}
+
+/// Check the external tools are present. A missing pmtiles is replaced by the one in
+/// TOOLS_DIR, downloaded first if `install` allows it, and `config` points at it.
+pub async fn ensure_required_tools(
+    config: &mut Config,
+    install: ToolInstall,
+) -> InitializationResult<()> {
     info!("Ensuring required tools are present");
-    crate::utils::ensure_tools_are_present(&[&config.bzip2_cmd, &config.pmtiles_cmd]).await?;
+    ensure_pmtiles_is_present(config, install).await?;
+
+    let mut tools = vec![config.pmtiles_cmd.as_str()];
+    if config.upload_compression == Compression::Zstd {
+        tools.push(&config.zstd_cmd);
+    }
+    crate::utils::ensure_tools_are_present(&tools).await?;
+    match crate::utils::ensure_pmtiles_version(&config.pmtiles_cmd).await? {
+        Some(version) => info!("Using pmtiles {}", version),
+        None => warn!(
+            "Could not read the version of {}, assuming it is {} or later",
+            config.pmtiles_cmd,
+            crate::utils::MIN_PMTILES_VERSION
+        ),
+    }
     info!("All required tools are present");
     Ok(())
 }
+
+/// Point `config` at the pmtiles of TOOLS_DIR when PMTILES_CMD is missing, installing it
+/// first when needed. Left as it is when no release fits this platform or the install is
+/// declined, for the tools check to report.
+async fn ensure_pmtiles_is_present(
+    config: &mut Config,
+    install: ToolInstall,
+) -> InitializationResult<()> {
+    if is_tool_available(&config.pmtiles_cmd).await {
+        return Ok(());
+    }
+
+    let installed = config.tools_dir.join("pmtiles");
+    let installed_cmd = installed.to_string_lossy().to_string();
+    if !is_tool_available(&installed_cmd).await {
+        let Some(asset) = pmtiles_release_asset(std::env::consts::OS, std::env::consts::ARCH)
+        else {
+            warn!(
+                "{} not found and go-pmtiles publishes no release for {} {}",
+                config.pmtiles_cmd,
+                std::env::consts::OS,
+                std::env::consts::ARCH
+            );
+            return Ok(());
+        };
+
+        let confirmed = match install {
+            ToolInstall::Auto => true,
+            ToolInstall::Ask => {
+                print!(
+                    "{} not found. Do you want to download pmtiles {} into {}? (y/n) ",
+                    config.pmtiles_cmd,
+                    PMTILES_RELEASE,
+                    config.tools_dir.display()
+                );
+                io::stdout().flush()?;
+
+                let mut input = String::new();
+                io::stdin().read_line(&mut input)?;
+                input.trim().to_lowercase() == "y"
+            }
+            ToolInstall::Never => false,
+        };
+        if !confirmed {
+            return Ok(());
+        }
+        install_pmtiles(config, &asset, &installed).await?;
+    }
+
+    info!(
+        "{} not found, using {}",
+        config.pmtiles_cmd,
+        installed.display()
+    );
+    config.pmtiles_cmd = installed_cmd;
+    Ok(())
+}
+
+/// Name of the go-pmtiles release archive for a platform, as `std::env::consts` reports it
+fn pmtiles_release_asset(os: &str, arch: &str) -> Option<String> {
+    let arch = match arch {
+        "x86_64" => "x86_64",
+        "aarch64" => "arm64",
+        _ => return None,
+    };
+    match os {
+        "linux" => Some(format!(
+            "go-pmtiles_{}_Linux_{}.tar.gz",
+            PMTILES_RELEASE, arch
+        )),
+        "macos" => Some(format!(
+            "go-pmtiles-{}_Darwin_{}.zip",
+            PMTILES_RELEASE, arch
+        )),
+        _ => None,
+    }
+}
+
+/// Download a go-pmtiles release archive into TOOLS_DIR and unpack its binary to `installed`
+async fn install_pmtiles(
+    config: &Config,
+    asset: &str,
+    installed: &Path,
+) -> InitializationResult<()> {
+    tokio::fs::create_dir_all(&config.tools_dir).await?;
+    let url = format!(
+        "https://github.com/protomaps/go-pmtiles/releases/download/v{}/{}",
+        PMTILES_RELEASE, asset
+    );
+    let archive = config.tools_dir.join(asset);
+
+    info!("Downloading pmtiles {} from {}...", PMTILES_RELEASE, url);
+    let client = http_client(config.proxy_url.as_deref(), &HeaderMap::new())?;
+    download_file_with_progress(&client, &[url], &archive, None, 1).await?;
+
+    // macOS releases are zip archives, unzip ships with the system
+    let result: InitializationResult<()> = match asset.ends_with(".zip") {
+        true => {
+            let archive = archive.to_string_lossy();
+            let tools_dir = config.tools_dir.to_string_lossy();
+            run_command(
+                "unzip",
+                &["-o", "-j", &archive, "pmtiles", "-d", &tools_dir],
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(Into::into)
+        }
+        false => {
+            let (archive, installed) = (archive.clone(), installed.to_path_buf());
+            tokio::task::spawn_blocking(move || unpack_pmtiles(&archive, &installed))
+                .await
+                .map_err(io::Error::other)
+                .and_then(|result| result)
+                .map_err(Into::into)
+        }
+    };
+    tokio::fs::remove_file(&archive).await?;
+    result?;
+
+    info!(
+        "Installed pmtiles {} to {}",
+        PMTILES_RELEASE,
+        installed.display()
+    );
+    Ok(())
+}
+
+/// Write the `pmtiles` binary of a release tarball to `installed`, executable
+fn unpack_pmtiles(archive: &Path, installed: &Path) -> io::Result<()> {
+    let mut tarball = tar::Archive::new(flate2::read::GzDecoder::new(File::open(archive)?));
+    for entry in tarball.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.file_name() != Some("pmtiles".as_ref()) {
+            continue;
+        }
+
+        let partial = installed.with_extension("part");
+        let mut file = File::create(&partial)?;
+        io::copy(&mut entry, &mut file)?;
+        file.set_permissions(Permissions::from_mode(0o755))?;
+        std::fs::rename(&partial, installed)?;
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} holds no pmtiles binary", archive.display()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_release_of_the_host_platform() {
+        assert_eq!(
+            pmtiles_release_asset("linux", "x86_64").as_deref(),
+            Some("go-pmtiles_1.22.1_Linux_x86_64.tar.gz")
+        );
+        assert_eq!(
+            pmtiles_release_asset("macos", "aarch64").as_deref(),
+            Some("go-pmtiles-1.22.1_Darwin_arm64.zip")
+        );
+        assert_eq!(pmtiles_release_asset("linux", "riscv64"), None);
+        assert_eq!(pmtiles_release_asset("freebsd", "x86_64"), None);
+    }
+
+    #[test]
+    fn unpacks_the_binary_of_a_release_tarball() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = dir.path().join("release.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&archive).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut builder = tar::Builder::new(encoder);
+        for (name, content) in [("LICENSE", "license"), ("pmtiles", "binary")] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap();
+
+        let installed = dir.path().join("pmtiles");
+        unpack_pmtiles(&archive, &installed).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&installed).unwrap(), "binary");
+        let mode = std::fs::metadata(&installed).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o755);
+    }
+}
@@ -1,26 +1,31 @@
 pub mod app;
 pub mod cli;
+pub mod commands;
 pub mod config;
 pub mod initialization;
 pub mod services;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
 pub mod types;
 pub mod utils;
 
-pub use app::{ApplicationError, ApplicationResult, NodeRunner};
+pub use app::{AnyNode, AnyNodeBuilder, ApplicationError, ApplicationResult, NodeRunner};
 pub use cli::Cli;
 pub use config::{Config, ConfigError};
 pub use initialization::{
     ensure_database_is_present, ensure_directories, ensure_required_tools, initialize_cid_db,
     initialize_country_service, initialize_extraction_service, initialize_area_upload_service,
     initialize_storage_service, initialize_whosonfirst_db, print_final_stats, print_startup_info,
-    validate_config, InitializationError, InitializationResult,
+    validate_config, DatabaseDownload, InitializationError, InitializationResult, ToolInstall,
 };
 pub use services::{
     AreaUploadError, AreaUploadService, CountryService, DatabaseError, DatabaseService,
-    DownloadResult, ExtractionError, ExtractionService, NodeInfo, StorageError, StorageService,
-    StorageStatus, UploadResult,
+    DownloadResult, ExtractionError, ExtractionService, NodeInfo, PeerEntry, StorageBackend,
+    StorageError, StorageStatus, StorageUsage, UploadResult,
 };
+#[cfg(feature = "storage-bindings")]
+pub use services::StorageService;
 pub use types::{
-    AdministrativeArea, AreaInfo, CompletedUpload, PaginatedAreasResult, PaginationInfo,
-    PendingUpload, UploadQueue, UploadStats,
+    AdministrativeArea, AreaInfo, CompletedUpload, CountryPriority, PaginatedAreasResult,
+    PaginationInfo, PendingUpload, UploadQueue, UploadStats,
 };
@@ -1,22 +1,35 @@
-use anynode::app::NodeRunner;
+use anynode::app::{
+    start_db_snapshot_publisher, start_events_server, start_node_supervisor, start_peer_watch,
+    start_progress_events, start_spr_file_writer, wait_for_shutdown_signal, AnyNode,
+    AnyNodeBuilder, AreaCatalog, ExitStatus, Notifiers, SystemdNotifier, TuiLogLayer, TuiState,
+};
 use anynode::cli::Cli;
+use anynode::commands::dispatch;
 use anynode::config::Config;
-use anynode::initialization::{
-    ensure_database_is_present, ensure_directories, ensure_required_tools, initialize_cid_db,
-    initialize_country_service, initialize_extraction_service, initialize_area_upload_service,
-    initialize_storage_service, initialize_whosonfirst_db, print_startup_info, validate_config,
-};
+use anynode::services::PauseService;
+use anynode::initialization::{print_startup_info, DatabaseDownload, ToolInstall};
+use std::process::ExitCode;
 use std::sync::Arc;
-use tokio::signal;
 use tracing::{error, info};
 use tracing_indicatif::IndicatifLayer;
+use tracing_subscriber::filter::filter_fn;
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::fmt;
-use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
 use tracing_subscriber::util::SubscriberInitExt;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> ExitCode {
+    match run().await {
+        Ok(status) => status.into(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitStatus::from_error(e.as_ref()).into()
+        }
+    }
+}
+
+async fn run() -> Result<ExitStatus, Box<dyn std::error::Error>> {
     let cli = Cli::parse_args();
 
     let log_level = cli.get_log_level();
@@ -26,108 +39,190 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Set up tracing with indicatif layer to keep progress bar visible
     let indicatif_layer = IndicatifLayer::new();
 
+    // The dashboard collects warnings and errors itself and hides log lines while drawn
+    let tui = (cli.tui && cli.command.is_none()).then(TuiState::new);
+    let dashboard = tui.clone();
+    let show_logs = filter_fn(move |_| !dashboard.as_ref().is_some_and(TuiState::is_active));
+
     tracing_subscriber::registry()
         .with(filter)
-        .with(fmt::layer().with_writer(indicatif_layer.get_stderr_writer()))
+        .with(
+            fmt::layer()
+                .with_writer(indicatif_layer.get_stderr_writer())
+                .with_filter(show_logs),
+        )
         .with(indicatif_layer)
+        .with(tui.clone().map(TuiLogLayer::new))
         .init();
 
-    info!("AnyNode v0.1.0 starting...");
-
-    let config = Config::load()?;
-    let config = Arc::new(config);
-
-    print_startup_info(&config, &cli);
-
-    if let Err(e) = ensure_required_tools(&config).await {
-        error!("Failed to ensure required tools: {}", e);
-        return Err(e.into());
-    }
-
-    if let Err(e) = ensure_database_is_present(&config, &cli).await {
-        error!("Failed to ensure database is present: {}", e);
-        return Err(e.into());
+    if let Some(command) = &cli.command {
+        dispatch(&cli, command).await?;
+        return Ok(ExitStatus::Success);
     }
 
-    if let Err(e) = validate_config(&config) {
-        error!("Configuration validation failed: {}", e);
-        return Err(e.into());
-    }
+    info!("AnyNode v0.1.0 starting...");
 
-    ensure_directories(&config).await?;
-
-    let whosonfirst_db = initialize_whosonfirst_db(&config).await?;
-    let cid_db = initialize_cid_db(&config).await?;
-    let country_service = initialize_country_service();
-    let bootstrap_nodes = cli.get_bootstrap_nodes(config.bootstrap_nodes.clone());
-    let nat = cli.get_nat(config.nat.clone());
-    let listen_addrs = cli.get_listen_addrs(config.listen_addrs.clone());
-    let storage_service = initialize_storage_service(
-        &config,
-        cli.get_port(Some(config.discovery_port)),
-        cli.get_data_dir(Some(config.storage_data_dir.clone())),
-        bootstrap_nodes,
-        Some(nat),
-        Some(listen_addrs),
-    )
-    .await?;
-    let area_ids = cli.get_area_ids(config.area_ids.clone());
-
-    let extraction_service = initialize_extraction_service(&config, whosonfirst_db.clone())?;
-    let upload_service = initialize_area_upload_service(
-        cid_db.clone(),
-        whosonfirst_db.clone(),
-        storage_service.clone(),
-        &config,
-        area_ids.clone(),
-    )?;
-
-    if !area_ids.is_empty() {
-        info!("Processing {} specific area IDs", area_ids.len());
-    } else {
-        info!("Retrieving list of all countries...");
-        let countries = country_service.get_countries_to_process(&config.target_countries);
-        info!("Processing {} countries", countries.len());
+    let mut config = Config::load()?;
+    config.shard = cli.get_shard(config.shard);
+    config.extraction_mode = cli.get_extraction_mode(config.extraction_mode);
+    config.area_ids = cli.get_area_ids(config.area_ids.clone())?;
+    if let Some(port) = cli.get_port(None) {
+        config.discovery_port = port;
     }
-
-    let runner = NodeRunner::new(
-        config.clone(),
-        storage_service.clone(),
-        extraction_service,
-        upload_service,
-        country_service,
-        area_ids,
-        cli.should_skip_extract(),
-    );
-
-    if let Err(e) = runner.run().await {
-        error!("Application error: {}", e);
-        return Err(e.into());
+    if let Some(data_dir) = cli.get_data_dir(None) {
+        config.storage_data_dir = data_dir;
     }
+    config.bootstrap_nodes = cli.get_bootstrap_nodes(config.bootstrap_nodes.clone());
+    config.nat = cli.get_nat(config.nat.clone());
+    config.listen_addrs = cli.get_listen_addrs(config.listen_addrs.clone());
 
-    info!("Press Ctrl+C to stop the node gracefully");
-
-    let monitor_handle = runner.start_monitoring();
+    print_startup_info(&config, &cli);
 
-    tokio::select! {
-        _ = async {
-            signal::ctrl_c().await.expect("Failed to listen for ctrl+c");
-        } => {
-            info!("Received Ctrl+C, shutting down gracefully...");
+    let systemd = SystemdNotifier::from_env();
+    let watchdog_handle = systemd.start_watchdog();
+
+    let builder = AnyNodeBuilder::new(config)
+        .with_database_download(DatabaseDownload::from_cli(&cli))
+        .with_tool_install(ToolInstall::from_cli(&cli))
+        .with_skip_extract(cli.should_skip_extract())
+        .with_fresh(cli.fresh)
+        .with_force(cli.force)
+        .with_connect_peers(cli.connect.clone())
+        .with_systemd(systemd.clone());
+    let builder = match tui {
+        Some(state) => builder.with_tui(state),
+        None => builder,
+    };
+    let AnyNode {
+        config,
+        whosonfirst_db,
+        cid_db,
+        storage_service,
+        events,
+        pause,
+        gossip,
+        runner,
+    } = builder.build().await?;
+
+    let events_handle = match config.events_listen_addr {
+        Some(addr) => {
+            let catalog = AreaCatalog {
+                cid_db: cid_db.clone(),
+                whosonfirst_db,
+            };
+            Some(
+                start_events_server(
+                    addr,
+                    events.clone(),
+                    pause.clone(),
+                    gossip.clone(),
+                    Some(catalog),
+                )
+                .await?,
+            )
         }
-        _ = async {
-            let mut sig_term = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
-                .expect("Failed to setup SIGTERM handler");
-            sig_term.recv().await;
-        } => {
-            info!("Received termination signal, shutting down gracefully...");
+        None => None,
+    };
+    let gossip_handle = gossip.as_ref().map(|gossip| gossip.start(&events));
+    let progress_events_handle = cli
+        .get_progress_events_target()
+        .map(|target| start_progress_events(&events, target));
+    let pause_signals_handle = start_pause_signal_handler(pause.clone())?;
+
+    let spr_file_handle = config
+        .spr_file
+        .clone()
+        .map(|path| start_spr_file_writer(storage_service.clone(), path));
+    let snapshot_handle =
+        start_db_snapshot_publisher(cid_db.clone(), storage_service.clone(), config.clone());
+
+    // Monitor from the start so upload progress and the ETA are visible during the run
+    let monitor_handle = runner.start_monitoring();
+    let supervisor_handle = start_node_supervisor(storage_service.clone());
+    let peer_watch_handle = config.no_peers_alert_after.map(|alert_after| {
+        let targets = config
+            .bootstrap_nodes
+            .iter()
+            .map(|spr| spr.to_string())
+            .chain(cli.connect.iter().cloned())
+            .collect();
+        start_peer_watch(
+            storage_service.clone(),
+            targets,
+            alert_after,
+            Notifiers::from_config(&config),
+        )
+    });
+
+    let status = match runner.run().await {
+        Ok(status) => status,
+        Err(e) => {
+            error!("Application error: {}", e);
+            monitor_handle.abort();
+            supervisor_handle.abort();
+            if let Some(handle) = peer_watch_handle {
+                handle.abort();
+            }
+            return Err(e.into());
         }
-    }
+    };
 
+    info!("Press Ctrl+C to stop the node gracefully");
+
+    wait_for_shutdown_signal().await;
+
+    systemd.stopping();
     monitor_handle.abort();
+    supervisor_handle.abort();
+    if let Some(handle) = peer_watch_handle {
+        handle.abort();
+    }
+    if let Some(handle) = watchdog_handle {
+        handle.abort();
+    }
+    pause_signals_handle.abort();
+    if let Some(handle) = gossip_handle {
+        handle.abort();
+    }
+    if let Some(handle) = events_handle {
+        handle.abort();
+    }
+    if let Some(handle) = spr_file_handle {
+        handle.abort();
+    }
+    if let Some(handle) = snapshot_handle {
+        handle.abort();
+    }
+    if let Some(handle) = progress_events_handle {
+        handle.abort();
+    }
 
     runner.shutdown().await?;
 
     info!("AnyNode shutdown complete");
-    Ok(())
+    Ok(status)
+}
+
+/// SIGUSR1 pauses extraction and uploads, SIGUSR2 resumes them
+fn start_pause_signal_handler(
+    pause: Arc<PauseService>,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sig_pause = signal(SignalKind::user_defined1())?;
+    let mut sig_resume = signal(SignalKind::user_defined2())?;
+
+    Ok(tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(()) = sig_pause.recv() => {
+                    pause.pause();
+                }
+                Some(()) = sig_resume.recv() => {
+                    pause.resume();
+                }
+                else => break,
+            }
+        }
+    }))
 }
@@ -1,9 +1,27 @@
-use crate::services::{DatabaseService, StorageService};
-use crate::types::{CompletedUpload, PendingUpload, UploadQueue, UploadStats};
-use futures::future::join_all;
+use crate::config::Config;
+use crate::services::{
+    BackpressureService, DatabaseError, DatabaseService, EventService, PauseService,
+    StorageBackend, StorageStatus,
+};
+use crate::types::{
+    area_metadata_path, area_parts_dir, AdministrativeArea, AreaMetadata, AreaPart, AreaPartUpload,
+    CompletedExtract,
+    CompletedUpload, Compression, CountryIndexEntry, CountryManifest, CountryUsage, DatasetIndex, ManifestEntry,
+    PendingUpload, PhaseTimings, PublishedIndex,
+    PipelineEvent, PipelineStage, RetentionPolicy, RunPhase, RunStats, SplitAreaManifest, UploadProgress,
+    UploadConcurrency, UploadQueue, UploadStats, AREA_PARTS_EXTENSION, AREA_PARTS_MANIFEST,
+    CUSTOM_AREA_ID_BASE, SPLIT_AREA_INDEX,
+};
+use crate::utils::{
+    format_bytes, payload_cache_key, prepare_payload, sha256_file, EncryptionInfo,
+    EncryptionKey,
+};
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::sync::Mutex;
+use tokio::sync::{mpsc, Mutex};
 use tracing::{error, info, warn};
 
 #[derive(Error, Debug)]
@@ -14,62 +32,312 @@ pub enum AreaUploadError {
     StorageError(#[from] crate::services::StorageError),
     #[error("File error: {0}")]
     FileError(#[from] std::io::Error),
+    #[error("Payload error: {0}")]
+    PayloadError(#[from] crate::utils::PayloadError),
     #[error("Upload queue error: {0}")]
     QueueError(String),
 }
 
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Base delay between upload attempts, multiplied by the attempt number
+const UPLOAD_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Content stored on the node for a local file
+struct StoredContent {
+    cid: String,
+    /// Size of the stored content, after compression and encryption
+    size: u64,
+    /// Whether an earlier CID was reused
+    cached: bool,
+    encryption: Option<EncryptionInfo>,
+}
+
 pub struct AreaUploadService {
     cid_db: Arc<DatabaseService>,
-    whosonfirst_db: Arc<DatabaseService>,
-    storage: Arc<StorageService>,
+    /// Checks area IDs found on disk and describes areas in their metadata sidecar. Without
+    /// it every file named after an ID is uploaded, without a sidecar.
+    whosonfirst_db: Option<Arc<DatabaseService>>,
+    storage: Arc<dyn StorageBackend>,
     upload_queue: Arc<Mutex<UploadQueue>>,
+    concurrency: Mutex<UploadConcurrency>,
     stats: Arc<Mutex<UploadStats>>,
-    areas_dir: std::path::PathBuf,
-    target_countries: Vec<String>,
+    progress: Arc<Mutex<UploadProgress>>,
+    progress_logged_at: Mutex<Instant>,
+    /// Start of the current upload run, set when `process_areas` begins
+    started_at: Mutex<String>,
+    config: Arc<Config>,
     area_ids: Vec<u32>,
+    events: Arc<EventService>,
+    pause: Arc<PauseService>,
+    timings: Arc<Mutex<PhaseTimings>>,
+    /// Countries an interrupted run already uploaded, set when this run keeps a checkpoint
+    checkpoint: Option<HashSet<String>>,
+    backpressure: Option<Arc<BackpressureService>>,
 }
 
 impl AreaUploadService {
     pub fn new(
         cid_db: Arc<DatabaseService>,
-        whosonfirst_db: Arc<DatabaseService>,
-        storage: Arc<StorageService>,
-        areas_dir: std::path::PathBuf,
-        target_countries: Vec<String>,
+        whosonfirst_db: Option<Arc<DatabaseService>>,
+        storage: Arc<dyn StorageBackend>,
+        config: Arc<Config>,
         area_ids: Vec<u32>,
+        events: Arc<EventService>,
     ) -> Self {
         Self {
             cid_db,
             whosonfirst_db,
             storage,
-            upload_queue: Arc::new(Mutex::new(UploadQueue::new(10, 100))),
+            upload_queue: Arc::new(Mutex::new(UploadQueue::new(
+                config.upload_batch_size,
+                config.upload_queue_capacity,
+            ))),
+            concurrency: Mutex::new(UploadConcurrency::new(
+                config.min_concurrent_uploads,
+                config.max_concurrent_uploads,
+                config.upload_slow_after,
+            )),
             stats: Arc::new(Mutex::new(UploadStats::new())),
-            areas_dir,
-            target_countries,
+            progress: Arc::new(Mutex::new(UploadProgress::new())),
+            progress_logged_at: Mutex::new(Instant::now()),
+            started_at: Mutex::new(now_rfc3339()),
+            config,
             area_ids,
+            events,
+            pause: Arc::new(PauseService::new()),
+            timings: Arc::new(Mutex::new(PhaseTimings::new())),
+            checkpoint: None,
+            backpressure: None,
         }
     }
 
+    /// Share a pause switch with the rest of the pipeline
+    pub fn with_pause(mut self, pause: Arc<PauseService>) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Record upload times in timings shared with the rest of the pipeline
+    pub fn with_timings(mut self, timings: Arc<Mutex<PhaseTimings>>) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    /// Keep the run checkpoint in the CID database up to date, skipping the countries an
+    /// interrupted run already uploaded. The checkpoint is cleared once uploads finish.
+    pub fn with_checkpoint(mut self, uploaded_countries: HashSet<String>) -> Self {
+        self.checkpoint = Some(uploaded_countries);
+        self
+    }
+
+    /// Report extracts handed over by the extraction stage as done once they are uploaded,
+    /// see `BackpressureService`
+    pub fn with_backpressure(mut self, backpressure: Arc<BackpressureService>) -> Self {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
     pub async fn process_areas(&self) -> Result<(), AreaUploadError> {
-        if !self.areas_dir.exists() {
-            warn!("Areas directory not found: {:?}", self.areas_dir);
+        let started = Instant::now();
+        if self.checkpoint.is_some() {
+            if let Err(e) = self.cid_db.set_checkpoint_phase(RunPhase::Upload).await {
+                warn!("Failed to update the run checkpoint: {}", e);
+            }
+        }
+        let result = self.process_pending_areas().await;
+        self.timings.lock().await.upload = Some(started.elapsed());
+        if result.is_ok() && self.checkpoint.is_some() {
+            if let Err(e) = self.cid_db.clear_run_checkpoint().await {
+                warn!("Failed to clear the run checkpoint: {}", e);
+            }
+        }
+        result
+    }
+
+    /// Upload extracts as the extraction stage hands them over, until it closes the channel.
+    /// Batches are flushed whenever no other extract is waiting, so uploads overlap
+    /// extraction. Extracts handed over outside upload windows stay on disk for the final
+    /// pass of `process_areas`, which also picks up anything this one missed.
+    pub async fn process_extracts(
+        &self,
+        receiver: mpsc::Receiver<CompletedExtract>,
+    ) -> Result<(), AreaUploadError> {
+        let result = self.stream_extracts(receiver).await;
+        // Nothing handed over from now on is uploaded before the final pass
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.reset();
+        }
+        result
+    }
+
+    async fn stream_extracts(
+        &self,
+        mut receiver: mpsc::Receiver<CompletedExtract>,
+    ) -> Result<(), AreaUploadError> {
+        let mut queued = 0;
+
+        while let Some(extract) = receiver.recv().await {
+            if !self.config.upload_schedule.is_open_now() {
+                self.release_extracts(1);
+                continue;
+            }
+
+            let country_dir = self.config.areas_dir.join(&extract.country_code);
+            let parts_dir = area_parts_dir(&country_dir, extract.area_id as i64);
+            if parts_dir.is_dir() {
+                // Split areas are uploaded right away, not through the queue
+                let result = self
+                    .process_split_area(&parts_dir, &extract.country_code, extract.area_id)
+                    .await;
+                self.release_extracts(1);
+                if result? {
+                    queued += 1;
+                }
+            } else {
+                let file_path = country_dir.join(format!("{}.pmtiles", extract.area_id));
+                let processed = file_path.is_file()
+                    && self
+                        .process_file_for_upload(
+                            &file_path,
+                            &extract.country_code,
+                            extract.area_id,
+                        )
+                        .await?;
+                // Queued extracts are released once their batch is uploaded
+                if processed {
+                    queued += 1;
+                } else {
+                    self.release_extracts(1);
+                }
+            }
+
+            if receiver.is_empty() && !self.upload_queue.lock().await.is_empty() {
+                self.process_upload_queue().await?;
+            }
+        }
+
+        while !self.upload_queue.lock().await.is_empty() {
+            self.process_upload_queue().await?;
+        }
+
+        info!("Processed {} extracts for upload while extraction was running", queued);
+        Ok(())
+    }
+
+    /// Report extracts the upload stage is done with to the extraction stage
+    fn release_extracts(&self, count: usize) {
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.release(count);
+        }
+    }
+
+    async fn process_pending_areas(&self) -> Result<(), AreaUploadError> {
+        *self.started_at.lock().await = now_rfc3339();
+
+        if !self.config.areas_dir.exists() {
+            warn!("Areas directory not found: {:?}", self.config.areas_dir);
             return Ok(());
         }
 
+        if let RetentionPolicy::DeleteAfterDays(days) = self.config.retention_policy {
+            let expired = self.cid_db.get_cid_mappings_older_than(days).await?;
+            self.remove_local_extracts(&expired).await;
+        }
+
+        let (pending_files, pending_bytes, pending_areas) = self.count_pending_uploads().await?;
+        info!(
+            "{} files ({}) waiting for upload",
+            pending_files,
+            format_bytes(pending_bytes)
+        );
+        self.progress.lock().await.start(pending_files, pending_bytes);
+
         if !self.area_ids.is_empty() {
             info!("Processing {} specific area IDs", self.area_ids.len());
             self.process_areas_by_ids().await
         } else {
             info!("Processing areas by country from filesystem");
-            self.process_areas_by_country().await
+            self.process_areas_by_country(&pending_areas).await
+        }
+    }
+
+    /// Files on disk that are in scope and have no CID yet, with their total size and the
+    /// number of areas they make up per country
+    async fn count_pending_uploads(
+        &self,
+    ) -> Result<(u64, u64, HashMap<String, u64>), AreaUploadError> {
+        let mut files = 0;
+        let mut bytes = 0;
+        let mut areas = HashMap::new();
+        let (uploaded_areas, uploaded_parts) = self.cid_db.get_uploaded_keys().await?;
+
+        for country_dir_entry in std::fs::read_dir(&self.config.areas_dir)? {
+            let country_path = country_dir_entry?.path();
+            if !country_path.is_dir() {
+                continue;
+            }
+
+            let Some(country_code) = country_path.file_name().and_then(|name| name.to_str())
+            else {
+                continue;
+            };
+
+            if self.area_ids.is_empty() && !self.config.includes_country(country_code) {
+                continue;
+            }
+
+            for file_entry in std::fs::read_dir(&country_path)? {
+                let file_entry = file_entry?;
+                let file_path = file_entry.path();
+
+                let is_parts_dir = file_path.is_dir()
+                    && file_path.extension().is_some_and(|ext| ext == AREA_PARTS_EXTENSION);
+                let is_extract =
+                    file_path.is_file() && file_path.extension().is_some_and(|ext| ext == "pmtiles");
+                if !is_parts_dir && !is_extract {
+                    continue;
+                }
+
+                let Some(area_id) = parse_area_id(&file_path) else {
+                    continue;
+                };
+
+                if !self.area_ids.is_empty() && !self.area_ids.contains(&area_id) {
+                    continue;
+                }
+
+                if uploaded_areas.contains(&(country_code.to_string(), area_id)) {
+                    continue;
+                }
+
+                *areas.entry(country_code.to_string()).or_insert(0) += 1;
+                if is_parts_dir {
+                    for part in read_area_parts(&file_path).await? {
+                        let key = (country_code.to_string(), area_id, part.index);
+                        if !uploaded_parts.contains(&key) {
+                            files += 1;
+                            bytes += std::fs::metadata(file_path.join(part.file_name()))?.len();
+                        }
+                    }
+                } else {
+                    files += 1;
+                    bytes += file_entry.metadata()?.len();
+                }
+            }
         }
+
+        Ok((files, bytes, areas))
     }
 
-    async fn process_areas_by_country(&self) -> Result<(), AreaUploadError> {
+    async fn process_areas_by_country(
+        &self,
+        pending_areas: &HashMap<String, u64>,
+    ) -> Result<(), AreaUploadError> {
         let mut total_files = 0;
         let mut processed_files = 0;
 
-        for country_dir_entry in std::fs::read_dir(&self.areas_dir)? {
+        for country_dir_entry in std::fs::read_dir(&self.config.areas_dir)? {
             let country_dir = country_dir_entry?;
             let country_path = country_dir.path();
 
@@ -84,18 +352,56 @@ impl AreaUploadService {
                     AreaUploadError::QueueError("Invalid country directory name".to_string())
                 })?;
 
-            if !self.target_countries.is_empty() && !self.target_countries.contains(&country_code.to_string()) {
-                info!("Skipping country directory (not in target list): {}", country_code);
+            if !self.config.includes_country(country_code) {
+                info!("Skipping country directory (not targeted by this node): {}", country_code);
+                continue;
+            }
+
+            if self
+                .checkpoint
+                .as_ref()
+                .is_some_and(|uploaded| uploaded.contains(country_code))
+            {
+                info!("Skipping country {}, uploaded before the interruption", country_code);
                 continue;
             }
 
             info!("Scanning country directory: {}", country_code);
+            let started = Instant::now();
+            self.events.emit(PipelineEvent::CountryStarted {
+                country_code: country_code.to_string(),
+                stage: PipelineStage::Upload,
+                area_count: pending_areas.get(country_code).copied().unwrap_or(0),
+                completed: 0,
+            });
 
             let (country_files, country_processed) = self
                 .process_country_directory(&country_path, country_code)
                 .await?;
             total_files += country_files;
             processed_files += country_processed;
+
+            // Flush the country's remaining uploads so its completion can be reported
+            while !self.upload_queue.lock().await.is_empty() {
+                self.process_upload_queue().await?;
+            }
+            self.timings
+                .lock()
+                .await
+                .record_country_upload(country_code, started.elapsed());
+            self.events.emit(PipelineEvent::CountryCompleted {
+                country_code: country_code.to_string(),
+                stage: PipelineStage::Upload,
+            });
+            if self.checkpoint.is_some() {
+                if let Err(e) = self
+                    .cid_db
+                    .record_checkpoint_country(RunPhase::Upload, country_code)
+                    .await
+                {
+                    warn!("Failed to checkpoint country {}: {}", country_code, e);
+                }
+            }
         }
 
         if !self.upload_queue.lock().await.is_empty() {
@@ -152,6 +458,21 @@ impl AreaUploadService {
             let file_entry = file_entry?;
             let file_path = file_entry.path();
 
+            if file_path.is_dir()
+                && file_path.extension().is_some_and(|ext| ext == AREA_PARTS_EXTENSION)
+            {
+                let Some(area_id) = parse_area_id(&file_path) else {
+                    warn!("Invalid parts directory name: {}", file_path.display());
+                    continue;
+                };
+
+                total_files += 1;
+                if self.process_split_area(&file_path, country_code, area_id).await? {
+                    processed_files += 1;
+                }
+                continue;
+            }
+
             if !file_path.is_file() || file_path.extension().is_none_or(|ext| ext != "pmtiles") {
                 continue;
             }
@@ -167,12 +488,8 @@ impl AreaUploadService {
                 AreaUploadError::QueueError(format!("Invalid area ID in filename: {}", filename))
             })?;
 
-            match self
-                .whosonfirst_db
-                .get_area_by_id(area_id as i64)
-                .await
-            {
-                Ok(Some(_area)) => {
+            match self.is_known_area(country_code, area_id).await {
+                Ok(true) => {
                     if self
                         .process_file_for_upload(&file_path, country_code, area_id)
                         .await?
@@ -180,11 +497,12 @@ impl AreaUploadService {
                         processed_files += 1;
                     }
                 }
-                Ok(None) => {
+                Ok(false) => {
                     warn!(
                         "Area ID {} found in filesystem but not in database, skipping",
                         area_id
                     );
+                    self.record_skipped(&file_path).await;
                 }
                 Err(e) => {
                     error!("Database error checking area {}: {}", area_id, e);
@@ -200,7 +518,7 @@ impl AreaUploadService {
     }
 
     async fn find_and_process_area_file(&self, area_id: u32) -> Result<bool, AreaUploadError> {
-        for country_dir_entry in std::fs::read_dir(&self.areas_dir)? {
+        for country_dir_entry in std::fs::read_dir(&self.config.areas_dir)? {
             let country_dir = country_dir_entry?;
             let country_path = country_dir.path();
 
@@ -208,6 +526,18 @@ impl AreaUploadService {
                 continue;
             }
 
+            let parts_dir = area_parts_dir(&country_path, area_id as i64);
+            if parts_dir.is_dir() {
+                let country_code = country_path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .ok_or_else(|| {
+                        AreaUploadError::QueueError("Invalid country directory name".to_string())
+                    })?;
+
+                return self.process_split_area(&parts_dir, country_code, area_id).await;
+            }
+
             let file_path = country_path.join(format!("{}.pmtiles", area_id));
             if file_path.exists() {
                 let country_code = country_path
@@ -217,17 +547,18 @@ impl AreaUploadService {
                         AreaUploadError::QueueError("Invalid country directory name".to_string())
                     })?;
 
-                match self.whosonfirst_db.get_area_by_id(area_id as i64).await {
-                    Ok(Some(_area)) => {
+                match self.is_known_area(country_code, area_id).await {
+                    Ok(true) => {
                         if self.process_file_for_upload(&file_path, country_code, area_id).await? {
                             return Ok(true);
                         }
                     }
-                    Ok(None) => {
+                    Ok(false) => {
                         warn!(
                             "Area ID {} found in filesystem but not in database, skipping",
                             area_id
                         );
+                        self.record_skipped(&file_path).await;
                     }
                     Err(e) => {
                         error!("Database error checking area {}: {}", area_id, e);
@@ -239,6 +570,34 @@ impl AreaUploadService {
         Ok(false)
     }
 
+    /// Whether an area ID found on disk is a grid cell, a custom region or in the
+    /// WhosOnFirst database, always true without one
+    async fn is_known_area(&self, country_code: &str, area_id: u32) -> Result<bool, DatabaseError> {
+        match &self.whosonfirst_db {
+            Some(_) => Ok(self.find_area(country_code, area_id).await?.is_some()),
+            None => Ok(true),
+        }
+    }
+
+    /// The area an ID found on disk stands for, a custom region, a cell of the extraction
+    /// grid or a WhosOnFirst area
+    async fn find_area(
+        &self,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<Option<AdministrativeArea>, DatabaseError> {
+        if area_id >= CUSTOM_AREA_ID_BASE {
+            return self.cid_db.get_custom_area(area_id).await;
+        }
+        if let Some(cell) = self.config.grid.cell(country_code, area_id) {
+            return Ok(Some(cell));
+        }
+        match &self.whosonfirst_db {
+            Some(db) => db.get_area_by_id(area_id as i64).await,
+            None => Ok(None),
+        }
+    }
+
     async fn process_file_for_upload(
         &self,
         file_path: &std::path::Path,
@@ -271,7 +630,192 @@ impl AreaUploadService {
         Ok(true)
     }
 
+    /// Uploads the parts of a split area that are not uploaded yet, then an index of all
+    /// parts and their CIDs. The area maps to the index CID, so it stays a single entry in
+    /// the CID mappings. Parts uploaded before an interruption are not uploaded again.
+    async fn process_split_area(
+        &self,
+        parts_dir: &std::path::Path,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<bool, AreaUploadError> {
+        if self.cid_db.has_cid_mapping(country_code, area_id).await? {
+            info!("Area {} already uploaded, skipping", area_id);
+            return Ok(false);
+        }
+
+        let parts = read_area_parts(parts_dir).await?;
+        let mut uploaded = self.cid_db.get_area_parts(country_code, area_id).await?;
+
+        info!(
+            "Uploading area {} from country {} in {} parts",
+            area_id,
+            country_code,
+            parts.len()
+        );
+        self.events.emit(PipelineEvent::UploadStarted {
+            country_code: country_code.to_string(),
+            area_id,
+            file_size: parts
+                .iter()
+                .filter_map(|p| std::fs::metadata(parts_dir.join(p.file_name())).ok())
+                .map(|m| m.len())
+                .sum(),
+        });
+
+        for part in parts {
+            if uploaded.iter().any(|u| u.part.index == part.index) {
+                continue;
+            }
+
+            self.wait_for_upload_window().await;
+
+            let part_path = parts_dir.join(part.file_name());
+            let file_size = tokio::fs::metadata(&part_path).await?.len();
+
+            let compression = self.config.upload_compression;
+            match self.upload_with_retries(&part_path, compression, self.encryption_key()).await {
+                Ok(stored) => {
+                    let cached = stored.cached;
+                    let upload = AreaPartUpload {
+                        part,
+                        cid: stored.cid,
+                        file_size: stored.size,
+                        compression,
+                        encryption: stored.encryption,
+                    };
+                    self.cid_db
+                        .record_area_part(country_code, area_id, &upload)
+                        .await?;
+                    let mut progress = self.progress.lock().await;
+                    if cached {
+                        progress.record_skipped(file_size);
+                    } else {
+                        progress.record_uploaded(file_size);
+                    }
+                    uploaded.push(upload);
+                }
+                Err(e) => {
+                    error!("Upload failed for part {} of area {}: {}", part.index, area_id, e);
+                    self.progress.lock().await.record_skipped(file_size);
+                    self.fail_split_area(country_code, area_id, parts_dir, e).await;
+                    return Ok(false);
+                }
+            }
+            self.log_progress().await;
+        }
+
+        uploaded.sort_by_key(|u| u.part.index);
+        let file_size = uploaded.iter().map(|u| u.file_size).sum();
+        let manifest = SplitAreaManifest {
+            area_id,
+            country_code: country_code.to_string(),
+            parts: uploaded,
+        };
+        let index_path = parts_dir.join(SPLIT_AREA_INDEX);
+        let index = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| AreaUploadError::QueueError(e.to_string()))?;
+        tokio::fs::write(&index_path, index).await?;
+
+        // The index stays uncompressed so clients can read the part codecs from it, it is
+        // still encrypted when a key is configured
+        let key = self.encryption_key();
+        let index = match self.upload_with_retries(&index_path, Compression::None, key).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                error!("Upload failed for the index of area {}: {}", area_id, e);
+                self.fail_split_area(country_code, area_id, parts_dir, e).await;
+                return Ok(false);
+            }
+        };
+        let mut upload =
+            CompletedUpload::new(country_code.to_string(), area_id, index.cid, file_size);
+        upload.encryption = index.encryption;
+        if let Some(country_dir) = parts_dir.parent() {
+            let local_size = manifest
+                .parts
+                .iter()
+                .filter_map(|p| std::fs::metadata(parts_dir.join(p.part.file_name())).ok())
+                .map(|m| m.len())
+                .sum();
+            self.upload_metadata(&mut upload, country_dir, local_size).await;
+        }
+        self.batch_update_cid_mappings(std::slice::from_ref(&upload))
+            .await?;
+
+        info!(
+            "Successfully uploaded area {} as {} parts with index CID: {}",
+            area_id,
+            manifest.parts.len(),
+            upload.cid
+        );
+        self.events.emit(PipelineEvent::AreaUploaded {
+            country_code: upload.country_code,
+            area_id,
+            cid: upload.cid,
+            file_size,
+        });
+        self.stats.lock().await.increment_uploaded(file_size);
+
+        Ok(true)
+    }
+
+    /// Record a split area whose part or index upload exhausted its attempts. Parts already
+    /// stored are kept, so a later run only uploads what is missing.
+    async fn fail_split_area(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        parts_dir: &std::path::Path,
+        error: AreaUploadError,
+    ) {
+        self.record_failure(country_code, area_id, parts_dir, &error)
+            .await;
+        self.events.emit(PipelineEvent::UploadFailed {
+            country_code: country_code.to_string(),
+            area_id,
+            error: error.to_string(),
+        });
+        self.stats.lock().await.increment_failed();
+    }
+
+    /// Blocks while the pipeline is paused, while the storage node is down and until the
+    /// current time falls inside an allowed upload window
+    async fn wait_for_upload_window(&self) {
+        self.pause.wait_until_resumed().await;
+        self.wait_for_storage_node().await;
+
+        if self.config.upload_schedule.is_open_now() {
+            return;
+        }
+
+        info!(
+            "Outside upload windows ({}), pausing uploads for about {} minutes",
+            self.config.upload_schedule,
+            self.config.upload_schedule.minutes_until_open_now()
+        );
+
+        // Re-check every minute so clock changes and DST shifts are picked up
+        while !self.config.upload_schedule.is_open_now() {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+        }
+
+        info!("Upload window opened, resuming uploads");
+    }
+
+    /// Blocks until the storage node is started, e.g. while the supervisor restarts it
+    async fn wait_for_storage_node(&self) {
+        if self.storage.get_status().await == StorageStatus::Connected {
+            return;
+        }
+        info!("Storage node is down, pausing uploads until it is back");
+        self.storage.wait_until_connected().await;
+        info!("Storage node is back, resuming uploads");
+    }
+
     async fn process_upload_queue(&self) -> Result<(), AreaUploadError> {
+        self.wait_for_upload_window().await;
+
         let batch = {
             let mut queue = self.upload_queue.lock().await;
             queue.take_batch()
@@ -282,33 +826,101 @@ impl AreaUploadService {
         }
 
         info!("Processing batch of {} uploads", batch.len());
+        let batch_len = batch.len();
 
-        let upload_tasks: Vec<_> = batch
-            .into_iter()
-            .map(|pending| self.upload_single_file(pending))
+        let batch_areas: Vec<_> = batch
+            .iter()
+            .map(|pending| {
+                let file_size = std::fs::metadata(&pending.file_path).map_or(0, |m| m.len());
+                (
+                    pending.country_code.clone(),
+                    pending.area_id,
+                    pending.file_path.clone(),
+                    file_size,
+                )
+            })
             .collect();
 
-        let results = join_all(upload_tasks).await;
+        // Results stay in batch order so they line up with batch_areas
+        let limit = self.concurrency.lock().await.limit();
+        let timed_results: Vec<_> = stream::iter(batch)
+            .map(|pending| async move {
+                let started = Instant::now();
+                let result = self.upload_single_file(pending).await;
+                (result, started.elapsed())
+            })
+            .buffered(limit)
+            .collect()
+            .await;
+
+        let outcomes: Vec<_> = timed_results
+            .iter()
+            .map(|(result, elapsed)| (result.is_ok(), *elapsed))
+            .collect();
+        if let Some(new_limit) = self.concurrency.lock().await.record_batch(&outcomes) {
+            match new_limit < limit {
+                true => warn!(
+                    "Uploads failing or slower than {}s, lowering concurrency from {} to {}",
+                    self.config.upload_slow_after.as_secs(),
+                    limit,
+                    new_limit
+                ),
+                false => info!(
+                    "Uploads healthy, raising concurrency from {} to {}",
+                    limit, new_limit
+                ),
+            }
+        }
+        let results = timed_results.into_iter().map(|(result, _)| result);
 
         let mut successful_uploads = Vec::new();
         let mut failed_count = 0;
 
-        for result in results {
+        for ((country_code, area_id, file_path, file_size), result) in
+            batch_areas.into_iter().zip(results)
+        {
             match result {
-                Ok(upload) => successful_uploads.push(upload),
+                Ok(upload) => successful_uploads.push((upload, file_size)),
                 Err(e) => {
                     error!("Upload failed: {}", e);
+                    self.record_failure(&country_code, area_id, &file_path, &e)
+                        .await;
+                    self.progress.lock().await.record_skipped(file_size);
+                    self.events.emit(PipelineEvent::UploadFailed {
+                        country_code,
+                        area_id,
+                        error: e.to_string(),
+                    });
                     failed_count += 1;
                 }
             }
         }
 
         if !successful_uploads.is_empty() {
-            self.batch_update_cid_mappings(&successful_uploads).await?;
+            let (uploads, local_sizes): (Vec<_>, Vec<_>) =
+                successful_uploads.iter().cloned().unzip();
+            self.batch_update_cid_mappings(&uploads).await?;
+
+            for upload in &uploads {
+                self.events.emit(PipelineEvent::AreaUploaded {
+                    country_code: upload.country_code.clone(),
+                    area_id: upload.area_id,
+                    cid: upload.cid.clone(),
+                    file_size: upload.file_size,
+                });
+            }
 
             let mut stats = self.stats.lock().await;
-            for upload in &successful_uploads {
-                stats.increment_uploaded(upload.file_size);
+            let mut progress = self.progress.lock().await;
+            // Progress is counted in local bytes, upload totals in the bytes stored
+            for (upload, local_size) in uploads.iter().zip(local_sizes) {
+                if upload.cached {
+                    stats.increment_reused();
+                    progress.record_skipped(local_size);
+                } else {
+                    stats.increment_uploaded(upload.file_size);
+                    progress.record_uploaded(local_size);
+                }
             }
         }
 
@@ -319,15 +931,34 @@ impl AreaUploadService {
             }
         }
 
+        self.release_extracts(batch_len);
         info!(
             "Batch completed: {} successful, {} failed",
             successful_uploads.len(),
             failed_count
         );
+        self.log_progress().await;
 
         Ok(())
     }
 
+    /// Count a file that will not be uploaded as done
+    async fn record_skipped(&self, file_path: &std::path::Path) {
+        let file_size = std::fs::metadata(file_path).map_or(0, |m| m.len());
+        self.progress.lock().await.record_skipped(file_size);
+    }
+
+    /// Log throughput and the ETA, at most once per `PROGRESS_LOG_INTERVAL`
+    async fn log_progress(&self) {
+        let mut logged_at = self.progress_logged_at.lock().await;
+        if logged_at.elapsed() < PROGRESS_LOG_INTERVAL {
+            return;
+        }
+        *logged_at = Instant::now();
+
+        info!("Upload progress: {}", self.progress.lock().await);
+    }
+
     async fn upload_single_file(
         &self,
         pending: PendingUpload,
@@ -347,50 +978,483 @@ impl AreaUploadService {
             "Uploading area {} from country {} ({} bytes)",
             pending.area_id, pending.country_code, file_size
         );
+        self.events.emit(PipelineEvent::UploadStarted {
+            country_code: pending.country_code.clone(),
+            area_id: pending.area_id,
+            file_size,
+        });
 
-        let result = self.storage.upload_file(file_path).await.map_err(|e| {
-            error!("Upload failed for area {}: {}", pending.area_id, e);
-            e
-        })?;
+        let compression = self.config.upload_compression;
+        let stored = self
+            .upload_with_retries(file_path, compression, self.encryption_key())
+            .await
+            .map_err(|e| {
+                error!("Upload failed for area {}: {}", pending.area_id, e);
+                e
+            })?;
 
-        let completed_upload = CompletedUpload::new(
+        if stored.cached {
+            info!(
+                "Area {} matches previously uploaded content, reusing CID: {}",
+                pending.area_id, stored.cid
+            );
+        } else {
+            info!("Successfully uploaded area {} with CID: {}", pending.area_id, stored.cid);
+        }
+
+        let mut completed_upload = CompletedUpload::new(
             pending.country_code.clone(),
             pending.area_id,
-            result.cid.clone(),
-            file_size,
-        );
-
-        info!(
-            "Successfully uploaded area {} with CID: {}",
-            pending.area_id, result.cid
+            stored.cid,
+            stored.size,
         );
+        completed_upload.cached = stored.cached;
+        completed_upload.compression = compression;
+        completed_upload.encryption = stored.encryption;
+        if let Some(country_dir) = file_path.parent() {
+            self.upload_metadata(&mut completed_upload, country_dir, file_size)
+                .await;
+        }
 
         Ok(completed_upload)
     }
 
+    /// Upload the metadata sidecar of an uploaded area and record its CID on `upload`.
+    /// A failed sidecar upload is logged and leaves the area without one, it does not fail
+    /// the extract upload. Nothing is uploaded without a WhosOnFirst database.
+    async fn upload_metadata(
+        &self,
+        upload: &mut CompletedUpload,
+        country_dir: &std::path::Path,
+        local_size: u64,
+    ) {
+        let area = match self.find_area(&upload.country_code, upload.area_id).await {
+            Ok(Some(area)) => area,
+            Ok(None) if self.whosonfirst_db.is_none() => return,
+            Ok(None) => {
+                warn!("Area {} not in database, uploading no metadata", upload.area_id);
+                return;
+            }
+            Err(e) => {
+                warn!("Failed to read metadata of area {}: {}", upload.area_id, e);
+                return;
+            }
+        };
+        let extracted_at = self
+            .cid_db
+            .get_extraction_time(&upload.country_code, upload.area_id)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Failed to read extraction time of area {}: {}", upload.area_id, e);
+                None
+            });
+
+        let metadata = AreaMetadata::new(&area, local_size, extracted_at);
+        let path = area_metadata_path(country_dir, upload.area_id);
+        let result = match serde_json::to_vec_pretty(&metadata) {
+            Ok(json) => match tokio::fs::write(&path, json).await {
+                Ok(()) => {
+                    let key = self.encryption_key();
+                    self.upload_with_retries(&path, Compression::None, key).await
+                }
+                Err(e) => Err(e.into()),
+            },
+            Err(e) => Err(AreaUploadError::QueueError(e.to_string())),
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+
+        match result {
+            Ok(stored) => {
+                upload.metadata_cid = Some(stored.cid);
+                upload.metadata_encryption = stored.encryption;
+            }
+            Err(e) => warn!("Metadata upload failed for area {}: {}", upload.area_id, e),
+        }
+    }
+
+    /// `upload_or_reuse`, retried with a growing delay up to the configured attempts.
+    /// Failures while the storage node is down do not count as attempts, the upload waits
+    /// for the node to come back instead.
+    async fn upload_with_retries(
+        &self,
+        file_path: &std::path::Path,
+        compression: Compression,
+        key: Option<&EncryptionKey>,
+    ) -> Result<StoredContent, AreaUploadError> {
+        let mut attempt = 1;
+        loop {
+            match self.upload_or_reuse(file_path, compression, key).await {
+                Ok(result) => return Ok(result),
+                Err(e) if self.storage.get_status().await != StorageStatus::Connected => {
+                    warn!("Upload of {} interrupted: {}", file_path.display(), e);
+                    self.wait_for_storage_node().await;
+                }
+                Err(e) if attempt < self.config.upload_max_attempts => {
+                    warn!(
+                        "Upload attempt {}/{} failed for {}: {}",
+                        attempt,
+                        self.config.upload_max_attempts,
+                        file_path.display(),
+                        e
+                    );
+                    tokio::time::sleep(UPLOAD_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Keep an upload that exhausted its attempts for `retry-failed`
+    async fn record_failure(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        path: &std::path::Path,
+        error: &AreaUploadError,
+    ) {
+        if let Err(e) = self
+            .cid_db
+            .record_failed_upload(
+                country_code,
+                area_id,
+                &path.to_string_lossy(),
+                &error.to_string(),
+                self.config.upload_max_attempts,
+            )
+            .await
+        {
+            warn!("Failed to record failed upload of area {}: {}", area_id, e);
+        }
+    }
+
+    /// Upload a file, compressed with `compression` and encrypted with `key`, unless
+    /// byte-identical content was uploaded before with the same codec and key
+    async fn upload_or_reuse(
+        &self,
+        file_path: &std::path::Path,
+        compression: Compression,
+        key: Option<&EncryptionKey>,
+    ) -> Result<StoredContent, AreaUploadError> {
+        let cache_key = payload_cache_key(&sha256_file(file_path).await?, compression, key);
+        let path = file_path.to_string_lossy();
+
+        if let Some((cid, size)) = self.cid_db.get_cached_cid(&cache_key, &path).await? {
+            // The node may have lost the content since, e.g. after its data dir was reset
+            match self.storage.has_content(&cid).await {
+                Ok(true) => {
+                    // Nonces derive from the cache key, so they are the same as on upload
+                    return Ok(StoredContent {
+                        cid,
+                        size,
+                        cached: true,
+                        encryption: key.map(|key| key.info_for(&cache_key)),
+                    });
+                }
+                Ok(false) => warn!(
+                    "Cached CID {} for {} is no longer held by the node, uploading again",
+                    cid,
+                    file_path.display()
+                ),
+                Err(e) => warn!(
+                    "Could not check cached CID {} for {}, uploading again: {}",
+                    cid,
+                    file_path.display(),
+                    e
+                ),
+            }
+            self.cid_db
+                .delete_cache_entries(std::slice::from_ref(&cache_key))
+                .await?;
+        }
+
+        let payload =
+            prepare_payload(file_path, compression, key, &cache_key, &self.config.zstd_cmd)
+                .await?;
+        let result = self.storage.upload_file(&payload.path).await;
+        let encryption = payload.encryption.clone();
+        payload.remove().await;
+        let result = result?;
+
+        self.cid_db
+            .insert_cache_entry(&cache_key, &result.cid, result.size, &path)
+            .await?;
+
+        Ok(StoredContent {
+            cid: result.cid,
+            size: result.size,
+            cached: false,
+            encryption,
+        })
+    }
+
+    /// Key uploads are encrypted with, `None` when encryption is off
+    fn encryption_key(&self) -> Option<&EncryptionKey> {
+        self.config.encryption_key.as_ref()
+    }
+
     async fn batch_update_cid_mappings(
         &self,
         uploads: &[CompletedUpload],
     ) -> Result<(), AreaUploadError> {
-        let mappings: Vec<_> = uploads
-            .iter()
-            .map(|upload| {
-                (
-                    upload.country_code.clone(),
-                    upload.area_id,
-                    upload.cid.clone(),
-                    upload.file_size,
-                )
-            })
-            .collect();
+        self.cid_db.batch_insert_cid_mappings(uploads).await?;
 
-        self.cid_db.batch_insert_cid_mappings(&mappings).await?;
+        info!("Updated {} CID mappings in database", uploads.len());
 
-        info!("Updated {} CID mappings in database", mappings.len());
+        if self.config.retention_policy == RetentionPolicy::DeleteAfterUpload {
+            self.remove_local_extracts(uploads).await;
+        }
         Ok(())
     }
 
+    /// Delete the local extracts or parts directories of areas whose CID mapping is
+    /// recorded. A file that cannot be removed is left for the next run.
+    async fn remove_local_extracts(&self, uploads: &[CompletedUpload]) {
+        let mut removed = 0;
+        let mut freed = 0;
+
+        for upload in uploads {
+            let country_dir = self.config.areas_dir.join(&upload.country_code);
+            let extract = country_dir.join(format!("{}.pmtiles", upload.area_id));
+            let parts_dir = area_parts_dir(&country_dir, upload.area_id as i64);
+
+            let result = if extract.is_file() {
+                tokio::fs::remove_file(&extract).await
+            } else if parts_dir.is_dir() {
+                tokio::fs::remove_dir_all(&parts_dir).await
+            } else {
+                continue;
+            };
+
+            match result {
+                Ok(()) => {
+                    removed += 1;
+                    freed += upload.file_size;
+                }
+                Err(e) => warn!(
+                    "Failed to remove local extract of area {}: {}",
+                    upload.area_id, e
+                ),
+            }
+        }
+
+        if removed > 0 {
+            info!(
+                "Removed {} uploaded extracts ({}) per the {} retention policy",
+                removed,
+                format_bytes(freed),
+                self.config.retention_policy
+            );
+        }
+    }
+
+    /// Re-drive every upload recorded as failed. Entries whose area has been uploaded since
+    /// or whose file is gone are dropped. Returns the number of uploads attempted.
+    pub async fn retry_failed(&self) -> Result<usize, AreaUploadError> {
+        let failed = self.cid_db.get_failed_uploads().await?;
+        let mut attempted = 0;
+
+        for entry in failed {
+            if self.cid_db.has_cid_mapping(&entry.country_code, entry.area_id).await? {
+                info!("Area {} has been uploaded since it failed", entry.area_id);
+                self.cid_db
+                    .delete_failed_upload(&entry.country_code, entry.area_id)
+                    .await?;
+                continue;
+            }
+
+            if !entry.path.exists() {
+                warn!(
+                    "Dropping failed upload of area {}, {} no longer exists",
+                    entry.area_id,
+                    entry.path.display()
+                );
+                self.cid_db
+                    .delete_failed_upload(&entry.country_code, entry.area_id)
+                    .await?;
+                continue;
+            }
+
+            info!(
+                "Retrying area {} from country {} ({} previous attempts)",
+                entry.area_id, entry.country_code, entry.attempts
+            );
+            attempted += 1;
+            if entry.path.is_dir() {
+                self.process_split_area(&entry.path, &entry.country_code, entry.area_id)
+                    .await?;
+            } else {
+                self.process_file_for_upload(&entry.path, &entry.country_code, entry.area_id)
+                    .await?;
+            }
+        }
+
+        while !self.upload_queue.lock().await.is_empty() {
+            self.process_upload_queue().await?;
+        }
+
+        Ok(attempted)
+    }
+
+    /// Shared view of the remaining work and throughput, for progress displays
+    pub fn progress(&self) -> Arc<Mutex<UploadProgress>> {
+        self.progress.clone()
+    }
+
     pub async fn get_stats(&self) -> UploadStats {
         self.stats.lock().await.clone()
     }
+
+    pub async fn get_timings(&self) -> PhaseTimings {
+        self.timings.lock().await.clone()
+    }
+
+    /// Persist this run's statistics and return the run count and totals across all runs
+    pub async fn record_run(&self) -> Result<(u64, UploadStats), AreaUploadError> {
+        let run = RunStats {
+            started_at: self.started_at.lock().await.clone(),
+            finished_at: now_rfc3339(),
+            stats: self.get_stats().await,
+        };
+        self.cid_db.record_run_stats(&run).await?;
+        Ok(self.cid_db.get_lifetime_stats().await?)
+    }
+
+    pub async fn get_bytes_by_country(&self) -> Result<Vec<CountryUsage>, AreaUploadError> {
+        Ok(self.cid_db.get_bytes_by_country().await?)
+    }
+
+    /// Upload a manifest of each country's areas, then an index mapping every country to
+    /// its manifest CID, and record the index CID as the root of the dataset. `None` when
+    /// nothing is uploaded yet. Manifests and index are never encrypted: they hold CIDs and
+    /// encryption parameters but no map data, and clients need them to find anything.
+    pub async fn publish_index(&self) -> Result<Option<PublishedIndex>, AreaUploadError> {
+        let mappings = self.cid_db.get_cid_mappings(None).await?;
+        if mappings.is_empty() {
+            return Ok(None);
+        }
+
+        let (_, parts) = self.cid_db.get_uploaded_keys().await?;
+        let split: HashSet<(String, u32)> = parts
+            .into_iter()
+            .map(|(country_code, area_id, _)| (country_code, area_id))
+            .collect();
+
+        let mut manifests: BTreeMap<String, CountryManifest> = BTreeMap::new();
+        for mapping in &mappings {
+            let is_split = split.contains(&(mapping.country_code.clone(), mapping.area_id));
+            manifests
+                .entry(mapping.country_code.clone())
+                .or_insert_with(|| CountryManifest {
+                    country_code: mapping.country_code.clone(),
+                    areas: Vec::new(),
+                })
+                .areas
+                .push(ManifestEntry::new(mapping, is_split));
+        }
+
+        let work_dir =
+            std::env::temp_dir().join(format!("anynode-index-{}", uuid::Uuid::new_v4()));
+        tokio::fs::create_dir_all(&work_dir).await?;
+        let result = self.upload_dataset_index(&work_dir, manifests).await;
+        let _ = tokio::fs::remove_dir_all(&work_dir).await;
+        let (root_cid, index) = result?;
+
+        let published = PublishedIndex {
+            root_cid,
+            country_count: index.countries.len() as u64,
+            area_count: mappings.len() as u64,
+            published_at: index.generated_at,
+        };
+        self.cid_db
+            .record_published_index(
+                &published.root_cid,
+                published.country_count,
+                published.area_count,
+            )
+            .await?;
+
+        Ok(Some(published))
+    }
+
+    /// CID of the manifest's whole-country extract, found by the ID of the country's own
+    /// WhosOnFirst record. `None` without one or without a WhosOnFirst database.
+    async fn country_extract_cid(&self, manifest: &CountryManifest) -> Option<String> {
+        let whosonfirst_db = self.whosonfirst_db.as_ref()?;
+        let country = match whosonfirst_db.get_country_record(&manifest.country_code).await {
+            Ok(country) => country?,
+            Err(e) => {
+                warn!("Failed to look up the {} record: {}", manifest.country_code, e);
+                return None;
+            }
+        };
+        manifest
+            .areas
+            .iter()
+            .find(|entry| entry.area_id as i64 == country.id)
+            .map(|entry| entry.cid.clone())
+    }
+
+    /// Upload the country manifests and the index listing them from `work_dir`, returning
+    /// the index CID and the index itself
+    async fn upload_dataset_index(
+        &self,
+        work_dir: &std::path::Path,
+        manifests: BTreeMap<String, CountryManifest>,
+    ) -> Result<(String, DatasetIndex), AreaUploadError> {
+        let mut index = DatasetIndex {
+            generated_at: now_rfc3339(),
+            countries: BTreeMap::new(),
+        };
+
+        for (country_code, manifest) in manifests {
+            let path = work_dir.join(format!("{}.json", country_code));
+            write_json(&path, &manifest).await?;
+            let stored = self.upload_with_retries(&path, Compression::None, None).await?;
+            let country_cid = self.country_extract_cid(&manifest).await;
+            index.countries.insert(
+                country_code,
+                CountryIndexEntry {
+                    manifest_cid: stored.cid,
+                    area_count: manifest.areas.len() as u64,
+                    total_bytes: manifest.total_bytes(),
+                    country_cid,
+                },
+            );
+        }
+
+        let path = work_dir.join("index.json");
+        write_json(&path, &index).await?;
+        let stored = self.upload_with_retries(&path, Compression::None, None).await?;
+
+        Ok((stored.cid, index))
+    }
+}
+
+fn now_rfc3339() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+async fn write_json<T: serde::Serialize>(
+    path: &std::path::Path,
+    value: &T,
+) -> Result<(), AreaUploadError> {
+    let json = serde_json::to_vec_pretty(value)
+        .map_err(|e| AreaUploadError::QueueError(e.to_string()))?;
+    tokio::fs::write(path, json).await?;
+    Ok(())
+}
+
+/// Area ID from an extract or parts directory name, `<id>.pmtiles` or `<id>.parts`
+fn parse_area_id(path: &std::path::Path) -> Option<u32> {
+    path.file_stem()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.parse().ok())
+}
+
+async fn read_area_parts(parts_dir: &std::path::Path) -> Result<Vec<AreaPart>, AreaUploadError> {
+    let manifest = tokio::fs::read_to_string(parts_dir.join(AREA_PARTS_MANIFEST)).await?;
+    serde_json::from_str(&manifest).map_err(|e| {
+        AreaUploadError::QueueError(format!("Invalid {}: {}", parts_dir.display(), e))
+    })
 }
@@ -0,0 +1,207 @@
+use crate::config::Config;
+use crate::utils::{available_space, format_bytes};
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// How often extraction re-checks free space while it waits for uploads to free some
+const DISK_RECHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Extracts handed to the upload stage and not uploaded yet
+#[derive(Debug, Clone, Copy, Default)]
+struct Backlog {
+    pending: usize,
+    throttled: bool,
+}
+
+/// Holds extraction back when uploads cannot keep up. Once the backlog of extracts waiting
+/// for upload reaches the high watermark, new extractions wait until uploads bring it down
+/// to the low watermark. They also wait while the areas volume has less free space than
+/// the configured minimum and uploads are still pending that could free some.
+#[derive(Clone)]
+pub struct BackpressureService {
+    backlog: watch::Sender<Backlog>,
+    high_watermark: usize,
+    low_watermark: usize,
+    areas_dir: PathBuf,
+    min_free_space: Option<u64>,
+}
+
+impl BackpressureService {
+    pub fn new(config: &Config) -> Self {
+        let (backlog, _) = watch::channel(Backlog::default());
+        Self {
+            backlog,
+            high_watermark: config.upload_backlog_high,
+            low_watermark: config.upload_backlog_low,
+            areas_dir: config.areas_dir.clone(),
+            min_free_space: config.extraction_min_free_space,
+        }
+    }
+
+    /// Extracts that may wait for upload before extraction is held back
+    pub fn high_watermark(&self) -> usize {
+        self.high_watermark
+    }
+
+    /// Count an extract handed to the upload stage
+    pub fn add(&self) {
+        let high_watermark = self.high_watermark;
+        self.backlog.send_if_modified(|backlog| {
+            backlog.pending += 1;
+            if backlog.throttled || backlog.pending < high_watermark {
+                return false;
+            }
+            backlog.throttled = true;
+            info!(
+                "{} extracts waiting for upload, holding extraction back until uploads catch up",
+                backlog.pending
+            );
+            true
+        });
+    }
+
+    /// Count `count` extracts the upload stage is done with, uploaded or not
+    pub fn release(&self, count: usize) {
+        let low_watermark = self.low_watermark;
+        self.backlog.send_if_modified(|backlog| {
+            backlog.pending = backlog.pending.saturating_sub(count);
+            if !backlog.throttled || backlog.pending > low_watermark {
+                return false;
+            }
+            backlog.throttled = false;
+            info!(
+                "{} extracts waiting for upload, resuming extraction",
+                backlog.pending
+            );
+            true
+        });
+    }
+
+    /// Forget the backlog once the upload stage stops taking extracts, so extraction is
+    /// never left waiting on uploads that will not happen
+    pub fn reset(&self) {
+        self.backlog.send_if_modified(|backlog| {
+            let throttled = backlog.throttled;
+            *backlog = Backlog::default();
+            throttled
+        });
+    }
+
+    pub fn pending(&self) -> usize {
+        self.backlog.borrow().pending
+    }
+
+    pub fn is_throttled(&self) -> bool {
+        self.backlog.borrow().throttled
+    }
+
+    /// Returns once another extraction may start, immediately when uploads keep up
+    pub async fn wait_for_capacity(&self) {
+        let mut receiver = self.backlog.subscribe();
+        // The sender lives in self, so the channel cannot close while we wait
+        let _ = receiver.wait_for(|backlog| !backlog.throttled).await;
+
+        let Some(min_free_space) = self.min_free_space else {
+            return;
+        };
+        let mut logged = false;
+        loop {
+            let available = match available_space(&self.areas_dir) {
+                Ok(available) => available,
+                Err(e) => {
+                    warn!(
+                        "Failed to check free space of {}: {}",
+                        self.areas_dir.display(),
+                        e
+                    );
+                    return;
+                }
+            };
+            // Waiting only helps while uploads are pending that may free space
+            if available >= min_free_space || self.pending() == 0 {
+                if logged {
+                    info!("Free space recovered, resuming extraction");
+                }
+                return;
+            }
+            if !logged {
+                info!(
+                    "Only {} free on the areas volume, below the {} minimum, holding extraction \
+                     back until uploads free some",
+                    format_bytes(available),
+                    format_bytes(min_free_space)
+                );
+                logged = true;
+            }
+            tokio::time::sleep(DISK_RECHECK_INTERVAL).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn backpressure(high_watermark: usize, low_watermark: usize) -> BackpressureService {
+        let (backlog, _) = watch::channel(Backlog::default());
+        BackpressureService {
+            backlog,
+            high_watermark,
+            low_watermark,
+            areas_dir: PathBuf::from("."),
+            min_free_space: None,
+        }
+    }
+
+    #[test]
+    fn throttles_between_the_watermarks() {
+        let backpressure = backpressure(3, 1);
+        backpressure.add();
+        backpressure.add();
+        assert!(!backpressure.is_throttled());
+        backpressure.add();
+        assert!(backpressure.is_throttled());
+
+        backpressure.release(1);
+        assert!(backpressure.is_throttled());
+        backpressure.release(1);
+        assert!(!backpressure.is_throttled());
+        assert_eq!(backpressure.pending(), 1);
+
+        backpressure.release(5);
+        assert_eq!(backpressure.pending(), 0);
+    }
+
+    #[tokio::test]
+    async fn waiting_blocks_until_the_backlog_drains() {
+        let backpressure = backpressure(2, 0);
+        backpressure.wait_for_capacity().await;
+
+        backpressure.add();
+        backpressure.add();
+        let waiting = tokio::spawn({
+            let backpressure = backpressure.clone();
+            async move { backpressure.wait_for_capacity().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        backpressure.release(2);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    #[test]
+    fn reset_lifts_the_throttle() {
+        let backpressure = backpressure(1, 0);
+        backpressure.add();
+        assert!(backpressure.is_throttled());
+        backpressure.reset();
+        assert!(!backpressure.is_throttled());
+        assert_eq!(backpressure.pending(), 0);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::services::{StorageBackend, StorageError};
+use crate::types::{CountryManifest, DatasetIndex, ManifestEntry};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::sync::Arc;
+use thiserror::Error;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+#[derive(Error, Debug)]
+pub enum CatalogError {
+    #[error("Storage error: {0}")]
+    StorageError(#[from] StorageError),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Invalid manifest {0}: {1}")]
+    InvalidManifest(String, serde_json::Error),
+}
+
+/// Dataset indexes published by trusted nodes, see `AreaUploadService::publish_index`.
+/// Areas they already uploaded are pinned from the network instead of being extracted and
+/// uploaded again.
+pub struct CatalogService {
+    storage: Arc<dyn StorageBackend>,
+    /// Dataset root CIDs, earlier roots win when several hold the same area
+    roots: Vec<String>,
+    /// Fingerprint of UPLOAD_ENCRYPTION_KEY, entries encrypted with another key are ignored
+    key_id: Option<String>,
+    indexes: Mutex<Option<Vec<DatasetIndex>>>,
+    countries: Mutex<HashMap<String, Arc<HashMap<u32, ManifestEntry>>>>,
+}
+
+impl CatalogService {
+    pub fn new(storage: Arc<dyn StorageBackend>, roots: Vec<String>, key_id: Option<String>) -> Self {
+        Self {
+            storage,
+            roots,
+            key_id,
+            indexes: Mutex::new(None),
+            countries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adoptable areas of a country across every trusted root, by area ID. Roots and
+    /// manifests that cannot be read are logged and skipped.
+    pub async fn country_entries(&self, country_code: &str) -> Arc<HashMap<u32, ManifestEntry>> {
+        if let Some(entries) = self.countries.lock().await.get(country_code) {
+            return entries.clone();
+        }
+
+        let manifest_cids: Vec<String> = self
+            .indexes()
+            .await
+            .iter()
+            .filter_map(|index| index.countries.get(country_code))
+            .map(|country| country.manifest_cid.clone())
+            .collect();
+
+        let mut entries = HashMap::new();
+        for manifest_cid in manifest_cids {
+            let manifest: CountryManifest = match self.download_json(&manifest_cid).await {
+                Ok(manifest) => manifest,
+                Err(e) => {
+                    warn!("Skipping the {} manifest {}: {}", country_code, manifest_cid, e);
+                    continue;
+                }
+            };
+            for entry in manifest.areas {
+                if self.is_adoptable(&entry) {
+                    entries.entry(entry.area_id).or_insert(entry);
+                }
+            }
+        }
+
+        let entries = Arc::new(entries);
+        self.countries
+            .lock()
+            .await
+            .insert(country_code.to_string(), entries.clone());
+        entries
+    }
+
+    /// Fetch an area's content and metadata sidecar into the local repo
+    pub async fn pin(&self, entry: &ManifestEntry) -> Result<(), CatalogError> {
+        self.storage.pin_content(&entry.cid).await?;
+        if let Some(metadata_cid) = &entry.metadata_cid {
+            if let Err(e) = self.storage.pin_content(metadata_cid).await {
+                warn!("Failed to pin the metadata of area {}: {}", entry.area_id, e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whole extracts that are plain or encrypted with this node's key. Split areas would
+    /// need every part pinned and are extracted instead.
+    fn is_adoptable(&self, entry: &ManifestEntry) -> bool {
+        !entry.split
+            && entry
+                .encryption
+                .as_ref()
+                .is_none_or(|encryption| Some(&encryption.key_id) == self.key_id.as_ref())
+    }
+
+    async fn indexes(&self) -> tokio::sync::MappedMutexGuard<'_, Vec<DatasetIndex>> {
+        let mut indexes = self.indexes.lock().await;
+        if indexes.is_none() {
+            let mut loaded = Vec::new();
+            for root in &self.roots {
+                match self.download_json::<DatasetIndex>(root).await {
+                    Ok(index) => {
+                        info!(
+                            "Loaded trusted dataset index {} with {} countries",
+                            root,
+                            index.countries.len()
+                        );
+                        loaded.push(index);
+                    }
+                    Err(e) => warn!("Skipping trusted dataset index {}: {}", root, e),
+                }
+            }
+            *indexes = Some(loaded);
+        }
+        tokio::sync::MutexGuard::map(indexes, |indexes| indexes.get_or_insert_with(Vec::new))
+    }
+
+    async fn download_json<T: DeserializeOwned>(&self, cid: &str) -> Result<T, CatalogError> {
+        let path =
+            std::env::temp_dir().join(format!("anynode-catalog-{}.json", uuid::Uuid::new_v4()));
+        let content = match self.storage.download_file(cid, &path).await {
+            Ok(_) => tokio::fs::read(&path).await,
+            Err(e) => Err(std::io::Error::other(e)),
+        };
+        let _ = tokio::fs::remove_file(&path).await;
+
+        serde_json::from_slice(&content?)
+            .map_err(|e| CatalogError::InvalidManifest(cid.to_string(), e))
+    }
+}
@@ -0,0 +1,225 @@
+use crate::services::DatabaseError;
+use rusqlite::Connection;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use tracing::warn;
+
+/// How long a node waits for another node's write to the claims database
+const BUSY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Country claims in a SQLite database shared by cooperating nodes, so no two nodes
+/// extract the same country. A claim is a lease the owner renews while it works; when a
+/// node dies its lease runs out and another node takes the country over. Completed
+/// countries stay with the node that extracted them.
+pub struct ClaimService {
+    conn: Arc<Mutex<Connection>>,
+    node_id: String,
+    lease: Duration,
+}
+
+impl ClaimService {
+    pub async fn open(
+        path: &Path,
+        node_id: String,
+        lease: Duration,
+    ) -> Result<Self, DatabaseError> {
+        let path = path.to_path_buf();
+
+        let conn = tokio::task::spawn_blocking(move || {
+            let conn = Connection::open(path)?;
+            conn.busy_timeout(BUSY_TIMEOUT)?;
+
+            let create_claims_table = r#"
+            CREATE TABLE IF NOT EXISTS work_claims (
+                country_code TEXT PRIMARY KEY,
+                node_id TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                completed_at INTEGER
+            )
+            "#;
+
+            conn.execute(create_claims_table, [])?;
+            Ok::<_, DatabaseError>(conn)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            node_id,
+            lease,
+        })
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    pub fn lease(&self) -> Duration {
+        self.lease
+    }
+
+    /// Claim a country for this node. Succeeds when nobody holds it, when this node already
+    /// does, or when another node's unfinished claim has expired.
+    pub async fn try_claim(&self, country_code: &str) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let node_id = self.node_id.clone();
+        let now = unix_now();
+        let expires_at = now + self.lease.as_secs() as i64;
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO work_claims (country_code, node_id, expires_at, completed_at)
+            VALUES (?1, ?2, ?3, NULL)
+            ON CONFLICT(country_code) DO UPDATE SET
+                node_id = excluded.node_id,
+                expires_at = excluded.expires_at,
+                completed_at = NULL
+            WHERE work_claims.node_id = excluded.node_id
+                OR (work_claims.completed_at IS NULL AND work_claims.expires_at <= ?4)
+            "#;
+
+            let params = rusqlite::params![&country_code, &node_id, expires_at, now];
+            let changed = conn.execute(query, params)?;
+            Ok(changed > 0)
+        })
+        .await?
+    }
+
+    /// Extend this node's lease on a country. Returns false when the claim was lost to
+    /// another node after expiring.
+    pub async fn renew(&self, country_code: &str) -> Result<bool, DatabaseError> {
+        let expires_at = unix_now() + self.lease.as_secs() as i64;
+        self.update_own_claim(
+            "UPDATE work_claims SET expires_at = ?3 WHERE country_code = ?1 AND node_id = ?2",
+            country_code,
+            expires_at,
+        )
+        .await
+    }
+
+    /// Mark a claimed country as extracted, other nodes no longer take it over
+    pub async fn complete(&self, country_code: &str) -> Result<bool, DatabaseError> {
+        self.update_own_claim(
+            "UPDATE work_claims SET completed_at = ?3 WHERE country_code = ?1 AND node_id = ?2",
+            country_code,
+            unix_now(),
+        )
+        .await
+    }
+
+    /// Give an unfinished claim back, so another node can pick the country up right away
+    pub async fn release(&self, country_code: &str) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let node_id = self.node_id.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            DELETE FROM work_claims
+            WHERE country_code = ?1 AND node_id = ?2 AND completed_at IS NULL
+            "#;
+
+            let changed = conn.execute(query, rusqlite::params![&country_code, &node_id])?;
+            Ok(changed > 0)
+        })
+        .await?
+    }
+
+    /// Renew the lease on a country every third of the lease until the returned task is
+    /// aborted
+    pub fn keep_alive(self: &Arc<Self>, country_code: &str) -> tokio::task::JoinHandle<()> {
+        let claims = self.clone();
+        let country_code = country_code.to_string();
+
+        tokio::spawn(async move {
+            let mut tick = tokio::time::interval(claims.lease / 3);
+            tick.tick().await;
+            loop {
+                tick.tick().await;
+                match claims.renew(&country_code).await {
+                    Ok(true) => {}
+                    Ok(false) => warn!(
+                        "Lost the claim on {} to another node, its lease expired",
+                        country_code
+                    ),
+                    Err(e) => warn!("Failed to renew the claim on {}: {}", country_code, e),
+                }
+            }
+        })
+    }
+
+    async fn update_own_claim(
+        &self,
+        query: &'static str,
+        country_code: &str,
+        value: i64,
+    ) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let node_id = self.node_id.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let changed = conn.execute(query, rusqlite::params![&country_code, &node_id, value])?;
+            Ok(changed > 0)
+        })
+        .await?
+    }
+}
+
+fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn open(path: &Path, node_id: &str, lease: Duration) -> ClaimService {
+        ClaimService::open(path, node_id.to_string(), lease)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn a_claimed_country_is_not_taken_by_another_node() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("claims.db");
+        let first = open(&path, "node-a", Duration::from_secs(600)).await;
+        let second = open(&path, "node-b", Duration::from_secs(600)).await;
+
+        assert!(first.try_claim("FR").await.unwrap());
+        assert!(!second.try_claim("FR").await.unwrap());
+        assert!(second.try_claim("DE").await.unwrap());
+        // Reclaiming its own country, e.g. after a restart, is fine
+        assert!(first.try_claim("FR").await.unwrap());
+
+        assert!(first.release("FR").await.unwrap());
+        assert!(second.try_claim("FR").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn expired_claims_are_taken_over_unless_completed() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("claims.db");
+        let crashed = open(&path, "node-a", Duration::ZERO).await;
+        let survivor = open(&path, "node-b", Duration::from_secs(600)).await;
+
+        assert!(crashed.try_claim("FR").await.unwrap());
+        assert!(crashed.try_claim("DE").await.unwrap());
+        assert!(crashed.complete("DE").await.unwrap());
+
+        assert!(survivor.try_claim("FR").await.unwrap());
+        assert!(!survivor.try_claim("DE").await.unwrap());
+        assert!(!crashed.renew("FR").await.unwrap());
+    }
+}
@@ -1,4 +1,9 @@
-use tracing::info;
+use crate::config::Config;
+use crate::services::{DatabaseError, DatabaseService};
+use crate::types::{CountryInfo, CountryPriority};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{info, warn};
 
 const ALL_COUNTRIES: &[&str] = &[
     "AD", "AE", "AF", "AG", "AI", "AL", "AM", "AN", "AO", "AQ", "AR", "AS", "AT", "AU", "AW", "AX", "AZ",
@@ -19,20 +24,43 @@ const ALL_COUNTRIES: &[&str] = &[
     "ZM", "ZW",
 ];
 
-pub struct CountryService;
-
-impl Default for CountryService {
-    fn default() -> Self {
-        Self::new()
-    }
+pub struct CountryService {
+    whosonfirst_db: Arc<DatabaseService>,
+    config: Arc<Config>,
 }
 
 impl CountryService {
-    pub fn new() -> Self {
-        Self
+    pub fn new(whosonfirst_db: Arc<DatabaseService>, config: Arc<Config>) -> Self {
+        Self {
+            whosonfirst_db,
+            config,
+        }
     }
 
-    pub fn get_countries_to_process(&self, target_countries: &[String]) -> Vec<String> {
+    /// Every known country with its number of areas, counted in a single query
+    pub async fn get_all_countries(&self) -> Result<Vec<CountryInfo>, DatabaseError> {
+        let counts = self.whosonfirst_db.get_country_area_counts().await?;
+        Ok(ALL_COUNTRIES
+            .iter()
+            .map(|country| CountryInfo {
+                country_code: country.to_string(),
+                area_count: counts.get(*country).copied().unwrap_or(0),
+            })
+            .collect())
+    }
+
+    /// Countries to process, ordered according to the configured priority
+    pub async fn get_countries_to_process(&self, target_countries: &[String]) -> Vec<String> {
+        let mut countries = self.get_target_countries(target_countries);
+        if let Some(shard) = self.config.shard {
+            let total = countries.len();
+            countries.retain(|country| shard.owns(country));
+            info!("Shard {}: {} of {} target countries", shard, countries.len(), total);
+        }
+        self.prioritize(countries).await
+    }
+
+    fn get_target_countries(&self, target_countries: &[String]) -> Vec<String> {
         if target_countries.is_empty() || target_countries.iter().any(|c| c == "ALL") {
             ALL_COUNTRIES.iter().map(|s| s.to_string()).collect()
         } else {
@@ -53,4 +81,62 @@ impl CountryService {
             valid_countries
         }
     }
+
+    async fn prioritize(&self, mut countries: Vec<String>) -> Vec<String> {
+        match &self.config.country_priority {
+            CountryPriority::TargetOrder => {}
+            CountryPriority::Alphabetical => countries.sort(),
+            CountryPriority::SmallestFirst | CountryPriority::LargestFirst => {
+                let counts = match self.count_areas_to_extract(&countries).await {
+                    Ok(counts) => counts,
+                    Err(e) => {
+                        warn!("Failed to count areas per country, keeping the target order: {}", e);
+                        return countries;
+                    }
+                };
+                let count = |country: &String| counts.get(country).copied().unwrap_or(0);
+
+                // Stable sorts keep ties in alphabetical order
+                countries.sort();
+                if self.config.country_priority == CountryPriority::SmallestFirst {
+                    countries.sort_by_key(count);
+                } else {
+                    countries.sort_by_key(|country| std::cmp::Reverse(count(country)));
+                }
+            }
+            CountryPriority::Explicit(order) => {
+                let rank = |country: &String| {
+                    order.iter().position(|c| c == country).unwrap_or(order.len())
+                };
+                countries.sort();
+                countries.sort_by_key(rank);
+            }
+        }
+
+        countries
+    }
+
+    /// Areas per country that extraction will actually pick up, after the population and
+    /// bbox size filters
+    async fn count_areas_to_extract(
+        &self,
+        countries: &[String],
+    ) -> Result<HashMap<String, usize>, DatabaseError> {
+        let (min, max) = (self.config.min_bbox_area_km2, self.config.max_bbox_area_km2);
+        let mut counts = HashMap::new();
+
+        for country in countries {
+            let areas = self
+                .whosonfirst_db
+                .get_country_areas(country, self.config.min_population)
+                .await?;
+            let count = areas
+                .iter()
+                .filter(|area| area.bbox_size_violation(min, max).is_none())
+                .count();
+            counts.insert(country.clone(), count);
+        }
+
+        Ok(counts)
+    }
 }
@@ -1,6 +1,16 @@
-use crate::types::AdministrativeArea;
-use rusqlite::Connection;
+use crate::types::{
+    AdministrativeArea, AreaInfo, AreaPart, AreaPartUpload, BoundingBox, CatalogRecord,
+    CidAnnouncement, CompletedUpload, Compression, CountryUsage, DbSnapshot, ExtractionFailure,
+    FailedUpload, OversizedExtract, PaginatedAreasResult, PaginationInfo, PublishedIndex,
+    RunCheckpoint, RunPhase, RunStats, UploadStats, CUSTOM_AREA_ID_BASE,
+};
+use crate::utils::EncryptionInfo;
+use rusqlite::backup::Backup;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
 use tokio::sync::Mutex;
 
@@ -12,8 +22,87 @@ pub enum DatabaseError {
     JoinError(#[from] tokio::task::JoinError),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error(
+        "MIN_POPULATION is set but the WhosOnFirst database has no population data: it needs \
+         a properties or geojson table, or a population column in spr. Use a full WOF SQLite \
+         distribution or unset MIN_POPULATION"
+    )]
+    NoPopulationSource,
+    #[error(
+        "The WhosOnFirst database has no spr table, it is not a WhosOnFirst SQLite \
+         distribution or its download was cut short"
+    )]
+    MissingSprTable,
+    #[error(
+        "The WhosOnFirst database has no {} areas, use the admin distribution",
+        .0.join(" or ")
+    )]
+    MissingPlacetypes(Vec<String>),
+    #[error(
+        "The WhosOnFirst database {0} is corrupted, it was deleted to be downloaded again: {1}"
+    )]
+    CorruptDatabase(String, String),
+    #[error("The WhosOnFirst spr table lacks the columns: {}", .0.join(", "))]
+    MissingSprColumns(Vec<String>),
+    #[error("No WhosOnFirst country database in {0} for the target countries")]
+    EmptyBundle(String),
+    #[error(
+        "{0} WhosOnFirst country databases selected, at most {1} can be opened together: set \
+         TARGET_COUNTRIES to fewer countries or use the global database"
+    )]
+    BundleTooLarge(usize, usize),
 }
 
+/// Path that opens a private database living in memory, gone once the service is dropped
+pub const IN_MEMORY: &str = ":memory:";
+
+/// Columns of the WhosOnFirst `spr` table the area queries rely on
+pub const REQUIRED_SPR_COLUMNS: &[&str] = &[
+    "id",
+    "name",
+    "country",
+    "placetype",
+    "latitude",
+    "longitude",
+    "min_longitude",
+    "min_latitude",
+    "max_longitude",
+    "max_latitude",
+    "is_current",
+    "is_deprecated",
+];
+
+/// Placetypes the node extracts or looks countries up by. Distributions other than the admin
+/// one, such as postalcode or venue, lack them.
+pub const REQUIRED_PLACETYPES: &[&str] = &["country", "region"];
+
+/// Databases SQLite attaches to one connection at most, the bundled library's default
+pub const MAX_BUNDLE_FILES: usize = 10;
+
+/// WhosOnFirst tables a bundle exposes, `spr` and the tables population is read from
+const BUNDLE_TABLES: [&str; 3] = ["spr", "properties", "geojson"];
+
+/// The `spr` columns the node queries, enough to stand in for a WhosOnFirst database
+#[cfg(any(test, feature = "test-util"))]
+const SPR_FIXTURE_SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS spr (
+        id INTEGER PRIMARY KEY, name TEXT, country TEXT, placetype TEXT,
+        latitude REAL, longitude REAL, min_longitude REAL, min_latitude REAL,
+        max_longitude REAL, max_latitude REAL, is_current INTEGER, is_deprecated INTEGER
+    )";
+
+/// R-tree over the bounding boxes of current regions and counties, derived from `spr` as a
+/// temporary table of the connection so it always matches the database it was built from
+const BBOX_INDEX_SCHEMA: &str = "
+    CREATE VIRTUAL TABLE temp.area_bbox_index
+    USING rtree(id, min_longitude, max_longitude, min_latitude, max_latitude)";
+
+/// Pages copied per step of an online backup or restore
+const BACKUP_PAGES_PER_STEP: i32 = 256;
+
+/// Pause between backup steps, letting other connections write to the database meanwhile
+const BACKUP_STEP_PAUSE: Duration = Duration::from_millis(10);
+
 pub struct DatabaseService {
     conn: Arc<Mutex<Connection>>,
 }
@@ -33,6 +122,104 @@ impl DatabaseService {
         Ok(service)
     }
 
+    /// Open a directory of per-country WhosOnFirst databases as one. Each file is attached to
+    /// an in-memory connection and every table present in all of them is exposed as a view
+    /// over their union, so area queries run unchanged over every attached file. Only the
+    /// files of `countries` are attached, see `select_whosonfirst_bundle_files`, and at most
+    /// `MAX_BUNDLE_FILES` of them; the config refuses TARGET_COUNTRIES selecting more.
+    pub async fn open_whosonfirst_bundle(
+        dir: &Path,
+        countries: &[String],
+    ) -> Result<Self, DatabaseError> {
+        let files = select_whosonfirst_bundle_files(dir, countries)?;
+        if files.is_empty() {
+            return Err(DatabaseError::EmptyBundle(dir.display().to_string()));
+        }
+        if files.len() > MAX_BUNDLE_FILES {
+            return Err(DatabaseError::BundleTooLarge(files.len(), MAX_BUNDLE_FILES));
+        }
+
+        let conn = tokio::task::spawn_blocking(move || -> Result<Connection, DatabaseError> {
+            let conn = Connection::open_in_memory()?;
+            let schemas: Vec<String> = (0..files.len()).map(|i| format!("bundle{}", i)).collect();
+            for (path, schema) in files.iter().zip(&schemas) {
+                conn.execute(
+                    "ATTACH DATABASE ?1 AS ?2",
+                    rusqlite::params![path.to_string_lossy(), schema],
+                )?;
+            }
+
+            for table in BUNDLE_TABLES {
+                let columns = schemas
+                    .iter()
+                    .map(|schema| schema_table_columns(&conn, schema, table))
+                    .collect::<Result<Vec<_>, _>>()?;
+                if columns[0].is_empty() || columns.iter().any(|c| *c != columns[0]) {
+                    continue;
+                }
+                let union = schemas
+                    .iter()
+                    .map(|schema| format!("SELECT * FROM {}.{}", schema, table))
+                    .collect::<Vec<_>>()
+                    .join(" UNION ALL ");
+                conn.execute_batch(&format!("CREATE TEMP VIEW {} AS {}", table, union))?;
+            }
+            Ok(conn)
+        })
+        .await??;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Database held in memory, for tests of the query layer that need no file on disk
+    pub async fn in_memory(create_cid_tables: bool) -> Result<Self, DatabaseError> {
+        Self::new(IN_MEMORY, create_cid_tables).await
+    }
+
+    /// Create a minimal `spr` table and add `areas` to it as current, non-deprecated places,
+    /// so the database answers area queries like a WhosOnFirst one
+    #[cfg(any(test, feature = "test-util"))]
+    pub async fn load_spr_fixture(
+        &self,
+        areas: &[AdministrativeArea],
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let areas = areas.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.blocking_lock();
+            conn.execute_batch(SPR_FIXTURE_SCHEMA)?;
+            // Rebuilt from the new rows on the next bounding-box query
+            conn.execute_batch("DROP TABLE IF EXISTS temp.area_bbox_index")?;
+
+            let tx = conn.transaction()?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT OR REPLACE INTO spr VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 1, 0)",
+                )?;
+                for area in &areas {
+                    insert.execute(rusqlite::params![
+                        area.id,
+                        area.name,
+                        area.country,
+                        area.placetype,
+                        area.latitude,
+                        area.longitude,
+                        area.min_longitude,
+                        area.min_latitude,
+                        area.max_longitude,
+                        area.max_latitude,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(())
+        })
+        .await?
+    }
+
     async fn create_cid_tables(&self) -> Result<(), DatabaseError> {
         let conn = self.conn.clone();
 
@@ -55,17 +242,214 @@ impl DatabaseService {
             ON area_cids(country_code, area_id)
             "#;
 
+            let create_extractions_table = r#"
+            CREATE TABLE IF NOT EXISTS area_extractions (
+                country_code TEXT NOT NULL,
+                area_id INTEGER NOT NULL,
+                planet_version TEXT NOT NULL,
+                extraction_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (country_code, area_id)
+            )
+            "#;
+
+            let create_run_stats_table = r#"
+            CREATE TABLE IF NOT EXISTS run_stats (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                started_at TEXT NOT NULL,
+                finished_at TEXT NOT NULL,
+                uploaded INTEGER NOT NULL,
+                failed INTEGER NOT NULL,
+                bytes_uploaded INTEGER NOT NULL
+            )
+            "#;
+
             conn.execute(create_cid_table, [])?;
             conn.execute(create_cid_index, [])?;
+            conn.execute(create_extractions_table, [])?;
+            // Areas split into several extracts keep one CID per part, the area itself maps
+            // to the CID of the uploaded part list in area_cids
+            let create_parts_table = r#"
+            CREATE TABLE IF NOT EXISTS area_parts (
+                country_code TEXT NOT NULL,
+                area_id INTEGER NOT NULL,
+                part_index INTEGER NOT NULL,
+                min_longitude REAL NOT NULL,
+                min_latitude REAL NOT NULL,
+                max_longitude REAL NOT NULL,
+                max_latitude REAL NOT NULL,
+                cid TEXT NOT NULL,
+                file_size INTEGER NOT NULL,
+                upload_time DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (country_code, area_id, part_index)
+            )
+            "#;
+
+            // Content hash of every uploaded extract, so identical output is never uploaded twice
+            let create_cache_table = r#"
+            CREATE TABLE IF NOT EXISTS extract_cache (
+                content_hash TEXT PRIMARY KEY,
+                cid TEXT NOT NULL,
+                file_size INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                last_used DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#;
+
+            let create_failed_uploads_table = r#"
+            CREATE TABLE IF NOT EXISTS failed_uploads (
+                country_code TEXT NOT NULL,
+                area_id INTEGER NOT NULL,
+                path TEXT NOT NULL,
+                reason TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                first_failed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                last_failed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (country_code, area_id)
+            )
+            "#;
+
+            // Failed extractions of each area, cleared once the area is extracted.
+            // failed_at is the time of the last failed attempt.
+            let create_extraction_failures_table = r#"
+            CREATE TABLE IF NOT EXISTS extraction_failures (
+                country_code TEXT NOT NULL,
+                area_id INTEGER NOT NULL,
+                error TEXT NOT NULL,
+                log_path TEXT,
+                attempts INTEGER NOT NULL DEFAULT 1,
+                failed_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (country_code, area_id)
+            )
+            "#;
+
+            // Areas whose extract exceeded MAX_OUTPUT_SIZE, cleared once the area is extracted
+            // within the budget
+            let create_oversized_extracts_table = r#"
+            CREATE TABLE IF NOT EXISTS oversized_extracts (
+                country_code TEXT NOT NULL,
+                area_id INTEGER NOT NULL,
+                file_size INTEGER NOT NULL,
+                planet_version TEXT NOT NULL,
+                skipped_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (country_code, area_id)
+            )
+            "#;
+
+            // Root CID of every dataset index published, see DatasetIndex
+            let create_published_indexes_table = r#"
+            CREATE TABLE IF NOT EXISTS published_indexes (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                root_cid TEXT NOT NULL,
+                country_count INTEGER NOT NULL,
+                area_count INTEGER NOT NULL,
+                published_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#;
+
+            // CID database snapshots uploaded to storage, see DbSnapshot
+            let create_db_snapshots_table = r#"
+            CREATE TABLE IF NOT EXISTS db_snapshots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                cid TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                compression TEXT,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#;
+
+            // Areas other nodes announced, kept apart from this node's own uploads
+            let create_peer_cids_table = r#"
+            CREATE TABLE IF NOT EXISTS peer_cids (
+                origin TEXT NOT NULL,
+                country_code TEXT NOT NULL,
+                area_id INTEGER NOT NULL,
+                cid TEXT NOT NULL,
+                file_size INTEGER,
+                received_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (origin, country_code, area_id)
+            )
+            "#;
+
+            // Progress of an unfinished run, see RunCheckpoint. One row at most.
+            let create_run_checkpoint_table = r#"
+            CREATE TABLE IF NOT EXISTS run_checkpoint (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                phase TEXT NOT NULL,
+                last_country_code TEXT,
+                last_area_id INTEGER,
+                updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+            )
+            "#;
+
+            let create_checkpoint_countries_table = r#"
+            CREATE TABLE IF NOT EXISTS checkpoint_countries (
+                phase TEXT NOT NULL,
+                country_code TEXT NOT NULL,
+                PRIMARY KEY (phase, country_code)
+            )
+            "#;
+
+            // One-off regions extracted by `extract-bbox`, under IDs from CUSTOM_AREA_ID_BASE
+            let create_custom_areas_table = r#"
+            CREATE TABLE IF NOT EXISTS custom_areas (
+                id INTEGER PRIMARY KEY,
+                min_longitude REAL NOT NULL,
+                min_latitude REAL NOT NULL,
+                max_longitude REAL NOT NULL,
+                max_latitude REAL NOT NULL,
+                created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                UNIQUE (min_longitude, min_latitude, max_longitude, max_latitude)
+            )
+            "#;
+
+            conn.execute(create_run_stats_table, [])?;
+            conn.execute(create_custom_areas_table, [])?;
+            conn.execute(create_run_checkpoint_table, [])?;
+            conn.execute(create_checkpoint_countries_table, [])?;
+            conn.execute(create_parts_table, [])?;
+            conn.execute(create_published_indexes_table, [])?;
+            conn.execute(create_db_snapshots_table, [])?;
+            conn.execute(create_peer_cids_table, [])?;
+            conn.execute(create_cache_table, [])?;
+            conn.execute(create_failed_uploads_table, [])?;
+            conn.execute(create_extraction_failures_table, [])?;
+            conn.execute(create_oversized_extracts_table, [])?;
+
+            ensure_column(&conn, "area_cids", "stale", "INTEGER NOT NULL DEFAULT 0")?;
+            ensure_column(&conn, "run_stats", "reused", "INTEGER NOT NULL DEFAULT 0")?;
+            // Codec of compressed uploads, NULL for content uploaded as extracted
+            ensure_column(&conn, "area_cids", "compression", "TEXT")?;
+            ensure_column(&conn, "area_parts", "compression", "TEXT")?;
+            // Name of a region extracted from a GeoJSON boundary
+            ensure_column(&conn, "custom_areas", "name", "TEXT")?;
+            // Key fingerprint and nonce prefix of encrypted uploads, NULL when unencrypted
+            for table in ["area_cids", "area_parts"] {
+                ensure_column(&conn, table, "encryption_key_id", "TEXT")?;
+                ensure_column(&conn, table, "encryption_nonce", "TEXT")?;
+            }
+            // Metadata sidecar uploaded with each area, see AreaMetadata
+            ensure_column(&conn, "area_cids", "metadata_cid", "TEXT")?;
+            ensure_column(&conn, "area_cids", "metadata_key_id", "TEXT")?;
+            ensure_column(&conn, "area_cids", "metadata_nonce", "TEXT")?;
+            ensure_column(
+                &conn,
+                "extraction_failures",
+                "attempts",
+                "INTEGER NOT NULL DEFAULT 1",
+            )?;
 
             Ok::<(), DatabaseError>(())
         })
         .await?
     }
 
+    /// Extractable areas of a country, optionally only those with at least `min_population`
+    /// inhabitants. Population is read from wherever the database stores it, see
+    /// `population_source`.
     pub async fn get_country_areas(
         &self,
         country_code: &str,
+        min_population: Option<u64>,
     ) -> Result<Vec<AdministrativeArea>, DatabaseError> {
         let conn = self.conn.clone();
         let country_code = country_code.to_string();
@@ -73,7 +457,7 @@ impl DatabaseService {
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
 
-            let conditions = [
+            let mut conditions = vec![
                 "placetype IN ('region', 'county')",
                 "is_current = 1",
                 "is_deprecated = 0",
@@ -87,6 +471,16 @@ impl DatabaseService {
                 "max_latitude IS NOT NULL",
                 "country = ?1",
             ];
+            let mut params = vec![rusqlite::types::Value::from(country_code)];
+
+            let population_condition;
+            if let Some(min_population) = min_population {
+                let population =
+                    population_source(&conn)?.ok_or(DatabaseError::NoPopulationSource)?;
+                population_condition = format!("{} >= ?2", population);
+                conditions.push(&population_condition);
+                params.push(rusqlite::types::Value::from(min_population as i64));
+            }
 
             let where_clause = conditions.join(" AND ");
             let query_str = format!(
@@ -95,7 +489,10 @@ impl DatabaseService {
             );
 
             let mut stmt = conn.prepare(&query_str)?;
-            let rows = stmt.query_map([&country_code], |row| AdministrativeArea::from_row(row))?;
+            let rows = stmt.query_map(
+                rusqlite::params_from_iter(params),
+                AdministrativeArea::from_row,
+            )?;
 
             let areas = rows.collect::<Result<Vec<_>, _>>()?;
             Ok(areas)
@@ -103,6 +500,134 @@ impl DatabaseService {
         .await?
     }
 
+    /// Whether areas can be filtered on population in this database
+    pub async fn has_population_source(&self) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            Ok(population_source(&conn)?.is_some())
+        })
+        .await?
+    }
+
+    /// Column names of `table`, empty when the table does not exist
+    pub async fn get_table_columns(&self, table: &str) -> Result<Vec<String>, DatabaseError> {
+        let conn = self.conn.clone();
+        let table = table.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            Ok(table_columns(&conn, &table)?)
+        })
+        .await?
+    }
+
+    /// Check the database has the `spr` table with the columns the area queries use, so a
+    /// wrong file fails with a clear error instead of on the first query
+    pub async fn validate_whosonfirst_schema(&self) -> Result<(), DatabaseError> {
+        let columns = self.get_table_columns("spr").await?;
+        if columns.is_empty() {
+            return Err(DatabaseError::MissingSprTable);
+        }
+        let missing: Vec<String> = REQUIRED_SPR_COLUMNS
+            .iter()
+            .filter(|required| !columns.iter().any(|c| c == *required))
+            .map(|required| required.to_string())
+            .collect();
+        match missing.is_empty() {
+            true => Ok(()),
+            false => Err(DatabaseError::MissingSprColumns(missing)),
+        }
+    }
+
+    /// `REQUIRED_PLACETYPES` without a single current place in the database
+    pub async fn missing_placetypes(&self) -> Result<Vec<&'static str>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let mut stmt = conn.prepare(
+                "SELECT EXISTS (SELECT 1 FROM spr WHERE placetype = ?1 AND is_current = 1)",
+            )?;
+
+            let mut missing = Vec::new();
+            for placetype in REQUIRED_PLACETYPES {
+                if !stmt.query_row([placetype], |row| row.get::<_, bool>(0))? {
+                    missing.push(*placetype);
+                }
+            }
+            Ok(missing)
+        })
+        .await?
+    }
+
+    /// Problems `PRAGMA quick_check` finds, then a read of the last `spr` row, which a cut
+    /// short file lacks. Empty when the database is sound.
+    pub async fn check_integrity(&self) -> Result<Vec<String>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let check = || -> rusqlite::Result<Vec<String>> {
+                let mut stmt = conn.prepare("PRAGMA quick_check")?;
+                let problems = stmt
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<Result<Vec<_>, _>>()?;
+                if problems != ["ok"] {
+                    return Ok(problems);
+                }
+
+                let last = conn
+                    .query_row(
+                        "SELECT id, name, placetype FROM spr ORDER BY rowid DESC LIMIT 1",
+                        [],
+                        |row| row.get::<_, i64>(0),
+                    )
+                    .optional()?;
+                Ok(match last {
+                    Some(_) => Vec::new(),
+                    None => vec!["the spr table is empty".to_string()],
+                })
+            };
+            // A file too damaged to read fails the check itself
+            Ok(check().unwrap_or_else(|e| vec![e.to_string()]))
+        })
+        .await?
+    }
+
+    /// Valid area with the smallest bounding box, cheap to extract for diagnostics
+    pub async fn get_smallest_area(&self) -> Result<Option<AdministrativeArea>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT id, name, country, placetype, latitude, longitude, min_longitude, min_latitude, max_longitude, max_latitude
+            FROM spr
+            WHERE placetype IN ('region', 'county')
+                AND is_current = 1
+                AND is_deprecated = 0
+                AND name IS NOT NULL
+                AND name != ''
+                AND max_longitude > min_longitude
+                AND max_latitude > min_latitude
+            ORDER BY (max_longitude - min_longitude) * (max_latitude - min_latitude)
+            LIMIT 1
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let mut rows = stmt.query_map([], AdministrativeArea::from_row)?;
+
+            match rows.next() {
+                Some(area) => Ok(Some(area?)),
+                None => Ok(None),
+            }
+        })
+        .await?
+    }
+
     pub async fn get_country_area_count(
         &self,
         country_code: &str,
@@ -129,6 +654,55 @@ impl DatabaseService {
         .await?
     }
 
+    /// Areas per country for every country at once, countries without areas are absent
+    pub async fn get_country_area_counts(&self) -> Result<HashMap<String, u32>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country, COUNT(*) FROM spr
+            WHERE placetype IN ('region', 'county') AND is_current = 1 AND is_deprecated = 0
+            GROUP BY country
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u32))
+            })?;
+
+            Ok(rows.collect::<Result<HashMap<_, _>, _>>()?)
+        })
+        .await?
+    }
+
+    /// The country's own WhosOnFirst record, whose bounding box covers the whole country
+    pub async fn get_country_record(
+        &self,
+        country_code: &str,
+    ) -> Result<Option<AdministrativeArea>, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT id, name, country, placetype, latitude, longitude, min_longitude, min_latitude, max_longitude, max_latitude
+            FROM spr
+            WHERE country = ?1 AND placetype = 'country' AND is_current = 1 AND is_deprecated = 0
+            ORDER BY id
+            LIMIT 1
+            "#;
+
+            Ok(conn
+                .query_row(query, [&country_code], AdministrativeArea::from_row)
+                .optional()?)
+        })
+        .await?
+    }
+
     pub async fn get_area_by_id(
         &self,
         area_id: i64,
@@ -141,11 +715,11 @@ impl DatabaseService {
             let query = r#"
             SELECT id, name, country, placetype, latitude, longitude, min_longitude, min_latitude, max_longitude, max_latitude
             FROM spr
-            WHERE id = ?1 AND placetype IN ('region', 'county') AND is_current = 1 AND is_deprecated = 0
+            WHERE id = ?1 AND placetype IN ('region', 'county', 'country') AND is_current = 1 AND is_deprecated = 0
             "#;
 
             let mut stmt = conn.prepare(query)?;
-            let rows = stmt.query_map([&area_id], |row| AdministrativeArea::from_row(row))?;
+            let rows = stmt.query_map([&area_id], AdministrativeArea::from_row)?;
 
             let areas: Result<Vec<_>, _> = rows.collect();
             match areas {
@@ -182,7 +756,7 @@ impl DatabaseService {
 
             let mut stmt = conn.prepare(&query_str)?;
             let params: Vec<&dyn rusqlite::ToSql> = area_ids.iter().map(|id| id as &dyn rusqlite::ToSql).collect();
-            let rows = stmt.query_map(params.as_slice(), |row| AdministrativeArea::from_row(row))?;
+            let rows = stmt.query_map(params.as_slice(), AdministrativeArea::from_row)?;
 
             let areas = rows.collect::<Result<Vec<_>, _>>()?;
             Ok(areas)
@@ -192,10 +766,10 @@ impl DatabaseService {
 
     pub async fn batch_insert_cid_mappings(
         &self,
-        mappings: &[(String, u32, String, u64)],
+        uploads: &[CompletedUpload],
     ) -> Result<(), DatabaseError> {
         let conn = self.conn.clone();
-        let mappings = mappings.to_vec();
+        let uploads = uploads.to_vec();
 
         tokio::task::spawn_blocking(move || {
             let mut conn = conn.blocking_lock();
@@ -204,22 +778,33 @@ impl DatabaseService {
 
             let query = r#"
             INSERT OR REPLACE INTO area_cids
-            (country_code, area_id, cid, file_size, upload_time)
-            VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+            (country_code, area_id, cid, file_size, compression, encryption_key_id,
+             encryption_nonce, metadata_cid, metadata_key_id, metadata_nonce, upload_time)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, CURRENT_TIMESTAMP)
             "#;
 
-            for (country_code, area_id, cid, file_size) in mappings {
-                let area_id_i64 = area_id as i64;
-                let file_size_i64 = file_size as i64;
+            for upload in uploads {
+                let area_id_i64 = upload.area_id as i64;
+                let file_size_i64 = upload.file_size as i64;
                 tx.execute(
                     query,
                     rusqlite::params![
-                        &country_code,
+                        &upload.country_code,
                         &area_id_i64,
-                        &cid,
+                        &upload.cid,
                         &file_size_i64,
+                        upload.compression.as_db_value(),
+                        upload.encryption.as_ref().map(|e| &e.key_id),
+                        upload.encryption.as_ref().map(|e| &e.nonce_prefix),
+                        &upload.metadata_cid,
+                        upload.metadata_encryption.as_ref().map(|e| &e.key_id),
+                        upload.metadata_encryption.as_ref().map(|e| &e.nonce_prefix),
                     ],
                 )?;
+                tx.execute(
+                    "DELETE FROM failed_uploads WHERE country_code = ?1 AND area_id = ?2",
+                    rusqlite::params![&upload.country_code, &area_id_i64],
+                )?;
             }
 
             tx.commit()?;
@@ -228,48 +813,2478 @@ impl DatabaseService {
         .await?
     }
 
-    pub async fn has_cid_mapping(
+    pub async fn record_area_part(
         &self,
         country_code: &str,
         area_id: u32,
-    ) -> Result<bool, DatabaseError> {
+        upload: &AreaPartUpload,
+    ) -> Result<(), DatabaseError> {
         let conn = self.conn.clone();
         let country_code = country_code.to_string();
+        let upload = upload.clone();
 
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
 
             let query = r#"
-            SELECT COUNT(*) as count FROM area_cids
-            WHERE country_code = ?1 AND area_id = ?2
+            INSERT OR REPLACE INTO area_parts
+            (country_code, area_id, part_index, min_longitude, min_latitude, max_longitude, max_latitude, cid, file_size, compression, encryption_key_id, encryption_nonce, upload_time)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, CURRENT_TIMESTAMP)
             "#;
 
-            let area_id_i64 = area_id as i64;
-            let count = conn.query_row(
+            conn.execute(
                 query,
-                rusqlite::params![&country_code, &area_id_i64],
-                |row| row.get::<_, i64>(0),
+                rusqlite::params![
+                    &country_code,
+                    area_id as i64,
+                    upload.part.index as i64,
+                    upload.part.min_longitude,
+                    upload.part.min_latitude,
+                    upload.part.max_longitude,
+                    upload.part.max_latitude,
+                    &upload.cid,
+                    upload.file_size as i64,
+                    upload.compression.as_db_value(),
+                    upload.encryption.as_ref().map(|e| &e.key_id),
+                    upload.encryption.as_ref().map(|e| &e.nonce_prefix),
+                ],
             )?;
 
-            Ok(count > 0)
+            Ok(())
         })
         .await?
     }
 
-    pub async fn get_cid_mapping_stats(&self) -> Result<(u64, u64), DatabaseError> {
+    /// Parts of a split area that have already been uploaded, by part index
+    pub async fn get_area_parts(
+        &self,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<Vec<AreaPartUpload>, DatabaseError> {
         let conn = self.conn.clone();
+        let country_code = country_code.to_string();
 
         tokio::task::spawn_blocking(move || {
             let conn = conn.blocking_lock();
 
-            let total_query = "SELECT COUNT(*) as count FROM area_cids";
-            let total_count = conn.query_row(total_query, [], |row| row.get::<_, i64>(0))?;
+            let query = r#"
+            SELECT part_index, min_longitude, min_latitude, max_longitude, max_latitude, cid, file_size, compression, encryption_key_id, encryption_nonce
+            FROM area_parts
+            WHERE country_code = ?1 AND area_id = ?2
+            ORDER BY part_index
+            "#;
 
-            let countries_query = "SELECT COUNT(DISTINCT country_code) as count FROM area_cids";
-            let countries_count = conn.query_row(countries_query, [], |row| row.get::<_, i64>(0))?;
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map(rusqlite::params![&country_code, area_id as i64], |row| {
+                Ok(AreaPartUpload {
+                    part: AreaPart {
+                        index: row.get::<_, i64>(0)? as u32,
+                        min_longitude: row.get(1)?,
+                        min_latitude: row.get(2)?,
+                        max_longitude: row.get(3)?,
+                        max_latitude: row.get(4)?,
+                    },
+                    cid: row.get(5)?,
+                    file_size: row.get::<_, i64>(6)? as u64,
+                    compression: Compression::from_db_value(row.get(7)?),
+                    encryption: encryption_from_row(row, 8)?,
+                })
+            })?;
 
-            Ok((total_count as u64, countries_count as u64))
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// Add an upload that exhausted its attempts, or add to the attempts of a known failure
+    pub async fn record_failed_upload(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        path: &str,
+        reason: &str,
+        attempts: u32,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let path = path.to_string();
+        let reason = reason.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO failed_uploads (country_code, area_id, path, reason, attempts)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT (country_code, area_id) DO UPDATE SET
+                path = excluded.path,
+                reason = excluded.reason,
+                attempts = attempts + excluded.attempts,
+                last_failed_at = CURRENT_TIMESTAMP
+            "#;
+
+            conn.execute(
+                query,
+                rusqlite::params![&country_code, area_id as i64, &path, &reason, attempts],
+            )?;
+
+            Ok(())
         })
         .await?
     }
+
+    pub async fn get_failed_uploads(&self) -> Result<Vec<FailedUpload>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id, path, reason, attempts, first_failed_at, last_failed_at
+            FROM failed_uploads
+            ORDER BY first_failed_at, country_code, area_id
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok(FailedUpload {
+                    country_code: row.get(0)?,
+                    area_id: row.get::<_, i64>(1)? as u32,
+                    path: std::path::PathBuf::from(row.get::<_, String>(2)?),
+                    reason: row.get(3)?,
+                    attempts: row.get(4)?,
+                    first_failed_at: row.get(5)?,
+                    last_failed_at: row.get(6)?,
+                })
+            })?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    pub async fn delete_failed_upload(
+        &self,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            conn.execute(
+                "DELETE FROM failed_uploads WHERE country_code = ?1 AND area_id = ?2",
+                rusqlite::params![&country_code, area_id as i64],
+            )?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// CID and stored size of previously uploaded content with this hash, refreshing the
+    /// entry's path and last use when found
+    pub async fn get_cached_cid(
+        &self,
+        content_hash: &str,
+        path: &str,
+    ) -> Result<Option<(String, u64)>, DatabaseError> {
+        let conn = self.conn.clone();
+        let content_hash = content_hash.to_string();
+        let path = path.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let cid = conn
+                .query_row(
+                    "SELECT cid, file_size FROM extract_cache WHERE content_hash = ?1",
+                    [&content_hash],
+                    |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)),
+                )
+                .optional()?;
+
+            if cid.is_some() {
+                conn.execute(
+                    "UPDATE extract_cache SET path = ?2, last_used = CURRENT_TIMESTAMP WHERE content_hash = ?1",
+                    [&content_hash, &path],
+                )?;
+            }
+
+            Ok(cid)
+        })
+        .await?
+    }
+
+    pub async fn insert_cache_entry(
+        &self,
+        content_hash: &str,
+        cid: &str,
+        file_size: u64,
+        path: &str,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let content_hash = content_hash.to_string();
+        let cid = cid.to_string();
+        let path = path.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT OR REPLACE INTO extract_cache (content_hash, cid, file_size, path, last_used)
+            VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+            "#;
+
+            conn.execute(
+                query,
+                rusqlite::params![&content_hash, &cid, file_size as i64, &path],
+            )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Cache entries whose CID no longer backs any area, part or metadata sidecar, as
+    /// (hash, path, size)
+    pub async fn get_unreferenced_cache_entries(
+        &self,
+    ) -> Result<Vec<(String, String, u64)>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT content_hash, path, file_size FROM extract_cache
+            WHERE cid NOT IN (SELECT cid FROM area_cids)
+              AND cid NOT IN (SELECT cid FROM area_parts)
+              AND cid NOT IN (SELECT metadata_cid FROM area_cids WHERE metadata_cid IS NOT NULL)
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get::<_, i64>(2)? as u64))
+            })?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    pub async fn delete_cache_entries(&self, content_hashes: &[String]) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let content_hashes = content_hashes.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.blocking_lock();
+
+            let tx = conn.transaction()?;
+            for content_hash in content_hashes {
+                tx.execute("DELETE FROM extract_cache WHERE content_hash = ?1", [&content_hash])?;
+            }
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Every area with a current CID mapping and every uploaded part of a split area, read
+    /// in one query. Parts are keyed by `(country_code, area_id, part_index)`.
+    pub async fn get_uploaded_keys(
+        &self,
+    ) -> Result<(HashSet<(String, u32)>, HashSet<(String, u32, u32)>), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id, NULL FROM area_cids WHERE stale = 0
+            UNION ALL
+            SELECT country_code, area_id, part_index FROM area_parts
+            "#;
+
+            let mut areas = HashSet::new();
+            let mut parts = HashSet::new();
+            let mut stmt = conn.prepare(query)?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let country_code: String = row.get(0)?;
+                let area_id = row.get::<_, i64>(1)? as u32;
+                match row.get::<_, Option<i64>>(2)? {
+                    Some(index) => parts.insert((country_code, area_id, index as u32)),
+                    None => areas.insert((country_code, area_id)),
+                };
+            }
+
+            Ok((areas, parts))
+        })
+        .await?
+    }
+
+    pub async fn has_cid_mapping(
+        &self,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT COUNT(*) as count FROM area_cids
+            WHERE country_code = ?1 AND area_id = ?2 AND stale = 0
+            "#;
+
+            let area_id_i64 = area_id as i64;
+            let count = conn.query_row(
+                query,
+                rusqlite::params![&country_code, &area_id_i64],
+                |row| row.get::<_, i64>(0),
+            )?;
+
+            Ok(count > 0)
+        })
+        .await?
+    }
+
+    pub async fn get_cid_mapping_stats(&self) -> Result<(u64, u64), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let total_query = "SELECT COUNT(*) as count FROM area_cids";
+            let total_count = conn.query_row(total_query, [], |row| row.get::<_, i64>(0))?;
+
+            let countries_query = "SELECT COUNT(DISTINCT country_code) as count FROM area_cids";
+            let countries_count = conn.query_row(countries_query, [], |row| row.get::<_, i64>(0))?;
+
+            Ok((total_count as u64, countries_count as u64))
+        })
+        .await?
+    }
+
+    /// Current (non-stale) CID mappings, optionally limited to one country
+    pub async fn get_cid_mappings(
+        &self,
+        country_code: Option<&str>,
+    ) -> Result<Vec<CompletedUpload>, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.map(str::to_string);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id, cid, COALESCE(file_size, 0), compression,
+                encryption_key_id, encryption_nonce, metadata_cid, metadata_key_id, metadata_nonce
+            FROM area_cids
+            WHERE stale = 0 AND (?1 IS NULL OR country_code = ?1)
+            ORDER BY country_code, area_id
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([&country_code], completed_upload_from_row)?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// Current CID mappings recorded at least `days` days ago
+    pub async fn get_cid_mappings_older_than(
+        &self,
+        days: u32,
+    ) -> Result<Vec<CompletedUpload>, DatabaseError> {
+        let conn = self.conn.clone();
+        let modifier = format!("-{} days", days);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id, cid, COALESCE(file_size, 0), compression,
+                encryption_key_id, encryption_nonce, metadata_cid, metadata_key_id, metadata_nonce
+            FROM area_cids
+            WHERE stale = 0 AND upload_time <= datetime('now', ?1)
+            ORDER BY country_code, area_id
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([&modifier], completed_upload_from_row)?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// One page of a country's uploaded areas with their CIDs, by area ID, counting pages
+    /// from 1. Area details are read from `whosonfirst_db`, mapped areas it no longer holds
+    /// are left out of the page.
+    pub async fn get_areas_paginated(
+        &self,
+        whosonfirst_db: &DatabaseService,
+        country_code: &str,
+        page: u32,
+        limit: u32,
+    ) -> Result<PaginatedAreasResult, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let page = page.max(1);
+        let limit = limit.max(1);
+        let offset = (page as i64 - 1) * limit as i64;
+
+        let (total, mappings) = tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let total = conn.query_row(
+                "SELECT COUNT(*) FROM area_cids WHERE country_code = ?1 AND stale = 0",
+                [&country_code],
+                |row| row.get::<_, i64>(0),
+            )?;
+
+            let query = r#"
+            SELECT area_id, cid, COALESCE(file_size, 0)
+            FROM area_cids
+            WHERE country_code = ?1 AND stale = 0
+            ORDER BY area_id
+            LIMIT ?2 OFFSET ?3
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map(
+                rusqlite::params![&country_code, limit as i64, offset],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)? as u32,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i64>(2)? as u64,
+                    ))
+                },
+            )?;
+
+            Ok::<_, DatabaseError>((total as u32, rows.collect::<Result<Vec<_>, _>>()?))
+        })
+        .await??;
+
+        let area_ids: Vec<u32> = mappings.iter().map(|(area_id, _, _)| *area_id).collect();
+        let mut areas: HashMap<i64, AdministrativeArea> = whosonfirst_db
+            .get_areas_by_ids(&area_ids)
+            .await?
+            .into_iter()
+            .map(|area| (area.id, area))
+            .collect();
+
+        Ok(PaginatedAreasResult {
+            areas: mappings
+                .into_iter()
+                .filter_map(|(area_id, cid, file_size)| {
+                    let area = areas.remove(&(area_id as i64))?;
+                    Some(AreaInfo::new(area, file_size, cid))
+                })
+                .collect(),
+            pagination: PaginationInfo {
+                page,
+                limit,
+                total,
+                total_pages: total.div_ceil(limit),
+            },
+        })
+    }
+
+    /// Build the R-tree bounding-box queries of this WhosOnFirst database are answered from.
+    /// It is built on the first query otherwise, which takes a few seconds on a full database.
+    pub async fn create_bbox_index(&self) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || build_bbox_index(&conn.blocking_lock())).await?
+    }
+
+    /// Uploaded areas whose bounding box intersects the given one, with their CIDs, by area
+    /// ID. A `min_lon` greater than `max_lon` describes a box crossing the antimeridian.
+    /// Areas are looked up in `whosonfirst_db` through its bounding-box index, their CIDs in
+    /// this database in a single join on country and area ID.
+    pub async fn get_localities_in_bbox(
+        &self,
+        whosonfirst_db: &DatabaseService,
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+    ) -> Result<Vec<AreaInfo>, DatabaseError> {
+        let conn = whosonfirst_db.conn.clone();
+        // A box crossing the antimeridian is looked up as its two halves
+        let longitudes = match min_lon <= max_lon {
+            true => vec![(min_lon, max_lon)],
+            false => vec![(min_lon, 180.0), (-180.0, max_lon)],
+        };
+
+        let areas = tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            build_bbox_index(&conn)?;
+
+            // The R-tree stores bounds rounded outwards, candidates are checked against spr
+            let query = r#"
+            SELECT s.id, s.name, s.country, s.placetype, s.latitude, s.longitude,
+                s.min_longitude, s.min_latitude, s.max_longitude, s.max_latitude
+            FROM temp.area_bbox_index i
+            JOIN spr s ON s.id = i.id
+            WHERE i.min_longitude <= ?2 AND i.max_longitude >= ?1
+                AND i.min_latitude <= ?4 AND i.max_latitude >= ?3
+                AND s.min_longitude <= ?2 AND s.max_longitude >= ?1
+                AND s.min_latitude <= ?4 AND s.max_latitude >= ?3
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let mut areas = BTreeMap::new();
+            for (west, east) in longitudes {
+                let rows = stmt.query_map(
+                    rusqlite::params![west, east, min_lat, max_lat],
+                    AdministrativeArea::from_row,
+                )?;
+                for area in rows {
+                    let area = area?;
+                    areas.insert(area.id, area);
+                }
+            }
+
+            Ok::<_, DatabaseError>(areas.into_values().collect::<Vec<_>>())
+        })
+        .await??;
+
+        let conn = self.conn.clone();
+        let keys: Vec<(String, i64)> = areas
+            .iter()
+            .map(|area| (area.country.clone(), area.id))
+            .collect();
+        let mut cids = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.blocking_lock();
+            let tx = conn.transaction()?;
+
+            // Candidates are joined on the primary key, an area ID may be mapped under
+            // several countries
+            tx.execute_batch(
+                r#"
+                CREATE TEMP TABLE IF NOT EXISTS bbox_candidates (
+                    country_code TEXT NOT NULL,
+                    area_id INTEGER NOT NULL
+                );
+                DELETE FROM temp.bbox_candidates;
+                "#,
+            )?;
+            {
+                let mut insert = tx.prepare(
+                    "INSERT INTO temp.bbox_candidates (country_code, area_id) VALUES (?1, ?2)",
+                )?;
+                for (country_code, area_id) in &keys {
+                    insert.execute(rusqlite::params![country_code, area_id])?;
+                }
+            }
+
+            let query = r#"
+            SELECT c.area_id, c.cid, COALESCE(c.file_size, 0)
+            FROM temp.bbox_candidates b
+            JOIN area_cids c ON c.country_code = b.country_code AND c.area_id = b.area_id
+            WHERE c.stale = 0
+            "#;
+            let mut cids = HashMap::new();
+            {
+                let mut stmt = tx.prepare(query)?;
+                let rows = stmt.query_map([], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        (row.get::<_, String>(1)?, row.get::<_, i64>(2)? as u64),
+                    ))
+                })?;
+                for row in rows {
+                    let (area_id, mapping) = row?;
+                    cids.insert(area_id, mapping);
+                }
+            }
+            tx.execute("DELETE FROM temp.bbox_candidates", [])?;
+            tx.commit()?;
+
+            Ok::<_, DatabaseError>(cids)
+        })
+        .await??;
+
+        Ok(areas
+            .into_iter()
+            .filter_map(|area| {
+                let (cid, file_size) = cids.remove(&area.id)?;
+                Some(AreaInfo::new(area, file_size, cid))
+            })
+            .collect())
+    }
+
+    /// Every current upload with its area details and the times it was extracted and
+    /// uploaded, optionally limited to one country. Area details are read from
+    /// `whosonfirst_db`, mapped areas it no longer holds are left out.
+    pub async fn get_catalog_records(
+        &self,
+        whosonfirst_db: &DatabaseService,
+        country_code: Option<&str>,
+    ) -> Result<Vec<CatalogRecord>, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.map(str::to_string);
+
+        let mappings = tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT c.area_id, c.cid, COALESCE(c.file_size, 0),
+                CAST(strftime('%s', c.upload_time) AS INTEGER) * 1000,
+                CAST(strftime('%s', e.extraction_time) AS INTEGER) * 1000
+            FROM area_cids c
+            LEFT JOIN area_extractions e
+                ON e.country_code = c.country_code AND e.area_id = c.area_id
+            WHERE c.stale = 0 AND (?1 IS NULL OR c.country_code = ?1)
+            ORDER BY c.country_code, c.area_id
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([&country_code], |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as u32,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)? as u64,
+                    row.get::<_, Option<i64>>(3)?,
+                    row.get::<_, Option<i64>>(4)?,
+                ))
+            })?;
+
+            Ok::<_, DatabaseError>(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await??;
+
+        let mut areas = HashMap::new();
+        let area_ids: Vec<u32> = mappings.iter().map(|(area_id, ..)| *area_id).collect();
+        for chunk in area_ids.chunks(500) {
+            for area in whosonfirst_db.get_areas_by_ids(chunk).await? {
+                areas.insert(area.id, area);
+            }
+        }
+
+        Ok(mappings
+            .into_iter()
+            .filter_map(|(area_id, cid, file_size, uploaded_at, extracted_at)| {
+                let area = areas.get(&(area_id as i64))?.clone();
+                Some(CatalogRecord {
+                    info: AreaInfo::new(area, file_size, cid),
+                    uploaded_at,
+                    extracted_at,
+                })
+            })
+            .collect())
+    }
+
+    /// Uploaded areas and bytes per country, largest first
+    pub async fn get_bytes_by_country(&self) -> Result<Vec<CountryUsage>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, COUNT(*), COALESCE(SUM(file_size), 0)
+            FROM area_cids
+            GROUP BY country_code
+            ORDER BY SUM(file_size) DESC, country_code
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok(CountryUsage {
+                    country_code: row.get(0)?,
+                    area_count: row.get::<_, i64>(1)? as u64,
+                    total_bytes: row.get::<_, i64>(2)? as u64,
+                })
+            })?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    pub async fn record_run_stats(&self, run: &RunStats) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let run = run.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO run_stats (started_at, finished_at, uploaded, reused, failed, bytes_uploaded)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#;
+
+            conn.execute(
+                query,
+                rusqlite::params![
+                    &run.started_at,
+                    &run.finished_at,
+                    run.stats.total_uploaded as i64,
+                    run.stats.total_reused as i64,
+                    run.stats.total_failed as i64,
+                    run.stats.total_bytes_uploaded as i64,
+                ],
+            )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Number of recorded runs and their summed statistics
+    pub async fn get_lifetime_stats(&self) -> Result<(u64, UploadStats), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT COUNT(*), COALESCE(SUM(uploaded), 0), COALESCE(SUM(reused), 0),
+                COALESCE(SUM(failed), 0), COALESCE(SUM(bytes_uploaded), 0)
+            FROM run_stats
+            "#;
+
+            let lifetime = conn.query_row(query, [], |row| {
+                Ok((
+                    row.get::<_, i64>(0)? as u64,
+                    UploadStats {
+                        total_uploaded: row.get::<_, i64>(1)? as u64,
+                        total_reused: row.get::<_, i64>(2)? as u64,
+                        total_failed: row.get::<_, i64>(3)? as u64,
+                        total_bytes_uploaded: row.get::<_, i64>(4)? as u64,
+                    },
+                ))
+            })?;
+
+            Ok(lifetime)
+        })
+        .await?
+    }
+
+    /// The `limit` most recent runs, newest first
+    pub async fn get_recent_runs(&self, limit: u32) -> Result<Vec<RunStats>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT started_at, finished_at, uploaded, reused, failed, bytes_uploaded
+            FROM run_stats
+            ORDER BY id DESC
+            LIMIT ?1
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([limit], |row| {
+                Ok(RunStats {
+                    started_at: row.get(0)?,
+                    finished_at: row.get(1)?,
+                    stats: UploadStats {
+                        total_uploaded: row.get::<_, i64>(2)? as u64,
+                        total_reused: row.get::<_, i64>(3)? as u64,
+                        total_failed: row.get::<_, i64>(4)? as u64,
+                        total_bytes_uploaded: row.get::<_, i64>(5)? as u64,
+                    },
+                })
+            })?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    pub async fn record_published_index(
+        &self,
+        root_cid: &str,
+        country_count: u64,
+        area_count: u64,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let root_cid = root_cid.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO published_indexes (root_cid, country_count, area_count, published_at)
+            VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+            "#;
+
+            conn.execute(
+                query,
+                rusqlite::params![&root_cid, country_count as i64, area_count as i64],
+            )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// The most recently published dataset index, `None` before the first one
+    pub async fn get_latest_published_index(
+        &self,
+    ) -> Result<Option<PublishedIndex>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT root_cid, country_count, area_count,
+                strftime('%Y-%m-%dT%H:%M:%SZ', published_at)
+            FROM published_indexes
+            ORDER BY id DESC
+            LIMIT 1
+            "#;
+
+            let index = conn
+                .query_row(query, [], |row| {
+                    Ok(PublishedIndex {
+                        root_cid: row.get(0)?,
+                        country_count: row.get::<_, i64>(1)? as u64,
+                        area_count: row.get::<_, i64>(2)? as u64,
+                        published_at: row.get(3)?,
+                    })
+                })
+                .optional()?;
+
+            Ok(index)
+        })
+        .await?
+    }
+
+    pub async fn record_db_snapshot(
+        &self,
+        cid: &str,
+        size: u64,
+        compression: Compression,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let cid = cid.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO db_snapshots (cid, size, compression, created_at)
+            VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+            "#;
+
+            conn.execute(
+                query,
+                rusqlite::params![&cid, size as i64, compression.as_db_value()],
+            )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// The most recently uploaded database snapshot, `None` before the first one
+    pub async fn get_latest_db_snapshot(&self) -> Result<Option<DbSnapshot>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT cid, size, compression, strftime('%Y-%m-%dT%H:%M:%SZ', created_at)
+            FROM db_snapshots
+            ORDER BY id DESC
+            LIMIT 1
+            "#;
+
+            let snapshot = conn
+                .query_row(query, [], |row| {
+                    Ok(DbSnapshot {
+                        cid: row.get(0)?,
+                        size: row.get::<_, i64>(1)? as u64,
+                        compression: Compression::from_db_value(row.get(2)?),
+                        created_at: row.get(3)?,
+                    })
+                })
+                .optional()?;
+
+            Ok(snapshot)
+        })
+        .await?
+    }
+
+    /// Problems reported by SQLite's integrity check, empty when the database is sound
+    pub async fn integrity_check(&self) -> Result<Vec<String>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+            let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+            let problems = rows
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|line| line != "ok")
+                .collect();
+
+            Ok(problems)
+        })
+        .await?
+    }
+
+    /// Refresh the query planner statistics and rebuild the file without its free pages.
+    /// Fails while another connection holds the database, e.g. a running node.
+    pub async fn compact(&self) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            conn.execute_batch("ANALYZE; VACUUM;")?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Copy the database to `path` with SQLite's online backup API. The copy is consistent
+    /// even while other connections, e.g. a running node, write to the database.
+    pub async fn backup_to(&self, path: &Path) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let path = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let mut destination = Connection::open(&path)?;
+            let backup = Backup::new(&conn, &mut destination)?;
+            backup.run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Replace the database's content with the backup at `path`
+    pub async fn restore_from(&self, path: &Path) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let path = path.to_path_buf();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.blocking_lock();
+
+            let source = Connection::open_with_flags(&path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            let backup = Backup::new(&source, &mut conn)?;
+            backup.run_to_completion(BACKUP_PAGES_PER_STEP, BACKUP_STEP_PAUSE, None)?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Whether the database holds CID mappings, to tell a CID database from another file
+    pub async fn has_cid_tables(&self) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            Ok(!table_columns(&conn, "area_cids")?.is_empty())
+        })
+        .await?
+    }
+
+    /// Checkpoint left by a run that did not finish, `None` when the last run completed
+    pub async fn get_run_checkpoint(&self) -> Result<Option<RunCheckpoint>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT phase, last_country_code, last_area_id,
+                strftime('%Y-%m-%dT%H:%M:%SZ', updated_at)
+            FROM run_checkpoint
+            WHERE id = 1
+            "#;
+
+            let row = conn
+                .query_row(query, [], |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<i64>>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                })
+                .optional()?;
+            let Some((phase, last_country_code, last_area_id, updated_at)) = row else {
+                return Ok(None);
+            };
+            // A phase written by a newer version is not resumed from
+            let Ok(phase) = phase.parse::<RunPhase>() else {
+                return Ok(None);
+            };
+
+            let mut extracted_countries = HashSet::new();
+            let mut uploaded_countries = HashSet::new();
+            let mut stmt = conn.prepare("SELECT phase, country_code FROM checkpoint_countries")?;
+            let rows = stmt.query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (country_phase, country_code) = row?;
+                match country_phase.parse::<RunPhase>() {
+                    Ok(RunPhase::Extraction) => extracted_countries.insert(country_code),
+                    Ok(RunPhase::Upload) => uploaded_countries.insert(country_code),
+                    Err(_) => continue,
+                };
+            }
+
+            Ok(Some(RunCheckpoint {
+                phase,
+                extracted_countries,
+                uploaded_countries,
+                last_area: last_country_code.zip(last_area_id.map(|id| id as u32)),
+                updated_at,
+            }))
+        })
+        .await?
+    }
+
+    /// Record the phase the current run entered, keeping the progress already checkpointed
+    pub async fn set_checkpoint_phase(&self, phase: RunPhase) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO run_checkpoint (id, phase, updated_at)
+            VALUES (1, ?1, CURRENT_TIMESTAMP)
+            ON CONFLICT(id) DO UPDATE SET
+                phase = excluded.phase,
+                updated_at = excluded.updated_at
+            "#;
+
+            conn.execute(query, [phase.to_string()])?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Record that `phase` finished for a country
+    pub async fn record_checkpoint_country(
+        &self,
+        phase: RunPhase,
+        country_code: &str,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            conn.execute(
+                "INSERT OR IGNORE INTO checkpoint_countries (phase, country_code) VALUES (?1, ?2)",
+                rusqlite::params![phase.to_string(), &country_code],
+            )?;
+            conn.execute(
+                "UPDATE run_checkpoint SET updated_at = CURRENT_TIMESTAMP WHERE id = 1",
+                [],
+            )?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Record the last area extracted by the current run
+    pub async fn record_checkpoint_area(
+        &self,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            UPDATE run_checkpoint
+            SET last_country_code = ?1, last_area_id = ?2, updated_at = CURRENT_TIMESTAMP
+            WHERE id = 1
+            "#;
+
+            conn.execute(query, rusqlite::params![&country_code, area_id as i64])?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Forget the checkpoint, so the next run starts from scratch
+    pub async fn clear_run_checkpoint(&self) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.blocking_lock();
+
+            let tx = conn.transaction()?;
+            tx.execute("DELETE FROM run_checkpoint", [])?;
+            tx.execute("DELETE FROM checkpoint_countries", [])?;
+            tx.commit()?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Record an area another node announced. Returns false when the same CID was already
+    /// known for it, so announcements are only passed on once.
+    pub async fn record_peer_cid(
+        &self,
+        announcement: &CidAnnouncement,
+    ) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+        let announcement = announcement.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO peer_cids (origin, country_code, area_id, cid, file_size, received_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, CURRENT_TIMESTAMP)
+            ON CONFLICT(origin, country_code, area_id) DO UPDATE SET
+                cid = excluded.cid,
+                file_size = excluded.file_size,
+                received_at = excluded.received_at
+            WHERE peer_cids.cid != excluded.cid
+            "#;
+
+            let changed = conn.execute(
+                query,
+                rusqlite::params![
+                    &announcement.origin,
+                    &announcement.country_code,
+                    announcement.area_id,
+                    &announcement.cid,
+                    announcement.file_size as i64,
+                ],
+            )?;
+
+            Ok(changed > 0)
+        })
+        .await?
+    }
+
+    /// Number of areas and of distinct nodes in the catalog built from announcements
+    pub async fn count_peer_cids(&self) -> Result<(u64, u64), DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT COUNT(*), COUNT(DISTINCT origin) FROM peer_cids
+            "#;
+
+            let counts = conn.query_row(query, [], |row| {
+                Ok((row.get::<_, i64>(0)? as u64, row.get::<_, i64>(1)? as u64))
+            })?;
+
+            Ok(counts)
+        })
+        .await?
+    }
+
+    /// Average size of uploaded extracts, or `None` when nothing was uploaded yet
+    pub async fn get_average_file_size(&self) -> Result<Option<u64>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = "SELECT AVG(file_size) FROM area_cids WHERE file_size IS NOT NULL";
+            let average = conn.query_row(query, [], |row| row.get::<_, Option<f64>>(0))?;
+
+            Ok(average.map(|avg| avg as u64))
+        })
+        .await?
+    }
+
+    pub async fn record_extraction(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        planet_version: &str,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let planet_version = planet_version.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT OR REPLACE INTO area_extractions
+            (country_code, area_id, planet_version, extraction_time)
+            VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+            "#;
+
+            let area_id_i64 = area_id as i64;
+            conn.execute(
+                query,
+                rusqlite::params![&country_code, &area_id_i64, &planet_version],
+            )?;
+            conn.execute(
+                "DELETE FROM extraction_failures WHERE country_code = ?1 AND area_id = ?2",
+                rusqlite::params![&country_code, &area_id_i64],
+            )?;
+            conn.execute(
+                "DELETE FROM oversized_extracts WHERE country_code = ?1 AND area_id = ?2",
+                rusqlite::params![&country_code, &area_id_i64],
+            )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Record an extract found on disk as extracted from `planet_version`, unless its
+    /// extraction is already recorded. Extracts predating the record are adopted this way,
+    /// so the next planet change invalidates them like any other.
+    pub async fn adopt_extraction(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        planet_version: &str,
+    ) -> Result<bool, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let planet_version = planet_version.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT OR IGNORE INTO area_extractions
+            (country_code, area_id, planet_version, extraction_time)
+            VALUES (?1, ?2, ?3, CURRENT_TIMESTAMP)
+            "#;
+
+            let adopted = conn.execute(
+                query,
+                rusqlite::params![&country_code, area_id as i64, &planet_version],
+            )?;
+            Ok(adopted > 0)
+        })
+        .await?
+    }
+
+    /// The custom region of a bounding box, recorded under the next free ID from
+    /// `CUSTOM_AREA_ID_BASE` unless the same box already was. A `name` replaces the one the
+    /// box was recorded with, regions without one are named after their box.
+    pub async fn record_custom_area(
+        &self,
+        bbox: BoundingBox,
+        name: Option<&str>,
+    ) -> Result<AdministrativeArea, DatabaseError> {
+        let conn = self.conn.clone();
+        let name = name.map(str::to_string);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+            let bounds = rusqlite::params![
+                bbox.min_longitude,
+                bbox.min_latitude,
+                bbox.max_longitude,
+                bbox.max_latitude
+            ];
+
+            let query = r#"
+            INSERT OR IGNORE INTO custom_areas
+            (id, min_longitude, min_latitude, max_longitude, max_latitude)
+            SELECT MAX(COALESCE(MAX(id) + 1, 0), ?5), ?1, ?2, ?3, ?4 FROM custom_areas
+            "#;
+            conn.execute(
+                query,
+                rusqlite::params![
+                    bbox.min_longitude,
+                    bbox.min_latitude,
+                    bbox.max_longitude,
+                    bbox.max_latitude,
+                    CUSTOM_AREA_ID_BASE as i64
+                ],
+            )?;
+
+            let query = r#"
+            SELECT id FROM custom_areas
+            WHERE min_longitude = ?1 AND min_latitude = ?2 AND max_longitude = ?3 AND max_latitude = ?4
+            "#;
+            let id = conn.query_row(query, bounds, |row| row.get::<_, i64>(0))?;
+            if let Some(name) = &name {
+                conn.execute(
+                    "UPDATE custom_areas SET name = ?1 WHERE id = ?2",
+                    rusqlite::params![name, id],
+                )?;
+            }
+            Ok(custom_area(bbox, id as u32, name))
+        })
+        .await?
+    }
+
+    /// A custom region recorded by `record_custom_area`
+    pub async fn get_custom_area(
+        &self,
+        area_id: u32,
+    ) -> Result<Option<AdministrativeArea>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT min_longitude, min_latitude, max_longitude, max_latitude, name
+            FROM custom_areas
+            WHERE id = ?1
+            "#;
+            Ok(conn
+                .query_row(query, [area_id as i64], |row| {
+                    let bbox = BoundingBox {
+                        min_longitude: row.get(0)?,
+                        min_latitude: row.get(1)?,
+                        max_longitude: row.get(2)?,
+                        max_latitude: row.get(3)?,
+                    };
+                    Ok(custom_area(bbox, area_id, row.get(4)?))
+                })
+                .optional()?)
+        })
+        .await?
+    }
+
+    /// Areas not to extract this run, as (country_code, area_id): those that failed
+    /// `max_attempts` times, 0 for no limit, and those whose last attempt is more recent
+    /// than `retry_after`
+    pub async fn get_held_back_extractions(
+        &self,
+        max_attempts: u32,
+        retry_after: std::time::Duration,
+    ) -> Result<HashSet<(String, u32)>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id
+            FROM extraction_failures
+            WHERE (?1 > 0 AND attempts >= ?1) OR failed_at > datetime('now', ?2)
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map(
+                rusqlite::params![max_attempts, format!("-{} seconds", retry_after.as_secs())],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u32)),
+            )?;
+
+            Ok(rows.collect::<Result<HashSet<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// Record a failed extraction attempt, keeping the error and log of the latest one
+    pub async fn record_extraction_failure(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        error: &str,
+        log_path: Option<&str>,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let error = error.to_string();
+        let log_path = log_path.map(str::to_string);
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT INTO extraction_failures (country_code, area_id, error, log_path)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (country_code, area_id) DO UPDATE SET
+                error = excluded.error,
+                log_path = excluded.log_path,
+                attempts = attempts + 1,
+                failed_at = CURRENT_TIMESTAMP
+            "#;
+
+            conn.execute(
+                query,
+                rusqlite::params![&country_code, area_id as i64, &error, &log_path],
+            )?;
+
+            Ok(())
+        })
+        .await?
+    }
+
+    pub async fn get_extraction_failures(&self) -> Result<Vec<ExtractionFailure>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id, error, log_path, attempts, failed_at
+            FROM extraction_failures
+            ORDER BY country_code, area_id
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok(ExtractionFailure {
+                    country_code: row.get(0)?,
+                    area_id: row.get::<_, i64>(1)? as u32,
+                    error: row.get(2)?,
+                    log_path: row.get::<_, Option<String>>(3)?.map(std::path::PathBuf::from),
+                    attempts: row.get(4)?,
+                    failed_at: row.get(5)?,
+                })
+            })?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// Record an extract deleted for exceeding MAX_OUTPUT_SIZE
+    pub async fn record_oversized_extract(
+        &self,
+        country_code: &str,
+        area_id: u32,
+        file_size: u64,
+        planet_version: &str,
+    ) -> Result<(), DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+        let planet_version = planet_version.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            INSERT OR REPLACE INTO oversized_extracts
+            (country_code, area_id, file_size, planet_version, skipped_at)
+            VALUES (?1, ?2, ?3, ?4, CURRENT_TIMESTAMP)
+            "#;
+            conn.execute(
+                query,
+                rusqlite::params![country_code, area_id as i64, file_size as i64, planet_version],
+            )?;
+            Ok(())
+        })
+        .await?
+    }
+
+    /// Areas not to extract again, as (country_code, area_id): those whose extract of
+    /// `planet_version` was larger than `max_size`. Areas skipped under a smaller budget or
+    /// from another planet build are tried again.
+    pub async fn get_oversized_keys(
+        &self,
+        planet_version: &str,
+        max_size: u64,
+    ) -> Result<HashSet<(String, u32)>, DatabaseError> {
+        let conn = self.conn.clone();
+        let planet_version = planet_version.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id
+            FROM oversized_extracts
+            WHERE planet_version = ?1 AND file_size > ?2
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map(
+                rusqlite::params![planet_version, max_size as i64],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u32)),
+            )?;
+
+            Ok(rows.collect::<Result<HashSet<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// Extracts deleted for exceeding MAX_OUTPUT_SIZE, largest first
+    pub async fn get_oversized_extracts(&self) -> Result<Vec<OversizedExtract>, DatabaseError> {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT country_code, area_id, file_size, skipped_at
+            FROM oversized_extracts
+            ORDER BY file_size DESC, country_code, area_id
+            "#;
+
+            let mut stmt = conn.prepare(query)?;
+            let rows = stmt.query_map([], |row| {
+                Ok(OversizedExtract {
+                    country_code: row.get(0)?,
+                    area_id: row.get::<_, i64>(1)? as u32,
+                    file_size: row.get::<_, i64>(2)? as u64,
+                    skipped_at: row.get(3)?,
+                })
+            })?;
+
+            Ok(rows.collect::<Result<Vec<_>, _>>()?)
+        })
+        .await?
+    }
+
+    /// When an area was last extracted, RFC 3339
+    pub async fn get_extraction_time(
+        &self,
+        country_code: &str,
+        area_id: u32,
+    ) -> Result<Option<String>, DatabaseError> {
+        let conn = self.conn.clone();
+        let country_code = country_code.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let conn = conn.blocking_lock();
+
+            let query = r#"
+            SELECT strftime('%Y-%m-%dT%H:%M:%SZ', extraction_time)
+            FROM area_extractions
+            WHERE country_code = ?1 AND area_id = ?2
+            "#;
+
+            let area_id_i64 = area_id as i64;
+            let time = conn
+                .query_row(query, rusqlite::params![&country_code, &area_id_i64], |row| {
+                    row.get::<_, Option<String>>(0)
+                })
+                .optional()?;
+
+            Ok(time.flatten())
+        })
+        .await?
+    }
+
+    /// Flags the CID mappings of every area extracted from a different planet build
+    /// as stale, and returns the affected (country_code, area_id) pairs.
+    pub async fn invalidate_stale_extractions(
+        &self,
+        planet_version: &str,
+    ) -> Result<Vec<(String, u32)>, DatabaseError> {
+        let conn = self.conn.clone();
+        let planet_version = planet_version.to_string();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.blocking_lock();
+
+            let tx = conn.transaction()?;
+
+            let stale_areas = {
+                let mut stmt = tx.prepare(
+                    "SELECT country_code, area_id FROM area_extractions WHERE planet_version != ?1",
+                )?;
+                let rows = stmt.query_map([&planet_version], |row| {
+                    Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u32))
+                })?;
+                rows.collect::<Result<Vec<_>, _>>()?
+            };
+
+            tx.execute(
+                r#"
+                UPDATE area_cids SET stale = 1
+                WHERE (country_code, area_id) IN (
+                    SELECT country_code, area_id FROM area_extractions WHERE planet_version != ?1
+                )
+                "#,
+                [&planet_version],
+            )?;
+
+            tx.execute(
+                r#"
+                DELETE FROM area_parts
+                WHERE (country_code, area_id) IN (
+                    SELECT country_code, area_id FROM area_extractions WHERE planet_version != ?1
+                )
+                "#,
+                [&planet_version],
+            )?;
+
+            tx.commit()?;
+            Ok(stale_areas)
+        })
+        .await?
+    }
+}
+
+/// Adds a column to an existing table when it predates the column's introduction
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), rusqlite::Error> {
+    let columns = table_columns(conn, table)?;
+
+    if !columns.iter().any(|name| name == column) {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Mapping read from `area_cids` as (country_code, area_id, cid, file_size, compression,
+/// encryption_key_id, encryption_nonce, metadata_cid, metadata_key_id, metadata_nonce)
+fn completed_upload_from_row(row: &rusqlite::Row) -> Result<CompletedUpload, rusqlite::Error> {
+    let mut upload = CompletedUpload::new(
+        row.get(0)?,
+        row.get::<_, i64>(1)? as u32,
+        row.get(2)?,
+        row.get::<_, i64>(3)? as u64,
+    );
+    upload.compression = Compression::from_db_value(row.get(4)?);
+    upload.encryption = encryption_from_row(row, 5)?;
+    upload.metadata_cid = row.get(7)?;
+    upload.metadata_encryption = encryption_from_row(row, 8)?;
+    Ok(upload)
+}
+
+/// Encryption parameters from the key ID and nonce columns starting at `index`
+fn encryption_from_row(
+    row: &rusqlite::Row,
+    index: usize,
+) -> Result<Option<EncryptionInfo>, rusqlite::Error> {
+    let key_id: Option<String> = row.get(index)?;
+    let nonce_prefix: Option<String> = row.get(index + 1)?;
+    Ok(key_id
+        .zip(nonce_prefix)
+        .map(|(key_id, nonce_prefix)| EncryptionInfo { key_id, nonce_prefix }))
+}
+
+/// The custom region of a box, named after it unless recorded with a name
+fn custom_area(bbox: BoundingBox, id: u32, name: Option<String>) -> AdministrativeArea {
+    let mut area = bbox.custom_area(id);
+    if let Some(name) = name {
+        area.name = name;
+    }
+    area
+}
+
+/// SQL expression giving the population of the `spr` row being queried. WOF distributions
+/// keep it in the `wof:population` property (falling back to GeoNames' `gn:population`) of
+/// the `properties` or `geojson` tables; some trimmed builds add a `population` column to
+/// `spr` instead.
+fn population_source(conn: &Connection) -> Result<Option<String>, rusqlite::Error> {
+    let has_columns = |table: &str| -> Result<bool, rusqlite::Error> {
+        let columns = table_columns(conn, table)?;
+        Ok(["id", "body"].iter().all(|c| columns.iter().any(|col| col == c)))
+    };
+
+    if table_columns(conn, "spr")?.iter().any(|c| c == "population") {
+        return Ok(Some("spr.population".to_string()));
+    }
+
+    for (table, prefix) in [("properties", "$"), ("geojson", "$.properties")] {
+        if has_columns(table)? {
+            return Ok(Some(format!(
+                r#"(SELECT COALESCE(json_extract(body, '{prefix}."wof:population"'), json_extract(body, '{prefix}."gn:population"')) FROM {table} WHERE {table}.id = spr.id)"#,
+                prefix = prefix,
+                table = table
+            )));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Column names of a table in an attached database, empty when it does not have the table
+fn schema_table_columns(
+    conn: &Connection,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {}.table_info({})", schema, table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+/// Fill the bounding-box R-tree of a WhosOnFirst connection unless it already exists. Areas
+/// whose bounds are missing or inverted cannot be indexed and are left out.
+fn build_bbox_index(conn: &Connection) -> Result<(), DatabaseError> {
+    let exists = conn
+        .prepare("SELECT 1 FROM temp.sqlite_master WHERE name = 'area_bbox_index'")?
+        .exists([])?;
+    if exists {
+        return Ok(());
+    }
+
+    let tx = conn.unchecked_transaction()?;
+    tx.execute_batch(BBOX_INDEX_SCHEMA)?;
+    tx.execute(
+        "INSERT INTO temp.area_bbox_index
+         SELECT id, min_longitude, max_longitude, min_latitude, max_latitude
+         FROM spr
+         WHERE placetype IN ('region', 'county') AND is_current = 1 AND is_deprecated = 0
+             AND min_longitude <= max_longitude AND min_latitude <= max_latitude",
+        [],
+    )?;
+    tx.commit()?;
+    Ok(())
+}
+
+/// Name WhosOnFirst gives the admin database of a country, e.g.
+/// `whosonfirst-data-admin-fr-latest.db`
+pub fn whosonfirst_bundle_file_name(country_code: &str) -> String {
+    format!(
+        "whosonfirst-data-admin-{}-latest.db",
+        country_code.to_lowercase()
+    )
+}
+
+/// Country of a per-country WhosOnFirst database, read from the code after `admin` in its
+/// file name
+pub fn bundle_country(path: &Path) -> Option<String> {
+    let stem = path.file_stem()?.to_str()?;
+    let mut parts = stem.split('-');
+    parts.find(|part| *part == "admin")?;
+    parts
+        .next()
+        .filter(|code| code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|code| code.to_uppercase())
+}
+
+/// SQLite files of a per-country WhosOnFirst directory, sorted by name
+pub fn whosonfirst_bundle_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "db") {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Files of a per-country WhosOnFirst directory a bundle of `countries` attaches, every file
+/// when `countries` is empty. Files whose name does not tell their country are always kept.
+pub fn select_whosonfirst_bundle_files(
+    dir: &Path,
+    countries: &[String],
+) -> std::io::Result<Vec<PathBuf>> {
+    Ok(whosonfirst_bundle_files(dir)?
+        .into_iter()
+        .filter(|path| {
+            countries.is_empty()
+                || bundle_country(path).is_none_or(|country| {
+                    countries
+                        .iter()
+                        .any(|target| target.eq_ignore_ascii_case(&country))
+                })
+        })
+        .collect())
+}
+
+/// Column names of a table, empty when the table does not exist
+fn table_columns(conn: &Connection, table: &str) -> Result<Vec<String>, rusqlite::Error> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(columns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mapping(country_code: &str, area_id: u32, cid: &str) -> CompletedUpload {
+        CompletedUpload::new(country_code.to_string(), area_id, cid.to_string(), 1024)
+    }
+
+    /// WhosOnFirst database whose spr table holds `rows`, given as SQL value tuples
+    async fn whosonfirst_db(rows: &str) -> DatabaseService {
+        let db = DatabaseService::in_memory(false).await.unwrap();
+        db.load_spr_fixture(&[]).await.unwrap();
+        {
+            let conn = db.conn.lock().await;
+            conn.execute(&format!("INSERT INTO spr VALUES {}", rows), []).unwrap();
+        }
+        db
+    }
+
+    #[tokio::test]
+    async fn integrity_check_catches_damaged_and_empty_databases() {
+        let db = whosonfirst_db("(1, 'Alsace', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0)").await;
+        assert!(db.check_integrity().await.unwrap().is_empty());
+
+        let empty = whosonfirst_db("(1, 'Alsace', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0)").await;
+        empty.conn.lock().await.execute("DELETE FROM spr", []).unwrap();
+        assert_eq!(
+            empty.check_integrity().await.unwrap(),
+            vec!["the spr table is empty"]
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("whosonfirst.db");
+        std::fs::write(&path, vec![0x42; 4096]).unwrap();
+        let damaged = DatabaseService::new(&path.to_string_lossy(), false)
+            .await
+            .unwrap();
+        assert!(!damaged.check_integrity().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn area_counts_cover_every_country_in_one_query() {
+        let db = whosonfirst_db(
+            "(1, 'Alsace', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0),
+             (2, 'Paris', 'FR', 'locality', 0, 0, 0, 0, 1, 1, 1, 0),
+             (3, 'Bayern', 'DE', 'region', 0, 0, 0, 0, 1, 1, 1, 0),
+             (4, 'Baden', 'DE', 'region', 0, 0, 0, 0, 1, 1, 0, 1),
+             (5, 'Cork', 'IE', 'county', 0, 0, 0, 0, 1, 1, 1, 0)",
+        )
+        .await;
+
+        let counts = db.get_country_area_counts().await.unwrap();
+        assert_eq!(counts.len(), 3);
+        assert_eq!((counts["FR"], counts["DE"], counts["IE"]), (1, 1, 1));
+        assert_eq!(db.get_country_area_count("FR").await.unwrap(), counts["FR"]);
+    }
+
+    #[tokio::test]
+    async fn cid_mappings_are_filtered_by_country_and_skip_stale_areas() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[
+            mapping("DE", 2, "cid-de-2"),
+            mapping("FR", 1, "cid-fr-1"),
+            mapping("DE", 1, "cid-de-1"),
+        ])
+        .await
+        .unwrap();
+        db.record_extraction("DE", 1, "20240101").await.unwrap();
+        db.record_extraction("DE", 2, "20240201").await.unwrap();
+        db.record_extraction("FR", 1, "20240201").await.unwrap();
+        db.invalidate_stale_extractions("20240201").await.unwrap();
+
+        let keys = |mappings: Vec<CompletedUpload>| -> Vec<(String, u32, String)> {
+            mappings
+                .into_iter()
+                .map(|m| (m.country_code, m.area_id, m.cid))
+                .collect()
+        };
+
+        assert_eq!(
+            keys(db.get_cid_mappings(None).await.unwrap()),
+            vec![
+                ("DE".to_string(), 2, "cid-de-2".to_string()),
+                ("FR".to_string(), 1, "cid-fr-1".to_string()),
+            ]
+        );
+        assert_eq!(
+            keys(db.get_cid_mappings(Some("FR")).await.unwrap()),
+            vec![("FR".to_string(), 1, "cid-fr-1".to_string())]
+        );
+        assert!(db.get_cid_mappings(Some("IT")).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn adopted_extracts_are_invalidated_by_the_next_planet() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[mapping("FR", 1, "cid-1"), mapping("FR", 2, "cid-2")])
+            .await
+            .unwrap();
+        db.record_extraction("FR", 2, "20240101").await.unwrap();
+
+        // Only the extract without a recorded extraction is adopted
+        assert!(db.adopt_extraction("FR", 1, "20240101").await.unwrap());
+        assert!(!db.adopt_extraction("FR", 2, "20240201").await.unwrap());
+        assert!(db.invalidate_stale_extractions("20240101").await.unwrap().is_empty());
+
+        let mut stale = db.invalidate_stale_extractions("20240201").await.unwrap();
+        stale.sort();
+        assert_eq!(stale, vec![("FR".to_string(), 1), ("FR".to_string(), 2)]);
+        assert!(db.get_cid_mappings(None).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn country_records_are_found_apart_from_their_areas() {
+        let db = whosonfirst_db(
+            "(85633147, 'France', 'FR', 'country', 0, 0, -5, 41, 10, 51, 1, 0),
+             (1, 'Alsace', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0)",
+        )
+        .await;
+
+        let france = db.get_country_record("FR").await.unwrap().unwrap();
+        assert_eq!((france.id, france.max_latitude), (85633147, 51.0));
+        assert!(db.get_country_record("DE").await.unwrap().is_none());
+        assert!(db.get_area_by_id(85633147).await.unwrap().is_some());
+        assert_eq!(db.get_country_area_count("FR").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn spr_fixture_answers_area_queries() {
+        use crate::testing::fixture_area;
+
+        let db = DatabaseService::in_memory(false).await.unwrap();
+        db.load_spr_fixture(&[
+            fixture_area(1, "Alsace", "FR", "region", [7.0, 47.5, 8.2, 49.1]),
+            fixture_area(2, "Cork", "IE", "county", [-10.2, 51.4, -7.8, 52.4]),
+            fixture_area(3, "Paris", "FR", "locality", [2.2, 48.8, 2.5, 48.9]),
+        ])
+        .await
+        .unwrap();
+
+        let areas = db.get_country_areas("FR", None).await.unwrap();
+        assert_eq!(areas.len(), 1);
+        assert_eq!((areas[0].id, areas[0].latitude), (1, 48.3));
+        assert!(!db.has_cid_tables().await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn bundles_answer_queries_over_each_country_database() {
+        use crate::testing::fixture_area;
+
+        let dir = tempfile::tempdir().unwrap();
+        for area in [
+            fixture_area(1, "Alsace", "FR", "region", [7.0, 47.5, 8.2, 49.1]),
+            fixture_area(2, "Cork", "IE", "county", [-10.2, 51.4, -7.8, 52.4]),
+        ] {
+            let path = dir.path().join(whosonfirst_bundle_file_name(&area.country));
+            DatabaseService::new(&path.to_string_lossy(), false)
+                .await
+                .unwrap()
+                .load_spr_fixture(&[area])
+                .await
+                .unwrap();
+        }
+
+        let db = DatabaseService::open_whosonfirst_bundle(dir.path(), &[])
+            .await
+            .unwrap();
+        assert_eq!(db.get_country_areas("FR", None).await.unwrap()[0].id, 1);
+        assert_eq!(db.get_area_by_id(2).await.unwrap().unwrap().country, "IE");
+        assert_eq!(db.get_country_area_counts().await.unwrap().len(), 2);
+
+        let db = DatabaseService::open_whosonfirst_bundle(dir.path(), &["IE".to_string()])
+            .await
+            .unwrap();
+        assert!(db.get_country_areas("FR", None).await.unwrap().is_empty());
+        assert!(matches!(
+            DatabaseService::open_whosonfirst_bundle(dir.path(), &["DE".to_string()]).await,
+            Err(DatabaseError::EmptyBundle(_))
+        ));
+    }
+
+    #[test]
+    fn bundles_select_the_files_of_the_target_countries() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in [
+            whosonfirst_bundle_file_name("FR"),
+            whosonfirst_bundle_file_name("IE"),
+            whosonfirst_bundle_file_name("DE"),
+            "custom.db".to_string(),
+        ] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let names = |countries: &[&str]| -> Vec<String> {
+            let countries: Vec<String> = countries.iter().map(|c| c.to_string()).collect();
+            select_whosonfirst_bundle_files(dir.path(), &countries)
+                .unwrap()
+                .iter()
+                .map(|path| bundle_country(path).unwrap_or_else(|| "?".to_string()))
+                .collect()
+        };
+        assert_eq!(names(&[]).len(), 4);
+        let mut selected = names(&["fr", "IE"]);
+        selected.sort();
+        assert_eq!(selected, vec!["?", "FR", "IE"]);
+    }
+
+    #[tokio::test]
+    async fn schema_and_placetypes_are_checked() {
+        use crate::testing::fixture_area;
+
+        let db = DatabaseService::in_memory(false).await.unwrap();
+        assert!(matches!(
+            db.validate_whosonfirst_schema().await,
+            Err(DatabaseError::MissingSprTable)
+        ));
+
+        db.load_spr_fixture(&[fixture_area(
+            1,
+            "75001",
+            "FR",
+            "postalcode",
+            [2.3, 48.8, 2.4, 48.9],
+        )])
+        .await
+        .unwrap();
+        db.validate_whosonfirst_schema().await.unwrap();
+        assert_eq!(
+            db.missing_placetypes().await.unwrap(),
+            vec!["country", "region"]
+        );
+
+        db.load_spr_fixture(&[
+            fixture_area(85633147, "France", "FR", "country", [-5.0, 41.0, 10.0, 51.0]),
+            fixture_area(2, "Alsace", "FR", "region", [7.0, 47.5, 8.2, 49.1]),
+        ])
+        .await
+        .unwrap();
+        assert!(db.missing_placetypes().await.unwrap().is_empty());
+    }
+
+    #[test]
+    fn bundle_files_are_named_by_country() {
+        assert_eq!(
+            bundle_country(Path::new("whosonfirst-data-admin-fr-latest.db")),
+            Some("FR".to_string())
+        );
+        assert_eq!(
+            bundle_country(Path::new("whosonfirst-data-admin-latest.db")),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn areas_are_paginated_with_their_cids() {
+        let whosonfirst_db = whosonfirst_db(
+            "(1, 'Alsace', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0),
+             (2, 'Bretagne', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0),
+             (3, 'Corse', 'FR', 'region', 0, 0, 0, 0, 1, 1, 1, 0)",
+        )
+        .await;
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[
+            mapping("FR", 3, "cid-3"),
+            mapping("FR", 1, "cid-1"),
+            mapping("FR", 2, "cid-2"),
+            mapping("DE", 4, "cid-4"),
+        ])
+        .await
+        .unwrap();
+
+        let first = db.get_areas_paginated(&whosonfirst_db, "FR", 1, 2).await.unwrap();
+        let cids: Vec<_> = first.areas.iter().map(|info| info.cid.as_str()).collect();
+        assert_eq!(cids, vec!["cid-1", "cid-2"]);
+        assert_eq!(first.areas[1].area.name, "Bretagne");
+        assert_eq!((first.pagination.total, first.pagination.total_pages), (3, 2));
+
+        let last = db.get_areas_paginated(&whosonfirst_db, "FR", 2, 2).await.unwrap();
+        assert_eq!(last.areas.len(), 1);
+        assert_eq!(last.areas[0].cid, "cid-3");
+        let past = db.get_areas_paginated(&whosonfirst_db, "FR", 3, 2).await.unwrap();
+        assert!(past.areas.is_empty());
+    }
+
+    #[tokio::test]
+    async fn localities_in_a_bbox_get_the_cids_of_their_own_country() {
+        let whosonfirst_db = whosonfirst_db(
+            "(1, 'Alsace', 'FR', 'region', 0, 0, 7, 47, 8, 49, 1, 0),
+             (2, 'Bretagne', 'FR', 'region', 0, 0, -5, 47, -1, 49, 1, 0),
+             (3, 'Chatham', 'NZ', 'region', 0, 0, 176, -45, 180, -43, 1, 0),
+             (4, 'Lorraine', 'FR', 'region', 0, 0, 5, 48, 7.5, 50, 1, 0)",
+        )
+        .await;
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[
+            mapping("DE", 1, "cid-de-1"),
+            mapping("FR", 1, "cid-1"),
+            mapping("FR", 2, "cid-2"),
+            mapping("NZ", 3, "cid-3"),
+            mapping("US", 3, "cid-us-3"),
+        ])
+        .await
+        .unwrap();
+        whosonfirst_db.create_bbox_index().await.unwrap();
+        whosonfirst_db.create_bbox_index().await.unwrap();
+        let indexed: i64 = whosonfirst_db
+            .conn
+            .lock()
+            .await
+            .query_row("SELECT COUNT(*) FROM temp.area_bbox_index", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(indexed, 4);
+
+        let found = db
+            .get_localities_in_bbox(&whosonfirst_db, 6.0, 48.0, 7.2, 48.5)
+            .await
+            .unwrap();
+        let cids: Vec<_> = found.iter().map(|info| info.cid.as_str()).collect();
+        assert_eq!(cids, vec!["cid-1"]);
+
+        let across = db
+            .get_localities_in_bbox(&whosonfirst_db, 179.0, -44.0, -170.0, -40.0)
+            .await
+            .unwrap();
+        assert_eq!(across.len(), 1);
+        assert_eq!(across[0].area.name, "Chatham");
+        assert_eq!(across[0].cid, "cid-3");
+        assert!(db
+            .get_localities_in_bbox(&whosonfirst_db, 10.0, 0.0, 20.0, 10.0)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn catalog_records_carry_their_times() {
+        let whosonfirst_db = whosonfirst_db(
+            "(1, 'Alsace', 'FR', 'region', 0, 0, 7, 47, 8, 49, 1, 0),
+             (2, 'Bretagne', 'FR', 'region', 0, 0, -5, 47, -1, 49, 1, 0)",
+        )
+        .await;
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[
+            mapping("FR", 2, "cid-2"),
+            mapping("FR", 1, "cid-1"),
+            mapping("FR", 9, "cid-9"),
+        ])
+        .await
+        .unwrap();
+        db.record_extraction("FR", 1, "20240101").await.unwrap();
+
+        let records = db.get_catalog_records(&whosonfirst_db, None).await.unwrap();
+        let cids: Vec<_> = records.iter().map(|record| record.info.cid.as_str()).collect();
+        assert_eq!(cids, vec!["cid-1", "cid-2"]);
+        assert_eq!(records[0].info.area.min_longitude, 7.0);
+        assert!(records[0].uploaded_at.unwrap() > 1_700_000_000_000);
+        assert!(records[0].extracted_at.is_some());
+        assert_eq!(records[1].extracted_at, None);
+        assert!(db
+            .get_catalog_records(&whosonfirst_db, Some("DE"))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn custom_areas_keep_one_id_per_box() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        let paris: BoundingBox = "2.2,48.8,2.5,48.9".parse().unwrap();
+        let lyon: BoundingBox = "4.7,45.7,4.9,45.8".parse().unwrap();
+
+        let first = db.record_custom_area(paris, None).await.unwrap();
+        let second = db.record_custom_area(lyon, Some("Lyon")).await.unwrap();
+        assert_eq!(first.id, CUSTOM_AREA_ID_BASE as i64);
+        assert_eq!(second.id, first.id + 1);
+        assert_eq!(db.record_custom_area(paris, None).await.unwrap().id, first.id);
+
+        let found = db.get_custom_area(second.id as u32).await.unwrap().unwrap();
+        assert_eq!((found.name.as_str(), found.min_longitude), ("Lyon", 4.7));
+        assert!(db.get_custom_area(1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn only_mappings_past_the_retention_age_are_returned() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[mapping("DE", 1, "old"), mapping("DE", 2, "new")])
+            .await
+            .unwrap();
+        db.conn
+            .lock()
+            .await
+            .execute(
+                "UPDATE area_cids SET upload_time = datetime('now', '-10 days') WHERE area_id = 1",
+                [],
+            )
+            .unwrap();
+
+        let old = db.get_cid_mappings_older_than(7).await.unwrap();
+        assert_eq!(old.len(), 1);
+        assert_eq!(old[0].cid, "old");
+        assert_eq!(db.get_cid_mappings_older_than(0).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn compression_is_recorded_with_mappings_and_parts() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        let mut compressed = mapping("DE", 1, "gz");
+        compressed.compression = Compression::Gzip;
+        db.batch_insert_cid_mappings(&[compressed, mapping("DE", 2, "plain")])
+            .await
+            .unwrap();
+
+        let codecs: Vec<_> = db
+            .get_cid_mappings(None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|m| m.compression)
+            .collect();
+        assert_eq!(codecs, vec![Compression::Gzip, Compression::None]);
+
+        let part = AreaPartUpload {
+            part: AreaPart {
+                index: 0,
+                min_longitude: 0.0,
+                min_latitude: 0.0,
+                max_longitude: 1.0,
+                max_latitude: 1.0,
+            },
+            cid: "part".to_string(),
+            file_size: 10,
+            compression: Compression::Zstd,
+            encryption: None,
+        };
+        db.record_area_part("DE", 3, &part).await.unwrap();
+        let parts = db.get_area_parts("DE", 3).await.unwrap();
+        assert_eq!(parts[0].compression, Compression::Zstd);
+    }
+
+    #[tokio::test]
+    async fn encryption_parameters_are_recorded_with_mappings() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        let info = EncryptionInfo {
+            key_id: "0011223344556677".to_string(),
+            nonce_prefix: "8899aabbccddeeff".to_string(),
+        };
+        let mut encrypted = mapping("DE", 1, "sealed");
+        encrypted.encryption = Some(info.clone());
+        db.batch_insert_cid_mappings(&[encrypted, mapping("DE", 2, "plain")])
+            .await
+            .unwrap();
+
+        let mappings = db.get_cid_mappings(Some("DE")).await.unwrap();
+        assert_eq!(mappings[0].encryption, Some(info));
+        assert_eq!(mappings[1].encryption, None);
+    }
+
+    #[tokio::test]
+    async fn metadata_sidecar_is_recorded_with_mappings() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        let mut upload = mapping("DE", 1, "extract");
+        upload.metadata_cid = Some("sidecar".to_string());
+        db.batch_insert_cid_mappings(&[upload, mapping("DE", 2, "bare")])
+            .await
+            .unwrap();
+        db.record_extraction("DE", 1, "20260101").await.unwrap();
+
+        let mappings = db.get_cid_mappings(Some("DE")).await.unwrap();
+        assert_eq!(mappings[0].metadata_cid.as_deref(), Some("sidecar"));
+        assert_eq!(mappings[1].metadata_cid, None);
+
+        let extracted_at = db.get_extraction_time("DE", 1).await.unwrap().unwrap();
+        assert!(chrono::DateTime::parse_from_rfc3339(&extracted_at).is_ok());
+        assert_eq!(db.get_extraction_time("DE", 2).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn latest_published_index_is_returned() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        assert!(db.get_latest_published_index().await.unwrap().is_none());
+
+        db.record_published_index("first", 1, 10).await.unwrap();
+        db.record_published_index("second", 2, 25).await.unwrap();
+
+        let latest = db.get_latest_published_index().await.unwrap().unwrap();
+        assert_eq!(latest.root_cid, "second");
+        assert_eq!((latest.country_count, latest.area_count), (2, 25));
+    }
+
+    #[tokio::test]
+    async fn latest_db_snapshot_is_returned() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        assert!(db.get_latest_db_snapshot().await.unwrap().is_none());
+
+        db.record_db_snapshot("first", 100, Compression::None)
+            .await
+            .unwrap();
+        db.record_db_snapshot("second", 200, Compression::Gzip)
+            .await
+            .unwrap();
+
+        let latest = db.get_latest_db_snapshot().await.unwrap().unwrap();
+        assert_eq!(latest.cid, "second");
+        assert_eq!((latest.size, latest.compression), (200, Compression::Gzip));
+    }
+
+    #[tokio::test]
+    async fn announced_cids_are_recorded_once() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        let mut announcement = CidAnnouncement {
+            origin: "node-a".to_string(),
+            country_code: "FR".to_string(),
+            area_id: 12,
+            cid: "first".to_string(),
+            file_size: 2048,
+            ttl: 3,
+        };
+
+        assert!(db.record_peer_cid(&announcement).await.unwrap());
+        assert!(!db.record_peer_cid(&announcement).await.unwrap());
+        announcement.cid = "second".to_string();
+        assert!(db.record_peer_cid(&announcement).await.unwrap());
+        announcement.origin = "node-b".to_string();
+        assert!(db.record_peer_cid(&announcement).await.unwrap());
+
+        assert_eq!(db.count_peer_cids().await.unwrap(), (2, 2));
+    }
+
+    #[tokio::test]
+    async fn reuploaded_mapping_replaces_the_old_cid() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[mapping("DE", 1, "lost")]).await.unwrap();
+        db.batch_insert_cid_mappings(&[mapping("DE", 1, "repaired")]).await.unwrap();
+
+        let mappings = db.get_cid_mappings(Some("DE")).await.unwrap();
+        assert_eq!(mappings.len(), 1);
+        assert_eq!(mappings[0].cid, "repaired");
+        assert_eq!(mappings[0].file_size, 1024);
+    }
+
+    #[tokio::test]
+    async fn run_checkpoint_keeps_progress_until_cleared() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        assert!(db.get_run_checkpoint().await.unwrap().is_none());
+
+        db.set_checkpoint_phase(RunPhase::Extraction).await.unwrap();
+        db.record_checkpoint_country(RunPhase::Extraction, "FR").await.unwrap();
+        db.record_checkpoint_country(RunPhase::Extraction, "FR").await.unwrap();
+        db.record_checkpoint_area("DE", 42).await.unwrap();
+        db.set_checkpoint_phase(RunPhase::Upload).await.unwrap();
+        db.record_checkpoint_country(RunPhase::Upload, "FR").await.unwrap();
+
+        let checkpoint = db.get_run_checkpoint().await.unwrap().unwrap();
+        assert_eq!(checkpoint.phase, RunPhase::Upload);
+        assert_eq!(checkpoint.extracted_countries, HashSet::from(["FR".to_string()]));
+        assert_eq!(checkpoint.uploaded_countries, HashSet::from(["FR".to_string()]));
+        assert_eq!(checkpoint.last_area, Some(("DE".to_string(), 42)));
+
+        db.clear_run_checkpoint().await.unwrap();
+        assert!(db.get_run_checkpoint().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn extraction_failures_are_cleared_once_extracted() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.record_extraction_failure("FR", 1, "bad bbox", Some("failures/FR/1.log"))
+            .await
+            .unwrap();
+        db.record_extraction_failure("FR", 2, "timed out", None)
+            .await
+            .unwrap();
+
+        let failures = db.get_extraction_failures().await.unwrap();
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].error, "bad bbox");
+        assert_eq!(
+            failures[0].log_path.as_deref(),
+            Some(std::path::Path::new("failures/FR/1.log"))
+        );
+
+        db.record_extraction("FR", 1, "v1").await.unwrap();
+        let failures = db.get_extraction_failures().await.unwrap();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].area_id, 2);
+    }
+
+    #[tokio::test]
+    async fn oversized_extracts_are_skipped_within_their_budget() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.record_oversized_extract("FR", 1, 900, "v1").await.unwrap();
+        db.record_oversized_extract("FR", 2, 3000, "v1").await.unwrap();
+
+        let sizes: Vec<_> = db
+            .get_oversized_extracts()
+            .await
+            .unwrap()
+            .iter()
+            .map(|extract| extract.file_size)
+            .collect();
+        assert_eq!(sizes, vec![3000, 900]);
+
+        let skipped = db.get_oversized_keys("v1", 500).await.unwrap();
+        assert_eq!(skipped.len(), 2);
+        let skipped = db.get_oversized_keys("v1", 1000).await.unwrap();
+        assert!(skipped.contains(&("FR".to_string(), 2)) && skipped.len() == 1);
+        assert!(db.get_oversized_keys("v2", 500).await.unwrap().is_empty());
+
+        db.record_extraction("FR", 2, "v1").await.unwrap();
+        assert_eq!(db.get_oversized_extracts().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn areas_are_held_back_after_too_many_or_recent_failures() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        for _ in 0..3 {
+            db.record_extraction_failure("FR", 1, "bad bbox", None)
+                .await
+                .unwrap();
+        }
+        db.record_extraction_failure("FR", 2, "timed out", None)
+            .await
+            .unwrap();
+        assert_eq!(db.get_extraction_failures().await.unwrap()[0].attempts, 3);
+
+        let held_back = db
+            .get_held_back_extractions(3, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        assert_eq!(held_back, HashSet::from([("FR".to_string(), 1)]));
+
+        let held_back = db
+            .get_held_back_extractions(0, std::time::Duration::from_secs(3600))
+            .await
+            .unwrap();
+        assert_eq!(held_back.len(), 2);
+
+        let held_back = db
+            .get_held_back_extractions(0, std::time::Duration::ZERO)
+            .await
+            .unwrap();
+        assert!(held_back.is_empty());
+    }
+
+    #[tokio::test]
+    async fn compacted_database_passes_integrity_check() {
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[mapping("FR", 1, "cid-1")])
+            .await
+            .unwrap();
+
+        db.compact().await.unwrap();
+        assert!(db.integrity_check().await.unwrap().is_empty());
+        assert!(db.has_cid_mapping("FR", 1).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn backup_restores_into_another_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup_path = dir.path().join("backup.db");
+
+        let db = DatabaseService::in_memory(true).await.unwrap();
+        db.batch_insert_cid_mappings(&[mapping("FR", 1, "cid-1")])
+            .await
+            .unwrap();
+        db.backup_to(&backup_path).await.unwrap();
+
+        let restored = DatabaseService::in_memory(false).await.unwrap();
+        assert!(!restored.has_cid_tables().await.unwrap());
+        restored.restore_from(&backup_path).await.unwrap();
+
+        assert!(restored.has_cid_tables().await.unwrap());
+        assert!(restored.has_cid_mapping("FR", 1).await.unwrap());
+    }
 }
@@ -0,0 +1,33 @@
+use crate::types::PipelineEvent;
+use tokio::sync::broadcast;
+
+/// Number of events buffered per subscriber before slow subscribers start missing events
+const EVENT_BUFFER_SIZE: usize = 1024;
+
+/// Fan-out of pipeline events to any number of subscribers. Emitting never blocks the
+/// pipeline, events are dropped when nobody is listening.
+#[derive(Clone)]
+pub struct EventService {
+    sender: broadcast::Sender<PipelineEvent>,
+}
+
+impl EventService {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUFFER_SIZE);
+        Self { sender }
+    }
+
+    pub fn emit(&self, event: PipelineEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<PipelineEvent> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,233 @@
+use crate::services::{DatabaseError, DatabaseService};
+use crate::types::AREA_PARTS_EXTENSION;
+use crate::utils::format_bytes;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+/// An extract or parts directory in AREAS_DIR
+#[derive(Debug)]
+struct LocalExtract {
+    country_code: String,
+    area_id: u32,
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Keeps AREAS_DIR under AREAS_DIR_MAX_SIZE by deleting the least recently used extracts
+/// that are already uploaded, their CID mappings stay. Extracts waiting for upload are
+/// never deleted, the directory may exceed the cap until they are uploaded.
+pub struct EvictionService {
+    areas_dir: PathBuf,
+    max_size: u64,
+    cid_db: Arc<DatabaseService>,
+    /// Size of the directory as of the last scan plus the extracts recorded since
+    usage: AtomicU64,
+    /// Whether the directory was scanned yet, also serializes evictions
+    scanned: Mutex<bool>,
+}
+
+impl EvictionService {
+    pub fn new(areas_dir: PathBuf, max_size: u64, cid_db: Arc<DatabaseService>) -> Self {
+        Self {
+            areas_dir,
+            max_size,
+            cid_db,
+            usage: AtomicU64::new(0),
+            scanned: Mutex::new(false),
+        }
+    }
+
+    /// Count an extract written to the directory
+    pub fn record_extract(&self, size: u64) {
+        self.usage.fetch_add(size, Ordering::Relaxed);
+    }
+
+    /// Evict uploaded extracts until the directory is back under its cap. The directory is
+    /// only scanned once the recorded usage reaches the cap, or on the first call.
+    pub async fn make_room(&self) -> Result<(), DatabaseError> {
+        let mut scanned = self.scanned.lock().await;
+        if *scanned && self.usage.load(Ordering::Relaxed) <= self.max_size {
+            return Ok(());
+        }
+
+        let areas_dir = self.areas_dir.clone();
+        let mut extracts = tokio::task::spawn_blocking(move || scan_extracts(&areas_dir)).await?;
+        let mut usage: u64 = extracts.iter().map(|extract| extract.size).sum();
+        *scanned = true;
+        if usage <= self.max_size {
+            self.usage.store(usage, Ordering::Relaxed);
+            return Ok(());
+        }
+
+        let (uploaded, _) = self.cid_db.get_uploaded_keys().await?;
+        extracts
+            .retain(|extract| uploaded.contains(&(extract.country_code.clone(), extract.area_id)));
+        extracts.sort_by_key(|extract| extract.last_used);
+
+        let (mut evicted, mut freed) = (0, 0);
+        for extract in extracts {
+            if usage <= self.max_size {
+                break;
+            }
+            let result = match extract.path.is_dir() {
+                true => tokio::fs::remove_dir_all(&extract.path).await,
+                false => tokio::fs::remove_file(&extract.path).await,
+            };
+            match result {
+                Ok(()) => {
+                    usage -= extract.size;
+                    evicted += 1;
+                    freed += extract.size;
+                }
+                Err(e) => warn!("Failed to evict {}: {}", extract.path.display(), e),
+            }
+        }
+        self.usage.store(usage, Ordering::Relaxed);
+
+        if evicted > 0 {
+            info!(
+                "Evicted {} uploaded extracts ({}) to keep {} under {}",
+                evicted,
+                format_bytes(freed),
+                self.areas_dir.display(),
+                format_bytes(self.max_size)
+            );
+        }
+        if usage > self.max_size {
+            warn!(
+                "{} holds {} of extracts waiting for upload, over its {} cap",
+                self.areas_dir.display(),
+                format_bytes(usage),
+                format_bytes(self.max_size)
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Every extract and parts directory under `<areas_dir>/<country>/`
+fn scan_extracts(areas_dir: &Path) -> Vec<LocalExtract> {
+    let mut extracts = Vec::new();
+    let Ok(countries) = std::fs::read_dir(areas_dir) else {
+        return extracts;
+    };
+
+    for country_dir in countries.filter_map(Result::ok) {
+        let Some(country_code) = country_dir.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        let Ok(entries) = std::fs::read_dir(country_dir.path()) else {
+            continue;
+        };
+
+        for entry in entries.filter_map(Result::ok) {
+            let path = entry.path();
+            let Some(extension) = path.extension().and_then(|ext| ext.to_str()) else {
+                continue;
+            };
+            if extension != "pmtiles" && extension != AREA_PARTS_EXTENSION {
+                continue;
+            }
+            let Some(area_id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse().ok())
+            else {
+                continue;
+            };
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+
+            let size = match metadata.is_dir() {
+                true => std::fs::read_dir(&path)
+                    .map(|parts| {
+                        parts
+                            .filter_map(|part| part.ok()?.metadata().ok())
+                            .map(|part| part.len())
+                            .sum()
+                    })
+                    .unwrap_or(0),
+                false => metadata.len(),
+            };
+            // Access times are only updated now and then on most mounts, the later of both
+            // times is the best guess of the last use
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let last_used = metadata.accessed().unwrap_or(modified).max(modified);
+            extracts.push(LocalExtract {
+                country_code: country_code.clone(),
+                area_id,
+                path,
+                size,
+                last_used,
+            });
+        }
+    }
+    extracts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{CompletedUpload, Compression};
+    use std::time::Duration;
+
+    fn uploaded(country_code: &str, area_id: u32) -> CompletedUpload {
+        CompletedUpload {
+            country_code: country_code.to_string(),
+            area_id,
+            cid: format!("cid-{}", area_id),
+            file_size: 100,
+            cached: false,
+            compression: Compression::None,
+            encryption: None,
+            metadata_cid: None,
+            metadata_encryption: None,
+        }
+    }
+
+    fn write_extract(dir: &Path, name: &str, age_secs: u64) {
+        let path = dir.join(name);
+        std::fs::write(&path, vec![0u8; 100]).unwrap();
+        let used = SystemTime::now() - Duration::from_secs(age_secs);
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_times(
+            std::fs::FileTimes::new()
+                .set_accessed(used)
+                .set_modified(used),
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn evicts_the_least_recently_used_uploaded_extracts() {
+        let dir = tempfile::tempdir().unwrap();
+        let country_dir = dir.path().join("FR");
+        std::fs::create_dir_all(&country_dir).unwrap();
+        write_extract(&country_dir, "1.pmtiles", 300);
+        write_extract(&country_dir, "2.pmtiles", 200);
+        write_extract(&country_dir, "3.pmtiles", 400);
+        write_extract(&country_dir, "4.pmtiles", 100);
+
+        let cid_db = Arc::new(DatabaseService::in_memory(true).await.unwrap());
+        cid_db
+            .batch_insert_cid_mappings(&[uploaded("FR", 1), uploaded("FR", 2), uploaded("FR", 4)])
+            .await
+            .unwrap();
+
+        let eviction = EvictionService::new(dir.path().to_path_buf(), 250, cid_db);
+        eviction.make_room().await.unwrap();
+
+        // Area 3 is the oldest but not uploaded, 1 and 2 go before the more recent 4
+        assert!(country_dir.join("3.pmtiles").exists());
+        assert!(!country_dir.join("1.pmtiles").exists());
+        assert!(!country_dir.join("2.pmtiles").exists());
+        assert!(country_dir.join("4.pmtiles").exists());
+        assert_eq!(eviction.usage.load(Ordering::Relaxed), 200);
+    }
+}
@@ -1,11 +1,23 @@
 use crate::config::Config;
-use crate::services::DatabaseService;
-use crate::types::AdministrativeArea;
-use std::collections::HashMap;
+use crate::services::{
+    BackpressureService, CatalogService, ClaimService, DatabaseService, EventService,
+    EvictionService, PauseService,
+};
+use crate::types::{
+    area_parts_dir, AdministrativeArea, CompletedExtract, CountryPlan, ExtractionMode,
+    PhaseTimings, PipelineEvent, PipelineStage, RunPhase, RunPlan, AREA_PARTS_MANIFEST,
+};
+use crate::utils::{
+    available_space, fetch_pmtiles_header, format_bytes, format_duration, http_client,
+    parse_s3_location, presign_url, probe_remote_file, DirectoryCache, spawn_throttled_proxy, volume_id, RateLimiter, ThrottledProxy,
+};
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use thiserror::Error;
-use tokio::sync::Semaphore;
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tokio::task::JoinSet;
 use tracing::{error, info, warn};
 
 #[derive(Error, Debug)]
@@ -18,25 +30,63 @@ pub enum ExtractionError {
     ExtractionFailed(i64, String),
     #[error("Database error: {0}")]
     DatabaseError(String),
+    #[error("Failed to identify planet build: {0}")]
+    PlanetVersionUnavailable(String),
+    #[error("Planet source {0} is unusable: {1}")]
+    PlanetSourceInvalid(String, String),
+    #[error("S3 error: {0}")]
+    S3Error(#[from] crate::utils::S3Error),
+    #[error("S3 planet source requires AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY")]
+    S3CredentialsMissing,
+    #[error("Insufficient disk space on {path}: {required} needed, {available} available")]
+    InsufficientDiskSpace {
+        path: String,
+        required: String,
+        available: String,
+    },
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] crate::utils::FileError),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
 
+/// Size assumed for an extract when no upload history is available to average from
+const DEFAULT_AREA_SIZE_ESTIMATE: u64 = 50 * 1024 * 1024;
+
+/// CID database growth per area: its mapping, cache entry, part rows and their indexes
+const CID_DB_BYTES_PER_AREA: u64 = 16 * 1024;
+
+/// Room for an SQLite database's journal, roughly one WAL auto-checkpoint of 1000 pages
+const SQLITE_JOURNAL_HEADROOM: u64 = 4 * 1024 * 1024;
+
+/// How much longer than EXTRACTION_TIMEOUT_SECS a failed extraction may run on its retry
+const RETRY_TIMEOUT_FACTOR: u32 = 3;
+
+/// Validity of the presigned URLs handed to the pmtiles CLI for S3 planet sources
+const S3_PRESIGN_EXPIRY_SECS: u64 = 6 * 60 * 60;
+
+/// Validity of the presigned URL behind the throttling proxy, which lives for a whole run.
+/// This is the longest expiry SigV4 allows.
+const S3_PROXY_PRESIGN_EXPIRY_SECS: u64 = 7 * 24 * 60 * 60;
+
 #[derive(Clone, Debug)]
 pub enum PlanetSource {
     Local(PathBuf),
     Remote(String),
+    /// Object in an S3-compatible bucket, as (bucket, key)
+    S3(String, String),
 }
 
 impl PlanetSource {
     pub fn is_remote(&self) -> bool {
-        matches!(self, PlanetSource::Remote(_))
+        matches!(self, PlanetSource::Remote(_) | PlanetSource::S3(_, _))
     }
 
-    pub fn as_str(&self) -> &str {
+    pub fn location(&self) -> String {
         match self {
-            PlanetSource::Local(path) => path.to_str().unwrap_or(""),
-            PlanetSource::Remote(url) => url,
+            PlanetSource::Local(path) => path.to_string_lossy().to_string(),
+            PlanetSource::Remote(url) => url.clone(),
+            PlanetSource::S3(bucket, key) => format!("s3://{}/{}", bucket, key),
         }
     }
 }
@@ -44,11 +94,96 @@ impl PlanetSource {
 pub struct ExtractionService {
     config: Arc<Config>,
     db_service: Arc<DatabaseService>,
+    cid_db: Arc<DatabaseService>,
+    events: Arc<EventService>,
+    pause: Arc<PauseService>,
+    claims: Option<Arc<ClaimService>>,
+    catalog: Option<Arc<CatalogService>>,
+    timings: Arc<Mutex<PhaseTimings>>,
+    /// Countries an interrupted run already extracted, set when this run keeps a checkpoint
+    checkpoint: Option<Arc<HashSet<String>>>,
+    /// Receives each extract once it is on disk, see `with_handoff`
+    handoff: Option<mpsc::Sender<CompletedExtract>>,
+    backpressure: Option<Arc<BackpressureService>>,
+    eviction: Option<Arc<EvictionService>>,
+    /// Limit on a single pmtiles extract, longer for retries
+    extraction_timeout: Option<Duration>,
 }
 
 impl ExtractionService {
-    pub fn new(config: Arc<Config>, db_service: Arc<DatabaseService>) -> Self {
-        Self { config, db_service }
+    pub fn new(
+        config: Arc<Config>,
+        db_service: Arc<DatabaseService>,
+        cid_db: Arc<DatabaseService>,
+        events: Arc<EventService>,
+    ) -> Self {
+        let extraction_timeout = config.extraction_timeout;
+        Self {
+            config,
+            db_service,
+            cid_db,
+            events,
+            pause: Arc::new(PauseService::new()),
+            claims: None,
+            catalog: None,
+            timings: Arc::new(Mutex::new(PhaseTimings::new())),
+            checkpoint: None,
+            handoff: None,
+            backpressure: None,
+            eviction: None,
+            extraction_timeout,
+        }
+    }
+
+    /// Share a pause switch with the rest of the pipeline
+    pub fn with_pause(mut self, pause: Arc<PauseService>) -> Self {
+        self.pause = pause;
+        self
+    }
+
+    /// Only extract countries this node claims in a store shared with other nodes
+    pub fn with_claims(mut self, claims: Arc<ClaimService>) -> Self {
+        self.claims = Some(claims);
+        self
+    }
+
+    /// Pin areas trusted nodes already published instead of extracting them
+    pub fn with_catalog(mut self, catalog: Arc<CatalogService>) -> Self {
+        self.catalog = Some(catalog);
+        self
+    }
+
+    /// Record extraction times in timings shared with the rest of the pipeline
+    pub fn with_timings(mut self, timings: Arc<Mutex<PhaseTimings>>) -> Self {
+        self.timings = timings;
+        self
+    }
+
+    /// Keep the run checkpoint in the CID database up to date, skipping the countries an
+    /// interrupted run already extracted
+    pub fn with_checkpoint(mut self, extracted_countries: HashSet<String>) -> Self {
+        self.checkpoint = Some(Arc::new(extracted_countries));
+        self
+    }
+
+    /// Hand each extract to the upload stage as soon as it is on disk, including those
+    /// left by earlier runs. Sending waits while the channel is full, so a bounded channel
+    /// keeps extraction from running too far ahead of uploads.
+    pub fn with_handoff(mut self, sender: mpsc::Sender<CompletedExtract>) -> Self {
+        self.handoff = Some(sender);
+        self
+    }
+
+    /// Hold new extractions back while uploads lag behind, see `BackpressureService`
+    pub fn with_backpressure(mut self, backpressure: Arc<BackpressureService>) -> Self {
+        self.backpressure = Some(backpressure);
+        self
+    }
+
+    /// Evict uploaded extracts before each extraction to keep AREAS_DIR under its cap
+    pub fn with_eviction(mut self, eviction: Arc<EvictionService>) -> Self {
+        self.eviction = Some(eviction);
+        self
     }
 
     pub fn get_planet_source(&self) -> Result<PlanetSource, ExtractionError> {
@@ -61,6 +196,13 @@ impl ExtractionService {
         if location.starts_with("http://") || location.starts_with("https://") {
             info!("Using remote PMTiles source: {}", location);
             Ok(PlanetSource::Remote(location.clone()))
+        } else if location.starts_with("s3://") {
+            let (bucket, key) = parse_s3_location(location)?;
+            if self.config.s3_credentials.is_none() {
+                return Err(ExtractionError::S3CredentialsMissing);
+            }
+            info!("Using S3 PMTiles source: {}", location);
+            Ok(PlanetSource::S3(bucket, key))
         } else {
             let path = PathBuf::from(location);
             if !path.exists() {
@@ -73,16 +215,568 @@ impl ExtractionService {
         }
     }
 
+    /// Reads the header of a remote planet to check the server answers range requests and
+    /// serves a complete PMTiles archive, before thousands of extractions fail against it.
+    /// Local planets and a missing location are left to extraction.
+    pub async fn validate_planet_source(&self) -> Result<(), ExtractionError> {
+        if self.config.planet_pmtiles_location.is_none() {
+            return Ok(());
+        }
+        let planet_source = self.get_planet_source()?;
+        if !planet_source.is_remote() {
+            return Ok(());
+        }
+
+        let url = self.presign_planet_url(&planet_source, "GET", S3_PRESIGN_EXPIRY_SECS)?;
+        let client = self.planet_client(&planet_source)?;
+        let invalid = |reason: String| {
+            ExtractionError::PlanetSourceInvalid(planet_source.location(), reason)
+        };
+        let (header, file_size) = fetch_pmtiles_header(&client, &url)
+            .await
+            .map_err(|e| invalid(e.to_string()))?;
+        header
+            .check_size(file_size)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        info!(
+            "Planet source {} serves {} with zoom levels {}-{}",
+            planet_source.location(),
+            format_bytes(file_size),
+            header.min_zoom,
+            header.max_zoom
+        );
+        Ok(())
+    }
+
+    /// Identifies the planet build behind a source: the ETag or Last-Modified header
+    /// for remote files, the size and modification time for local ones.
+    pub async fn get_planet_version(
+        &self,
+        planet_source: &PlanetSource,
+    ) -> Result<String, ExtractionError> {
+        let build = match planet_source {
+            PlanetSource::Local(path) => {
+                let metadata = tokio::fs::metadata(path).await?;
+                let modified = metadata
+                    .modified()?
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                format!("{}:{}", metadata.len(), modified)
+            }
+            PlanetSource::Remote(_) | PlanetSource::S3(_, _) => {
+                let url =
+                    self.presign_planet_url(planet_source, "HEAD", S3_PRESIGN_EXPIRY_SECS)?;
+                let client = self.planet_client(planet_source)?;
+                let info = probe_remote_file(&client, &url)
+                    .await
+                    .map_err(|e| ExtractionError::PlanetVersionUnavailable(e.to_string()))?;
+                info.etag
+                    .or(info.last_modified)
+                    .or(info.content_length.map(|len| len.to_string()))
+                    .ok_or_else(|| {
+                        ExtractionError::PlanetVersionUnavailable(
+                            "server advertises no ETag, Last-Modified or Content-Length".to_string(),
+                        )
+                    })?
+            }
+        };
+
+        Ok(format!("{}|{}", planet_source.location(), build))
+    }
+
+    /// Resolves the location handed to the pmtiles CLI or probed over HTTP. S3 objects
+    /// are exposed through a presigned URL for the given method.
+    fn presign_planet_url(
+        &self,
+        planet_source: &PlanetSource,
+        method: &str,
+        expires_secs: u64,
+    ) -> Result<String, ExtractionError> {
+        match planet_source {
+            PlanetSource::S3(bucket, key) => {
+                let credentials = self
+                    .config
+                    .s3_credentials
+                    .as_ref()
+                    .ok_or(ExtractionError::S3CredentialsMissing)?;
+                Ok(presign_url(
+                    credentials,
+                    method,
+                    bucket,
+                    key,
+                    expires_secs,
+                )?)
+            }
+            _ => Ok(planet_source.location()),
+        }
+    }
+
+    /// Client for reads of the planet. An http(s) planet gets PLANET_PMTILES_HEADERS, an
+    /// S3 planet none as its presigned URLs carry their own authorization.
+    fn planet_client(
+        &self,
+        planet_source: &PlanetSource,
+    ) -> Result<reqwest::Client, ExtractionError> {
+        let headers = match planet_source {
+            PlanetSource::Remote(_) => self.config.planet_pmtiles_headers.clone(),
+            _ => Default::default(),
+        };
+        Ok(http_client(self.config.proxy_url.as_deref(), &headers)?)
+    }
+
+    /// Routes remote planet reads through a local proxy, since the pmtiles CLI can neither
+    /// limit its rate, send headers nor keep the planet's directories between extractions.
+    /// The returned proxy must be kept alive for as long as the returned source is used.
+    pub async fn proxy_planet_source(
+        &self,
+        planet_source: PlanetSource,
+    ) -> Result<(PlanetSource, Option<ThrottledProxy>), ExtractionError> {
+        if !planet_source.is_remote() {
+            return Ok((planet_source, None));
+        }
+
+        let upstream =
+            self.presign_planet_url(&planet_source, "GET", S3_PROXY_PRESIGN_EXPIRY_SECS)?;
+        let client = self.planet_client(&planet_source)?;
+        let limiter = self
+            .config
+            .download_rate_limit
+            .map(|rate_limit| Arc::new(RateLimiter::new(rate_limit)));
+        let cache = match self.open_directory_cache(&planet_source, &client).await {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                warn!("Planet directories will not be cached: {}", e);
+                None
+            }
+        };
+        let proxy = spawn_throttled_proxy(client, upstream, limiter, cache).await?;
+        if let Some(rate_limit) = self.config.download_rate_limit {
+            info!("Limiting planet reads to {}/s", format_bytes(rate_limit));
+        }
+
+        Ok((PlanetSource::Remote(proxy.url().to_string()), Some(proxy)))
+    }
+
+    /// Cache of the planet's directories in PLANET_CACHE_DIR, started over whenever the
+    /// planet build changes
+    async fn open_directory_cache(
+        &self,
+        planet_source: &PlanetSource,
+        client: &reqwest::Client,
+    ) -> Result<DirectoryCache, ExtractionError> {
+        let head_url = self.presign_planet_url(planet_source, "HEAD", S3_PRESIGN_EXPIRY_SECS)?;
+        let info = probe_remote_file(client, &head_url).await?;
+        let url = self.presign_planet_url(planet_source, "GET", S3_PRESIGN_EXPIRY_SECS)?;
+        let (header, file_size) = fetch_pmtiles_header(client, &url)
+            .await
+            .map_err(|e| ExtractionError::PlanetVersionUnavailable(e.to_string()))?;
+        let version = self.get_planet_version(planet_source).await?;
+
+        Ok(DirectoryCache::open(
+            &self.config.planet_cache_dir,
+            &planet_source.location(),
+            &version,
+            info.etag,
+            &header,
+            file_size,
+        )
+        .await?)
+    }
+
+    fn emit_country_completed(&self, country_code: &str) {
+        self.events.emit(PipelineEvent::CountryCompleted {
+            country_code: country_code.to_string(),
+            stage: PipelineStage::Extraction,
+        });
+    }
+
+    /// Record a skipped extract with no recorded extraction as one of `planet_version`, so
+    /// `invalidate_stale_areas` finds it once the planet changes
+    async fn adopt_existing_extract(
+        &self,
+        area: &AdministrativeArea,
+        planet_version: &str,
+    ) -> Result<(), ExtractionError> {
+        let adopted = self
+            .cid_db
+            .adopt_extraction(&area.country, area.id as u32, planet_version)
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        if adopted {
+            info!(
+                "Recorded existing extract of area {} as extracted from planet {}",
+                area.id, planet_version
+            );
+        }
+        Ok(())
+    }
+
+    /// Removes local extracts produced from an older planet build so they get
+    /// re-extracted, and flags their CID mappings for re-upload.
+    async fn invalidate_stale_areas(&self, planet_version: &str) -> Result<(), ExtractionError> {
+        let stale_areas = self
+            .cid_db
+            .invalidate_stale_extractions(planet_version)
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+
+        if stale_areas.is_empty() {
+            return Ok(());
+        }
+
+        warn!(
+            "Planet source changed, {} areas are stale and will be re-extracted",
+            stale_areas.len()
+        );
+
+        for (country_code, area_id) in stale_areas {
+            let output_path = self.area_output_path(&country_code, area_id as i64);
+            if output_path.exists() {
+                tokio::fs::remove_file(&output_path).await?;
+            }
+            let parts_dir =
+                area_parts_dir(&self.config.areas_dir.join(&country_code), area_id as i64);
+            if parts_dir.exists() {
+                tokio::fs::remove_dir_all(&parts_dir).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Drops areas whose bounding box falls outside the configured size limits. Some WOF
+    /// records carry continent-sized bboxes that would produce enormous extracts.
+    fn filter_by_bbox_size(&self, areas: Vec<AdministrativeArea>) -> Vec<AdministrativeArea> {
+        let (min, max) = (self.config.min_bbox_area_km2, self.config.max_bbox_area_km2);
+        if min.is_none() && max.is_none() {
+            return areas;
+        }
+
+        let total = areas.len();
+        let kept: Vec<_> = areas
+            .into_iter()
+            .filter(|area| {
+                let Some(reason) = area.bbox_size_violation(min, max) else {
+                    return true;
+                };
+                let bbox_area = area.bbox_area_km2();
+
+                warn!(
+                    "Skipping area {} ({}): bbox of {:.0} km² is {}",
+                    area.id, area.name, bbox_area, reason
+                );
+                self.events.emit(PipelineEvent::AreaSkipped {
+                    country_code: area.country.clone(),
+                    area_id: area.id as u32,
+                    reason: format!("bbox of {:.0} km² is {}", bbox_area, reason),
+                });
+                false
+            })
+            .collect();
+
+        if kept.len() < total {
+            info!(
+                "Skipped {} of {} areas outside the bbox size limits",
+                total - kept.len(),
+                total
+            );
+        }
+        kept
+    }
+
+    /// What EXTRACTION_MODE extracts for a country: its regions and counties within the
+    /// population and bbox size limits, the country's own record for a whole-country
+    /// extract, or both. The country record is exempt from the bbox size limits.
+    async fn country_extraction_areas(
+        &self,
+        country_code: &str,
+    ) -> Result<Vec<AdministrativeArea>, ExtractionError> {
+        let mode = self.config.extraction_mode;
+        if mode == ExtractionMode::Grid {
+            return self.country_grid_cells(country_code).await;
+        }
+
+        let mut areas = Vec::new();
+        if mode.includes_areas() {
+            let country_areas = self
+                .db_service
+                .get_country_areas(country_code, self.config.min_population)
+                .await
+                .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+            areas = self.filter_by_bbox_size(country_areas);
+        }
+
+        if mode.includes_country() {
+            match self
+                .db_service
+                .get_country_record(country_code)
+                .await
+                .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?
+            {
+                Some(country) => areas.push(country),
+                None => warn!(
+                    "No country record for {} in the WhosOnFirst database, no whole-country \
+                     extract",
+                    country_code
+                ),
+            }
+        }
+
+        Ok(areas)
+    }
+
+    /// The grid cells covering a country's regions and counties within the bbox size limits,
+    /// or its own bounding box when it has none
+    async fn country_grid_cells(
+        &self,
+        country_code: &str,
+    ) -> Result<Vec<AdministrativeArea>, ExtractionError> {
+        let areas = self
+            .db_service
+            .get_country_areas(country_code, None)
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        let mut areas = self.filter_by_bbox_size(areas);
+        if areas.is_empty() {
+            areas.extend(
+                self.db_service
+                    .get_country_record(country_code)
+                    .await
+                    .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?,
+            );
+        }
+
+        let cells = self.config.grid.cells_covering(country_code, &areas);
+        info!(
+            "{} cells of {}° cover the {} areas of {}",
+            cells.len(),
+            self.config.grid.cell_degrees,
+            areas.len(),
+            country_code
+        );
+        Ok(cells)
+    }
+
+    /// Drops areas with a current CID mapping when uploaded extracts are deleted or evicted,
+    /// as their missing file does not mean they still need extracting
+    async fn drop_uploaded_areas(
+        &self,
+        areas: Vec<AdministrativeArea>,
+    ) -> Result<Vec<AdministrativeArea>, ExtractionError> {
+        if !self.config.deletes_uploaded_extracts() {
+            return Ok(areas);
+        }
+
+        let (uploaded, _) = self
+            .cid_db
+            .get_uploaded_keys()
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+
+        Ok(areas
+            .into_iter()
+            .filter(|area| !uploaded.contains(&(area.country.clone(), area.id as u32)))
+            .collect())
+    }
+
+    /// Pins areas found in trusted dataset manifests and records their CIDs, returning the
+    /// areas that still need extracting. Areas adopted on an earlier run have a mapping but
+    /// no local file and are dropped as well.
+    async fn adopt_published_areas(
+        &self,
+        country_code: &str,
+        areas: Vec<AdministrativeArea>,
+    ) -> Result<Vec<AdministrativeArea>, ExtractionError> {
+        let Some(catalog) = &self.catalog else {
+            return Ok(areas);
+        };
+        if areas
+            .iter()
+            .all(|area| self.is_area_extracted(country_code, area.id))
+        {
+            return Ok(areas);
+        }
+
+        let (uploaded, _) = self
+            .cid_db
+            .get_uploaded_keys()
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        let entries = catalog.country_entries(country_code).await;
+
+        let mut adopted = Vec::new();
+        let mut remaining = Vec::with_capacity(areas.len());
+        for area in areas {
+            let area_id = area.id as u32;
+            if self.is_area_extracted(country_code, area.id) {
+                remaining.push(area);
+                continue;
+            }
+            if uploaded.contains(&(country_code.to_string(), area_id)) {
+                continue;
+            }
+            let Some(entry) = entries.get(&area_id) else {
+                remaining.push(area);
+                continue;
+            };
+
+            self.pause.wait_until_resumed().await;
+            match catalog.pin(entry).await {
+                Ok(()) => {
+                    info!("Pinned published area {} of {}: {}", area_id, country_code, entry.cid);
+                    adopted.push(entry.completed_upload(country_code));
+                    self.events.emit(PipelineEvent::AreaSkipped {
+                        country_code: country_code.to_string(),
+                        area_id,
+                        reason: "pinned from a trusted manifest".to_string(),
+                    });
+                }
+                Err(e) => {
+                    warn!("Failed to pin published area {}, extracting it: {}", area_id, e);
+                    remaining.push(area);
+                }
+            }
+        }
+
+        if !adopted.is_empty() {
+            info!(
+                "Adopted {} published areas of {} instead of extracting them",
+                adopted.len(),
+                country_code
+            );
+            self.cid_db
+                .batch_insert_cid_mappings(&adopted)
+                .await
+                .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        }
+        Ok(remaining)
+    }
+
+    fn area_output_path(&self, country_code: &str, area_id: i64) -> PathBuf {
+        self.config
+            .areas_dir
+            .join(country_code)
+            .join(format!("{}.pmtiles", area_id))
+    }
+
+    /// Size of an area's extract on disk, all of its parts for a split one, `None` until it
+    /// is extracted
+    fn extracted_size(&self, country_code: &str, area_id: i64) -> Option<u64> {
+        if let Ok(metadata) = std::fs::metadata(self.area_output_path(country_code, area_id)) {
+            return Some(metadata.len());
+        }
+        let parts_dir = area_parts_dir(&self.config.areas_dir.join(country_code), area_id);
+        let parts = std::fs::read_dir(parts_dir).ok()?;
+        Some(
+            parts
+                .filter_map(|entry| entry.ok()?.metadata().ok())
+                .map(|metadata| metadata.len())
+                .sum(),
+        )
+    }
+
+    /// Whether the area has been extracted, either whole or split into parts
+    fn is_area_extracted(&self, country_code: &str, area_id: i64) -> bool {
+        self.area_output_path(country_code, area_id).exists()
+            || area_parts_dir(&self.config.areas_dir.join(country_code), area_id).exists()
+    }
+
+    /// Aborts before extraction when the areas volume, the storage volume the extracts are
+    /// uploaded into, or the volumes holding the databases cannot take the estimated growth
+    /// of the run.
+    async fn ensure_disk_space(&self, remaining_count: usize) -> Result<(), ExtractionError> {
+        if remaining_count == 0 {
+            return Ok(());
+        }
+
+        let average_size = self
+            .cid_db
+            .get_average_file_size()
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?
+            .unwrap_or(DEFAULT_AREA_SIZE_ESTIMATE);
+        let required = average_size * remaining_count as u64;
+
+        info!(
+            "Estimated space needed for {} remaining areas: {} (average {} per area)",
+            remaining_count,
+            format_bytes(required),
+            format_bytes(average_size)
+        );
+
+        let cid_db_growth =
+            CID_DB_BYTES_PER_AREA * remaining_count as u64 + SQLITE_JOURNAL_HEADROOM;
+
+        // Extracts are stored once on disk and once more in the storage repo, next to the
+        // new CID database rows and the journals of both databases
+        let mut checks: Vec<(&Path, Option<u64>, u64)> = Vec::new();
+        for (path, growth) in [
+            (self.config.areas_dir.as_path(), required),
+            (self.config.storage_data_dir.as_path(), required),
+            (self.config.cid_db_path.as_path(), cid_db_growth),
+            (self.config.whosonfirst_db_path.as_path(), SQLITE_JOURNAL_HEADROOM),
+        ] {
+            let volume = volume_id(path)?;
+            match checks
+                .iter_mut()
+                .find(|(_, other, _)| volume.is_some() && *other == volume)
+            {
+                Some(check) => check.2 += growth,
+                None => checks.push((path, volume, growth)),
+            }
+        }
+
+        for (path, _, required) in checks {
+            let available = available_space(path)?;
+            if available < required {
+                return Err(ExtractionError::InsufficientDiskSpace {
+                    path: path.display().to_string(),
+                    required: format_bytes(required),
+                    available: format_bytes(available),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn extract_area(
         &self,
         area: &AdministrativeArea,
         planet_source: &PlanetSource,
+        planet_version: &str,
+        country_dir: &Path,
+    ) -> Result<(), ExtractionError> {
+        self.extract_area_clipped(area, None, planet_source, planet_version, country_dir)
+            .await
+    }
+
+    /// Extract an area keeping only the tiles of a GeoJSON `region` file instead of every
+    /// tile of its bounding box. Parts of an oversized area are still cut by bounding box.
+    pub async fn extract_area_clipped(
+        &self,
+        area: &AdministrativeArea,
+        region: Option<&Path>,
+        planet_source: &PlanetSource,
+        planet_version: &str,
         country_dir: &Path,
     ) -> Result<(), ExtractionError> {
         let output_path = country_dir.join(format!("{}.pmtiles", area.id));
 
         if output_path.exists() {
             info!("Skipping existing file: {}", output_path.display());
+            self.adopt_existing_extract(area, planet_version).await?;
+            self.hand_off(area).await;
+            return Ok(());
+        }
+
+        let parts_dir = area_parts_dir(country_dir, area.id);
+        if parts_dir.exists() {
+            info!("Skipping existing parts: {}", parts_dir.display());
+            self.adopt_existing_extract(area, planet_version).await?;
+            self.hand_off(area).await;
             return Ok(());
         }
 
@@ -99,28 +793,66 @@ impl ExtractionService {
             area.placetype, area.id, area.name, bbox
         );
 
-        let output = tokio::process::Command::new(&self.config.pmtiles_cmd)
-            .args([
-                "extract",
-                planet_source.as_str(),
-                output_path.to_str().unwrap(),
-                &format!("--bbox={}", bbox),
-            ])
-            .output()
-            .await
-            .map_err(|e| ExtractionError::ExtractionFailed(area.id, e.to_string()))?;
+        if let Some(eviction) = &self.eviction {
+            if let Err(e) = eviction.make_room().await {
+                warn!("Failed to evict uploaded extracts: {}", e);
+            }
+        }
 
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr);
-            error!("Extraction failed for {} {}: {}", area.placetype, area.id, stderr);
-            return Err(ExtractionError::ExtractionFailed(
-                area.id,
-                stderr.to_string(),
-            ));
+        let planet_location =
+            self.presign_planet_url(planet_source, "GET", S3_PRESIGN_EXPIRY_SECS)?;
+
+        // The extract is written next to its final path and renamed once complete, so an
+        // interrupted extraction never leaves a truncated file that is skipped as done
+        let mut temp_path = output_path.as_os_str().to_owned();
+        temp_path.push(".tmp");
+        let temp_path = PathBuf::from(temp_path);
+        if temp_path.exists() {
+            tokio::fs::remove_file(&temp_path).await?;
         }
 
-        if output_path.exists() {
-            info!("Successfully created file: {}", output_path.display());
+        if let Err(e) = self
+            .run_extract(area, &planet_location, &temp_path, &bbox, region)
+            .await
+        {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            return Err(e);
+        }
+
+        if temp_path.exists() {
+            let file_size = tokio::fs::metadata(&temp_path).await?.len();
+            if let Some(budget) = self.config.max_output_size.filter(|max| file_size > *max) {
+                tokio::fs::remove_file(&temp_path).await?;
+                return self
+                    .skip_oversized_area(area, file_size, budget, planet_version)
+                    .await;
+            }
+            if let Some(eviction) = &self.eviction {
+                eviction.record_extract(file_size);
+            }
+            match self.config.max_extract_size.filter(|max| file_size > *max) {
+                Some(max_size) => {
+                    let result = self
+                        .split_area(area, planet_source, file_size, max_size, country_dir)
+                        .await;
+                    tokio::fs::remove_file(&temp_path).await?;
+                    result?;
+                }
+                None => {
+                    tokio::fs::rename(&temp_path, &output_path).await?;
+                    info!("Successfully created file: {}", output_path.display());
+                }
+            }
+
+            self.cid_db
+                .record_extraction(&area.country, area.id as u32, planet_version)
+                .await
+                .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+            self.events.emit(PipelineEvent::AreaExtracted {
+                country_code: area.country.clone(),
+                area_id: area.id as u32,
+            });
+            self.hand_off(area).await;
             Ok(())
         } else {
             error!("Failed to create file: {}", output_path.display());
@@ -131,134 +863,700 @@ impl ExtractionService {
         }
     }
 
-    pub async fn extract_areas(
+    /// Pass an extract on to the upload stage, when one is attached
+    async fn hand_off(&self, area: &AdministrativeArea) {
+        let Some(sender) = &self.handoff else {
+            return;
+        };
+
+        let extract = CompletedExtract {
+            country_code: area.country.clone(),
+            area_id: area.id as u32,
+        };
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.add();
+        }
+        // A closed channel means the upload stage stopped, the final upload pass picks the
+        // extract up from disk instead
+        if sender.send(extract).await.is_err() {
+            if let Some(backpressure) = &self.backpressure {
+                backpressure.release(1);
+            }
+        }
+    }
+
+    /// Blocks while uploads lag too far behind extraction
+    async fn wait_for_uploads(&self) {
+        if let Some(backpressure) = &self.backpressure {
+            backpressure.wait_for_capacity().await;
+        }
+    }
+
+    async fn run_extract(
         &self,
-        country_codes: &[String],
+        area: &AdministrativeArea,
+        planet_location: &str,
+        output_path: &Path,
+        bbox: &str,
+        region: Option<&Path>,
+    ) -> Result<(), ExtractionError> {
+        let clip = match region {
+            Some(region) => format!("--region={}", region.display()),
+            None => format!("--bbox={}", bbox),
+        };
+        let mut command = tokio::process::Command::new(&self.config.pmtiles_cmd);
+        command
+            .args([
+                "extract",
+                planet_location,
+                output_path.to_str().unwrap(),
+                &clip,
+            ])
+            .kill_on_drop(true);
+        // pmtiles honors the standard variables, NO_PROXY is passed on from the environment
+        if let Some(proxy_url) = &self.config.proxy_url {
+            command.env("HTTP_PROXY", proxy_url).env("HTTPS_PROXY", proxy_url);
+        }
+        if let Some(zoom) = self.config.zoom_profiles.for_placetype(&area.placetype) {
+            command.args([
+                format!("--minzoom={}", zoom.min),
+                format!("--maxzoom={}", zoom.max),
+            ]);
+        }
+
+        let output = match self.extraction_timeout {
+            Some(limit) => match tokio::time::timeout(limit, command.output()).await {
+                Ok(output) => output,
+                Err(_) => {
+                    let reason = format!("pmtiles timed out after {}", format_duration(limit));
+                    self.record_extract_failure(area, &reason, None).await;
+                    error!("Extraction failed for {} {}: {}", area.placetype, area.id, reason);
+                    return Err(ExtractionError::ExtractionFailed(area.id, reason));
+                }
+            },
+            None => command.output().await,
+        }
+        .map_err(|e| ExtractionError::ExtractionFailed(area.id, e.to_string()))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let last_line = stderr
+                .lines()
+                .map(str::trim)
+                .rfind(|line| !line.is_empty())
+                .unwrap_or("no error output");
+            let reason = format!("pmtiles {}: {}", output.status, last_line);
+
+            let log_path = self.write_failure_log(area, bbox, &output).await;
+            self.record_extract_failure(area, &reason, log_path.as_deref())
+                .await;
+
+            match &log_path {
+                Some(log_path) => error!(
+                    "Extraction failed for {} {}: {}, full output in {}",
+                    area.placetype,
+                    area.id,
+                    reason,
+                    log_path.display()
+                ),
+                None => error!("Extraction failed for {} {}: {}", area.placetype, area.id, reason),
+            }
+            return Err(ExtractionError::ExtractionFailed(area.id, reason));
+        }
+
+        Ok(())
+    }
+
+    /// Record an area whose extract was deleted for exceeding MAX_OUTPUT_SIZE, so it is not
+    /// extracted again for this planet build
+    async fn skip_oversized_area(
+        &self,
+        area: &AdministrativeArea,
+        file_size: u64,
+        budget: u64,
+        planet_version: &str,
+    ) -> Result<(), ExtractionError> {
+        warn!(
+            "Skipping {} {} ({}), its extract of {} exceeds MAX_OUTPUT_SIZE ({})",
+            area.placetype,
+            area.id,
+            area.name,
+            format_bytes(file_size),
+            format_bytes(budget)
+        );
+        self.cid_db
+            .record_oversized_extract(&area.country, area.id as u32, file_size, planet_version)
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        self.events.emit(PipelineEvent::AreaSkipped {
+            country_code: area.country.clone(),
+            area_id: area.id as u32,
+            reason: format!("extract of {} exceeds MAX_OUTPUT_SIZE", format_bytes(file_size)),
+        });
+        Ok(())
+    }
+
+    async fn record_extract_failure(
+        &self,
+        area: &AdministrativeArea,
+        reason: &str,
+        log_path: Option<&Path>,
+    ) {
+        if let Err(e) = self
+            .cid_db
+            .record_extraction_failure(
+                &area.country,
+                area.id as u32,
+                reason,
+                log_path.and_then(Path::to_str),
+            )
+            .await
+        {
+            warn!("Failed to record the extraction failure of {}: {}", area.id, e);
+        }
+    }
+
+    /// Keep the full output of a failed extraction in `<failures_dir>/<country>/<id>.log`,
+    /// replacing the log of an earlier failure. Returns the log's path once written.
+    async fn write_failure_log(
+        &self,
+        area: &AdministrativeArea,
+        bbox: &str,
+        output: &std::process::Output,
+    ) -> Option<PathBuf> {
+        let country_dir = self.config.failures_dir.join(&area.country);
+        let log_path = country_dir.join(format!("{}.log", area.id));
+
+        // The configured location, the URL handed to pmtiles may carry a signature
+        let planet = self
+            .config
+            .planet_pmtiles_location
+            .as_deref()
+            .unwrap_or("-");
+        let log = format!(
+            "Area: {} {} ({}), {}\n\
+             Bounding box: {}\n\
+             Planet: {}\n\
+             Failed at: {}\n\
+             Exit status: {}\n\
+             \n\
+             --- stdout ---\n\
+             {}\n\
+             --- stderr ---\n\
+             {}\n",
+            area.placetype,
+            area.id,
+            area.name,
+            area.country,
+            bbox,
+            planet,
+            chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            output.status,
+            String::from_utf8_lossy(&output.stdout).trim_end(),
+            String::from_utf8_lossy(&output.stderr).trim_end(),
+        );
+
+        let written = async {
+            tokio::fs::create_dir_all(&country_dir).await?;
+            tokio::fs::write(&log_path, log).await
+        };
+        match written.await {
+            Ok(()) => Some(log_path),
+            Err(e) => {
+                warn!("Failed to write {}: {}", log_path.display(), e);
+                None
+            }
+        }
+    }
+
+    /// Re-extracts an oversized area as a grid of parts sized to fit under `max_size`.
+    /// Parts are written to a temporary directory that is renamed into place once all of
+    /// them and their manifest exist, so an interrupted split is started over.
+    async fn split_area(
+        &self,
+        area: &AdministrativeArea,
+        planet_source: &PlanetSource,
+        file_size: u64,
+        max_size: u64,
+        country_dir: &Path,
     ) -> Result<(), ExtractionError> {
+        let grid = ((file_size as f64 / max_size as f64).sqrt().ceil() as u32).max(2);
+        let parts = area.split_bbox(grid);
+
+        info!(
+            "Extract of {} {} is {}, above {}, splitting into {} parts",
+            area.placetype,
+            area.id,
+            format_bytes(file_size),
+            format_bytes(max_size),
+            parts.len()
+        );
+
+        let parts_dir = area_parts_dir(country_dir, area.id);
+        let mut temp_dir = parts_dir.as_os_str().to_owned();
+        temp_dir.push(".tmp");
+        let temp_dir = PathBuf::from(temp_dir);
+        if temp_dir.exists() {
+            tokio::fs::remove_dir_all(&temp_dir).await?;
+        }
+        tokio::fs::create_dir_all(&temp_dir).await?;
+
+        for part in &parts {
+            // Presign per part, a long split can outlive a single presigned URL
+            let planet_location =
+                self.presign_planet_url(planet_source, "GET", S3_PRESIGN_EXPIRY_SECS)?;
+            let part_path = temp_dir.join(part.file_name());
+            self.run_extract(area, &planet_location, &part_path, &part.bbox(), None)
+                .await?;
+
+            let part_size = tokio::fs::metadata(&part_path).await?.len();
+            if part_size > max_size {
+                warn!(
+                    "Part {} of area {} is still {}, above {}",
+                    part.index,
+                    area.id,
+                    format_bytes(part_size),
+                    format_bytes(max_size)
+                );
+            }
+        }
+
+        let manifest = serde_json::to_string_pretty(&parts)
+            .map_err(|e| ExtractionError::ExtractionFailed(area.id, e.to_string()))?;
+        tokio::fs::write(temp_dir.join(AREA_PARTS_MANIFEST), manifest).await?;
+        tokio::fs::rename(&temp_dir, &parts_dir).await?;
+
+        info!("Created {} parts in {}", parts.len(), parts_dir.display());
+        Ok(())
+    }
+
+    /// Estimate what extracting and uploading the countries takes from `samples` extracts of
+    /// each, spread over its areas. Extracts already on disk count as samples, the others
+    /// are extracted and kept for the run.
+    pub async fn plan_countries(
+        &self,
+        country_codes: &[String],
+        samples: usize,
+    ) -> Result<RunPlan, ExtractionError> {
         let planet_source = self.get_planet_source()?;
+        let planet_version = self.get_planet_version(&planet_source).await?;
+        self.invalidate_stale_areas(&planet_version).await?;
+        let (planet_source, _proxy) = self.proxy_planet_source(planet_source).await?;
+        let fallback = self
+            .cid_db
+            .get_average_file_size()
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?
+            .unwrap_or(DEFAULT_AREA_SIZE_ESTIMATE);
+        let stored_bytes = self
+            .cid_db
+            .get_bytes_by_country()
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?
+            .iter()
+            .map(|country| country.total_bytes)
+            .sum();
 
+        let mut countries = Vec::with_capacity(country_codes.len());
         for country_code in country_codes {
-            info!("Processing country: {}", country_code);
+            let areas = self.country_extraction_areas(country_code).await?;
+            let areas = self.drop_uploaded_areas(areas).await?;
+            let mut sizes: Vec<u64> = areas
+                .iter()
+                .filter_map(|area| self.extracted_size(country_code, area.id))
+                .take(samples)
+                .collect();
 
+            let step = (areas.len() / samples.max(1)).max(1);
             let country_dir = self.config.areas_dir.join(country_code);
-            if !country_dir.exists() {
-                std::fs::create_dir_all(&country_dir)?;
+            for area in areas.iter().step_by(step) {
+                if sizes.len() >= samples {
+                    break;
+                }
+                if self.is_area_extracted(country_code, area.id) {
+                    continue;
+                }
+                tokio::fs::create_dir_all(&country_dir).await?;
+                match self
+                    .extract_area(area, &planet_source, &planet_version, &country_dir)
+                    .await
+                {
+                    Ok(()) => sizes.extend(self.extracted_size(country_code, area.id)),
+                    Err(e) => warn!("Failed to extract sample area {}: {}", area.id, e),
+                }
             }
 
-            let areas = self
-                .db_service
-                .get_country_areas(country_code)
+            countries.push(CountryPlan::new(
+                country_code,
+                areas.len() as u64,
+                &sizes,
+                fallback,
+            ));
+        }
+
+        Ok(RunPlan {
+            countries,
+            stored_bytes,
+            storage_quota: self.config.storage_quota,
+        })
+    }
+
+    pub async fn extract_areas(
+        &self,
+        country_codes: &[String],
+    ) -> Result<(), ExtractionError> {
+        let started = Instant::now();
+        let result = self.extract_countries(country_codes).await;
+        self.timings.lock().await.extraction = Some(started.elapsed());
+        result
+    }
+
+    async fn extract_countries(&self, country_codes: &[String]) -> Result<(), ExtractionError> {
+        let planet_source = self.get_planet_source()?;
+        let planet_version = self.get_planet_version(&planet_source).await?;
+        self.invalidate_stale_areas(&planet_version).await?;
+        self.checkpoint_phase().await;
+        let held_back = self
+            .cid_db
+            .get_held_back_extractions(
+                self.config.extraction_max_attempts,
+                self.config.extraction_retry_after,
+            )
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        let oversized = match self.config.max_output_size {
+            Some(max_size) => self
+                .cid_db
+                .get_oversized_keys(&planet_version, max_size)
                 .await
-                .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+                .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?,
+            None => HashSet::new(),
+        };
 
-            if areas.is_empty() {
-                info!("No areas found for country: {}", country_code);
+        let mut country_areas = Vec::with_capacity(country_codes.len());
+        let mut remaining_total = 0;
+        for country_code in country_codes {
+            if self
+                .checkpoint
+                .as_ref()
+                .is_some_and(|extracted| extracted.contains(country_code))
+            {
+                info!("Skipping country {}, extracted before the interruption", country_code);
                 continue;
             }
 
-            info!(
-                "Found {} areas for country: {}",
-                areas.len(),
-                country_code
+            let areas = self.country_extraction_areas(country_code).await?;
+            let areas = self.drop_uploaded_areas(areas).await?;
+            let areas = drop_held_back_areas(
+                areas,
+                &held_back,
+                "whose extraction failed too often or too recently",
             );
+            let areas = drop_held_back_areas(areas, &oversized, "over MAX_OUTPUT_SIZE");
+            remaining_total += areas
+                .iter()
+                .filter(|area| !self.is_area_extracted(country_code, area.id))
+                .count();
+            country_areas.push((country_code, areas));
+        }
 
-            let mut existing_count = 0;
-            for area in &areas {
-                let output_path = country_dir.join(format!("{}.pmtiles", area.id));
-                if output_path.exists() {
-                    existing_count += 1;
-                }
+        self.ensure_disk_space(remaining_total).await?;
+        let (planet_source, _proxy) = self.proxy_planet_source(planet_source).await?;
+
+        for (country_code, areas) in country_areas {
+            if !self.claim_country(country_code).await? {
+                continue;
             }
 
-            let total_count = areas.len();
-            let remaining_count = total_count - existing_count;
+            let keep_alive = self.claims.as_ref().map(|claims| claims.keep_alive(country_code));
+            let started = Instant::now();
+            let result = self
+                .extract_country(country_code, areas, &planet_source, &planet_version)
+                .await;
+            self.timings
+                .lock()
+                .await
+                .record_country_extraction(country_code, started.elapsed());
+            if let Some(handle) = keep_alive {
+                handle.abort();
+            }
+            self.settle_claim(country_code, result.is_ok()).await;
+            result?;
+            self.checkpoint_country(country_code).await;
+        }
 
-            if remaining_count == 0 {
-                info!(
-                    "All {} areas already exist for country: {}",
-                    total_count, country_code
-                );
-                continue;
+        Ok(())
+    }
+
+    /// Extract the areas of one country, skipping those already on disk
+    async fn extract_country(
+        &self,
+        country_code: &str,
+        areas: Vec<AdministrativeArea>,
+        planet_source: &PlanetSource,
+        planet_version: &str,
+    ) -> Result<(), ExtractionError> {
+        info!("Processing country: {}", country_code);
+        let areas = self.adopt_published_areas(country_code, areas).await?;
+
+        let country_dir = self.config.areas_dir.join(country_code);
+        if !country_dir.exists() {
+            std::fs::create_dir_all(&country_dir)?;
+        }
+
+        if areas.is_empty() {
+            info!("No areas found for country: {}", country_code);
+            return Ok(());
+        }
+
+        info!(
+            "Found {} areas for country: {}",
+            areas.len(),
+            country_code
+        );
+
+        let mut existing_count = 0;
+        for area in &areas {
+            if self.is_area_extracted(country_code, area.id) {
+                existing_count += 1;
             }
+        }
 
+        let total_count = areas.len();
+        let remaining_count = total_count - existing_count;
+        self.events.emit(PipelineEvent::CountryStarted {
+            country_code: country_code.to_string(),
+            stage: PipelineStage::Extraction,
+            area_count: total_count as u64,
+            completed: existing_count as u64,
+        });
+
+        if remaining_count == 0 {
             info!(
-                "Progress: {}/{} areas already exist, {} remaining to extract",
-                existing_count, total_count, remaining_count
+                "All {} areas already exist for country: {}",
+                total_count, country_code
             );
+            self.emit_country_completed(country_code);
+            return Ok(());
+        }
 
-            let semaphore = Arc::new(Semaphore::new(self.config.max_concurrent_extractions));
-            let mut tasks = Vec::new();
-            let completed_count = Arc::new(std::sync::atomic::AtomicUsize::new(existing_count));
+        info!(
+            "Progress: {}/{} areas already exist, {} remaining to extract",
+            existing_count, total_count, remaining_count
+        );
 
-            for area in areas {
-                let planet_source = planet_source.clone();
-                let country_dir = country_dir.clone();
-                let semaphore = semaphore.clone();
-                let extraction_service = self.clone();
-                let completed_count = completed_count.clone();
+        let semaphore = Arc::new(Semaphore::new(self.config.max_concurrent_extractions));
+        // Dropping the set aborts the areas still running or queued, so a cancelled run
+        // extracts nothing more
+        let mut tasks = JoinSet::new();
+        let completed_count = Arc::new(std::sync::atomic::AtomicUsize::new(existing_count));
 
-                let task = tokio::spawn(async move {
-                    let _permit = semaphore.acquire().await.unwrap();
-                    let result = extraction_service
-                        .extract_area(&area, &planet_source, &country_dir)
-                        .await;
+        for area in areas {
+            let planet_source = planet_source.clone();
+            let planet_version = planet_version.to_string();
+            let country_dir = country_dir.clone();
+            let semaphore = semaphore.clone();
+            let extraction_service = self.clone();
+            let completed_count = completed_count.clone();
 
-                    if result.is_ok() {
-                        let current =
-                            completed_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-                        info!(
-                            "Progress: {}/{} areas extracted for {}",
-                            current + 1,
-                            total_count,
-                            area.country
-                        );
-                    }
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire().await.unwrap();
+                extraction_service.pause.wait_until_resumed().await;
+                extraction_service.wait_for_uploads().await;
+                let result = extraction_service
+                    .extract_area(&area, &planet_source, &planet_version, &country_dir)
+                    .await;
 
-                    result
-                });
+                if result.is_ok() {
+                    let current =
+                        completed_count.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+                    info!(
+                        "Progress: {}/{} areas extracted for {}",
+                        current + 1,
+                        total_count,
+                        area.country
+                    );
+                    extraction_service.checkpoint_area(&area).await;
+                }
+
+                (area, result)
+            });
+        }
 
-                tasks.push(task);
+        let mut has_errors = false;
+        let mut failed = Vec::new();
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok((_, Ok(()))) => {}
+                Ok((area, Err(e))) => {
+                    error!("Extraction task failed: {}", e);
+                    failed.push(area);
+                }
+                Err(e) => {
+                    error!("Extraction task panicked: {:?}", e);
+                    has_errors = true;
+                }
             }
+        }
+        if self
+            .retry_failed_areas(failed, planet_source, planet_version)
+            .await
+            > 0
+        {
+            has_errors = true;
+        }
 
-            let results = futures::future::join_all(tasks).await;
+        if has_errors {
+            return Err(ExtractionError::ExtractionFailed(
+                0,
+                format!("Some extraction tasks failed for country: {}", country_code),
+            ));
+        }
 
-            let mut has_errors = false;
-            for result in results {
-                match result {
-                    Ok(Ok(())) => {}
-                    Ok(Err(e)) => {
-                        error!("Extraction task failed: {}", e);
-                        has_errors = true;
-                    }
-                    Err(e) => {
-                        error!("Extraction task panicked: {:?}", e);
-                        has_errors = true;
-                    }
+        self.emit_country_completed(country_code);
+        Ok(())
+    }
+
+    /// Retry failed areas one at a time with a longer timeout before giving up on them, as
+    /// many failures against remote planet sources are transient. Returns how many still
+    /// failed.
+    async fn retry_failed_areas(
+        &self,
+        areas: Vec<AdministrativeArea>,
+        planet_source: &PlanetSource,
+        planet_version: &str,
+    ) -> usize {
+        if areas.is_empty() {
+            return 0;
+        }
+
+        info!("Retrying {} failed extractions one at a time", areas.len());
+        let retry_service = Self {
+            extraction_timeout: self
+                .extraction_timeout
+                .map(|limit| limit * RETRY_TIMEOUT_FACTOR),
+            ..self.clone()
+        };
+
+        let mut still_failed = 0;
+        for area in areas {
+            self.pause.wait_until_resumed().await;
+            self.wait_for_uploads().await;
+            let country_dir = self.config.areas_dir.join(&area.country);
+            match retry_service
+                .extract_area(&area, planet_source, planet_version, &country_dir)
+                .await
+            {
+                Ok(()) => {
+                    info!("Extracted {} {} on retry", area.placetype, area.id);
+                    self.checkpoint_area(&area).await;
+                }
+                Err(e) => {
+                    error!("Retry of {} {} failed: {}", area.placetype, area.id, e);
+                    still_failed += 1;
                 }
             }
+        }
+        still_failed
+    }
 
-            if has_errors {
-                return Err(ExtractionError::ExtractionFailed(
-                    0,
-                    format!("Some extraction tasks failed for country: {}", country_code),
-                ));
-            }
+    /// Record that the run entered the extraction phase
+    async fn checkpoint_phase(&self) {
+        if self.checkpoint.is_none() {
+            return;
         }
+        if let Err(e) = self.cid_db.set_checkpoint_phase(RunPhase::Extraction).await {
+            warn!("Failed to update the run checkpoint: {}", e);
+        }
+    }
 
-        Ok(())
+    /// Record a country whose extraction finished, so a resumed run skips it
+    async fn checkpoint_country(&self, country_code: &str) {
+        if self.checkpoint.is_none() {
+            return;
+        }
+        if let Err(e) = self
+            .cid_db
+            .record_checkpoint_country(RunPhase::Extraction, country_code)
+            .await
+        {
+            warn!("Failed to checkpoint country {}: {}", country_code, e);
+        }
+    }
+
+    /// Record the last area extracted
+    async fn checkpoint_area(&self, area: &AdministrativeArea) {
+        if self.checkpoint.is_none() {
+            return;
+        }
+        if let Err(e) = self
+            .cid_db
+            .record_checkpoint_area(&area.country, area.id as u32)
+            .await
+        {
+            warn!("Failed to checkpoint area {}: {}", area.id, e);
+        }
+    }
+
+    /// Claim a country before extracting it, true when no claims store is configured
+    async fn claim_country(&self, country_code: &str) -> Result<bool, ExtractionError> {
+        let Some(claims) = &self.claims else {
+            return Ok(true);
+        };
+
+        let claimed = claims
+            .try_claim(country_code)
+            .await
+            .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        if !claimed {
+            info!("Skipping country {}, it is claimed by another node", country_code);
+        }
+        Ok(claimed)
+    }
+
+    /// Mark the claim on an extracted country complete, or release it after a failure so
+    /// another node can take the country over
+    async fn settle_claim(&self, country_code: &str, extracted: bool) {
+        let Some(claims) = &self.claims else {
+            return;
+        };
+
+        let result = match extracted {
+            true => claims.complete(country_code).await,
+            false => claims.release(country_code).await,
+        };
+        match result {
+            Ok(true) => {}
+            Ok(false) => warn!("The claim on {} was taken over by another node", country_code),
+            Err(e) => warn!("Failed to update the claim on {}: {}", country_code, e),
+        }
     }
 
     pub async fn extract_areas_by_ids(
         &self,
         area_ids: &[u32],
     ) -> Result<(), ExtractionError> {
+        let started = Instant::now();
+        let result = self.extract_listed_areas(area_ids).await;
+        self.timings.lock().await.extraction = Some(started.elapsed());
+        result
+    }
+
+    async fn extract_listed_areas(&self, area_ids: &[u32]) -> Result<(), ExtractionError> {
         let planet_source = self.get_planet_source()?;
+        let planet_version = self.get_planet_version(&planet_source).await?;
+        self.invalidate_stale_areas(&planet_version).await?;
+        self.checkpoint_phase().await;
 
         let areas = self
             .db_service
             .get_areas_by_ids(area_ids)
             .await
             .map_err(|e| ExtractionError::DatabaseError(e.to_string()))?;
+        let found_ids: HashSet<i64> =
+            areas.iter().map(|a| a.id).collect();
+        let areas = self.filter_by_bbox_size(areas);
+        let areas = self.drop_uploaded_areas(areas).await?;
 
         if areas.is_empty() {
             info!("No valid areas found for provided IDs");
@@ -271,8 +1569,6 @@ impl ExtractionService {
             area_ids.len()
         );
 
-        let found_ids: std::collections::HashSet<i64> =
-            areas.iter().map(|a| a.id).collect();
         for id in area_ids {
             if !found_ids.contains(&(*id as i64)) {
                 warn!(
@@ -282,18 +1578,27 @@ impl ExtractionService {
             }
         }
 
+        let remaining_count = areas
+            .iter()
+            .filter(|area| !self.is_area_extracted(&area.country, area.id))
+            .count();
+        self.ensure_disk_space(remaining_count).await?;
+        let (planet_source, _proxy) = self.proxy_planet_source(planet_source).await?;
+
         let mut by_country: HashMap<String, Vec<AdministrativeArea>> = HashMap::new();
         for area in areas {
             by_country
                 .entry(area.country.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(area);
         }
 
         let semaphore = Arc::new(Semaphore::new(self.config.max_concurrent_extractions));
-        let mut tasks = Vec::new();
+        // Aborted on drop like the per-country set
+        let mut tasks = JoinSet::new();
 
         for (country_code, country_areas) in by_country {
+            let country_areas = self.adopt_published_areas(&country_code, country_areas).await?;
             let country_dir = self.config.areas_dir.join(&country_code);
             if !country_dir.exists() {
                 std::fs::create_dir_all(&country_dir)?;
@@ -301,30 +1606,34 @@ impl ExtractionService {
 
             for area in country_areas {
                 let planet_source = planet_source.clone();
+                let planet_version = planet_version.clone();
                 let country_dir = country_dir.clone();
                 let semaphore = semaphore.clone();
                 let extraction_service = self.clone();
 
-                let task = tokio::spawn(async move {
+                tasks.spawn(async move {
                     let _permit = semaphore.acquire().await.unwrap();
-                    extraction_service
-                        .extract_area(&area, &planet_source, &country_dir)
-                        .await
+                    extraction_service.pause.wait_until_resumed().await;
+                    extraction_service.wait_for_uploads().await;
+                    let result = extraction_service
+                        .extract_area(&area, &planet_source, &planet_version, &country_dir)
+                        .await;
+                    if result.is_ok() {
+                        extraction_service.checkpoint_area(&area).await;
+                    }
+                    (area, result)
                 });
-
-                tasks.push(task);
             }
         }
 
-        let results = futures::future::join_all(tasks).await;
-
         let mut has_errors = false;
-        for result in results {
+        let mut failed = Vec::new();
+        while let Some(result) = tasks.join_next().await {
             match result {
-                Ok(Ok(_)) => {}
-                Ok(Err(e)) => {
+                Ok((_, Ok(_))) => {}
+                Ok((area, Err(e))) => {
                     error!("Extraction task failed: {}", e);
-                    has_errors = true;
+                    failed.push(area);
                 }
                 Err(e) => {
                     error!("Extraction task panicked: {:?}", e);
@@ -332,6 +1641,13 @@ impl ExtractionService {
                 }
             }
         }
+        if self
+            .retry_failed_areas(failed, &planet_source, &planet_version)
+            .await
+            > 0
+        {
+            has_errors = true;
+        }
 
         if has_errors {
             return Err(ExtractionError::ExtractionFailed(
@@ -377,11 +1693,46 @@ impl ExtractionService {
     }
 }
 
+/// Drops areas held back by an earlier run, those whose extractions failed too often or too
+/// recently, see EXTRACTION_MAX_ATTEMPTS and EXTRACTION_RETRY_AFTER_SECS, or whose extract
+/// was over MAX_OUTPUT_SIZE. `reason` describes them in the log.
+fn drop_held_back_areas(
+    areas: Vec<AdministrativeArea>,
+    held_back: &HashSet<(String, u32)>,
+    reason: &str,
+) -> Vec<AdministrativeArea> {
+    if held_back.is_empty() {
+        return areas;
+    }
+
+    let total = areas.len();
+    let kept: Vec<_> = areas
+        .into_iter()
+        .filter(|area| !held_back.contains(&(area.country.clone(), area.id as u32)))
+        .collect();
+
+    if kept.len() < total {
+        info!("Skipped {} of {} areas {}", total - kept.len(), total, reason);
+    }
+    kept
+}
+
 impl Clone for ExtractionService {
     fn clone(&self) -> Self {
         Self {
             config: self.config.clone(),
             db_service: self.db_service.clone(),
+            cid_db: self.cid_db.clone(),
+            events: self.events.clone(),
+            pause: self.pause.clone(),
+            claims: self.claims.clone(),
+            catalog: self.catalog.clone(),
+            timings: self.timings.clone(),
+            checkpoint: self.checkpoint.clone(),
+            handoff: self.handoff.clone(),
+            backpressure: self.backpressure.clone(),
+            eviction: self.eviction.clone(),
+            extraction_timeout: self.extraction_timeout,
         }
     }
 }
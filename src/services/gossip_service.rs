@@ -0,0 +1,162 @@
+use crate::config::Config;
+use crate::services::{DatabaseError, DatabaseService, EventService};
+use crate::types::{CidAnnouncement, PipelineEvent};
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+/// How long a peer gets to accept an announcement
+const ANNOUNCE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Error, Debug)]
+pub enum GossipError {
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DatabaseError),
+    #[error("HTTP error: {0}")]
+    HttpError(#[from] reqwest::Error),
+    #[error("Serialization error: {0}")]
+    SerializationError(#[from] serde_json::Error),
+}
+
+/// Builds a catalog shared between AnyNodes without a central index: every area this node
+/// uploads is announced to GOSSIP_PEERS, and announcements received from other nodes are
+/// recorded in the CID database and passed on while hops remain.
+pub struct GossipService {
+    cid_db: Arc<DatabaseService>,
+    client: reqwest::Client,
+    node_id: String,
+    peers: Vec<String>,
+    ttl: u8,
+}
+
+impl GossipService {
+    pub fn new(cid_db: Arc<DatabaseService>, config: &Config) -> Self {
+        Self {
+            cid_db,
+            client: reqwest::Client::new(),
+            node_id: config.node_id.clone(),
+            peers: config.gossip_peers.clone(),
+            ttl: config.gossip_ttl,
+        }
+    }
+
+    /// Announce each area this node uploads, until the events channel closes
+    pub fn start(self: &Arc<Self>, events: &EventService) -> tokio::task::JoinHandle<()> {
+        let gossip = self.clone();
+        let mut receiver = events.subscribe();
+
+        tokio::spawn(async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(PipelineEvent::AreaUploaded {
+                        country_code,
+                        area_id,
+                        cid,
+                        file_size,
+                    }) => {
+                        let announcement = CidAnnouncement {
+                            origin: gossip.node_id.clone(),
+                            country_code,
+                            area_id,
+                            cid,
+                            file_size,
+                            ttl: gossip.ttl,
+                        };
+                        gossip.send_to_peers(&announcement).await;
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Gossip lagged, {} uploads were not announced", skipped);
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        })
+    }
+
+    /// Record an announcement from another node and pass it on in the background when it
+    /// was news. Returns whether it was recorded.
+    pub async fn receive(
+        self: &Arc<Self>,
+        announcement: CidAnnouncement,
+    ) -> Result<bool, GossipError> {
+        if announcement.origin == self.node_id {
+            return Ok(false);
+        }
+        if !self.cid_db.record_peer_cid(&announcement).await? {
+            return Ok(false);
+        }
+
+        debug!(
+            "Recorded area {} of {} from {}: {}",
+            announcement.area_id, announcement.country_code, announcement.origin, announcement.cid
+        );
+        // Never pass on more hops than this node would give its own announcements
+        let announcement = CidAnnouncement {
+            ttl: announcement.ttl.min(self.ttl),
+            ..announcement
+        };
+        if let Some(forwarded) = announcement.forwarded() {
+            let gossip = self.clone();
+            tokio::spawn(async move { gossip.send_to_peers(&forwarded).await });
+        }
+        Ok(true)
+    }
+
+    async fn send_to_peers(&self, announcement: &CidAnnouncement) {
+        let results = futures::future::join_all(
+            self.peers.iter().map(|peer| self.send(peer, announcement)),
+        )
+        .await;
+
+        for (peer, result) in self.peers.iter().zip(results) {
+            if let Err(e) = result {
+                warn!("Failed to announce area {} to {}: {}", announcement.area_id, peer, e);
+            }
+        }
+    }
+
+    async fn send(&self, peer: &str, announcement: &CidAnnouncement) -> Result<(), GossipError> {
+        self.client
+            .post(format!("{}/announcements", peer))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(serde_json::to_vec(announcement)?)
+            .timeout(ANNOUNCE_TIMEOUT)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn records_announcements_from_other_nodes_only() {
+        let gossip = Arc::new(GossipService {
+            cid_db: Arc::new(DatabaseService::in_memory(true).await.unwrap()),
+            client: reqwest::Client::new(),
+            node_id: "node-a".to_string(),
+            peers: Vec::new(),
+            ttl: 3,
+        });
+        let mut announcement = CidAnnouncement {
+            origin: "node-a".to_string(),
+            country_code: "FR".to_string(),
+            area_id: 12,
+            cid: "cid".to_string(),
+            file_size: 2048,
+            ttl: 3,
+        };
+
+        assert!(!gossip.receive(announcement.clone()).await.unwrap());
+        announcement.origin = "node-b".to_string();
+        assert!(gossip.receive(announcement.clone()).await.unwrap());
+        assert!(!gossip.receive(announcement).await.unwrap());
+        assert_eq!(gossip.cid_db.count_peer_cids().await.unwrap(), (1, 1));
+    }
+}
@@ -0,0 +1,566 @@
+use futures::future::BoxFuture;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::watch;
+use tracing::info;
+
+use super::storage_backend::{
+    DownloadProgress, DownloadResult, LocalContent, NodeInfo, PeerEntry, StorageBackend,
+    StorageError, StorageStatus, StorageUsage, UploadResult,
+};
+
+/// IPFS node reached through the Kubo RPC API. The daemon runs on its own, starting and
+/// stopping the backend only checks that it answers. Uploads are added as CIDv1 and pinned,
+/// deleting content unpins it for the daemon's garbage collector.
+pub struct KuboBackend {
+    client: reqwest::Client,
+    api_url: String,
+    status: watch::Sender<StorageStatus>,
+}
+
+#[derive(Deserialize)]
+struct IdResponse {
+    #[serde(rename = "ID")]
+    id: String,
+    #[serde(rename = "AgentVersion")]
+    agent_version: Option<String>,
+    #[serde(rename = "Addresses", default)]
+    addresses: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct RepoStat {
+    repo_size: u64,
+    storage_max: u64,
+    num_objects: u64,
+    repo_path: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SwarmPeers {
+    peers: Option<Vec<SwarmPeer>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SwarmPeer {
+    peer: String,
+    addr: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AddResponse {
+    hash: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PinList {
+    keys: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct FileStat {
+    size: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ApiError {
+    message: String,
+}
+
+impl KuboBackend {
+    pub fn new(api_url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            status: watch::channel(StorageStatus::Initialized).0,
+        }
+    }
+
+    /// POST an RPC command, every Kubo command is a POST. Failures are returned as the
+    /// daemon's message for the caller to wrap in the error of its operation.
+    async fn call(
+        &self,
+        command: &str,
+        args: &[(&str, &str)],
+    ) -> Result<reqwest::Response, String> {
+        let response = self
+            .client
+            .post(format!("{}/api/v0/{}", self.api_url, command))
+            .query(args)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        check_response(response).await
+    }
+
+    async fn call_json<T: DeserializeOwned>(
+        &self,
+        command: &str,
+        args: &[(&str, &str)],
+    ) -> Result<T, String> {
+        let body = self
+            .call(command, args)
+            .await?
+            .bytes()
+            .await
+            .map_err(|e| e.to_string())?;
+        serde_json::from_slice(&body).map_err(|e| format!("{}: {}", command, e))
+    }
+
+    fn ensure_started(&self) -> Result<(), StorageError> {
+        match *self.status.borrow() {
+            StorageStatus::Connected => Ok(()),
+            _ => Err(StorageError::NodeNotStarted),
+        }
+    }
+
+    async fn start(&self) -> Result<(), StorageError> {
+        self.status.send_replace(StorageStatus::Connecting);
+        match self.call_json::<IdResponse>("id", &[]).await {
+            Ok(id) => {
+                self.status.send_replace(StorageStatus::Connected);
+                info!("Connected to Kubo node {} at {}", id.id, self.api_url);
+                Ok(())
+            }
+            Err(e) => {
+                self.status.send_replace(StorageStatus::Error);
+                Err(StorageError::NodeStart(format!("{}: {}", self.api_url, e)))
+            }
+        }
+    }
+
+    async fn file_size(&self, cid: &str) -> Result<u64, String> {
+        let path = format!("/ipfs/{}", cid);
+        let stat: FileStat = self.call_json("files/stat", &[("arg", &path)]).await?;
+        Ok(stat.size)
+    }
+
+    async fn node_info(&self) -> Result<NodeInfo, StorageError> {
+        let id: IdResponse = self
+            .call_json("id", &[])
+            .await
+            .map_err(StorageError::ConnectionFailed)?;
+        let peers = self.peers().await.unwrap_or_default();
+        let repo_stat = self.call_json::<RepoStat>("repo/stat", &[]).await.ok();
+
+        Ok(NodeInfo {
+            peer_id: Some(id.id),
+            version: id.agent_version,
+            repo_path: repo_stat.as_ref().and_then(|stat| stat.repo_path.clone()),
+            addresses: id.addresses.unwrap_or_default(),
+            announce_addresses: Vec::new(),
+            spr: None,
+            discovery_node_count: peers.len(),
+            peers,
+            storage_usage: repo_stat.map(StorageUsage::from),
+        })
+    }
+
+    async fn peers(&self) -> Result<Vec<PeerEntry>, StorageError> {
+        let peers: SwarmPeers = self
+            .call_json("swarm/peers", &[])
+            .await
+            .map_err(StorageError::ConnectionFailed)?;
+
+        Ok(peers
+            .peers
+            .unwrap_or_default()
+            .into_iter()
+            .map(|peer| PeerEntry {
+                peer_id: peer.peer,
+                node_id: None,
+                address: Some(peer.addr),
+                seen: true,
+            })
+            .collect())
+    }
+
+    async fn connect(&self, target: &str) -> Result<String, StorageError> {
+        self.ensure_started()?;
+        let peer_id = peer_id_of(target)?;
+
+        info!("Connecting to peer {} via {}", peer_id, target.trim());
+        self.call("swarm/connect", &[("arg", target.trim())])
+            .await
+            .map_err(|e| StorageError::ConnectionFailed(format!("{}: {}", peer_id, e)))?;
+        Ok(peer_id)
+    }
+
+    async fn upload(&self, file_path: &Path) -> Result<UploadResult, StorageError> {
+        self.ensure_started()?;
+
+        let file = tokio::fs::File::open(file_path).await?;
+        let file_size = file.metadata().await?.len();
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "extract".to_string());
+        info!(
+            "Uploading file: {} ({} bytes)",
+            file_path.display(),
+            file_size
+        );
+
+        let part =
+            reqwest::multipart::Part::stream_with_length(file, file_size).file_name(file_name);
+        let form = reqwest::multipart::Form::new().part("file", part);
+        let response = self
+            .client
+            .post(format!("{}/api/v0/add", self.api_url))
+            .query(&[("pin", "true"), ("cid-version", "1"), ("progress", "false")])
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| StorageError::UploadFailed(e.to_string()))?;
+        let body = check_response(response)
+            .await
+            .map_err(StorageError::UploadFailed)?
+            .bytes()
+            .await
+            .map_err(|e| StorageError::UploadFailed(e.to_string()))?;
+        let added: AddResponse =
+            serde_json::from_slice(&body).map_err(|e| StorageError::UploadFailed(e.to_string()))?;
+
+        info!("Upload complete. CID: {}", added.hash);
+        Ok(UploadResult {
+            cid: added.hash,
+            size: file_size,
+        })
+    }
+
+    async fn has(&self, cid: &str) -> Result<bool, StorageError> {
+        self.ensure_started()?;
+        match self
+            .call("pin/ls", &[("arg", cid), ("type", "recursive")])
+            .await
+        {
+            Ok(_) => Ok(true),
+            Err(e) if e.contains("not pinned") => Ok(false),
+            Err(e) => Err(StorageError::DownloadFailed(e)),
+        }
+    }
+
+    async fn local_content(&self) -> Result<Vec<LocalContent>, StorageError> {
+        self.ensure_started()?;
+        let pins: PinList = self
+            .call_json("pin/ls", &[("type", "recursive")])
+            .await
+            .map_err(StorageError::ConnectionFailed)?;
+
+        let mut content = Vec::with_capacity(pins.keys.len());
+        for cid in pins.keys.into_keys() {
+            let size = self.file_size(&cid).await.unwrap_or(0);
+            content.push(LocalContent {
+                cid,
+                size,
+                filename: None,
+            });
+        }
+        Ok(content)
+    }
+
+    async fn unpin(&self, cid: &str) -> Result<(), StorageError> {
+        self.ensure_started()?;
+        self.call("pin/rm", &[("arg", cid)])
+            .await
+            .map(|_| ())
+            .map_err(StorageError::DeleteFailed)
+    }
+
+    /// Stream `cat` into `destination`, from the local repo only when `offline`
+    async fn download(
+        &self,
+        cid: &str,
+        destination: &Path,
+        offline: bool,
+        on_progress: DownloadProgress,
+    ) -> Result<DownloadResult, StorageError> {
+        self.ensure_started()?;
+
+        let offline = if offline { "true" } else { "false" };
+        let response = self
+            .call("cat", &[("arg", cid), ("offline", offline)])
+            .await
+            .map_err(StorageError::DownloadFailed)?;
+        let total = response
+            .headers()
+            .get("X-Content-Length")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+
+        let mut file = tokio::fs::File::create(destination).await?;
+        let mut written = 0u64;
+        let mut body = response.bytes_stream();
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| StorageError::DownloadFailed(e.to_string()))?;
+            file.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+            on_progress(written, total);
+        }
+        file.flush().await?;
+
+        if total.is_some_and(|total| total != written) {
+            return Err(StorageError::DownloadFailed(format!(
+                "wrote {} of {} bytes to {}",
+                written,
+                total.unwrap_or_default(),
+                destination.display()
+            )));
+        }
+        Ok(DownloadResult {
+            cid: cid.to_string(),
+            size: written as usize,
+        })
+    }
+
+    async fn pin(&self, cid: &str) -> Result<DownloadResult, StorageError> {
+        self.ensure_started()?;
+        self.call("pin/add", &[("arg", cid)])
+            .await
+            .map_err(StorageError::DownloadFailed)?;
+        let size = self
+            .file_size(cid)
+            .await
+            .map_err(StorageError::DownloadFailed)?;
+
+        Ok(DownloadResult {
+            cid: cid.to_string(),
+            size: size as usize,
+        })
+    }
+}
+
+impl StorageBackend for KuboBackend {
+    fn start_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(self.start())
+    }
+
+    fn stop_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.status.send_replace(StorageStatus::Initialized);
+            Ok(())
+        })
+    }
+
+    fn restart_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(self.start())
+    }
+
+    fn get_status(&self) -> BoxFuture<'_, StorageStatus> {
+        Box::pin(async move { self.status.borrow().clone() })
+    }
+
+    fn is_started(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move { *self.status.borrow() == StorageStatus::Connected })
+    }
+
+    fn wait_until_connected(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let mut receiver = self.status.subscribe();
+            // The sender lives in self, so the channel cannot close while we wait
+            let _ = receiver
+                .wait_for(|status| *status == StorageStatus::Connected)
+                .await;
+        })
+    }
+
+    fn get_node_info(&self) -> BoxFuture<'_, Result<NodeInfo, StorageError>> {
+        Box::pin(self.node_info())
+    }
+
+    fn check_health(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            self.call("id", &[])
+                .await
+                .map(|_| ())
+                .map_err(StorageError::ConnectionFailed)
+        })
+    }
+
+    fn get_storage_usage(&self) -> BoxFuture<'_, Result<StorageUsage, StorageError>> {
+        Box::pin(async move {
+            self.call_json::<RepoStat>("repo/stat", &[])
+                .await
+                .map(StorageUsage::from)
+                .map_err(StorageError::ConnectionFailed)
+        })
+    }
+
+    fn connect_peer<'a>(&'a self, target: &'a str) -> BoxFuture<'a, Result<String, StorageError>> {
+        Box::pin(self.connect(target))
+    }
+
+    fn list_peers(&self) -> BoxFuture<'_, Result<Vec<PeerEntry>, StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            self.peers().await
+        })
+    }
+
+    fn upload_file<'a>(
+        &'a self,
+        file_path: &'a Path,
+    ) -> BoxFuture<'a, Result<UploadResult, StorageError>> {
+        Box::pin(self.upload(file_path))
+    }
+
+    fn has_content<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<bool, StorageError>> {
+        Box::pin(self.has(cid))
+    }
+
+    fn list_local_content(&self) -> BoxFuture<'_, Result<Vec<LocalContent>, StorageError>> {
+        Box::pin(self.local_content())
+    }
+
+    fn delete<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(self.unpin(cid))
+    }
+
+    fn download_local_file<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.download(cid, destination, true, Box::new(|_, _| {})))
+    }
+
+    fn download_file_with_progress<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+        on_progress: DownloadProgress,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.download(cid, destination, false, on_progress))
+    }
+
+    fn pin_content<'a>(
+        &'a self,
+        cid: &'a str,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.pin(cid))
+    }
+}
+
+impl From<RepoStat> for StorageUsage {
+    fn from(stat: RepoStat) -> Self {
+        Self {
+            used_bytes: stat.repo_size,
+            reserved_bytes: 0,
+            quota_bytes: stat.storage_max,
+            total_blocks: stat.num_objects as usize,
+        }
+    }
+}
+
+/// Turn an error answer into the daemon's message
+async fn check_response(response: reqwest::Response) -> Result<reqwest::Response, String> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+    let body = response.text().await.unwrap_or_default();
+    match serde_json::from_str::<ApiError>(&body) {
+        Ok(error) => Err(error.message),
+        Err(_) => Err(format!("{} {}", status, body.trim())),
+    }
+}
+
+/// Kubo dials multiaddresses only, signed peer records are a Logos Storage format
+fn peer_id_of(target: &str) -> Result<String, StorageError> {
+    let target = target.trim();
+    if target.starts_with('/') {
+        if let Some((address, peer_id)) = target.rsplit_once("/p2p/") {
+            if !address.is_empty() && !peer_id.is_empty() && !peer_id.contains('/') {
+                return Ok(peer_id.to_string());
+            }
+        }
+    }
+    Err(StorageError::InvalidPeerAddress(format!(
+        "{} is not a multiaddress ending in /p2p/<peer-id>, the only form Kubo dials",
+        target
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::post;
+    use axum::Router;
+
+    async fn fake_kubo() -> String {
+        let app = Router::new()
+            .route(
+                "/api/v0/id",
+                post(|| async { r#"{"ID":"12D3KooWFake","AgentVersion":"kubo/0.30.0"}"# }),
+            )
+            .route(
+                "/api/v0/add",
+                post(|| async { r#"{"Name":"1.pmtiles","Hash":"bafyfake","Size":"12"}"# }),
+            )
+            .route("/api/v0/cat", post(|| async { "pmtiles data" }))
+            .route(
+                "/api/v0/pin/ls",
+                post(|| async {
+                    (
+                        axum::http::StatusCode::INTERNAL_SERVER_ERROR,
+                        r#"{"Message":"path 'bafyother' is not pinned","Code":0,"Type":"error"}"#,
+                    )
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn uploads_and_downloads_through_the_rpc_api() {
+        let kubo = KuboBackend::new(&fake_kubo().await);
+        let dir = tempfile::tempdir().unwrap();
+        let extract = dir.path().join("1.pmtiles");
+        std::fs::write(&extract, "pmtiles data").unwrap();
+
+        assert!(matches!(
+            kubo.upload_file(&extract).await,
+            Err(StorageError::NodeNotStarted)
+        ));
+        kubo.start_node().await.unwrap();
+
+        let uploaded = kubo.upload_file(&extract).await.unwrap();
+        assert_eq!(uploaded.cid, "bafyfake");
+        assert_eq!(uploaded.size, 12);
+
+        let destination = dir.path().join("fetched.pmtiles");
+        let downloaded = kubo.download_file("bafyfake", &destination).await.unwrap();
+        assert_eq!(downloaded.size, 12);
+        assert_eq!(
+            std::fs::read_to_string(&destination).unwrap(),
+            "pmtiles data"
+        );
+
+        assert!(!kubo.has_content("bafyother").await.unwrap());
+    }
+
+    #[test]
+    fn dials_multiaddresses_only() {
+        assert_eq!(
+            peer_id_of("/ip4/10.0.0.1/tcp/4001/p2p/12D3KooWPeer").unwrap(),
+            "12D3KooWPeer"
+        );
+        assert!(peer_id_of("spr:CiUIAhIh").is_err());
+    }
+}
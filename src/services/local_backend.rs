@@ -0,0 +1,353 @@
+use crate::utils::sha256_file;
+use futures::future::BoxFuture;
+use std::path::{Path, PathBuf};
+use tokio::sync::watch;
+use tracing::info;
+
+use super::storage_backend::{
+    is_content_hash, DownloadProgress, DownloadResult, LocalContent, NodeInfo, PeerEntry,
+    StorageBackend, StorageError, StorageStatus, StorageUsage, UploadResult,
+};
+
+/// Content-addressed directory tree on the local disk, for air-gapped extraction runs and
+/// for producing a dataset that is imported into a networked node later. The "CID" of an
+/// extract is the hex SHA-256 of its content, stored at `<root>/<first two hex>/<hash>`.
+/// There is no network, starting the backend only creates the root.
+pub struct LocalBackend {
+    root: PathBuf,
+    quota: u64,
+    status: watch::Sender<StorageStatus>,
+}
+
+impl LocalBackend {
+    pub fn new(root: &Path, quota: u64) -> Self {
+        Self {
+            root: root.to_path_buf(),
+            quota,
+            status: watch::channel(StorageStatus::Initialized).0,
+        }
+    }
+
+    fn object_path(&self, cid: &str) -> PathBuf {
+        self.root.join(&cid[..2]).join(cid)
+    }
+
+    fn ensure_started(&self) -> Result<(), StorageError> {
+        match *self.status.borrow() {
+            StorageStatus::Connected => Ok(()),
+            _ => Err(StorageError::NodeNotStarted),
+        }
+    }
+
+    async fn start(&self) -> Result<(), StorageError> {
+        tokio::fs::create_dir_all(&self.root).await.map_err(|e| {
+            self.status.send_replace(StorageStatus::Error);
+            StorageError::NodeStart(format!("{}: {}", self.root.display(), e))
+        })?;
+        self.status.send_replace(StorageStatus::Connected);
+        info!("Storing extracts in {}", self.root.display());
+        Ok(())
+    }
+
+    /// Path of the stored object, checked to be a content hash so it cannot leave the root
+    async fn stored(&self, cid: &str) -> Result<Option<PathBuf>, StorageError> {
+        self.ensure_started()?;
+        if !is_content_hash(cid) {
+            return Ok(None);
+        }
+        let path = self.object_path(cid);
+        Ok(tokio::fs::try_exists(&path).await?.then_some(path))
+    }
+
+    async fn upload(&self, file_path: &Path) -> Result<UploadResult, StorageError> {
+        self.ensure_started()?;
+
+        let file_size = tokio::fs::metadata(file_path).await?.len();
+        let cid = sha256_file(file_path).await?;
+        let path = self.object_path(&cid);
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(UploadResult {
+                cid,
+                size: file_size,
+            });
+        }
+
+        info!(
+            "Storing file: {} ({} bytes)",
+            file_path.display(),
+            file_size
+        );
+        // Copied next to its final name and renamed, so a crash never leaves a partial object
+        let partial = path.with_extension("partial");
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        tokio::fs::copy(file_path, &partial).await?;
+        tokio::fs::rename(&partial, &path).await?;
+
+        info!("Stored. CID: {}", cid);
+        Ok(UploadResult {
+            cid,
+            size: file_size,
+        })
+    }
+
+    async fn objects(&self) -> Result<Vec<LocalContent>, StorageError> {
+        self.ensure_started()?;
+
+        let mut content = Vec::new();
+        let mut shards = tokio::fs::read_dir(&self.root).await?;
+        while let Some(shard) = shards.next_entry().await? {
+            if !shard.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut entries = tokio::fs::read_dir(shard.path()).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if !is_content_hash(&name) {
+                    continue;
+                }
+                content.push(LocalContent {
+                    size: entry.metadata().await?.len(),
+                    cid: name,
+                    filename: None,
+                });
+            }
+        }
+        Ok(content)
+    }
+
+    async fn remove(&self, cid: &str) -> Result<(), StorageError> {
+        match self.stored(cid).await? {
+            Some(path) => Ok(tokio::fs::remove_file(path).await?),
+            None => Err(StorageError::DeleteFailed(format!("{} is not stored", cid))),
+        }
+    }
+
+    async fn copy_out(
+        &self,
+        cid: &str,
+        destination: &Path,
+        on_progress: DownloadProgress,
+    ) -> Result<DownloadResult, StorageError> {
+        let Some(path) = self.stored(cid).await? else {
+            return Err(StorageError::DownloadFailed(format!(
+                "{} is not in {}",
+                cid,
+                self.root.display()
+            )));
+        };
+        let size = tokio::fs::copy(&path, destination).await?;
+        on_progress(size, Some(size));
+
+        Ok(DownloadResult {
+            cid: cid.to_string(),
+            size: size as usize,
+        })
+    }
+
+    /// Content in the tree stays until deleted, pinning only checks it is there
+    async fn pin(&self, cid: &str) -> Result<DownloadResult, StorageError> {
+        match self.stored(cid).await? {
+            Some(path) => Ok(DownloadResult {
+                cid: cid.to_string(),
+                size: tokio::fs::metadata(path).await?.len() as usize,
+            }),
+            None => Err(StorageError::DownloadFailed(format!(
+                "{} is not in {}",
+                cid,
+                self.root.display()
+            ))),
+        }
+    }
+
+    async fn usage(&self) -> Result<StorageUsage, StorageError> {
+        let objects = self.objects().await?;
+        Ok(StorageUsage {
+            used_bytes: objects.iter().map(|object| object.size).sum(),
+            reserved_bytes: 0,
+            quota_bytes: self.quota,
+            total_blocks: objects.len(),
+        })
+    }
+}
+
+impl StorageBackend for LocalBackend {
+    fn start_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(self.start())
+    }
+
+    fn stop_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.status.send_replace(StorageStatus::Initialized);
+            Ok(())
+        })
+    }
+
+    fn restart_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(self.start())
+    }
+
+    fn get_status(&self) -> BoxFuture<'_, StorageStatus> {
+        Box::pin(async move { self.status.borrow().clone() })
+    }
+
+    fn is_started(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move { *self.status.borrow() == StorageStatus::Connected })
+    }
+
+    fn wait_until_connected(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let mut receiver = self.status.subscribe();
+            // The sender lives in self, so the channel cannot close while we wait
+            let _ = receiver
+                .wait_for(|status| *status == StorageStatus::Connected)
+                .await;
+        })
+    }
+
+    fn get_node_info(&self) -> BoxFuture<'_, Result<NodeInfo, StorageError>> {
+        Box::pin(async move {
+            Ok(NodeInfo {
+                peer_id: None,
+                version: None,
+                repo_path: Some(self.root.display().to_string()),
+                addresses: Vec::new(),
+                announce_addresses: Vec::new(),
+                spr: None,
+                discovery_node_count: 0,
+                peers: Vec::new(),
+                storage_usage: self.usage().await.ok(),
+            })
+        })
+    }
+
+    fn check_health(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            match tokio::fs::try_exists(&self.root).await? {
+                true => Ok(()),
+                false => Err(StorageError::ConnectionFailed(format!(
+                    "{} is gone",
+                    self.root.display()
+                ))),
+            }
+        })
+    }
+
+    fn get_storage_usage(&self) -> BoxFuture<'_, Result<StorageUsage, StorageError>> {
+        Box::pin(self.usage())
+    }
+
+    fn connect_peer<'a>(&'a self, target: &'a str) -> BoxFuture<'a, Result<String, StorageError>> {
+        Box::pin(async move {
+            Err(StorageError::InvalidPeerAddress(format!(
+                "{}: the local backend has no peers to connect to",
+                target.trim()
+            )))
+        })
+    }
+
+    fn list_peers(&self) -> BoxFuture<'_, Result<Vec<PeerEntry>, StorageError>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    fn upload_file<'a>(
+        &'a self,
+        file_path: &'a Path,
+    ) -> BoxFuture<'a, Result<UploadResult, StorageError>> {
+        Box::pin(self.upload(file_path))
+    }
+
+    fn has_content<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<bool, StorageError>> {
+        Box::pin(async move { Ok(self.stored(cid).await?.is_some()) })
+    }
+
+    fn list_local_content(&self) -> BoxFuture<'_, Result<Vec<LocalContent>, StorageError>> {
+        Box::pin(self.objects())
+    }
+
+    fn delete<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(self.remove(cid))
+    }
+
+    fn download_local_file<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.copy_out(cid, destination, Box::new(|_, _| {})))
+    }
+
+    fn download_file_with_progress<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+        on_progress: DownloadProgress,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.copy_out(cid, destination, on_progress))
+    }
+
+    fn pin_content<'a>(
+        &'a self,
+        cid: &'a str,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.pin(cid))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_extracts_in_a_content_addressed_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = LocalBackend::new(&dir.path().join("store"), 0);
+        let extract = dir.path().join("1.pmtiles");
+        std::fs::write(&extract, "pmtiles data").unwrap();
+
+        assert!(matches!(
+            local.upload_file(&extract).await,
+            Err(StorageError::NodeNotStarted)
+        ));
+        local.start_node().await.unwrap();
+
+        let uploaded = local.upload_file(&extract).await.unwrap();
+        assert_eq!(uploaded.cid, sha256_file(&extract).await.unwrap());
+        assert!(dir
+            .path()
+            .join("store")
+            .join(&uploaded.cid[..2])
+            .join(&uploaded.cid)
+            .exists());
+        assert_eq!(local.upload_file(&extract).await.unwrap().cid, uploaded.cid);
+
+        let listed = local.list_local_content().await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].size, 12);
+
+        let destination = dir.path().join("fetched.pmtiles");
+        local
+            .download_file(&uploaded.cid, &destination)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&destination).unwrap(),
+            "pmtiles data"
+        );
+
+        local.delete(&uploaded.cid).await.unwrap();
+        assert!(!local.has_content(&uploaded.cid).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn ignores_cids_that_are_not_content_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        let local = LocalBackend::new(dir.path(), 0);
+        local.start_node().await.unwrap();
+
+        assert!(!local.has_content("../../etc/passwd").await.unwrap());
+        assert!(local.delete("bafyfake").await.is_err());
+    }
+}
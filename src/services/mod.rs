@@ -1,13 +1,42 @@
 pub mod area_upload_service;
+pub mod backpressure_service;
+pub mod catalog_service;
+pub mod claim_service;
 pub mod country_service;
 pub mod database_service;
+pub mod event_service;
+pub mod eviction_service;
 pub mod extraction_service;
+pub mod gossip_service;
+pub mod kubo_backend;
+pub mod local_backend;
+pub mod pause_service;
+pub mod s3_backend;
+pub mod storage_backend;
+#[cfg(feature = "storage-bindings")]
 pub mod storage_service;
 
 pub use area_upload_service::{AreaUploadError, AreaUploadService};
+pub use backpressure_service::BackpressureService;
+pub use catalog_service::{CatalogError, CatalogService};
+pub use claim_service::ClaimService;
 pub use country_service::CountryService;
-pub use database_service::{DatabaseError, DatabaseService};
-pub use extraction_service::{ExtractionError, ExtractionService};
-pub use storage_service::{
-    DownloadResult, NodeInfo, StorageError, StorageService, StorageStatus, UploadResult,
+pub use database_service::{
+    bundle_country, select_whosonfirst_bundle_files, whosonfirst_bundle_file_name,
+    whosonfirst_bundle_files, DatabaseError, DatabaseService, MAX_BUNDLE_FILES,
+    REQUIRED_PLACETYPES, REQUIRED_SPR_COLUMNS,
 };
+pub use event_service::EventService;
+pub use eviction_service::EvictionService;
+pub use extraction_service::{ExtractionError, ExtractionService, PlanetSource};
+pub use gossip_service::{GossipError, GossipService};
+pub use kubo_backend::KuboBackend;
+pub use local_backend::LocalBackend;
+pub use pause_service::PauseService;
+pub use s3_backend::S3Backend;
+pub use storage_backend::{
+    DownloadProgress, DownloadResult, LocalContent, NodeInfo, PeerEntry, StorageBackend,
+    StorageError, StorageStatus, StorageUsage, UploadResult, NODE_KEY_FILE,
+};
+#[cfg(feature = "storage-bindings")]
+pub use storage_service::StorageService;
@@ -0,0 +1,88 @@
+use tokio::sync::watch;
+use tracing::info;
+
+/// Runtime switch that holds back new extraction tasks and upload batches. Work already
+/// started when the pipeline is paused runs to completion, nothing is cancelled.
+#[derive(Clone)]
+pub struct PauseService {
+    paused: watch::Sender<bool>,
+}
+
+impl PauseService {
+    pub fn new() -> Self {
+        let (paused, _) = watch::channel(false);
+        Self { paused }
+    }
+
+    /// Returns false when the pipeline was already paused
+    pub fn pause(&self) -> bool {
+        let changed = self.paused.send_if_modified(|paused| !std::mem::replace(paused, true));
+        if changed {
+            info!("Pipeline paused, in-flight extractions and uploads will finish");
+        }
+        changed
+    }
+
+    /// Returns false when the pipeline was not paused
+    pub fn resume(&self) -> bool {
+        let changed = self.paused.send_if_modified(|paused| std::mem::replace(paused, false));
+        if changed {
+            info!("Pipeline resumed");
+        }
+        changed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Returns once the pipeline is not paused, immediately when it is running
+    pub async fn wait_until_resumed(&self) {
+        let mut receiver = self.paused.subscribe();
+        // The sender lives in self, so the channel cannot close while we wait
+        let _ = receiver.wait_for(|paused| !paused).await;
+    }
+}
+
+impl Default for PauseService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn pause_and_resume_report_changes() {
+        let pause = PauseService::new();
+        assert!(!pause.is_paused());
+        assert!(pause.pause());
+        assert!(!pause.pause());
+        assert!(pause.is_paused());
+        assert!(pause.resume());
+        assert!(!pause.resume());
+    }
+
+    #[tokio::test]
+    async fn waiting_blocks_until_resumed() {
+        let pause = PauseService::new();
+        pause.wait_until_resumed().await;
+
+        pause.pause();
+        let waiting = tokio::spawn({
+            let pause = pause.clone();
+            async move { pause.wait_until_resumed().await }
+        });
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!waiting.is_finished());
+
+        pause.resume();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
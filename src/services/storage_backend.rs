@@ -0,0 +1,192 @@
+use futures::future::BoxFuture;
+use serde::Serialize;
+use std::path::Path;
+use thiserror::Error;
+
+/// Name of the node's private key file inside the data dir. The key is generated on first
+/// start and reused afterwards, which keeps the peer ID stable across restarts.
+pub const NODE_KEY_FILE: &str = "key";
+
+#[derive(Error, Debug)]
+pub enum StorageError {
+    #[error("Node creation failed: {0}")]
+    NodeCreation(String),
+    #[error("Node start failed: {0}")]
+    NodeStart(String),
+    #[error("Node stop failed: {0}")]
+    NodeStop(String),
+    #[error("Node not initialized")]
+    NodeNotInitialized,
+    #[error("Node not started")]
+    NodeNotStarted,
+    #[error("Upload failed: {0}")]
+    UploadFailed(String),
+    #[error("Download failed: {0}")]
+    DownloadFailed(String),
+    #[error("Delete failed: {0}")]
+    DeleteFailed(String),
+    #[error("Connection failed: {0}")]
+    ConnectionFailed(String),
+    #[error("Invalid peer address: {0}")]
+    InvalidPeerAddress(String),
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum StorageStatus {
+    #[default]
+    Disconnected,
+    Initialized,
+    Connecting,
+    Connected,
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadResult {
+    pub cid: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct DownloadResult {
+    pub cid: String,
+    pub size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    pub peer_id: Option<String>,
+    pub version: Option<String>,
+    pub repo_path: Option<String>,
+    pub addresses: Vec<String>,
+    pub announce_addresses: Vec<String>,
+    pub spr: Option<String>,
+    pub discovery_node_count: usize,
+    pub peers: Vec<PeerEntry>,
+    pub storage_usage: Option<StorageUsage>,
+}
+
+/// Repo space accounting reported by the node
+#[derive(Debug, Clone, Copy)]
+pub struct StorageUsage {
+    pub used_bytes: u64,
+    pub reserved_bytes: u64,
+    pub quota_bytes: u64,
+    pub total_blocks: usize,
+}
+
+impl StorageUsage {
+    /// Share of the quota in use, including reserved space, as a percentage
+    pub fn used_percent(&self) -> f64 {
+        if self.quota_bytes == 0 {
+            return 0.0;
+        }
+        (self.used_bytes + self.reserved_bytes) as f64 * 100.0 / self.quota_bytes as f64
+    }
+}
+
+/// Dataset held in the node's repo
+#[derive(Debug, Clone)]
+pub struct LocalContent {
+    pub cid: String,
+    pub size: u64,
+    pub filename: Option<String>,
+}
+
+/// Peer present in the node's discovery table
+#[derive(Debug, Clone, Serialize)]
+pub struct PeerEntry {
+    pub peer_id: String,
+    pub node_id: Option<String>,
+    pub address: Option<String>,
+    /// Whether the peer has answered the node, as opposed to only being referenced by others
+    pub seen: bool,
+}
+
+/// Called with the bytes written so far and the total size, when known
+pub type DownloadProgress = Box<dyn Fn(u64, Option<u64>) + Send + Sync>;
+
+/// The storage network as the rest of AnyNode sees it: the node's lifecycle, uploads,
+/// downloads, pinning and node info. `StorageService` implements it on a storage_bindings
+/// node with the `storage-bindings` feature, another backend implements it and is handed to
+/// `AnyNodeBuilder::with_storage_service`.
+pub trait StorageBackend: Send + Sync {
+    fn start_node(&self) -> BoxFuture<'_, Result<(), StorageError>>;
+
+    fn stop_node(&self) -> BoxFuture<'_, Result<(), StorageError>>;
+
+    /// Stop the node, when it still runs, and start it again
+    fn restart_node(&self) -> BoxFuture<'_, Result<(), StorageError>>;
+
+    fn get_status(&self) -> BoxFuture<'_, StorageStatus>;
+
+    fn is_started(&self) -> BoxFuture<'_, bool>;
+
+    /// Returns once the node is started, immediately when it already is
+    fn wait_until_connected(&self) -> BoxFuture<'_, ()>;
+
+    fn get_node_info(&self) -> BoxFuture<'_, Result<NodeInfo, StorageError>>;
+
+    /// Fail when the node is not started or does not answer
+    fn check_health(&self) -> BoxFuture<'_, Result<(), StorageError>>;
+
+    fn get_storage_usage(&self) -> BoxFuture<'_, Result<StorageUsage, StorageError>>;
+
+    /// Dial a peer by signed peer record or multiaddress, returning its peer ID
+    fn connect_peer<'a>(&'a self, target: &'a str) -> BoxFuture<'a, Result<String, StorageError>>;
+
+    fn list_peers(&self) -> BoxFuture<'_, Result<Vec<PeerEntry>, StorageError>>;
+
+    fn upload_file<'a>(
+        &'a self,
+        file_path: &'a Path,
+    ) -> BoxFuture<'a, Result<UploadResult, StorageError>>;
+
+    /// Whether the node holds the content of `cid` locally
+    fn has_content<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<bool, StorageError>>;
+
+    fn list_local_content(&self) -> BoxFuture<'_, Result<Vec<LocalContent>, StorageError>>;
+
+    fn delete<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<(), StorageError>>;
+
+    /// Write the content of `cid` to `destination`, reading only what the node holds
+    fn download_local_file<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>>;
+
+    /// Write the content of `cid` to `destination`, fetching it from the network when the
+    /// node does not hold it
+    fn download_file_with_progress<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+        on_progress: DownloadProgress,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>>;
+
+    fn download_file<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        self.download_file_with_progress(cid, destination, Box::new(|_, _| {}))
+    }
+
+    /// Fetch the content of `cid` from the network into the node, which then serves it
+    fn pin_content<'a>(
+        &'a self,
+        cid: &'a str,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>>;
+}
+
+/// Whether `cid` is a hex SHA-256 digest, the "CID" of the backends that address content by
+/// its hash instead of an IPFS-style CID
+pub(crate) fn is_content_hash(cid: &str) -> bool {
+    cid.len() == 64
+        && cid
+            .bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
@@ -1,69 +1,25 @@
 use std::sync::Arc;
 use storage_bindings::node::config::RepoKind;
-use storage_bindings::{debug, upload_file, StorageConfig, StorageNode, LogLevel};
-use thiserror::Error;
-use tokio::sync::{Mutex, RwLock};
-use tracing::info;
-
-#[derive(Error, Debug)]
-pub enum StorageError {
-    #[error("Node creation failed: {0}")]
-    NodeCreation(String),
-    #[error("Node start failed: {0}")]
-    NodeStart(String),
-    #[error("Node stop failed: {0}")]
-    NodeStop(String),
-    #[error("Node not initialized")]
-    NodeNotInitialized,
-    #[error("Node not started")]
-    NodeNotStarted,
-    #[error("Upload failed: {0}")]
-    UploadFailed(String),
-    #[error("Download failed: {0}")]
-    DownloadFailed(String),
-    #[error("Connection failed: {0}")]
-    ConnectionFailed(String),
-    #[error("IO error: {0}")]
-    IoError(#[from] std::io::Error),
-}
-
-#[derive(Debug, Clone, PartialEq, Default)]
-pub enum StorageStatus {
-    #[default]
-    Disconnected,
-    Initialized,
-    Connecting,
-    Connected,
-    Error,
-}
-
-#[derive(Debug, Clone)]
-pub struct UploadResult {
-    pub cid: String,
-    pub size: u64,
-}
-
-#[derive(Debug, Clone)]
-pub struct DownloadResult {
-    pub cid: String,
-    pub size: usize,
-}
-
-#[derive(Debug, Clone)]
-pub struct NodeInfo {
-    pub peer_id: Option<String>,
-    pub version: Option<String>,
-    pub repo_path: Option<String>,
-    pub addresses: Vec<String>,
-    pub announce_addresses: Vec<String>,
-    pub spr: Option<String>,
-    pub discovery_node_count: usize,
-}
+use crate::types::{ListenAddr, SprUri};
+use crate::utils::decode_spr;
+use futures::future::BoxFuture;
+use std::path::Path;
+use storage_bindings::{
+    connect, debug, delete, download_stream, exists, fetch, manifests, space, upload_file,
+    DebugInfo, DownloadStreamOptions, StorageConfig, StorageNode, LogLevel,
+};
+use tokio::sync::{watch, Mutex};
+use tracing::{info, warn};
+
+use super::storage_backend::{
+    DownloadProgress, DownloadResult, LocalContent, NodeInfo, PeerEntry, StorageBackend,
+    StorageError, StorageStatus, StorageUsage, UploadResult, NODE_KEY_FILE,
+};
 
 pub struct StorageService {
     node: Arc<Mutex<Option<StorageNode>>>,
     config: StorageConfig,
-    status: Arc<RwLock<StorageStatus>>,
+    status: Arc<watch::Sender<StorageStatus>>,
 }
 
 impl StorageService {
@@ -72,9 +28,9 @@ impl StorageService {
         storage_quota: u64,
         discovery_port: u16,
         max_peers: u32,
-        bootstrap_nodes: Vec<String>,
+        bootstrap_nodes: Vec<SprUri>,
         nat: String,
-        listen_addrs: Vec<String>,
+        listen_addrs: Vec<ListenAddr>,
     ) -> Result<Self, StorageError> {
         let mut config = StorageConfig::new()
             .log_level(LogLevel::Info)
@@ -83,20 +39,21 @@ impl StorageService {
             .max_peers(max_peers)
             .discovery_port(discovery_port)
             .repo_kind(RepoKind::LevelDb)
+            .net_priv_key_file(data_dir.join(NODE_KEY_FILE))
             .nat(nat);
 
         for addr in listen_addrs {
-            config = config.add_listen_addr(addr);
+            config = config.add_listen_addr(addr.as_str());
         }
 
         for node in bootstrap_nodes {
-            config = config.add_bootstrap_node(node);
+            config = config.add_bootstrap_node(node.as_str());
         }
 
         let service = Self {
             node: Arc::new(Mutex::new(None)),
             config,
-            status: Arc::new(RwLock::new(StorageStatus::Disconnected)),
+            status: Arc::new(watch::channel(StorageStatus::Disconnected).0),
         };
 
         service.initialize_node().await?;
@@ -105,16 +62,12 @@ impl StorageService {
     }
 
     pub async fn initialize_node(&self) -> Result<(), StorageError> {
-        {
-            let mut status = self.status.write().await;
-            *status = StorageStatus::Connecting;
-        }
+        self.status.send_replace(StorageStatus::Connecting);
 
         {
             let node_guard = self.node.lock().await;
             if node_guard.is_some() {
-                let mut status = self.status.write().await;
-                *status = StorageStatus::Initialized;
+                self.status.send_replace(StorageStatus::Initialized);
                 return Ok(());
             }
         }
@@ -128,20 +81,14 @@ impl StorageService {
             *node_guard = Some(node);
         }
 
-        {
-            let mut status = self.status.write().await;
-            *status = StorageStatus::Initialized;
-        }
+        self.status.send_replace(StorageStatus::Initialized);
 
         info!("Storage node initialized");
         Ok(())
     }
 
     pub async fn start_node(&self) -> Result<(), StorageError> {
-        {
-            let mut status = self.status.write().await;
-            *status = StorageStatus::Connecting;
-        }
+        self.status.send_replace(StorageStatus::Connecting);
 
         let node = {
             let mut node_guard = self.node.lock().await;
@@ -156,29 +103,24 @@ impl StorageService {
             }
         };
 
-        node.start()
-            .await
-            .map_err(|e| StorageError::NodeStart(e.to_string()))?;
+        if let Err(e) = node.start().await {
+            self.status.send_replace(StorageStatus::Error);
+            return Err(StorageError::NodeStart(e.to_string()));
+        }
 
         {
             let mut node_guard = self.node.lock().await;
             *node_guard = Some(node);
         }
 
-        {
-            let mut status = self.status.write().await;
-            *status = StorageStatus::Connected;
-        }
+        self.status.send_replace(StorageStatus::Connected);
 
         info!("Storage node started");
         Ok(())
     }
 
     pub async fn stop_node(&self) -> Result<(), StorageError> {
-        {
-            let mut status = self.status.write().await;
-            *status = StorageStatus::Disconnected;
-        }
+        self.status.send_replace(StorageStatus::Disconnected);
 
         {
             let node_option = {
@@ -196,17 +138,33 @@ impl StorageService {
             }
         }
 
-        {
-            let mut status = self.status.write().await;
-            *status = StorageStatus::Initialized;
-        }
+        self.status.send_replace(StorageStatus::Initialized);
 
         info!("Storage node stopped");
         Ok(())
     }
 
+    /// Stop the node, when it still runs, and start it again. A failed start leaves the
+    /// status at `Error`.
+    pub async fn restart_node(&self) -> Result<(), StorageError> {
+        if let Err(e) = self.stop_node().await {
+            // stop_node drops a node it failed to stop, start_node creates a fresh one
+            warn!("Failed to stop the storage node before restarting it: {}", e);
+        }
+        self.start_node().await
+    }
+
     pub async fn get_status(&self) -> StorageStatus {
-        self.status.read().await.clone()
+        self.status.borrow().clone()
+    }
+
+    /// Returns once the node is started, immediately when it already is
+    pub async fn wait_until_connected(&self) {
+        let mut receiver = self.status.subscribe();
+        // The sender lives in self, so the channel cannot close while we wait
+        let _ = receiver
+            .wait_for(|status| *status == StorageStatus::Connected)
+            .await;
     }
 
     pub async fn get_node_info(&self) -> Result<NodeInfo, StorageError> {
@@ -223,15 +181,18 @@ impl StorageService {
         let repo_path = node.repo().await.ok();
 
         let debug_info = debug(&node).await.ok();
-        let (addresses, announce_addresses, spr, discovery_node_count) = match debug_info {
+        let (addresses, announce_addresses, spr, discovery_node_count, peers) = match debug_info {
             Some(info) => {
                 let spr = if info.spr.is_empty() { None } else { Some(info.spr.clone()) };
                 let node_count = info.discovery_node_count();
-                (info.addrs, info.announce_addresses, spr, node_count)
+                let peers = peers_from_debug_info(&info);
+                (info.addrs, info.announce_addresses, spr, node_count, peers)
             }
-            None => (Vec::new(), Vec::new(), None, 0),
+            None => (Vec::new(), Vec::new(), None, 0, Vec::new()),
         };
 
+        let storage_usage = space(&node).await.ok().map(StorageUsage::from);
+
         Ok(NodeInfo {
             peer_id,
             version,
@@ -240,9 +201,238 @@ impl StorageService {
             announce_addresses,
             spr,
             discovery_node_count,
+            peers,
+            storage_usage,
+        })
+    }
+
+    /// Fail when the node is not started or does not answer a debug query
+    pub async fn check_health(&self) -> Result<(), StorageError> {
+        let node = self.started_node().await?;
+
+        debug(&node)
+            .await
+            .map(|_| ())
+            .map_err(|e| StorageError::ConnectionFailed(e.to_string()))
+    }
+
+    /// Dial a peer given either its signed peer record (`spr:...`) or a multiaddress
+    /// ending in `/p2p/<peer-id>`. Returns the ID of the dialed peer. Records that only
+    /// advertise discovery (UDP) addresses may not be dialable, a multiaddress is more reliable.
+    pub async fn connect_peer(&self, target: &str) -> Result<String, StorageError> {
+        let (peer_id, addresses) = parse_peer_target(target)?;
+
+        let node = {
+            let node_guard = self.node.lock().await;
+            node_guard
+                .as_ref()
+                .ok_or(StorageError::NodeNotInitialized)?
+                .clone()
+        };
+
+        if !node.is_started() {
+            return Err(StorageError::NodeNotStarted);
+        }
+
+        info!("Connecting to peer {} via {:?}", peer_id, addresses);
+        connect(&node, &peer_id, &addresses)
+            .await
+            .map_err(|e| StorageError::ConnectionFailed(format!("{}: {}", peer_id, e)))?;
+
+        Ok(peer_id)
+    }
+
+    pub async fn get_storage_usage(&self) -> Result<StorageUsage, StorageError> {
+        let node = {
+            let node_guard = self.node.lock().await;
+            node_guard
+                .as_ref()
+                .ok_or(StorageError::NodeNotInitialized)?
+                .clone()
+        };
+
+        let usage = space(&node)
+            .await
+            .map_err(|e| StorageError::ConnectionFailed(e.to_string()))?;
+
+        Ok(usage.into())
+    }
+
+    /// Whether the node holds the content of `cid` locally
+    pub async fn has_content(&self, cid: &str) -> Result<bool, StorageError> {
+        let node = self.started_node().await?;
+
+        exists(&node, cid)
+            .await
+            .map_err(|e| StorageError::DownloadFailed(e.to_string()))
+    }
+
+    /// Datasets held in the node's repo, whether uploaded by this node or pinned from peers
+    pub async fn list_local_content(&self) -> Result<Vec<LocalContent>, StorageError> {
+        let node = self.started_node().await?;
+
+        let manifests = manifests(&node)
+            .await
+            .map_err(|e| StorageError::ConnectionFailed(e.to_string()))?;
+
+        Ok(manifests
+            .into_iter()
+            .map(|manifest| LocalContent {
+                cid: manifest.cid,
+                size: manifest.dataset_size as u64,
+                filename: Some(manifest.filename).filter(|name| !name.is_empty()),
+            })
+            .collect())
+    }
+
+    /// Remove the content of `cid` from the node's repo
+    pub async fn delete(&self, cid: &str) -> Result<(), StorageError> {
+        let node = self.started_node().await?;
+
+        delete(&node, cid)
+            .await
+            .map_err(|e| StorageError::DeleteFailed(e.to_string()))
+    }
+
+    /// Write the content of `cid` to `destination`, reading only from the local repo
+    pub async fn download_local_file(
+        &self,
+        cid: &str,
+        destination: &std::path::Path,
+    ) -> Result<DownloadResult, StorageError> {
+        let node = self.started_node().await?;
+
+        let options = DownloadStreamOptions::new(cid)
+            .filepath(destination)
+            .local(true)
+            .dataset_size_auto(true);
+        let result = download_stream(&node, cid, options)
+            .await
+            .map_err(|e| StorageError::DownloadFailed(e.to_string()))?;
+
+        Ok(DownloadResult {
+            cid: result.cid,
+            size: result.size,
+        })
+    }
+
+    /// Write the content of `cid` to `destination`, fetching it from the network when the
+    /// local repo does not hold it
+    pub async fn download_file(
+        &self,
+        cid: &str,
+        destination: &std::path::Path,
+    ) -> Result<DownloadResult, StorageError> {
+        self.download_file_with_progress(cid, destination, |_, _| {})
+            .await
+    }
+
+    /// Like `download_file`, reporting the bytes written so far and the total size, when
+    /// the manifest gives it, to `on_progress`
+    pub async fn download_file_with_progress<F>(
+        &self,
+        cid: &str,
+        destination: &std::path::Path,
+        on_progress: F,
+    ) -> Result<DownloadResult, StorageError>
+    where
+        F: Fn(u64, Option<u64>) + Send + Sync + 'static,
+    {
+        let node = self.started_node().await?;
+
+        // The bindings ignore `DownloadStreamOptions::on_progress` for streams, so progress is
+        // counted by the writer instead
+        let total = fetch(&node, cid)
+            .await
+            .ok()
+            .map(|manifest| manifest.dataset_size as u64);
+        let file = std::fs::File::create(destination)?;
+        let writer = ProgressWriter {
+            inner: file,
+            written: 0,
+            total,
+            on_progress,
+        };
+
+        let options = DownloadStreamOptions::new(cid)
+            .writer(writer)
+            .dataset_size_auto(true);
+        let result = download_stream(&node, cid, options)
+            .await
+            .map_err(|e| StorageError::DownloadFailed(e.to_string()))?;
+
+        // The bindings only log failed writes, so a short file is the sign of one
+        let written = std::fs::metadata(destination)?.len();
+        if written != result.size as u64 {
+            return Err(StorageError::DownloadFailed(format!(
+                "wrote {} of {} bytes to {}",
+                written,
+                result.size,
+                destination.display()
+            )));
+        }
+
+        Ok(DownloadResult {
+            cid: result.cid,
+            size: result.size,
         })
     }
 
+    /// Fetch every block of `cid` from the network into the local repo, so the node holds
+    /// and serves the content as if it had uploaded it
+    pub async fn pin_content(&self, cid: &str) -> Result<DownloadResult, StorageError> {
+        let node = self.started_node().await?;
+
+        let options = DownloadStreamOptions::new(cid)
+            .writer(std::io::sink())
+            .dataset_size_auto(true);
+        let result = download_stream(&node, cid, options)
+            .await
+            .map_err(|e| StorageError::DownloadFailed(e.to_string()))?;
+
+        Ok(DownloadResult {
+            cid: result.cid,
+            size: result.size,
+        })
+    }
+
+    async fn started_node(&self) -> Result<StorageNode, StorageError> {
+        let node = {
+            let node_guard = self.node.lock().await;
+            node_guard
+                .as_ref()
+                .ok_or(StorageError::NodeNotInitialized)?
+                .clone()
+        };
+
+        if !node.is_started() {
+            return Err(StorageError::NodeNotStarted);
+        }
+
+        Ok(node)
+    }
+
+    /// Known peers in the node's discovery table, whether or not they are connected
+    pub async fn list_peers(&self) -> Result<Vec<PeerEntry>, StorageError> {
+        let node = {
+            let node_guard = self.node.lock().await;
+            node_guard
+                .as_ref()
+                .ok_or(StorageError::NodeNotInitialized)?
+                .clone()
+        };
+
+        if !node.is_started() {
+            return Err(StorageError::NodeNotStarted);
+        }
+
+        let info = debug(&node)
+            .await
+            .map_err(|e| StorageError::ConnectionFailed(e.to_string()))?;
+
+        Ok(peers_from_debug_info(&info))
+    }
+
     pub async fn upload_file(&self, file_path: &std::path::Path) -> Result<UploadResult, StorageError> {
         let node = {
             let node_guard = self.node.lock().await;
@@ -301,6 +491,100 @@ impl StorageService {
     }
 }
 
+impl StorageBackend for StorageService {
+    fn start_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(StorageService::start_node(self))
+    }
+
+    fn stop_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(StorageService::stop_node(self))
+    }
+
+    fn restart_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(StorageService::restart_node(self))
+    }
+
+    fn get_status(&self) -> BoxFuture<'_, StorageStatus> {
+        Box::pin(StorageService::get_status(self))
+    }
+
+    fn is_started(&self) -> BoxFuture<'_, bool> {
+        Box::pin(StorageService::is_started(self))
+    }
+
+    fn wait_until_connected(&self) -> BoxFuture<'_, ()> {
+        Box::pin(StorageService::wait_until_connected(self))
+    }
+
+    fn get_node_info(&self) -> BoxFuture<'_, Result<NodeInfo, StorageError>> {
+        Box::pin(StorageService::get_node_info(self))
+    }
+
+    fn check_health(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(StorageService::check_health(self))
+    }
+
+    fn get_storage_usage(&self) -> BoxFuture<'_, Result<StorageUsage, StorageError>> {
+        Box::pin(StorageService::get_storage_usage(self))
+    }
+
+    fn connect_peer<'a>(&'a self, target: &'a str) -> BoxFuture<'a, Result<String, StorageError>> {
+        Box::pin(StorageService::connect_peer(self, target))
+    }
+
+    fn list_peers(&self) -> BoxFuture<'_, Result<Vec<PeerEntry>, StorageError>> {
+        Box::pin(StorageService::list_peers(self))
+    }
+
+    fn upload_file<'a>(
+        &'a self,
+        file_path: &'a Path,
+    ) -> BoxFuture<'a, Result<UploadResult, StorageError>> {
+        Box::pin(StorageService::upload_file(self, file_path))
+    }
+
+    fn has_content<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<bool, StorageError>> {
+        Box::pin(StorageService::has_content(self, cid))
+    }
+
+    fn list_local_content(&self) -> BoxFuture<'_, Result<Vec<LocalContent>, StorageError>> {
+        Box::pin(StorageService::list_local_content(self))
+    }
+
+    fn delete<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(StorageService::delete(self, cid))
+    }
+
+    fn download_local_file<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(StorageService::download_local_file(self, cid, destination))
+    }
+
+    fn download_file_with_progress<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+        on_progress: DownloadProgress,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(StorageService::download_file_with_progress(
+            self,
+            cid,
+            destination,
+            on_progress,
+        ))
+    }
+
+    fn pin_content<'a>(
+        &'a self,
+        cid: &'a str,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(StorageService::pin_content(self, cid))
+    }
+}
+
 impl Clone for StorageService {
     fn clone(&self) -> Self {
         Self {
@@ -310,3 +594,88 @@ impl Clone for StorageService {
         }
     }
 }
+
+impl From<storage_bindings::Space> for StorageUsage {
+    fn from(space: storage_bindings::Space) -> Self {
+        Self {
+            used_bytes: space.quota_used_bytes,
+            reserved_bytes: space.quota_reserved_bytes,
+            quota_bytes: space.quota_max_bytes,
+            total_blocks: space.total_blocks,
+        }
+    }
+}
+
+/// Writer handed to the bindings' download stream, reporting every write to a progress
+/// callback
+struct ProgressWriter<W: std::io::Write, F: Fn(u64, Option<u64>)> {
+    inner: W,
+    written: u64,
+    total: Option<u64>,
+    on_progress: F,
+}
+
+impl<W: std::io::Write, F: Fn(u64, Option<u64>)> std::io::Write for ProgressWriter<W, F> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        (self.on_progress)(self.written, self.total);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn peers_from_debug_info(info: &DebugInfo) -> Vec<PeerEntry> {
+    let field = |node: &serde_json::Value, name: &str| {
+        node.get(name)
+            .and_then(|v| v.as_str())
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+    };
+
+    info.table
+        .nodes
+        .iter()
+        .filter_map(|node| {
+            Some(PeerEntry {
+                peer_id: field(node, "peerId")?,
+                node_id: field(node, "nodeId"),
+                address: field(node, "address"),
+                seen: node.get("seen").and_then(|v| v.as_bool()).unwrap_or(false),
+            })
+        })
+        .collect()
+}
+
+/// Split a dial target into a peer ID and the addresses to reach it on
+fn parse_peer_target(target: &str) -> Result<(String, Vec<String>), StorageError> {
+    let target = target.trim();
+
+    if target.starts_with("spr:") {
+        let record =
+            decode_spr(target).map_err(|e| StorageError::InvalidPeerAddress(e.to_string()))?;
+        if record.addresses.is_empty() {
+            return Err(StorageError::InvalidPeerAddress(format!(
+                "signed peer record for {} has no addresses",
+                record.peer_id
+            )));
+        }
+        return Ok((record.peer_id, record.addresses));
+    }
+
+    if target.starts_with('/') {
+        if let Some((address, peer_id)) = target.rsplit_once("/p2p/") {
+            if !address.is_empty() && !peer_id.is_empty() && !peer_id.contains('/') {
+                return Ok((peer_id.to_string(), vec![address.to_string()]));
+            }
+        }
+    }
+
+    Err(StorageError::InvalidPeerAddress(format!(
+        "{} is neither an spr: record nor a multiaddress ending in /p2p/<peer-id>",
+        target
+    )))
+}
@@ -0,0 +1,415 @@
+//! Helpers for running the pipeline in tests without a network or a planet file: an
+//! in-memory storage backend, a WhosOnFirst fixture database and fake PMTiles files, plus a
+//! stand-in for the `pmtiles` tool that extracts by copying its source.
+
+use crate::services::{
+    DatabaseError, DatabaseService, DownloadProgress, DownloadResult, LocalContent, NodeInfo,
+    PeerEntry, StorageBackend, StorageError, StorageStatus, StorageUsage, UploadResult,
+};
+use crate::types::AdministrativeArea;
+use futures::future::BoxFuture;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use tokio::sync::watch;
+
+/// Storage backend keeping content in memory, keyed by the hex SHA-256 of the bytes. It has
+/// to be started like a real node, and can be told to fail uploads to exercise retries.
+pub struct MockStorageBackend {
+    contents: Mutex<HashMap<String, Vec<u8>>>,
+    uploads: Mutex<Vec<String>>,
+    fail_uploads: AtomicBool,
+    status: watch::Sender<StorageStatus>,
+}
+
+impl Default for MockStorageBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockStorageBackend {
+    pub fn new() -> Self {
+        Self {
+            contents: Mutex::new(HashMap::new()),
+            uploads: Mutex::new(Vec::new()),
+            fail_uploads: AtomicBool::new(false),
+            status: watch::channel(StorageStatus::Initialized).0,
+        }
+    }
+
+    /// Make every upload fail until called again with `false`
+    pub fn set_fail_uploads(&self, fail: bool) {
+        self.fail_uploads.store(fail, Ordering::SeqCst);
+    }
+
+    /// Store `data` as if another node had published it, returning its CID
+    pub fn insert(&self, data: &[u8]) -> String {
+        let cid = hex::encode(Sha256::digest(data));
+        self.contents
+            .lock()
+            .unwrap()
+            .insert(cid.clone(), data.to_vec());
+        cid
+    }
+
+    /// Bytes held for `cid`
+    pub fn content(&self, cid: &str) -> Option<Vec<u8>> {
+        self.contents.lock().unwrap().get(cid).cloned()
+    }
+
+    /// CIDs of the successful uploads, in the order they happened
+    pub fn uploads(&self) -> Vec<String> {
+        self.uploads.lock().unwrap().clone()
+    }
+
+    fn ensure_started(&self) -> Result<(), StorageError> {
+        match *self.status.borrow() {
+            StorageStatus::Connected => Ok(()),
+            _ => Err(StorageError::NodeNotStarted),
+        }
+    }
+
+    async fn upload(&self, file_path: &Path) -> Result<UploadResult, StorageError> {
+        self.ensure_started()?;
+        if self.fail_uploads.load(Ordering::SeqCst) {
+            return Err(StorageError::UploadFailed(format!(
+                "{}: uploads are set to fail",
+                file_path.display()
+            )));
+        }
+
+        let data = tokio::fs::read(file_path).await?;
+        let cid = self.insert(&data);
+        self.uploads.lock().unwrap().push(cid.clone());
+        Ok(UploadResult {
+            cid,
+            size: data.len() as u64,
+        })
+    }
+
+    async fn download(
+        &self,
+        cid: &str,
+        destination: &Path,
+        on_progress: DownloadProgress,
+    ) -> Result<DownloadResult, StorageError> {
+        self.ensure_started()?;
+        let data = self
+            .content(cid)
+            .ok_or_else(|| StorageError::DownloadFailed(format!("{} is not stored", cid)))?;
+        tokio::fs::write(destination, &data).await?;
+        on_progress(data.len() as u64, Some(data.len() as u64));
+
+        Ok(DownloadResult {
+            cid: cid.to_string(),
+            size: data.len(),
+        })
+    }
+
+    fn usage(&self) -> StorageUsage {
+        let contents = self.contents.lock().unwrap();
+        StorageUsage {
+            used_bytes: contents.values().map(|data| data.len() as u64).sum(),
+            reserved_bytes: 0,
+            quota_bytes: 0,
+            total_blocks: contents.len(),
+        }
+    }
+}
+
+impl StorageBackend for MockStorageBackend {
+    fn start_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.status.send_replace(StorageStatus::Connected);
+            Ok(())
+        })
+    }
+
+    fn stop_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.status.send_replace(StorageStatus::Initialized);
+            Ok(())
+        })
+    }
+
+    fn restart_node(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        self.start_node()
+    }
+
+    fn get_status(&self) -> BoxFuture<'_, StorageStatus> {
+        Box::pin(async move { self.status.borrow().clone() })
+    }
+
+    fn is_started(&self) -> BoxFuture<'_, bool> {
+        Box::pin(async move { *self.status.borrow() == StorageStatus::Connected })
+    }
+
+    fn wait_until_connected(&self) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let mut receiver = self.status.subscribe();
+            // The sender lives in self, so the channel cannot close while we wait
+            let _ = receiver
+                .wait_for(|status| *status == StorageStatus::Connected)
+                .await;
+        })
+    }
+
+    fn get_node_info(&self) -> BoxFuture<'_, Result<NodeInfo, StorageError>> {
+        Box::pin(async move {
+            Ok(NodeInfo {
+                peer_id: Some("mock".to_string()),
+                version: None,
+                repo_path: None,
+                addresses: Vec::new(),
+                announce_addresses: Vec::new(),
+                spr: None,
+                discovery_node_count: 0,
+                peers: Vec::new(),
+                storage_usage: Some(self.usage()),
+            })
+        })
+    }
+
+    fn check_health(&self) -> BoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move { self.ensure_started() })
+    }
+
+    fn get_storage_usage(&self) -> BoxFuture<'_, Result<StorageUsage, StorageError>> {
+        Box::pin(async move { Ok(self.usage()) })
+    }
+
+    fn connect_peer<'a>(&'a self, target: &'a str) -> BoxFuture<'a, Result<String, StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            Ok(target.trim().to_string())
+        })
+    }
+
+    fn list_peers(&self) -> BoxFuture<'_, Result<Vec<PeerEntry>, StorageError>> {
+        Box::pin(async move { Ok(Vec::new()) })
+    }
+
+    fn upload_file<'a>(
+        &'a self,
+        file_path: &'a Path,
+    ) -> BoxFuture<'a, Result<UploadResult, StorageError>> {
+        Box::pin(self.upload(file_path))
+    }
+
+    fn has_content<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<bool, StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            Ok(self.contents.lock().unwrap().contains_key(cid))
+        })
+    }
+
+    fn list_local_content(&self) -> BoxFuture<'_, Result<Vec<LocalContent>, StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            Ok(self
+                .contents
+                .lock()
+                .unwrap()
+                .iter()
+                .map(|(cid, data)| LocalContent {
+                    cid: cid.clone(),
+                    size: data.len() as u64,
+                    filename: None,
+                })
+                .collect())
+        })
+    }
+
+    fn delete<'a>(&'a self, cid: &'a str) -> BoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            match self.contents.lock().unwrap().remove(cid) {
+                Some(_) => Ok(()),
+                None => Err(StorageError::DeleteFailed(format!("{} is not stored", cid))),
+            }
+        })
+    }
+
+    fn download_local_file<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.download(cid, destination, Box::new(|_, _| {})))
+    }
+
+    fn download_file_with_progress<'a>(
+        &'a self,
+        cid: &'a str,
+        destination: &'a Path,
+        on_progress: DownloadProgress,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(self.download(cid, destination, on_progress))
+    }
+
+    fn pin_content<'a>(
+        &'a self,
+        cid: &'a str,
+    ) -> BoxFuture<'a, Result<DownloadResult, StorageError>> {
+        Box::pin(async move {
+            self.ensure_started()?;
+            match self.content(cid) {
+                Some(data) => Ok(DownloadResult {
+                    cid: cid.to_string(),
+                    size: data.len(),
+                }),
+                None => Err(StorageError::DownloadFailed(format!(
+                    "{} is not stored",
+                    cid
+                ))),
+            }
+        })
+    }
+}
+
+/// Current, non-deprecated area for a fixture, its centre in the middle of `bbox`
+/// (`[min_lon, min_lat, max_lon, max_lat]`)
+pub fn fixture_area(
+    id: i64,
+    name: &str,
+    country: &str,
+    placetype: &str,
+    bbox: [f64; 4],
+) -> AdministrativeArea {
+    let [min_longitude, min_latitude, max_longitude, max_latitude] = bbox;
+    AdministrativeArea {
+        id,
+        name: name.to_string(),
+        country: country.to_string(),
+        placetype: placetype.to_string(),
+        latitude: (min_latitude + max_latitude) / 2.0,
+        longitude: (min_longitude + max_longitude) / 2.0,
+        min_longitude,
+        min_latitude,
+        max_longitude,
+        max_latitude,
+    }
+}
+
+/// Write a WhosOnFirst database at `path` whose `spr` table holds `areas`, with the columns
+/// the node queries. Countries are found by areas with the `country` placetype.
+pub async fn write_whosonfirst_fixture(
+    path: &Path,
+    areas: &[AdministrativeArea],
+) -> Result<(), DatabaseError> {
+    DatabaseService::new(&path.to_string_lossy(), false)
+        .await?
+        .load_spr_fixture(areas)
+        .await
+}
+
+/// Write a small but valid PMTiles v3 archive at `path`, holding a single uncompressed
+/// z0 tile of `tile_size` bytes and declaring `bbox` (`[min_lon, min_lat, max_lon, max_lat]`)
+/// as its bounds
+pub fn write_fake_pmtiles(path: &Path, bbox: [f64; 4], tile_size: usize) -> std::io::Result<()> {
+    crate::utils::write_sample_pmtiles(path, bbox, tile_size)
+}
+
+/// Install a stand-in for the `pmtiles` tool in `dir` and return its path, to use as
+/// `PMTILES_CMD`. It answers `--help` and `version` and extracts by copying the planet file
+/// whole, so a fake planet from `write_fake_pmtiles` yields a valid extract for every area.
+#[cfg(unix)]
+pub fn write_fake_pmtiles_cmd(dir: &Path) -> std::io::Result<PathBuf> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.join("pmtiles");
+    std::fs::write(
+        &path,
+        "#!/bin/sh\n\
+         case \"$1\" in\n\
+         extract) cp \"$2\" \"$3\" ;;\n\
+         version) echo \"pmtiles 1.22.1, commit fake, built at unknown\" ;;\n\
+         *) exit 0 ;;\n\
+         esac\n",
+    )?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn mock_backend_round_trips_uploads() {
+        let dir = tempfile::tempdir().unwrap();
+        let extract = dir.path().join("1.pmtiles");
+        write_fake_pmtiles(&extract, [5.0, 45.0, 6.0, 46.0], 64).unwrap();
+
+        let storage = MockStorageBackend::new();
+        storage.start_node().await.unwrap();
+        let uploaded = storage.upload_file(&extract).await.unwrap();
+        assert_eq!(storage.uploads(), vec![uploaded.cid.clone()]);
+        assert!(storage.has_content(&uploaded.cid).await.unwrap());
+
+        storage.set_fail_uploads(true);
+        assert!(matches!(
+            storage.upload_file(&extract).await,
+            Err(StorageError::UploadFailed(_))
+        ));
+
+        let destination = dir.path().join("fetched.pmtiles");
+        storage
+            .download_file(&uploaded.cid, &destination)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&destination).unwrap(),
+            std::fs::read(&extract).unwrap()
+        );
+    }
+
+    #[test]
+    fn fake_pmtiles_has_a_consistent_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("planet.pmtiles");
+        write_fake_pmtiles(&path, [-5.0, 41.0, 10.0, 51.0], 100).unwrap();
+
+        let archive = std::fs::read(&path).unwrap();
+        let field = |offset: usize| {
+            u64::from_le_bytes(archive[offset..offset + 8].try_into().unwrap()) as usize
+        };
+        assert_eq!(&archive[..8], b"PMTiles\x03");
+        assert_eq!(field(56) + field(64), archive.len());
+        assert_eq!(field(64), 100);
+        assert_eq!(
+            i32::from_le_bytes(archive[114..118].try_into().unwrap()),
+            510_000_000
+        );
+    }
+
+    #[tokio::test]
+    async fn whosonfirst_fixture_is_queried_like_the_real_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("whosonfirst.db");
+        write_whosonfirst_fixture(
+            &path,
+            &[
+                fixture_area(
+                    85633147,
+                    "France",
+                    "FR",
+                    "country",
+                    [-5.0, 41.0, 10.0, 51.0],
+                ),
+                fixture_area(1, "Alsace", "FR", "region", [7.0, 47.5, 8.2, 49.1]),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let db = DatabaseService::new(&path.to_string_lossy(), false)
+            .await
+            .unwrap();
+        assert!(db.get_country_record("FR").await.unwrap().is_some());
+        assert_eq!(db.get_country_area_count("FR").await.unwrap(), 1);
+    }
+}
@@ -1,5 +1,8 @@
+use crate::types::Compression;
+use crate::utils::EncryptionInfo;
 use rusqlite::Row;
 use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
 
 /// Administrative area data from WhosOnFirst database (regions and counties)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +34,163 @@ impl AdministrativeArea {
             max_latitude: row.get(9)?,
         })
     }
+
+    /// Approximate surface of the bounding box in square kilometres
+    pub fn bbox_area_km2(&self) -> f64 {
+        const KM_PER_DEGREE_LATITUDE: f64 = 110.574;
+        const KM_PER_DEGREE_LONGITUDE: f64 = 111.320;
+
+        let mid_latitude = ((self.min_latitude + self.max_latitude) / 2.0).to_radians();
+        let height = (self.max_latitude - self.min_latitude).abs() * KM_PER_DEGREE_LATITUDE;
+        let width = (self.max_longitude - self.min_longitude).abs()
+            * KM_PER_DEGREE_LONGITUDE
+            * mid_latitude.cos();
+
+        height * width
+    }
+
+    /// Why the bounding box falls outside the given size limits, `None` when it is within them
+    pub fn bbox_size_violation(&self, min: Option<f64>, max: Option<f64>) -> Option<&'static str> {
+        let bbox_area = self.bbox_area_km2();
+        if max.is_some_and(|max| bbox_area > max) {
+            Some("above MAX_BBOX_AREA_KM2")
+        } else if min.is_some_and(|min| bbox_area < min) {
+            Some("below MIN_BBOX_AREA_KM2")
+        } else {
+            None
+        }
+    }
+
+    /// Split the bounding box into a `grid` x `grid` set of parts, row by row from the
+    /// south-west corner. Neighbouring parts share their edges exactly and the outer edges
+    /// are the area's own.
+    pub fn split_bbox(&self, grid: u32) -> Vec<AreaPart> {
+        let (width, height) = (
+            self.max_longitude - self.min_longitude,
+            self.max_latitude - self.min_latitude,
+        );
+        let longitude = |col: u32| match col {
+            col if col == grid => self.max_longitude,
+            col => self.min_longitude + width * col as f64 / grid as f64,
+        };
+        let latitude = |row: u32| match row {
+            row if row == grid => self.max_latitude,
+            row => self.min_latitude + height * row as f64 / grid as f64,
+        };
+
+        (0..grid)
+            .flat_map(|row| (0..grid).map(move |col| (row, col)))
+            .map(|(row, col)| AreaPart {
+                index: row * grid + col,
+                min_longitude: longitude(col),
+                min_latitude: latitude(row),
+                max_longitude: longitude(col + 1),
+                max_latitude: latitude(row + 1),
+            })
+            .collect()
+    }
+}
+
+/// Directory holding the parts of an area that was too large for a single extract,
+/// next to where its `<id>.pmtiles` would be
+pub fn area_parts_dir(country_dir: &Path, area_id: i64) -> PathBuf {
+    country_dir.join(format!("{}.{}", area_id, AREA_PARTS_EXTENSION))
+}
+
+pub const AREA_PARTS_EXTENSION: &str = "parts";
+
+/// Sidecar describing an area, written next to its extract for the upload,
+/// `<id>.meta.json`
+pub fn area_metadata_path(country_dir: &Path, area_id: u32) -> PathBuf {
+    country_dir.join(format!("{}.meta.json", area_id))
+}
+
+/// Human-readable description of an uploaded area, stored under its own CID so clients
+/// can label extracts without a WhosOnFirst database
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AreaMetadata {
+    pub id: i64,
+    pub name: String,
+    pub country: String,
+    pub placetype: String,
+    /// `[min_longitude, min_latitude, max_longitude, max_latitude]`
+    pub bbox: [f64; 4],
+    /// Size of the extract, or of all its parts, before compression and encryption
+    pub file_size: u64,
+    /// When the extract was made, RFC 3339, absent for extracts made outside the pipeline
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extracted_at: Option<String>,
+}
+
+impl AreaMetadata {
+    pub fn new(area: &AdministrativeArea, file_size: u64, extracted_at: Option<String>) -> Self {
+        Self {
+            id: area.id,
+            name: area.name.clone(),
+            country: area.country.clone(),
+            placetype: area.placetype.clone(),
+            bbox: [
+                area.min_longitude,
+                area.min_latitude,
+                area.max_longitude,
+                area.max_latitude,
+            ],
+            file_size,
+            extracted_at,
+        }
+    }
+}
+
+/// Part list written next to the part files, `<index>.pmtiles`
+pub const AREA_PARTS_MANIFEST: &str = "parts.json";
+
+/// Part list with CIDs, uploaded once every part is stored; the area maps to its CID
+pub const SPLIT_AREA_INDEX: &str = "index.json";
+
+/// One cell of an area's bounding box, extracted as its own PMTiles file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AreaPart {
+    pub index: u32,
+    pub min_longitude: f64,
+    pub min_latitude: f64,
+    pub max_longitude: f64,
+    pub max_latitude: f64,
+}
+
+impl AreaPart {
+    pub fn bbox(&self) -> String {
+        format!(
+            "{},{},{},{}",
+            self.min_longitude, self.min_latitude, self.max_longitude, self.max_latitude
+        )
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("{}.pmtiles", self.index)
+    }
+}
+
+/// Uploaded part of a split area
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AreaPartUpload {
+    #[serde(flatten)]
+    pub part: AreaPart,
+    pub cid: String,
+    pub file_size: u64,
+    /// Codec the part was compressed with before upload, absent when uncompressed
+    #[serde(default, skip_serializing_if = "Compression::is_none")]
+    pub compression: Compression,
+    /// Key and nonce the part was encrypted with, absent when unencrypted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionInfo>,
+}
+
+/// Index of a split area's parts, uploaded so the area resolves to a single CID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitAreaManifest {
+    pub area_id: u32,
+    pub country_code: String,
+    pub parts: Vec<AreaPartUpload>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,6 +211,16 @@ impl AreaInfo {
     }
 }
 
+/// An uploaded area with when it was extracted and uploaded, in milliseconds since the
+/// Unix epoch. One row of the exported catalog.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogRecord {
+    #[serde(flatten)]
+    pub info: AreaInfo,
+    pub uploaded_at: Option<i64>,
+    pub extracted_at: Option<i64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PaginatedAreasResult {
     pub areas: Vec<AreaInfo>,
@@ -64,3 +234,109 @@ pub struct PaginationInfo {
     pub total: u32,
     pub total_pages: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(
+        min_longitude: f64,
+        min_latitude: f64,
+        max_longitude: f64,
+        max_latitude: f64,
+    ) -> AdministrativeArea {
+        AdministrativeArea {
+            id: 85683431,
+            name: "Test".to_string(),
+            country: "FR".to_string(),
+            placetype: "region".to_string(),
+            latitude: (min_latitude + max_latitude) / 2.0,
+            longitude: (min_longitude + max_longitude) / 2.0,
+            min_longitude,
+            min_latitude,
+            max_longitude,
+            max_latitude,
+        }
+    }
+
+    #[test]
+    fn splits_row_by_row_from_the_south_west() {
+        let parts = area(0.0, 10.0, 4.0, 12.0).split_bbox(2);
+
+        assert_eq!(parts.len(), 4);
+        let bboxes: Vec<_> = parts.iter().map(|p| (p.index, p.bbox())).collect();
+        assert_eq!(
+            bboxes,
+            vec![
+                (0, "0,10,2,11".to_string()),
+                (1, "2,10,4,11".to_string()),
+                (2, "0,11,2,12".to_string()),
+                (3, "2,11,4,12".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn parts_tile_the_bbox_exactly() {
+        let area = area(-5.142_2, 41.333_7, 9.561_6, 51.124_2);
+        let grid = 3;
+        let parts = area.split_bbox(grid);
+
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(part.index, i as u32);
+            let (row, col) = (part.index / grid, part.index % grid);
+
+            if col + 1 < grid {
+                let east = &parts[i + 1];
+                assert_eq!(part.max_longitude, east.min_longitude);
+            } else {
+                assert_eq!(part.max_longitude, area.max_longitude);
+            }
+            if row + 1 < grid {
+                let north = &parts[i + grid as usize];
+                assert_eq!(part.max_latitude, north.min_latitude);
+            } else {
+                assert_eq!(part.max_latitude, area.max_latitude);
+            }
+            if col == 0 {
+                assert_eq!(part.min_longitude, area.min_longitude);
+            }
+            if row == 0 {
+                assert_eq!(part.min_latitude, area.min_latitude);
+            }
+        }
+    }
+
+    #[test]
+    fn grid_of_one_is_the_area_itself() {
+        let area = area(2.224_1, 48.815_6, 2.469_8, 48.902_1);
+        let parts = area.split_bbox(1);
+
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].index, 0);
+        assert_eq!(parts[0].file_name(), "0.pmtiles");
+        assert_eq!(
+            parts[0].bbox(),
+            format!(
+                "{},{},{},{}",
+                area.min_longitude, area.min_latitude, area.max_longitude, area.max_latitude
+            )
+        );
+    }
+
+    #[test]
+    fn metadata_describes_the_area() {
+        let metadata = AreaMetadata::new(&area(2.2, 48.8, 2.5, 48.9), 4096, None);
+        let json = serde_json::to_value(&metadata).unwrap();
+
+        assert_eq!(json["id"], 85683431);
+        assert_eq!(json["name"], "Test");
+        assert_eq!(json["bbox"], serde_json::json!([2.2, 48.8, 2.5, 48.9]));
+        assert_eq!(json["file_size"], 4096);
+        assert!(json.get("extracted_at").is_none());
+        assert_eq!(
+            area_metadata_path(Path::new("/areas/FR"), 12),
+            Path::new("/areas/FR/12.meta.json")
+        );
+    }
+}
@@ -0,0 +1,146 @@
+use crate::types::AdministrativeArea;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// First ID of the custom regions `extract-bbox` records, above every grid cell
+pub const CUSTOM_AREA_ID_BASE: u32 = 4_100_000_000;
+
+/// Placetype of custom regions
+pub const CUSTOM_AREA_PLACETYPE: &str = "custom";
+
+/// Directory and country code custom regions are kept under, a user-assigned ISO code no
+/// country has
+pub const CUSTOM_AREA_COUNTRY: &str = "XX";
+
+#[derive(Debug, Error)]
+pub enum BoundingBoxError {
+    #[error("Invalid bounding box '{0}', expected min_lon,min_lat,max_lon,max_lat such as 2.2,48.8,2.5,48.9")]
+    InvalidFormat(String),
+    #[error("Invalid bounding box '{0}', longitudes must be within ±180 and latitudes within ±90 with min_lat <= max_lat")]
+    OutOfRange(String),
+    #[error("Bounding box '{0}' crosses the antimeridian, extract each side of it separately")]
+    CrossesAntimeridian(String),
+}
+
+/// A viewport in WGS84 degrees. A minimum longitude greater than the maximum describes a
+/// box crossing the antimeridian.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub min_longitude: f64,
+    pub min_latitude: f64,
+    pub max_longitude: f64,
+    pub max_latitude: f64,
+}
+
+impl BoundingBox {
+    pub fn crosses_antimeridian(&self) -> bool {
+        self.min_longitude > self.max_longitude
+    }
+
+    /// The custom region of this box recorded under `id`
+    pub fn custom_area(&self, id: u32) -> AdministrativeArea {
+        AdministrativeArea {
+            id: id as i64,
+            name: format!("Custom region {}", self),
+            country: CUSTOM_AREA_COUNTRY.to_string(),
+            placetype: CUSTOM_AREA_PLACETYPE.to_string(),
+            latitude: (self.min_latitude + self.max_latitude) / 2.0,
+            longitude: (self.min_longitude + self.max_longitude) / 2.0,
+            min_longitude: self.min_longitude,
+            min_latitude: self.min_latitude,
+            max_longitude: self.max_longitude,
+            max_latitude: self.max_latitude,
+        }
+    }
+}
+
+impl FromStr for BoundingBox {
+    type Err = BoundingBoxError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let coordinates = value
+            .split(',')
+            .map(|part| part.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| BoundingBoxError::InvalidFormat(value.to_string()))?;
+        let [min_longitude, min_latitude, max_longitude, max_latitude] = coordinates[..] else {
+            return Err(BoundingBoxError::InvalidFormat(value.to_string()));
+        };
+
+        let longitude = -180.0..=180.0;
+        let latitude = -90.0..=90.0;
+        if !longitude.contains(&min_longitude)
+            || !longitude.contains(&max_longitude)
+            || !latitude.contains(&min_latitude)
+            || !latitude.contains(&max_latitude)
+            || min_latitude > max_latitude
+        {
+            return Err(BoundingBoxError::OutOfRange(value.to_string()));
+        }
+        Ok(Self {
+            min_longitude,
+            min_latitude,
+            max_longitude,
+            max_latitude,
+        })
+    }
+}
+
+impl fmt::Display for BoundingBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.min_longitude, self.min_latitude, self.max_longitude, self.max_latitude
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bounding_boxes() {
+        let bbox: BoundingBox = " 2.2, 48.8,2.5 ,48.9".parse().unwrap();
+        assert_eq!(bbox.min_longitude, 2.2);
+        assert_eq!(bbox.max_latitude, 48.9);
+        assert_eq!(bbox.to_string(), "2.2,48.8,2.5,48.9");
+        assert!("170,-20,-170,-10".parse::<BoundingBox>().is_ok());
+
+        assert!(matches!(
+            "2.2,48.8,2.5".parse::<BoundingBox>(),
+            Err(BoundingBoxError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            "a,48.8,2.5,48.9".parse::<BoundingBox>(),
+            Err(BoundingBoxError::InvalidFormat(_))
+        ));
+        assert!(matches!(
+            "2.2,48.9,2.5,48.8".parse::<BoundingBox>(),
+            Err(BoundingBoxError::OutOfRange(_))
+        ));
+        assert!(matches!(
+            "2.2,48.8,190,48.9".parse::<BoundingBox>(),
+            Err(BoundingBoxError::OutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn custom_areas_are_centred_on_their_box() {
+        let bbox: BoundingBox = "2,48,3,50".parse().unwrap();
+        let area = bbox.custom_area(CUSTOM_AREA_ID_BASE);
+
+        assert_eq!(area.id, CUSTOM_AREA_ID_BASE as i64);
+        assert_eq!(area.name, "Custom region 2,48,3,50");
+        assert_eq!(area.country, CUSTOM_AREA_COUNTRY);
+        assert_eq!((area.longitude, area.latitude), (2.5, 49.0));
+        assert!(!bbox.crosses_antimeridian());
+        assert!("170,-20,-170,-10"
+            .parse::<BoundingBox>()
+            .unwrap()
+            .crosses_antimeridian());
+    }
+}
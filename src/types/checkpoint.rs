@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RunPhaseError {
+    #[error("Invalid run phase '{0}', expected extraction or upload")]
+    InvalidPhase(String),
+}
+
+/// Phase a run was in when its checkpoint was last written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunPhase {
+    Extraction,
+    Upload,
+}
+
+impl FromStr for RunPhase {
+    type Err = RunPhaseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "extraction" => Ok(Self::Extraction),
+            "upload" => Ok(Self::Upload),
+            _ => Err(RunPhaseError::InvalidPhase(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for RunPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Extraction => write!(f, "extraction"),
+            Self::Upload => write!(f, "upload"),
+        }
+    }
+}
+
+/// Progress of a run that did not finish, kept in the CID database so the next run resumes
+/// from it instead of scanning every country again. Cleared once a run completes.
+#[derive(Debug, Clone)]
+pub struct RunCheckpoint {
+    pub phase: RunPhase,
+    /// Countries whose extraction finished
+    pub extracted_countries: HashSet<String>,
+    /// Countries whose extracts were all uploaded or recorded as failed
+    pub uploaded_countries: HashSet<String>,
+    /// Last area extracted, as (country_code, area_id)
+    pub last_area: Option<(String, u32)>,
+    pub updated_at: String,
+}
+
+impl fmt::Display for RunCheckpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} phase, {} countries extracted, {} uploaded",
+            self.phase,
+            self.extracted_countries.len(),
+            self.uploaded_countries.len()
+        )?;
+        if let Some((country_code, area_id)) = &self.last_area {
+            write!(f, ", last area {} of {}", area_id, country_code)?;
+        }
+        write!(f, " (saved {})", self.updated_at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_round_trip_through_their_name() {
+        for phase in [RunPhase::Extraction, RunPhase::Upload] {
+            assert_eq!(phase.to_string().parse::<RunPhase>().unwrap(), phase);
+        }
+        assert!("download".parse::<RunPhase>().is_err());
+    }
+}
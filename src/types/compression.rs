@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("Invalid compression '{0}', expected none, gzip or zstd")]
+    InvalidCodec(String),
+}
+
+/// Codec applied to extracts before upload. Clients decompress the content behind the
+/// CID according to the codec recorded with its mapping.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    /// Extracts are uploaded as they are
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn is_none(&self) -> bool {
+        *self == Self::None
+    }
+
+    /// Extension appended to the name of a compressed copy, `<id>.pmtiles.<ext>`
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Self::None => None,
+            Self::Gzip => Some("gz"),
+            Self::Zstd => Some("zst"),
+        }
+    }
+
+    /// Key of an extract in the extract cache. Compressed uploads are cached apart from
+    /// the same extract uploaded as is, since their CIDs differ.
+    pub fn cache_key(&self, content_hash: &str) -> String {
+        match self {
+            Self::None => content_hash.to_string(),
+            codec => format!("{}:{}", codec, content_hash),
+        }
+    }
+
+    /// Value stored in the CID database, NULL for uncompressed content
+    pub fn as_db_value(&self) -> Option<String> {
+        (!self.is_none()).then(|| self.to_string())
+    }
+
+    /// Codec of a CID database value, uncompressed for NULL and unknown values
+    pub fn from_db_value(value: Option<String>) -> Self {
+        value.and_then(|v| v.parse().ok()).unwrap_or_default()
+    }
+}
+
+impl FromStr for Compression {
+    type Err = CompressionError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "none" => Ok(Self::None),
+            "gzip" | "gz" => Ok(Self::Gzip),
+            "zstd" | "zst" => Ok(Self::Zstd),
+            _ => Err(CompressionError::InvalidCodec(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Gzip => write!(f, "gzip"),
+            Self::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_codecs_and_aliases() {
+        assert_eq!("".parse::<Compression>().unwrap(), Compression::None);
+        assert_eq!("None".parse::<Compression>().unwrap(), Compression::None);
+        assert_eq!("gzip".parse::<Compression>().unwrap(), Compression::Gzip);
+        assert_eq!("gz".parse::<Compression>().unwrap(), Compression::Gzip);
+        assert_eq!(" ZSTD ".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert!("brotli".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn database_values_round_trip() {
+        for codec in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            assert_eq!(Compression::from_db_value(codec.as_db_value()), codec);
+        }
+        assert_eq!(Compression::None.as_db_value(), None);
+        assert_eq!(
+            Compression::from_db_value(Some("lz4".to_string())),
+            Compression::None
+        );
+    }
+}
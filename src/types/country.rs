@@ -0,0 +1,72 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CountryPriorityError {
+    #[error("Invalid country code '{0}', expected a two-letter code")]
+    InvalidCountryCode(String),
+}
+
+/// A country and how many areas the WhosOnFirst database holds for it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CountryInfo {
+    pub country_code: String,
+    pub area_count: u32,
+}
+
+/// Order in which countries are extracted
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum CountryPriority {
+    /// The order countries are listed in TARGET_COUNTRIES
+    #[default]
+    TargetOrder,
+    /// Country codes in alphabetical order
+    Alphabetical,
+    /// Countries with the fewest areas first, so many countries complete early
+    SmallestFirst,
+    /// Countries with the most areas first
+    LargestFirst,
+    /// The listed countries first in the given order, then the rest alphabetically
+    Explicit(Vec<String>),
+}
+
+impl FromStr for CountryPriority {
+    type Err = CountryPriorityError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "target-order" => Ok(Self::TargetOrder),
+            "alphabetical" => Ok(Self::Alphabetical),
+            "smallest-first" => Ok(Self::SmallestFirst),
+            "largest-first" => Ok(Self::LargestFirst),
+            _ => {
+                let codes = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|code| !code.is_empty())
+                    .map(|code| {
+                        if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+                            Ok(code.to_ascii_uppercase())
+                        } else {
+                            Err(CountryPriorityError::InvalidCountryCode(code.to_string()))
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Self::Explicit(codes))
+            }
+        }
+    }
+}
+
+impl fmt::Display for CountryPriority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TargetOrder => write!(f, "target-order"),
+            Self::Alphabetical => write!(f, "alphabetical"),
+            Self::SmallestFirst => write!(f, "smallest-first"),
+            Self::LargestFirst => write!(f, "largest-first"),
+            Self::Explicit(codes) => write!(f, "{}", codes.join(",")),
+        }
+    }
+}
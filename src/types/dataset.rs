@@ -0,0 +1,145 @@
+use crate::types::{CompletedUpload, Compression};
+use crate::utils::EncryptionInfo;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Every uploaded area of one country, uploaded as JSON under its own CID. Manifests hold
+/// no timestamp, so a country whose areas did not change keeps its manifest CID.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountryManifest {
+    pub country_code: String,
+    pub areas: Vec<ManifestEntry>,
+}
+
+impl CountryManifest {
+    pub fn total_bytes(&self) -> u64 {
+        self.areas.iter().map(|area| area.file_size).sum()
+    }
+}
+
+/// One area of a country manifest, with what a client needs to fetch and decode it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub area_id: u32,
+    pub cid: String,
+    pub file_size: u64,
+    /// The CID is the index of a split area's parts rather than an extract
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub split: bool,
+    #[serde(default, skip_serializing_if = "Compression::is_none")]
+    pub compression: Compression,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_cid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata_encryption: Option<EncryptionInfo>,
+}
+
+impl ManifestEntry {
+    pub fn new(upload: &CompletedUpload, split: bool) -> Self {
+        Self {
+            area_id: upload.area_id,
+            cid: upload.cid.clone(),
+            file_size: upload.file_size,
+            split,
+            compression: upload.compression,
+            encryption: upload.encryption.clone(),
+            metadata_cid: upload.metadata_cid.clone(),
+            metadata_encryption: upload.metadata_encryption.clone(),
+        }
+    }
+
+    /// Mapping recorded when another node's upload of the area is adopted
+    pub fn completed_upload(&self, country_code: &str) -> CompletedUpload {
+        let mut upload = CompletedUpload::new(
+            country_code.to_string(),
+            self.area_id,
+            self.cid.clone(),
+            self.file_size,
+        );
+        upload.cached = true;
+        upload.compression = self.compression;
+        upload.encryption = self.encryption.clone();
+        upload.metadata_cid = self.metadata_cid.clone();
+        upload.metadata_encryption = self.metadata_encryption.clone();
+        upload
+    }
+}
+
+/// Top-level index of the node's dataset, mapping each country to its manifest. Its CID
+/// is the single root from which every uploaded area can be found.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DatasetIndex {
+    pub generated_at: String,
+    pub countries: BTreeMap<String, CountryIndexEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CountryIndexEntry {
+    pub manifest_cid: String,
+    pub area_count: u64,
+    pub total_bytes: u64,
+    /// CID of the whole-country extract, present when EXTRACTION_MODE produced one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub country_cid: Option<String>,
+}
+
+/// Dataset index recorded in the CID database after its upload
+#[derive(Debug, Clone, Serialize)]
+pub struct PublishedIndex {
+    pub root_cid: String,
+    pub country_count: u64,
+    pub area_count: u64,
+    pub published_at: String,
+}
+
+/// Snapshot of the CID database uploaded to storage, see `start_db_snapshot_publisher`.
+/// A node that lost its disk fetches the latest one and restores it with `db restore`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DbSnapshot {
+    pub cid: String,
+    pub size: u64,
+    pub compression: Compression,
+    pub created_at: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_entries_serialize_without_optional_fields() {
+        let upload = CompletedUpload::new("FR".to_string(), 12, "cid".to_string(), 2048);
+        let json = serde_json::to_value(ManifestEntry::new(&upload, false)).unwrap();
+
+        assert_eq!(
+            json,
+            serde_json::json!({"area_id": 12, "cid": "cid", "file_size": 2048})
+        );
+    }
+
+    #[test]
+    fn split_compressed_entries_round_trip() {
+        let mut upload = CompletedUpload::new("FR".to_string(), 12, "index".to_string(), 2048);
+        upload.compression = Compression::Gzip;
+        upload.metadata_cid = Some("meta".to_string());
+        let entry = ManifestEntry::new(&upload, true);
+
+        let json = serde_json::to_string(&entry).unwrap();
+        assert_eq!(serde_json::from_str::<ManifestEntry>(&json).unwrap(), entry);
+        assert!(json.contains(r#""split":true"#));
+        assert!(json.contains(r#""compression":"gzip""#));
+    }
+
+    #[test]
+    fn adopted_entries_map_back_to_cached_uploads() {
+        let mut upload = CompletedUpload::new("FR".to_string(), 12, "cid".to_string(), 2048);
+        upload.compression = Compression::Gzip;
+        upload.metadata_cid = Some("meta".to_string());
+        let adopted = ManifestEntry::new(&upload, false).completed_upload("FR");
+
+        assert!(adopted.cached);
+        assert_eq!(ManifestEntry::new(&adopted, false), ManifestEntry::new(&upload, false));
+    }
+}
@@ -0,0 +1,67 @@
+use serde::Serialize;
+
+/// Pipeline stage a country completion refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PipelineStage {
+    Extraction,
+    Upload,
+}
+
+/// Structured pipeline event broadcast to external subscribers
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PipelineEvent {
+    /// A country's extraction or upload begins, `completed` of its `area_count` areas are
+    /// already done
+    CountryStarted {
+        country_code: String,
+        stage: PipelineStage,
+        area_count: u64,
+        completed: u64,
+    },
+    AreaExtracted {
+        country_code: String,
+        area_id: u32,
+    },
+    UploadStarted {
+        country_code: String,
+        area_id: u32,
+        file_size: u64,
+    },
+    AreaUploaded {
+        country_code: String,
+        area_id: u32,
+        cid: String,
+        file_size: u64,
+    },
+    UploadFailed {
+        country_code: String,
+        area_id: u32,
+        error: String,
+    },
+    AreaSkipped {
+        country_code: String,
+        area_id: u32,
+        reason: String,
+    },
+    CountryCompleted {
+        country_code: String,
+        stage: PipelineStage,
+    },
+}
+
+impl PipelineEvent {
+    /// Event name, used as the SSE event type
+    pub fn name(&self) -> &'static str {
+        match self {
+            PipelineEvent::CountryStarted { .. } => "country_started",
+            PipelineEvent::AreaExtracted { .. } => "area_extracted",
+            PipelineEvent::UploadStarted { .. } => "upload_started",
+            PipelineEvent::AreaUploaded { .. } => "area_uploaded",
+            PipelineEvent::UploadFailed { .. } => "upload_failed",
+            PipelineEvent::AreaSkipped { .. } => "area_skipped",
+            PipelineEvent::CountryCompleted { .. } => "country_completed",
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ExtractionModeError {
+    #[error("Invalid extraction mode '{0}', expected areas, country, both or grid")]
+    InvalidMode(String),
+}
+
+/// What is extracted for each target country
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ExtractionMode {
+    /// One extract per region and county
+    #[default]
+    Areas,
+    /// A single extract covering the country's own bounding box
+    Country,
+    /// Both the per-area extracts and the whole-country extract
+    Both,
+    /// One extract per cell of a fixed longitude/latitude grid over the country's areas,
+    /// for archives of predictable size
+    Grid,
+}
+
+impl ExtractionMode {
+    pub fn includes_areas(&self) -> bool {
+        matches!(self, Self::Areas | Self::Both)
+    }
+
+    pub fn includes_country(&self) -> bool {
+        matches!(self, Self::Country | Self::Both)
+    }
+}
+
+impl FromStr for ExtractionMode {
+    type Err = ExtractionModeError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "areas" => Ok(Self::Areas),
+            "country" => Ok(Self::Country),
+            "both" => Ok(Self::Both),
+            "grid" => Ok(Self::Grid),
+            _ => Err(ExtractionModeError::InvalidMode(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for ExtractionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Areas => write!(f, "areas"),
+            Self::Country => write!(f, "country"),
+            Self::Both => write!(f, "both"),
+            Self::Grid => write!(f, "grid"),
+        }
+    }
+}
+
+/// Area whose last extraction failed, kept until it is extracted
+#[derive(Debug, Clone)]
+pub struct ExtractionFailure {
+    pub country_code: String,
+    pub area_id: u32,
+    pub error: String,
+    /// Full output of the last failed extraction, when it could be written
+    pub log_path: Option<PathBuf>,
+    pub attempts: u32,
+    /// Time of the last failed attempt
+    pub failed_at: String,
+}
+
+/// Area whose extract exceeded MAX_OUTPUT_SIZE and was deleted instead of uploaded
+#[derive(Debug, Clone, Serialize)]
+pub struct OversizedExtract {
+    pub country_code: String,
+    pub area_id: u32,
+    pub file_size: u64,
+    /// Time of the last extraction that exceeded the budget
+    pub skipped_at: String,
+}
+
+/// Area whose extract is on disk, handed from extraction to the upload stage while the
+/// rest of the countries are still being extracted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedExtract {
+    pub country_code: String,
+    pub area_id: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_mode() {
+        assert_eq!("".parse::<ExtractionMode>().unwrap(), ExtractionMode::Areas);
+        assert_eq!(" Country ".parse::<ExtractionMode>().unwrap(), ExtractionMode::Country);
+        assert_eq!("both".parse::<ExtractionMode>().unwrap().to_string(), "both");
+        assert_eq!("grid".parse::<ExtractionMode>().unwrap(), ExtractionMode::Grid);
+        assert!("countries".parse::<ExtractionMode>().is_err());
+    }
+
+    #[test]
+    fn modes_select_what_is_extracted() {
+        assert!(ExtractionMode::Areas.includes_areas());
+        assert!(!ExtractionMode::Areas.includes_country());
+        assert!(!ExtractionMode::Country.includes_areas());
+        assert!(ExtractionMode::Both.includes_areas() && ExtractionMode::Both.includes_country());
+        assert!(!ExtractionMode::Grid.includes_areas() && !ExtractionMode::Grid.includes_country());
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+
+/// Uploaded area announced to other AnyNodes, which record it in their CID database and
+/// pass it on while hops remain
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CidAnnouncement {
+    /// Node that uploaded the area
+    pub origin: String,
+    pub country_code: String,
+    pub area_id: u32,
+    pub cid: String,
+    pub file_size: u64,
+    /// Hops left, the announcement is forwarded while more than one remains
+    pub ttl: u8,
+}
+
+impl CidAnnouncement {
+    /// The announcement as passed on to the next peers, `None` when its hops are used up
+    pub fn forwarded(&self) -> Option<Self> {
+        (self.ttl > 1).then(|| Self {
+            ttl: self.ttl - 1,
+            ..self.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwarding_uses_up_hops() {
+        let announcement = CidAnnouncement {
+            origin: "node-a".to_string(),
+            country_code: "FR".to_string(),
+            area_id: 12,
+            cid: "cid".to_string(),
+            file_size: 2048,
+            ttl: 2,
+        };
+
+        let forwarded = announcement.forwarded().unwrap();
+        assert_eq!(forwarded.ttl, 1);
+        assert_eq!(forwarded.cid, "cid");
+        assert!(forwarded.forwarded().is_none());
+    }
+}
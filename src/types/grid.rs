@@ -0,0 +1,193 @@
+use crate::types::AdministrativeArea;
+use std::collections::BTreeSet;
+use thiserror::Error;
+
+/// First ID of grid cells, above every WhosOnFirst ID so cells and places never collide
+pub const GRID_CELL_ID_BASE: u32 = 4_000_000_000;
+
+/// Placetype of the areas standing for grid cells
+pub const GRID_CELL_PLACETYPE: &str = "grid";
+
+/// Cell sizes, the smallest keeps every cell ID of the planet within `u32`
+const MIN_CELL_DEGREES: f64 = 0.05;
+const MAX_CELL_DEGREES: f64 = 90.0;
+
+#[derive(Debug, Error)]
+pub enum GridError {
+    #[error("Invalid grid cell size '{0}', expected degrees between 0.05 and 90")]
+    InvalidCellSize(String),
+}
+
+/// Fixed longitude/latitude grid the `grid` extraction mode cuts countries into. Cells are
+/// numbered row by row from the south-west corner of the planet, offset by
+/// `GRID_CELL_ID_BASE`, so a cell has the same ID in every country it overlaps.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grid {
+    pub cell_degrees: f64,
+}
+
+impl Grid {
+    pub fn new(cell_degrees: f64) -> Result<Self, GridError> {
+        if !(MIN_CELL_DEGREES..=MAX_CELL_DEGREES).contains(&cell_degrees) {
+            return Err(GridError::InvalidCellSize(cell_degrees.to_string()));
+        }
+        Ok(Self { cell_degrees })
+    }
+
+    fn columns(&self) -> u32 {
+        (360.0 / self.cell_degrees).ceil() as u32
+    }
+
+    fn rows(&self) -> u32 {
+        (180.0 / self.cell_degrees).ceil() as u32
+    }
+
+    fn column(&self, longitude: f64) -> u32 {
+        (((longitude + 180.0) / self.cell_degrees).floor() as u32).min(self.columns() - 1)
+    }
+
+    fn row(&self, latitude: f64) -> u32 {
+        (((latitude + 90.0) / self.cell_degrees).floor() as u32).min(self.rows() - 1)
+    }
+
+    /// Index of the cell ending at or after `offset` degrees from the grid's origin
+    fn last_index(&self, offset: f64) -> u32 {
+        ((offset / self.cell_degrees).ceil() as u32).saturating_sub(1)
+    }
+
+    /// Edge of the `index`th cell from `origin`, rounded so cell bounds read cleanly
+    fn edge(&self, origin: f64, index: u32) -> f64 {
+        ((origin + index as f64 * self.cell_degrees) * 1e6).round() / 1e6
+    }
+
+    /// The cells overlapping the bounding box of any of `areas`, as areas of `country_code`
+    /// by ID. Cells only touching a box along an edge are left out.
+    pub fn cells_covering(
+        &self,
+        country_code: &str,
+        areas: &[AdministrativeArea],
+    ) -> Vec<AdministrativeArea> {
+        let mut ids = BTreeSet::new();
+        for area in areas {
+            if area.min_longitude > area.max_longitude || area.min_latitude > area.max_latitude {
+                continue;
+            }
+            let (west, south) = (self.column(area.min_longitude), self.row(area.min_latitude));
+            // A box ending exactly on a cell edge does not reach into the next cell
+            let east = self
+                .last_index(area.max_longitude + 180.0)
+                .clamp(west, self.columns() - 1);
+            let north = self
+                .last_index(area.max_latitude + 90.0)
+                .clamp(south, self.rows() - 1);
+            for row in south..=north {
+                for column in west..=east {
+                    ids.insert(GRID_CELL_ID_BASE + row * self.columns() + column);
+                }
+            }
+        }
+
+        ids.into_iter()
+            .filter_map(|id| self.cell(country_code, id))
+            .collect()
+    }
+
+    /// The cell with this ID as an area of `country_code`, `None` for IDs outside the grid
+    pub fn cell(&self, country_code: &str, id: u32) -> Option<AdministrativeArea> {
+        let index = id.checked_sub(GRID_CELL_ID_BASE)?;
+        let (row, column) = (index / self.columns(), index % self.columns());
+        if row >= self.rows() {
+            return None;
+        }
+
+        let min_longitude = self.edge(-180.0, column);
+        let min_latitude = self.edge(-90.0, row);
+        let max_longitude = self.edge(-180.0, column + 1).min(180.0);
+        let max_latitude = self.edge(-90.0, row + 1).min(90.0);
+        Some(AdministrativeArea {
+            id: id as i64,
+            name: format!(
+                "Grid cell {},{},{},{}",
+                min_longitude, min_latitude, max_longitude, max_latitude
+            ),
+            country: country_code.to_string(),
+            placetype: GRID_CELL_PLACETYPE.to_string(),
+            latitude: (min_latitude + max_latitude) / 2.0,
+            longitude: (min_longitude + max_longitude) / 2.0,
+            min_longitude,
+            min_latitude,
+            max_longitude,
+            max_latitude,
+        })
+    }
+}
+
+impl Default for Grid {
+    fn default() -> Self {
+        Self { cell_degrees: 1.0 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(bbox: [f64; 4]) -> AdministrativeArea {
+        AdministrativeArea {
+            id: 1,
+            name: "Area".to_string(),
+            country: "FR".to_string(),
+            placetype: "region".to_string(),
+            latitude: 0.0,
+            longitude: 0.0,
+            min_longitude: bbox[0],
+            min_latitude: bbox[1],
+            max_longitude: bbox[2],
+            max_latitude: bbox[3],
+        }
+    }
+
+    #[test]
+    fn cell_sizes_are_bounded() {
+        assert!(Grid::new(0.5).is_ok());
+        assert!(Grid::new(0.01).is_err());
+        assert!(Grid::new(120.0).is_err());
+    }
+
+    #[test]
+    fn cells_cover_every_area_once() {
+        let grid = Grid::new(1.0).unwrap();
+        let cells = grid.cells_covering(
+            "FR",
+            &[area([2.2, 48.8, 2.5, 48.9]), area([2.0, 48.0, 3.0, 49.5])],
+        );
+
+        let boxes: Vec<_> = cells
+            .iter()
+            .map(|cell| (cell.min_longitude, cell.min_latitude))
+            .collect();
+        assert_eq!(boxes, vec![(2.0, 48.0), (2.0, 49.0)]);
+        assert!(cells
+            .iter()
+            .all(|cell| cell.placetype == GRID_CELL_PLACETYPE));
+        assert_eq!(cells[0].country, "FR");
+    }
+
+    #[test]
+    fn cell_ids_round_trip() {
+        let grid = Grid::new(0.25).unwrap();
+        let cells = grid.cells_covering("NZ", &[area([179.9, -90.0, 180.0, -89.9])]);
+        assert_eq!(cells.len(), 1);
+
+        let cell = &cells[0];
+        assert_eq!((cell.max_longitude, cell.min_latitude), (180.0, -90.0));
+        let id = cell.id as u32;
+        assert!(id >= GRID_CELL_ID_BASE);
+        assert_eq!(
+            grid.cell("NZ", id).unwrap().min_longitude,
+            cell.min_longitude
+        );
+        assert!(grid.cell("NZ", 85633147).is_none());
+        assert!(grid.cell("NZ", u32::MAX).is_none());
+    }
+}
@@ -1,5 +1,52 @@
 pub mod area;
+pub mod bbox;
+pub mod checkpoint;
+pub mod compression;
+pub mod country;
+pub mod dataset;
+pub mod event;
+pub mod extraction;
+pub mod gossip;
+pub mod grid;
+pub mod network;
+pub mod notify;
+pub mod plan;
+pub mod retention;
+pub mod schedule;
+pub mod shard;
 pub mod storage;
+pub mod timing;
+pub mod zoom;
 
-pub use area::{AdministrativeArea, AreaInfo, PaginatedAreasResult, PaginationInfo};
-pub use storage::{CompletedUpload, PendingUpload, UploadQueue, UploadStats};
+pub use area::{
+    area_metadata_path, area_parts_dir, AdministrativeArea, AreaInfo, AreaMetadata, AreaPart,
+    AreaPartUpload, CatalogRecord, PaginatedAreasResult, PaginationInfo, SplitAreaManifest,
+    AREA_PARTS_EXTENSION, AREA_PARTS_MANIFEST, SPLIT_AREA_INDEX,
+};
+pub use bbox::{
+    BoundingBox, BoundingBoxError, CUSTOM_AREA_COUNTRY, CUSTOM_AREA_ID_BASE, CUSTOM_AREA_PLACETYPE,
+};
+pub use checkpoint::{RunCheckpoint, RunPhase, RunPhaseError};
+pub use compression::{Compression, CompressionError};
+pub use country::{CountryInfo, CountryPriority, CountryPriorityError};
+pub use dataset::{
+    CountryIndexEntry, CountryManifest, DatasetIndex, DbSnapshot, ManifestEntry, PublishedIndex,
+};
+pub use event::{PipelineEvent, PipelineStage};
+pub use extraction::{
+    CompletedExtract, ExtractionFailure, ExtractionMode, ExtractionModeError, OversizedExtract,
+};
+pub use gossip::CidAnnouncement;
+pub use grid::{Grid, GridError, GRID_CELL_ID_BASE, GRID_CELL_PLACETYPE};
+pub use network::{ListenAddr, NetworkAddressError, SprUri, Transport};
+pub use notify::{Notification, NotifierKind, NotifierKindError};
+pub use plan::{CountryPlan, RunPlan};
+pub use retention::{RetentionPolicy, RetentionPolicyError};
+pub use schedule::{ScheduleError, UploadSchedule, UploadWindow};
+pub use shard::{shard_index, Shard, ShardError};
+pub use storage::{
+    CompletedUpload, CountryUsage, FailedUpload, PendingUpload, RunStats, StorageBackendKind,
+    StorageBackendKindError, UploadConcurrency, UploadProgress, UploadQueue, UploadStats,
+};
+pub use timing::PhaseTimings;
+pub use zoom::{ZoomProfileError, ZoomProfiles, ZoomRange};
@@ -0,0 +1,149 @@
+use crate::utils::{abbreviate, decode_spr};
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NetworkAddressError {
+    #[error("invalid SPR URI '{uri}': {reason}")]
+    InvalidSpr { uri: String, reason: String },
+    #[error("invalid listen address '{addr}': {reason}, expected e.g. /ip4/0.0.0.0/tcp/8070")]
+    InvalidListenAddr { addr: String, reason: String },
+}
+
+/// Signed peer record URI (`spr:...`) of a bootstrap node, decoded at parse time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SprUri {
+    uri: String,
+    peer_id: String,
+}
+
+impl SprUri {
+    pub fn as_str(&self) -> &str {
+        &self.uri
+    }
+
+    pub fn peer_id(&self) -> &str {
+        &self.peer_id
+    }
+}
+
+impl FromStr for SprUri {
+    type Err = NetworkAddressError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let uri = value.trim();
+        let record = decode_spr(uri).map_err(|e| NetworkAddressError::InvalidSpr {
+            uri: abbreviate(uri),
+            reason: e.to_string(),
+        })?;
+
+        Ok(Self {
+            uri: uri.to_string(),
+            peer_id: record.peer_id,
+        })
+    }
+}
+
+impl fmt::Display for SprUri {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.uri)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+/// Multiaddress the storage node listens on, `/ip4|ip6/<address>/tcp|udp/<port>` followed
+/// by optional `ws`, `wss`, `quic` or `quic-v1` components
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListenAddr {
+    addr: String,
+    ip: IpAddr,
+    transport: Transport,
+    port: u16,
+}
+
+impl ListenAddr {
+    pub fn as_str(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+
+    pub fn transport(&self) -> Transport {
+        self.transport
+    }
+
+    /// Port to listen on, zero meaning a random port
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl FromStr for ListenAddr {
+    type Err = NetworkAddressError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let addr = value.trim();
+        let invalid = |reason: &str| NetworkAddressError::InvalidListenAddr {
+            addr: addr.to_string(),
+            reason: reason.to_string(),
+        };
+
+        let rest = addr
+            .strip_prefix('/')
+            .ok_or_else(|| invalid("a multiaddress must start with '/'"))?;
+        let parts: Vec<&str> = rest.split('/').collect();
+
+        let [family, ip, transport, port, extra @ ..] = parts.as_slice() else {
+            return Err(invalid("missing address, transport or port"));
+        };
+
+        let ip: IpAddr = match *family {
+            "ip4" => ip
+                .parse::<std::net::Ipv4Addr>()
+                .map(IpAddr::V4)
+                .map_err(|_| invalid("not an IPv4 address"))?,
+            "ip6" => ip
+                .parse::<std::net::Ipv6Addr>()
+                .map(IpAddr::V6)
+                .map_err(|_| invalid("not an IPv6 address"))?,
+            _ => return Err(invalid("only /ip4 and /ip6 addresses can be listened on")),
+        };
+
+        let transport = match *transport {
+            "tcp" => Transport::Tcp,
+            "udp" => Transport::Udp,
+            _ => return Err(invalid("transport must be tcp or udp")),
+        };
+
+        let port: u16 = port.parse().map_err(|_| invalid("port must be 0-65535"))?;
+
+        if let Some(unknown) = extra
+            .iter()
+            .find(|p| !matches!(**p, "ws" | "wss" | "quic" | "quic-v1"))
+        {
+            return Err(invalid(&format!("unsupported protocol '{}'", unknown)));
+        }
+
+        Ok(Self {
+            addr: addr.to_string(),
+            ip,
+            transport,
+            port,
+        })
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.addr)
+    }
+}
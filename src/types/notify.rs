@@ -0,0 +1,168 @@
+use crate::types::UploadStats;
+use crate::utils::format_bytes;
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NotifierKindError {
+    #[error("Invalid notifier '{0}', expected log, webhook or email")]
+    InvalidNotifier(String),
+}
+
+/// Channel run milestones are reported on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotifierKind {
+    /// Milestones are written to the log
+    Log,
+    /// Milestones are POSTed as JSON to NOTIFY_WEBHOOK_URL
+    Webhook,
+    /// Milestones are mailed through NOTIFY_SMTP_URL
+    Email,
+}
+
+impl FromStr for NotifierKind {
+    type Err = NotifierKindError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "log" => Ok(Self::Log),
+            "webhook" => Ok(Self::Webhook),
+            "email" | "smtp" => Ok(Self::Email),
+            _ => Err(NotifierKindError::InvalidNotifier(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for NotifierKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Log => write!(f, "log"),
+            Self::Webhook => write!(f, "webhook"),
+            Self::Email => write!(f, "email"),
+        }
+    }
+}
+
+/// Milestone of a node run reported to the configured notifiers
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Notification {
+    NodeStarted {
+        peer_id: Option<String>,
+    },
+    ExtractionFinished {
+        /// Why extraction stopped early, uploads continue with the extracts on disk
+        error: Option<String>,
+    },
+    UploadsFinished {
+        #[serde(flatten)]
+        stats: UploadStats,
+    },
+    IndexPublished {
+        root_cid: String,
+        country_count: u64,
+        area_count: u64,
+    },
+    RunFailed {
+        error: String,
+    },
+    /// The discovery table stayed empty for `secs` seconds
+    NoPeers {
+        secs: u64,
+    },
+}
+
+impl Notification {
+    /// One-line summary, used as the email subject
+    pub fn subject(&self) -> String {
+        let summary = match self {
+            Self::NodeStarted { .. } => "storage node started",
+            Self::ExtractionFinished { error: None } => "extraction finished",
+            Self::ExtractionFinished { error: Some(_) } => "extraction failed",
+            Self::UploadsFinished { stats } if stats.total_failed > 0 => {
+                "uploads finished with failures"
+            }
+            Self::UploadsFinished { .. } => "uploads finished",
+            Self::IndexPublished { .. } => "dataset index published",
+            Self::RunFailed { .. } => "run failed",
+            Self::NoPeers { .. } => "no peers",
+        };
+        format!("AnyNode: {}", summary)
+    }
+}
+
+impl fmt::Display for Notification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NodeStarted { peer_id } => write!(
+                f,
+                "Storage node started with peer ID {}",
+                peer_id.as_deref().unwrap_or("unknown")
+            ),
+            Self::ExtractionFinished { error: None } => write!(f, "Extraction finished"),
+            Self::ExtractionFinished { error: Some(error) } => write!(
+                f,
+                "Extraction failed, uploading the extracts on disk: {}",
+                error
+            ),
+            Self::UploadsFinished { stats } => write!(
+                f,
+                "Uploads finished: {} uploaded, {} reused, {} failed, {}",
+                stats.total_uploaded,
+                stats.total_reused,
+                stats.total_failed,
+                format_bytes(stats.total_bytes_uploaded)
+            ),
+            Self::IndexPublished {
+                root_cid,
+                country_count,
+                area_count,
+            } => write!(
+                f,
+                "Dataset root CID {} ({} countries, {} areas)",
+                root_cid, country_count, area_count
+            ),
+            Self::RunFailed { error } => write!(f, "Run failed: {}", error),
+            Self::NoPeers { secs } => write!(
+                f,
+                "No peers in the discovery table for {}s, the node is serving nothing to the \
+                 network. Re-dialing the bootstrap nodes.",
+                secs
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_notifiers() {
+        assert_eq!("log".parse::<NotifierKind>().unwrap(), NotifierKind::Log);
+        assert_eq!(" Webhook ".parse::<NotifierKind>().unwrap(), NotifierKind::Webhook);
+        assert_eq!("smtp".parse::<NotifierKind>().unwrap(), NotifierKind::Email);
+        assert!("slack".parse::<NotifierKind>().is_err());
+    }
+
+    #[test]
+    fn notifications_serialize_with_their_event_name() {
+        let mut stats = UploadStats::new();
+        stats.increment_uploaded(2048);
+        let json = serde_json::to_value(Notification::UploadsFinished { stats }).unwrap();
+
+        assert_eq!(json["event"], "uploads_finished");
+        assert_eq!(json["total_uploaded"], 1);
+        assert_eq!(json["total_bytes_uploaded"], 2048);
+    }
+
+    #[test]
+    fn failed_uploads_show_in_the_subject() {
+        let mut stats = UploadStats::new();
+        stats.increment_failed();
+        let notification = Notification::UploadsFinished { stats };
+        assert_eq!(notification.subject(), "AnyNode: uploads finished with failures");
+    }
+}
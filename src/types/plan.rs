@@ -0,0 +1,129 @@
+use crate::utils::format_bytes;
+use serde::Serialize;
+use std::fmt;
+
+/// Estimate of a country's share of a run, from the size of a sample of its extracts
+#[derive(Debug, Clone, Serialize)]
+pub struct CountryPlan {
+    pub country_code: String,
+    /// Areas not uploaded yet
+    pub pending_areas: u64,
+    /// Extracts measured for the estimate
+    pub sampled_areas: u64,
+    pub average_size: u64,
+    pub estimated_bytes: u64,
+}
+
+impl CountryPlan {
+    pub fn new(
+        country_code: &str,
+        pending_areas: u64,
+        sample_sizes: &[u64],
+        fallback: u64,
+    ) -> Self {
+        let average_size = match sample_sizes.len() {
+            0 => fallback,
+            count => sample_sizes.iter().sum::<u64>() / count as u64,
+        };
+        Self {
+            country_code: country_code.to_string(),
+            pending_areas,
+            sampled_areas: sample_sizes.len() as u64,
+            average_size,
+            estimated_bytes: average_size * pending_areas,
+        }
+    }
+}
+
+/// What a run is expected to need, printed before extraction starts
+#[derive(Debug, Clone, Serialize)]
+pub struct RunPlan {
+    pub countries: Vec<CountryPlan>,
+    /// Bytes already uploaded, counted against the quota
+    pub stored_bytes: u64,
+    pub storage_quota: u64,
+}
+
+impl RunPlan {
+    pub fn estimated_bytes(&self) -> u64 {
+        self.countries.iter().map(|c| c.estimated_bytes).sum()
+    }
+
+    /// Local disk the run fills, the extracts and their copy in the storage repo
+    pub fn disk_bytes(&self) -> u64 {
+        self.estimated_bytes() * 2
+    }
+
+    /// Storage the quota must hold once the run is done
+    pub fn quota_bytes(&self) -> u64 {
+        self.stored_bytes + self.estimated_bytes()
+    }
+
+    pub fn exceeds_quota(&self) -> bool {
+        self.quota_bytes() > self.storage_quota
+    }
+}
+
+impl fmt::Display for RunPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<8}  {:>8}  {:>8}  {:>12}  {:>12}",
+            "COUNTRY", "AREAS", "SAMPLED", "AVERAGE", "ESTIMATE"
+        )?;
+        for country in &self.countries {
+            writeln!(
+                f,
+                "{:<8}  {:>8}  {:>8}  {:>12}  {:>12}",
+                country.country_code,
+                country.pending_areas,
+                country.sampled_areas,
+                format_bytes(country.average_size),
+                format_bytes(country.estimated_bytes)
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "Extracts:  {}", format_bytes(self.estimated_bytes()))?;
+        writeln!(
+            f,
+            "Disk:      {} (extracts and storage repo)",
+            format_bytes(self.disk_bytes())
+        )?;
+        writeln!(
+            f,
+            "Bandwidth: {} uploaded, as much read from remote planet sources",
+            format_bytes(self.estimated_bytes())
+        )?;
+        write!(
+            f,
+            "Quota:     {} of {} ({} already stored)",
+            format_bytes(self.quota_bytes()),
+            format_bytes(self.storage_quota),
+            format_bytes(self.stored_bytes)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_from_the_sample_average() {
+        let plan = RunPlan {
+            countries: vec![
+                CountryPlan::new("FR", 10, &[100, 300], 50),
+                CountryPlan::new("LU", 4, &[], 50),
+            ],
+            stored_bytes: 1000,
+            storage_quota: 3000,
+        };
+
+        assert_eq!(plan.countries[0].estimated_bytes, 2000);
+        assert_eq!(plan.countries[1].average_size, 50);
+        assert_eq!(plan.estimated_bytes(), 2200);
+        assert_eq!(plan.disk_bytes(), 4400);
+        assert!(plan.exceeds_quota());
+        assert!(plan.to_string().starts_with("COUNTRY"));
+    }
+}
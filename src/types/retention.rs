@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RetentionPolicyError {
+    #[error(
+        "Invalid retention policy '{0}', expected keep, delete-after-upload or \
+         delete-after-days=N"
+    )]
+    InvalidPolicy(String),
+}
+
+/// What happens to a local extract once its CID mapping is committed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RetentionPolicy {
+    /// Extracts stay on disk
+    #[default]
+    Keep,
+    /// Extracts are deleted as soon as their CID mapping is recorded
+    DeleteAfterUpload,
+    /// Extracts are deleted once their CID mapping is older than this many days
+    DeleteAfterDays(u32),
+}
+
+impl RetentionPolicy {
+    /// Whether uploaded extracts may be missing from disk, so an area with a CID mapping
+    /// must not be taken for one that still needs extracting
+    pub fn deletes_files(&self) -> bool {
+        *self != Self::Keep
+    }
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = RetentionPolicyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let normalized = value.trim().to_ascii_lowercase();
+        match normalized.as_str() {
+            "" | "keep" => Ok(Self::Keep),
+            "delete-after-upload" => Ok(Self::DeleteAfterUpload),
+            _ => normalized
+                .strip_prefix("delete-after-days=")
+                .and_then(|days| days.trim().parse().ok())
+                .map(Self::DeleteAfterDays)
+                .ok_or_else(|| RetentionPolicyError::InvalidPolicy(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for RetentionPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Keep => write!(f, "keep"),
+            Self::DeleteAfterUpload => write!(f, "delete-after-upload"),
+            Self::DeleteAfterDays(days) => write!(f, "delete-after-days={}", days),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_policy() {
+        assert_eq!("".parse::<RetentionPolicy>().unwrap(), RetentionPolicy::Keep);
+        assert_eq!("keep".parse::<RetentionPolicy>().unwrap(), RetentionPolicy::Keep);
+        assert_eq!(
+            " Delete-After-Upload ".parse::<RetentionPolicy>().unwrap(),
+            RetentionPolicy::DeleteAfterUpload
+        );
+        assert_eq!(
+            "delete-after-days=30".parse::<RetentionPolicy>().unwrap(),
+            RetentionPolicy::DeleteAfterDays(30)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_policies() {
+        for value in ["delete", "delete-after-days=", "delete-after-days=-1", "purge"] {
+            assert!(value.parse::<RetentionPolicy>().is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for policy in [
+            RetentionPolicy::Keep,
+            RetentionPolicy::DeleteAfterUpload,
+            RetentionPolicy::DeleteAfterDays(7),
+        ] {
+            assert_eq!(policy.to_string().parse::<RetentionPolicy>().unwrap(), policy);
+        }
+    }
+}
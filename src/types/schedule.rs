@@ -0,0 +1,206 @@
+use chrono::{Local, Timelike};
+use thiserror::Error;
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Debug, Error)]
+pub enum ScheduleError {
+    #[error("Invalid upload window '{0}', expected HH:MM-HH:MM")]
+    InvalidWindow(String),
+}
+
+/// Daily time range in local time, as minutes since midnight. A window whose end is
+/// before its start wraps past midnight, and an end of 24:00 (1440) closes at midnight.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UploadWindow {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl UploadWindow {
+    pub fn parse(value: &str) -> Result<Self, ScheduleError> {
+        let invalid = || ScheduleError::InvalidWindow(value.to_string());
+
+        let (start, end) = value.trim().split_once('-').ok_or_else(invalid)?;
+        let start = parse_time_of_day(start, false).ok_or_else(invalid)?;
+        let end = parse_time_of_day(end, true).ok_or_else(invalid)?;
+
+        if start == end {
+            return Err(invalid());
+        }
+
+        Ok(Self { start, end })
+    }
+
+    pub fn contains(&self, minute_of_day: u32) -> bool {
+        if self.start < self.end {
+            minute_of_day >= self.start && minute_of_day < self.end
+        } else {
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+/// Set of windows during which uploads are allowed. An empty schedule allows uploads at
+/// any time.
+#[derive(Debug, Clone, Default)]
+pub struct UploadSchedule {
+    windows: Vec<UploadWindow>,
+}
+
+impl UploadSchedule {
+    /// Parse a comma-separated list of `HH:MM-HH:MM` windows
+    pub fn parse(value: &str) -> Result<Self, ScheduleError> {
+        let windows = value
+            .split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(UploadWindow::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { windows })
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    pub fn is_open_at(&self, minute_of_day: u32) -> bool {
+        self.is_unrestricted() || self.windows.iter().any(|w| w.contains(minute_of_day))
+    }
+
+    pub fn is_open_now(&self) -> bool {
+        self.is_open_at(current_minute_of_day())
+    }
+
+    /// Minutes until the next window opens, zero when one is already open
+    pub fn minutes_until_open(&self, minute_of_day: u32) -> u32 {
+        if self.is_open_at(minute_of_day) {
+            return 0;
+        }
+
+        self.windows
+            .iter()
+            .map(|w| (w.start + MINUTES_PER_DAY - minute_of_day) % MINUTES_PER_DAY)
+            .min()
+            .unwrap_or(0)
+    }
+
+    pub fn minutes_until_open_now(&self) -> u32 {
+        self.minutes_until_open(current_minute_of_day())
+    }
+}
+
+impl std::fmt::Display for UploadSchedule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_unrestricted() {
+            return write!(f, "any time");
+        }
+
+        let windows: Vec<String> = self
+            .windows
+            .iter()
+            .map(|w| {
+                format!(
+                    "{:02}:{:02}-{:02}:{:02}",
+                    w.start / 60,
+                    w.start % 60,
+                    w.end / 60,
+                    w.end % 60
+                )
+            })
+            .collect();
+        write!(f, "{}", windows.join(", "))
+    }
+}
+
+/// Minutes since midnight of an `HH:MM` time. `24:00` is only accepted as the end of a
+/// window, where it stands for the end of the day.
+fn parse_time_of_day(value: &str, end_of_window: bool) -> Option<u32> {
+    let (hours, minutes) = value.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+
+    if hours < 24 && minutes < 60 {
+        Some(hours * 60 + minutes)
+    } else if end_of_window && hours == 24 && minutes == 0 {
+        Some(MINUTES_PER_DAY)
+    } else {
+        None
+    }
+}
+
+fn current_minute_of_day() -> u32 {
+    let now = Local::now();
+    now.hour() * 60 + now.minute()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_same_day_window() {
+        let window = UploadWindow::parse("09:30-17:00").unwrap();
+        assert_eq!(window, UploadWindow { start: 570, end: 1020 });
+        assert!(!window.contains(569));
+        assert!(window.contains(570));
+        assert!(window.contains(1019));
+        assert!(!window.contains(1020));
+    }
+
+    #[test]
+    fn parses_window_wrapping_past_midnight() {
+        let window = UploadWindow::parse("22:00-06:00").unwrap();
+        assert!(window.contains(22 * 60));
+        assert!(window.contains(23 * 60 + 59));
+        assert!(window.contains(0));
+        assert!(window.contains(5 * 60 + 59));
+        assert!(!window.contains(6 * 60));
+        assert!(!window.contains(12 * 60));
+    }
+
+    #[test]
+    fn accepts_end_of_day() {
+        let window = UploadWindow::parse("00:00-24:00").unwrap();
+        assert_eq!(window, UploadWindow { start: 0, end: MINUTES_PER_DAY });
+        assert!(window.contains(0));
+        assert!(window.contains(MINUTES_PER_DAY - 1));
+
+        let evening = UploadWindow::parse("22:00-24:00").unwrap();
+        assert!(evening.contains(23 * 60 + 59));
+        assert!(!evening.contains(0));
+
+        assert!(UploadWindow::parse("24:00-06:00").is_err());
+    }
+
+    #[test]
+    fn rejects_malformed_windows() {
+        for value in [
+            "",
+            "09:00",
+            "09:00-",
+            "-17:00",
+            "9-17",
+            "09:60-17:00",
+            "25:00-26:00",
+            "24:01-06:00",
+            "aa:bb-cc:dd",
+            "10:00-10:00",
+        ] {
+            assert!(UploadWindow::parse(value).is_err(), "{} should be rejected", value);
+        }
+    }
+
+    #[test]
+    fn schedule_waits_for_next_window() {
+        let schedule = UploadSchedule::parse("01:00-02:00, 22:00-24:00").unwrap();
+        assert!(schedule.is_open_at(90));
+        assert_eq!(schedule.minutes_until_open(90), 0);
+        assert_eq!(schedule.minutes_until_open(3 * 60), 19 * 60);
+        assert_eq!(schedule.minutes_until_open(0), 60);
+        assert_eq!(schedule.to_string(), "01:00-02:00, 22:00-24:00");
+
+        assert!(UploadSchedule::parse("").unwrap().is_open_at(0));
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ShardError {
+    #[error("Invalid shard '{0}', expected <index>/<count> such as 2/5")]
+    InvalidFormat(String),
+    #[error("Invalid shard '{0}', the index must be between 1 and the shard count")]
+    OutOfRange(String),
+}
+
+/// Slice of the planet one node of a fleet is responsible for. Countries are assigned by
+/// a hash of their code, so every node computes the same split without coordination and a
+/// country stays on its node as long as the fleet size does not change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    /// 1-based index of this node's shard
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Shard {
+    /// Whether the country belongs to this shard
+    pub fn owns(&self, country_code: &str) -> bool {
+        shard_index(country_code, self.count) == self.index
+    }
+}
+
+/// 1-based shard a country is assigned to among `count` shards, using FNV-1a so the result
+/// is the same across builds and platforms
+pub fn shard_index(country_code: &str, count: u32) -> u32 {
+    let hash = country_code
+        .to_ascii_uppercase()
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    (hash % u64::from(count.max(1))) as u32 + 1
+}
+
+impl FromStr for Shard {
+    type Err = ShardError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (index, count) = value
+            .trim()
+            .split_once('/')
+            .ok_or_else(|| ShardError::InvalidFormat(value.to_string()))?;
+        let parse = |part: &str| {
+            part.trim()
+                .parse::<u32>()
+                .map_err(|_| ShardError::InvalidFormat(value.to_string()))
+        };
+        let (index, count) = (parse(index)?, parse(count)?);
+
+        if index == 0 || index > count {
+            return Err(ShardError::OutOfRange(value.to_string()));
+        }
+        Ok(Self { index, count })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_shards() {
+        assert_eq!("2/5".parse::<Shard>().unwrap(), Shard { index: 2, count: 5 });
+        assert_eq!(" 1 / 1 ".parse::<Shard>().unwrap().to_string(), "1/1");
+        assert!(matches!("0/5".parse::<Shard>(), Err(ShardError::OutOfRange(_))));
+        assert!(matches!("6/5".parse::<Shard>(), Err(ShardError::OutOfRange(_))));
+        assert!(matches!("2".parse::<Shard>(), Err(ShardError::InvalidFormat(_))));
+        assert!(matches!("a/5".parse::<Shard>(), Err(ShardError::InvalidFormat(_))));
+    }
+
+    #[test]
+    fn every_country_belongs_to_exactly_one_shard() {
+        let count = 5;
+        let shards: Vec<Shard> = (1..=count).map(|index| Shard { index, count }).collect();
+
+        for country in ["FR", "DE", "US", "JP", "BR", "ZA", "NZ", "fr"] {
+            let owners = shards.iter().filter(|shard| shard.owns(country)).count();
+            assert_eq!(owners, 1, "{} has {} owners", country, owners);
+        }
+        assert_eq!(shard_index("FR", count), shard_index("fr", count));
+    }
+
+    #[test]
+    fn assignment_is_stable() {
+        // Nodes of a fleet may run different builds, the split must not move between them
+        assert_eq!(shard_index("FR", 5), 5);
+        assert_eq!(shard_index("US", 5), 3);
+        assert_eq!(shard_index("JP", 3), 2);
+    }
+}
@@ -1,5 +1,11 @@
+use crate::types::Compression;
+use crate::utils::{format_bytes, format_duration, EncryptionInfo};
+use serde::Serialize;
 use std::collections::VecDeque;
+use std::fmt;
 use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use thiserror::Error;
 
 #[derive(Debug, Clone)]
@@ -25,6 +31,16 @@ pub struct CompletedUpload {
     pub area_id: u32,
     pub cid: String,
     pub file_size: u64,
+    /// Content was already in storage and its CID was reused instead of uploading
+    pub cached: bool,
+    /// Codec the content behind the CID was compressed with
+    pub compression: Compression,
+    /// Key and nonce the content was encrypted with, after compression
+    pub encryption: Option<EncryptionInfo>,
+    /// CID of the area's metadata sidecar, `None` when its upload failed
+    pub metadata_cid: Option<String>,
+    /// Key and nonce the metadata sidecar was encrypted with
+    pub metadata_encryption: Option<EncryptionInfo>,
 }
 
 impl CompletedUpload {
@@ -34,6 +50,11 @@ impl CompletedUpload {
             area_id,
             cid,
             file_size,
+            cached: false,
+            compression: Compression::None,
+            encryption: None,
+            metadata_cid: None,
+            metadata_encryption: None,
         }
     }
 }
@@ -88,9 +109,56 @@ impl UploadQueue {
     }
 }
 
-#[derive(Debug, Clone, Default)]
+/// How many uploads run at once, adjusted after each batch. The limit halves after a batch
+/// with a failed or slow upload and grows by one after a healthy batch that used it all,
+/// staying between the configured bounds.
+#[derive(Debug)]
+pub struct UploadConcurrency {
+    limit: usize,
+    min: usize,
+    max: usize,
+    slow_after: Duration,
+}
+
+impl UploadConcurrency {
+    /// Start at `max`, the fixed concurrency uploads had before it adapted
+    pub fn new(min: usize, max: usize, slow_after: Duration) -> Self {
+        let max = max.max(1);
+        Self {
+            limit: max,
+            min: min.clamp(1, max),
+            max,
+            slow_after,
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Adjust the limit to how long each upload of a batch took and whether it succeeded.
+    /// Returns the new limit when it changed.
+    pub fn record_batch(&mut self, outcomes: &[(bool, Duration)]) -> Option<usize> {
+        let previous = self.limit;
+        let struggling = outcomes
+            .iter()
+            .any(|(succeeded, elapsed)| !succeeded || *elapsed > self.slow_after);
+
+        if struggling {
+            self.limit = (self.limit / 2).max(self.min);
+        } else if outcomes.len() >= self.limit {
+            // A batch smaller than the limit says nothing about running more at once
+            self.limit = (self.limit + 1).min(self.max);
+        }
+        (self.limit != previous).then_some(self.limit)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct UploadStats {
     pub total_uploaded: u64,
+    /// Areas mapped to the CID of byte-identical content uploaded before, not re-uploaded
+    pub total_reused: u64,
     pub total_failed: u64,
     pub total_bytes_uploaded: u64,
 }
@@ -105,7 +173,282 @@ impl UploadStats {
         self.total_bytes_uploaded += bytes;
     }
 
+    pub fn increment_reused(&mut self) {
+        self.total_reused += 1;
+    }
+
     pub fn increment_failed(&mut self) {
         self.total_failed += 1;
     }
 }
+
+/// Upload statistics of one completed run
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStats {
+    pub started_at: String,
+    pub finished_at: String,
+    #[serde(flatten)]
+    pub stats: UploadStats,
+}
+
+/// Upload that still failed after every attempt, kept until a later upload succeeds
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedUpload {
+    pub country_code: String,
+    pub area_id: u32,
+    pub path: PathBuf,
+    pub reason: String,
+    pub attempts: u32,
+    pub first_failed_at: String,
+    pub last_failed_at: String,
+}
+
+/// Uploaded areas and bytes stored for one country
+#[derive(Debug, Clone, Serialize)]
+pub struct CountryUsage {
+    pub country_code: String,
+    pub area_count: u64,
+    pub total_bytes: u64,
+}
+
+/// How far back upload throughput is averaged
+const THROUGHPUT_WINDOW: Duration = Duration::from_secs(300);
+
+/// Remaining upload work and rolling throughput, used to estimate time to completion
+#[derive(Debug, Default)]
+pub struct UploadProgress {
+    remaining_files: u64,
+    remaining_bytes: u64,
+    uploaded_bytes: u64,
+    samples: VecDeque<(Instant, u64)>,
+}
+
+impl UploadProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reset the outstanding work and restart the throughput window
+    pub fn start(&mut self, files: u64, bytes: u64) {
+        self.start_at(files, bytes, Instant::now());
+    }
+
+    fn start_at(&mut self, files: u64, bytes: u64, now: Instant) {
+        self.remaining_files = files;
+        self.remaining_bytes = bytes;
+        self.samples.clear();
+        self.samples.push_back((now, self.uploaded_bytes));
+    }
+
+    pub fn record_uploaded(&mut self, bytes: u64) {
+        self.record_uploaded_at(bytes, Instant::now());
+    }
+
+    fn record_uploaded_at(&mut self, bytes: u64, now: Instant) {
+        self.uploaded_bytes += bytes;
+        self.complete(bytes);
+
+        self.samples.push_back((now, self.uploaded_bytes));
+        // Keep the newest sample older than the window so the average spans all of it
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) > THROUGHPUT_WINDOW {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Count a file as done without it contributing to throughput
+    pub fn record_skipped(&mut self, bytes: u64) {
+        self.complete(bytes);
+    }
+
+    fn complete(&mut self, bytes: u64) {
+        self.remaining_files = self.remaining_files.saturating_sub(1);
+        self.remaining_bytes = self.remaining_bytes.saturating_sub(bytes);
+    }
+
+    pub fn remaining_files(&self) -> u64 {
+        self.remaining_files
+    }
+
+    pub fn remaining_bytes(&self) -> u64 {
+        self.remaining_bytes
+    }
+
+    /// Bytes per second over the recent window, `None` until something has been uploaded
+    pub fn throughput(&self) -> Option<f64> {
+        let (first_at, first_bytes) = self.samples.front()?;
+        let (last_at, last_bytes) = self.samples.back()?;
+        let elapsed = last_at.duration_since(*first_at).as_secs_f64();
+
+        if last_bytes == first_bytes || elapsed <= 0.0 {
+            return None;
+        }
+        Some((last_bytes - first_bytes) as f64 / elapsed)
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        if self.remaining_files == 0 {
+            return None;
+        }
+        let throughput = self.throughput()?;
+        Some(Duration::from_secs_f64(self.remaining_bytes as f64 / throughput))
+    }
+}
+
+/// Renders as e.g. `120 files (1.20 GB) left at 2.50 MB/s, ETA 8m 00s`
+impl fmt::Display for UploadProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files ({}) left",
+            self.remaining_files,
+            format_bytes(self.remaining_bytes)
+        )?;
+        if let Some(throughput) = self.throughput() {
+            write!(f, " at {}/s", format_bytes(throughput as u64))?;
+        }
+        if let Some(eta) = self.eta() {
+            write!(f, ", ETA {}", format_duration(eta))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum StorageBackendKindError {
+    #[error("Invalid storage backend '{0}', expected logos, kubo, s3 or local")]
+    InvalidBackend(String),
+}
+
+/// Network extracts are published to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StorageBackendKind {
+    /// Logos Storage node embedded through storage-bindings
+    #[default]
+    Logos,
+    /// IPFS node reached through the Kubo RPC API
+    Kubo,
+    /// S3-compatible bucket, objects keyed by the SHA-256 of their content
+    S3,
+    /// Content-addressed directory tree in STORAGE_DATA_DIR, for offline runs
+    Local,
+}
+
+impl FromStr for StorageBackendKind {
+    type Err = StorageBackendKindError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "" | "logos" => Ok(Self::Logos),
+            "kubo" | "ipfs" => Ok(Self::Kubo),
+            "s3" => Ok(Self::S3),
+            "local" => Ok(Self::Local),
+            _ => Err(StorageBackendKindError::InvalidBackend(value.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for StorageBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Logos => write!(f, "logos"),
+            Self::Kubo => write!(f, "kubo"),
+            Self::S3 => write!(f, "s3"),
+            Self::Local => write!(f, "local"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upload_concurrency_backs_off_and_recovers_within_bounds() {
+        let fast = (true, Duration::from_secs(1));
+        let slow = (true, Duration::from_secs(300));
+        let failed = (false, Duration::from_secs(1));
+        let mut concurrency = UploadConcurrency::new(2, 8, Duration::from_secs(120));
+        assert_eq!(concurrency.limit(), 8);
+
+        assert_eq!(concurrency.record_batch(&[fast; 8]), None);
+        assert_eq!(concurrency.record_batch(&[fast, failed]), Some(4));
+        assert_eq!(concurrency.record_batch(&[slow, fast, fast, fast]), Some(2));
+        assert_eq!(concurrency.record_batch(&[failed; 2]), None);
+        assert_eq!(concurrency.limit(), 2);
+
+        // Only batches filling the limit raise it
+        assert_eq!(concurrency.record_batch(&[fast]), None);
+        assert_eq!(concurrency.record_batch(&[fast; 2]), Some(3));
+        for _ in 0..10 {
+            concurrency.record_batch(&[fast; 10]);
+        }
+        assert_eq!(concurrency.limit(), 8);
+    }
+
+    #[test]
+    fn estimates_nothing_before_the_first_upload() {
+        let mut progress = UploadProgress::new();
+        progress.start_at(10, 1000, Instant::now());
+
+        assert_eq!(progress.throughput(), None);
+        assert_eq!(progress.eta(), None);
+        assert_eq!(progress.to_string(), "10 files (1000 B) left");
+    }
+
+    #[test]
+    fn estimates_eta_from_throughput() {
+        let start = Instant::now();
+        let mut progress = UploadProgress::new();
+        progress.start_at(10, 1000, start);
+
+        progress.record_uploaded_at(100, start + Duration::from_secs(10));
+
+        assert_eq!(progress.remaining_files(), 9);
+        assert_eq!(progress.remaining_bytes(), 900);
+        assert_eq!(progress.throughput(), Some(10.0));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(90)));
+        assert_eq!(progress.to_string(), "9 files (900 B) left at 10 B/s, ETA 1m 30s");
+    }
+
+    #[test]
+    fn skipped_files_shorten_eta_without_adding_throughput() {
+        let start = Instant::now();
+        let mut progress = UploadProgress::new();
+        progress.start_at(3, 300, start);
+
+        progress.record_uploaded_at(100, start + Duration::from_secs(10));
+        progress.record_skipped(100);
+
+        assert_eq!(progress.remaining_files(), 1);
+        assert_eq!(progress.throughput(), Some(10.0));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn averages_throughput_over_the_recent_window() {
+        let start = Instant::now();
+        let mut progress = UploadProgress::new();
+        progress.start_at(100, 100_000, start);
+
+        // A slow start followed by a fast window: only the last five minutes count
+        progress.record_uploaded_at(100, start + Duration::from_secs(600));
+        for minute in 1..=10 {
+            progress.record_uploaded_at(6000, start + Duration::from_secs(600 + minute * 60));
+        }
+
+        let throughput = progress.throughput().unwrap();
+        assert!((throughput - 100.0).abs() < 1.0, "throughput {}", throughput);
+    }
+
+    #[test]
+    fn no_eta_once_everything_is_done() {
+        let start = Instant::now();
+        let mut progress = UploadProgress::new();
+        progress.start_at(1, 100, start);
+
+        progress.record_uploaded_at(100, start + Duration::from_secs(1));
+
+        assert_eq!(progress.remaining_files(), 0);
+        assert_eq!(progress.eta(), None);
+    }
+}
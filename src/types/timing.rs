@@ -0,0 +1,165 @@
+use crate::utils::format_duration;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Wall-clock time a run spent in each phase. Phases that did not run are left out, as
+/// are countries a phase did not process one by one, e.g. areas selected by ID.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PhaseTimings {
+    #[serde(
+        rename = "download_secs",
+        serialize_with = "serialize_secs",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub download: Option<Duration>,
+    #[serde(
+        rename = "decompress_secs",
+        serialize_with = "serialize_secs",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub decompress: Option<Duration>,
+    #[serde(
+        rename = "extraction_secs",
+        serialize_with = "serialize_secs",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub extraction: Option<Duration>,
+    #[serde(
+        rename = "upload_secs",
+        serialize_with = "serialize_secs",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub upload: Option<Duration>,
+    #[serde(
+        rename = "extraction_secs_by_country",
+        serialize_with = "serialize_secs_by_country",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub extraction_by_country: BTreeMap<String, Duration>,
+    #[serde(
+        rename = "upload_secs_by_country",
+        serialize_with = "serialize_secs_by_country",
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub upload_by_country: BTreeMap<String, Duration>,
+}
+
+impl PhaseTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `duration` to the country's extraction time, countries can be extracted in parts
+    pub fn record_country_extraction(&mut self, country_code: &str, duration: Duration) {
+        *self
+            .extraction_by_country
+            .entry(country_code.to_string())
+            .or_default() += duration;
+    }
+
+    /// Add `duration` to the country's upload time
+    pub fn record_country_upload(&mut self, country_code: &str, duration: Duration) {
+        *self
+            .upload_by_country
+            .entry(country_code.to_string())
+            .or_default() += duration;
+    }
+
+    /// Summary lines for the final statistics, the slowest countries first
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (name, duration) in [
+            ("Download", self.download),
+            ("Decompress", self.decompress),
+            ("Extraction", self.extraction),
+            ("Upload", self.upload),
+        ] {
+            if let Some(duration) = duration {
+                lines.push(format!("{}: {}", name, format_duration(duration)));
+            }
+        }
+        for (name, countries) in [
+            ("Extraction", &self.extraction_by_country),
+            ("Upload", &self.upload_by_country),
+        ] {
+            if countries.is_empty() {
+                continue;
+            }
+            let mut countries: Vec<_> = countries.iter().collect();
+            countries.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+            let countries: Vec<String> = countries
+                .into_iter()
+                .map(|(code, duration)| format!("{} {}", code, format_duration(*duration)))
+                .collect();
+            lines.push(format!("{} by country: {}", name, countries.join(", ")));
+        }
+        lines
+    }
+}
+
+fn serialize_secs<S: Serializer>(
+    duration: &Option<Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match duration {
+        Some(duration) => serializer.serialize_f64(duration.as_secs_f64()),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn serialize_secs_by_country<S: Serializer>(
+    countries: &BTreeMap<String, Duration>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(
+        countries
+            .iter()
+            .map(|(code, duration)| (code, duration.as_secs_f64())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn country_durations_accumulate() {
+        let mut timings = PhaseTimings::new();
+        timings.record_country_extraction("FR", Duration::from_secs(30));
+        timings.record_country_extraction("FR", Duration::from_secs(15));
+        timings.record_country_upload("DE", Duration::from_secs(5));
+
+        assert_eq!(timings.extraction_by_country["FR"], Duration::from_secs(45));
+        assert_eq!(timings.upload_by_country["DE"], Duration::from_secs(5));
+    }
+
+    #[test]
+    fn serializes_seconds_and_skips_phases_that_did_not_run() {
+        let mut timings = PhaseTimings::new();
+        timings.upload = Some(Duration::from_millis(1500));
+        timings.record_country_upload("FR", Duration::from_secs(2));
+        let json = serde_json::to_value(&timings).unwrap();
+
+        assert_eq!(json["upload_secs"], 1.5);
+        assert_eq!(json["upload_secs_by_country"]["FR"], 2.0);
+        assert!(json.get("download_secs").is_none());
+        assert!(json.get("extraction_secs_by_country").is_none());
+    }
+
+    #[test]
+    fn summary_lists_slowest_countries_first() {
+        let mut timings = PhaseTimings::new();
+        timings.extraction = Some(Duration::from_secs(200));
+        timings.record_country_extraction("DE", Duration::from_secs(50));
+        timings.record_country_extraction("FR", Duration::from_secs(150));
+
+        assert_eq!(
+            timings.summary(),
+            [
+                "Extraction: 3m 20s",
+                "Extraction by country: FR 2m 30s, DE 50s"
+            ]
+        );
+    }
+}
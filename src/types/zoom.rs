@@ -0,0 +1,131 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Deepest zoom level a profile may ask for
+const MAX_ZOOM: u8 = 24;
+
+#[derive(Debug, Error)]
+pub enum ZoomProfileError {
+    #[error("Invalid zoom profile '{0}', expected placetype:min-max or placetype:max such as region:0-10")]
+    InvalidProfile(String),
+    #[error("Invalid zoom range '{0}', expected levels from 0 to 24 with min <= max")]
+    InvalidRange(String),
+}
+
+/// Zoom levels kept in an extract, both included
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZoomRange {
+    pub min: u8,
+    pub max: u8,
+}
+
+impl FromStr for ZoomRange {
+    type Err = ZoomProfileError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let invalid = || ZoomProfileError::InvalidRange(value.to_string());
+        let (min, max) = match value.split_once('-') {
+            Some((min, max)) => (min.trim(), max.trim()),
+            None => ("0", value.trim()),
+        };
+        let min = min.parse::<u8>().map_err(|_| invalid())?;
+        let max = max.parse::<u8>().map_err(|_| invalid())?;
+        if min > max || max > MAX_ZOOM {
+            return Err(invalid());
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl fmt::Display for ZoomRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.min, self.max)
+    }
+}
+
+/// Zoom range extracted per placetype, e.g. shallow regions and detailed localities.
+/// Placetypes without a profile keep every zoom level of the planet.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ZoomProfiles(BTreeMap<String, ZoomRange>);
+
+impl ZoomProfiles {
+    pub fn for_placetype(&self, placetype: &str) -> Option<ZoomRange> {
+        self.0.get(placetype).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for ZoomProfiles {
+    type Err = ZoomProfileError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|profile| !profile.is_empty())
+            .map(|profile| {
+                let (placetype, range) = profile
+                    .split_once(':')
+                    .filter(|(placetype, _)| !placetype.trim().is_empty())
+                    .ok_or_else(|| ZoomProfileError::InvalidProfile(profile.to_string()))?;
+                Ok((placetype.trim().to_ascii_lowercase(), range.parse()?))
+            })
+            .collect::<Result<BTreeMap<_, _>, _>>()
+            .map(Self)
+    }
+}
+
+impl fmt::Display for ZoomProfiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let profiles: Vec<_> = self
+            .0
+            .iter()
+            .map(|(placetype, range)| format!("{}:{}", placetype, range))
+            .collect();
+        write!(f, "{}", profiles.join(","))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_profiles_per_placetype() {
+        let profiles: ZoomProfiles = "region:0-10, County:12,locality:4-14".parse().unwrap();
+
+        assert_eq!(
+            profiles.for_placetype("region"),
+            Some(ZoomRange { min: 0, max: 10 })
+        );
+        assert_eq!(
+            profiles.for_placetype("county"),
+            Some(ZoomRange { min: 0, max: 12 })
+        );
+        assert_eq!(profiles.for_placetype("country"), None);
+        assert_eq!(
+            profiles.to_string(),
+            "county:0-12,locality:4-14,region:0-10"
+        );
+        assert!("".parse::<ZoomProfiles>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!(matches!(
+            "region".parse::<ZoomProfiles>(),
+            Err(ZoomProfileError::InvalidProfile(_))
+        ));
+        assert!(matches!(
+            "region:10-4".parse::<ZoomProfiles>(),
+            Err(ZoomProfileError::InvalidRange(_))
+        ));
+        assert!("region:0-30".parse::<ZoomProfiles>().is_err());
+        assert!(":0-10".parse::<ZoomProfiles>().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use std::fmt;
 use std::path::Path;
 use std::process::Command;
 use thiserror::Error;
@@ -11,6 +12,82 @@ pub enum CmdError {
     IoError(#[from] std::io::Error),
     #[error("Command exited with non-zero status: {0}")]
     NonZeroExit(i32),
+    #[error("{tool} {found} is too old, {required} or later is required. {upgrade}")]
+    UnsupportedVersion {
+        tool: String,
+        found: ToolVersion,
+        required: ToolVersion,
+        upgrade: &'static str,
+    },
+}
+
+/// Oldest go-pmtiles release taking every flag AnyNode passes to `pmtiles extract`,
+/// `--region`, `--minzoom` and `--maxzoom` included
+pub const MIN_PMTILES_VERSION: ToolVersion = ToolVersion::new(1, 11, 0);
+
+/// Version of an external tool as `major.minor.patch`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ToolVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ToolVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    /// First version number in a tool's output, such as `1.22.1` in
+    /// `pmtiles 1.22.1, commit 2f3ea2a, built at 2024-10-01`. A missing patch is read as 0.
+    pub fn parse(output: &str) -> Option<Self> {
+        output
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .map(|word| word.trim_start_matches('v'))
+            .find_map(|word| {
+                let mut numbers = word.split('.').map(|n| n.parse::<u32>().ok());
+                let major = numbers.next()??;
+                let minor = numbers.next()??;
+                let patch = match numbers.next() {
+                    Some(patch) => patch?,
+                    None => 0,
+                };
+                numbers
+                    .next()
+                    .is_none()
+                    .then_some(Self::new(major, minor, patch))
+            })
+    }
+}
+
+impl fmt::Display for ToolVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+/// Check `pmtiles version` reports at least `MIN_PMTILES_VERSION`. Returns the version,
+/// `None` when the tool prints none, as development builds do.
+pub async fn ensure_pmtiles_version(pmtiles_cmd: &str) -> Result<Option<ToolVersion>, CmdError> {
+    let output = run_command(pmtiles_cmd, &["version"], None).await?;
+    let Some(version) = ToolVersion::parse(&format!("{} {}", output.stdout, output.stderr)) else {
+        return Ok(None);
+    };
+
+    if version < MIN_PMTILES_VERSION {
+        return Err(CmdError::UnsupportedVersion {
+            tool: pmtiles_cmd.to_string(),
+            found: version,
+            required: MIN_PMTILES_VERSION,
+            upgrade:
+                "Download a newer release from https://github.com/protomaps/go-pmtiles/releases",
+        });
+    }
+    Ok(Some(version))
 }
 
 pub async fn is_tool_available(tool: &str) -> bool {
@@ -69,3 +146,24 @@ pub async fn run_command(
 
     Ok(CommandOutput { stdout, stderr })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tool_versions() {
+        assert_eq!(
+            ToolVersion::parse("pmtiles 1.22.1, commit 2f3ea2a, built at 2024-10-01T12:00:00Z"),
+            Some(ToolVersion::new(1, 22, 1))
+        );
+        assert_eq!(
+            ToolVersion::parse("pmtiles v1.9"),
+            Some(ToolVersion::new(1, 9, 0))
+        );
+        assert_eq!(ToolVersion::parse("pmtiles dev, commit none"), None);
+        assert!(ToolVersion::new(1, 9, 0) < MIN_PMTILES_VERSION);
+        assert!(ToolVersion::new(1, 22, 1) > MIN_PMTILES_VERSION);
+        assert_eq!(MIN_PMTILES_VERSION.to_string(), "1.11.0");
+    }
+}
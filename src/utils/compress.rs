@@ -0,0 +1,210 @@
+use crate::types::Compression;
+use crate::utils::{run_command, CmdError};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Error, Debug)]
+pub enum CompressError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("Compression command failed: {0}")]
+    CommandFailed(#[from] CmdError),
+}
+
+/// Path of the compressed copy of `source`, `<source>.<ext>`, or `None` when uncompressed
+pub fn compressed_path(source: &Path, compression: Compression) -> Option<PathBuf> {
+    let extension = compression.extension()?;
+    let mut name = source.file_name()?.to_os_string();
+    name.push(".");
+    name.push(extension);
+    Some(source.with_file_name(name))
+}
+
+/// Write a compressed copy of `source` next to it and return its path. Gzip runs in
+/// process, zstd through `zstd_cmd`. Returns `source` itself when uncompressed.
+pub async fn compress_file(
+    source: &Path,
+    compression: Compression,
+    zstd_cmd: &str,
+) -> Result<PathBuf, CompressError> {
+    let Some(destination) = compressed_path(source, compression) else {
+        return Ok(source.to_path_buf());
+    };
+
+    let result = match compression {
+        Compression::None => Ok(()),
+        Compression::Gzip => gzip_file(source, &destination).await,
+        Compression::Zstd => {
+            let (source, destination) = (source.to_string_lossy(), destination.to_string_lossy());
+            run_command(zstd_cmd, &["-q", "-f", "-o", &destination, &source], None)
+                .await
+                .map(|_| ())
+                .map_err(CompressError::from)
+        }
+    };
+
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&destination).await;
+        return Err(e);
+    }
+    Ok(destination)
+}
+
+/// Codec of a file, told by its leading magic bytes
+pub fn detect_compression(path: &Path) -> std::io::Result<Compression> {
+    let mut magic = Vec::with_capacity(ZSTD_MAGIC.len());
+    std::fs::File::open(path)?
+        .take(ZSTD_MAGIC.len() as u64)
+        .read_to_end(&mut magic)?;
+
+    Ok(if magic.starts_with(&GZIP_MAGIC) {
+        Compression::Gzip
+    } else if magic.starts_with(&ZSTD_MAGIC) {
+        Compression::Zstd
+    } else {
+        Compression::None
+    })
+}
+
+/// Write the decompressed content of `source` to `destination`. Gzip runs in process,
+/// zstd through `zstd_cmd`. Uncompressed content is copied as is.
+pub async fn decompress_file(
+    source: &Path,
+    destination: &Path,
+    compression: Compression,
+    zstd_cmd: &str,
+) -> Result<(), CompressError> {
+    let result = match compression {
+        Compression::None => tokio::fs::copy(source, destination)
+            .await
+            .map(|_| ())
+            .map_err(CompressError::from),
+        Compression::Gzip => gunzip_file(source, destination).await,
+        Compression::Zstd => {
+            let (source, destination) = (source.to_string_lossy(), destination.to_string_lossy());
+            run_command(
+                zstd_cmd,
+                &["-q", "-d", "-f", "-o", &destination, &source],
+                None,
+            )
+            .await
+            .map(|_| ())
+            .map_err(CompressError::from)
+        }
+    };
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(destination).await;
+    }
+    result
+}
+
+async fn gunzip_file(source: &Path, destination: &Path) -> Result<(), CompressError> {
+    let source = source.to_path_buf();
+    let destination = destination.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let input = std::io::BufReader::new(std::fs::File::open(&source)?);
+        let mut decoder = flate2::read::GzDecoder::new(input);
+        let mut output = std::io::BufWriter::new(std::fs::File::create(&destination)?);
+        std::io::copy(&mut decoder, &mut output)?;
+        output.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+async fn gzip_file(source: &Path, destination: &Path) -> Result<(), CompressError> {
+    let source = source.to_path_buf();
+    let destination = destination.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        let mut input = std::io::BufReader::new(std::fs::File::open(&source)?);
+        let output = std::io::BufWriter::new(std::fs::File::create(&destination)?);
+        let mut encoder = flate2::write::GzEncoder::new(output, flate2::Compression::best());
+        std::io::copy(&mut input, &mut encoder)?;
+        encoder.finish()?.into_inner().map_err(|e| e.into_error())?;
+        Ok(())
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_copies_are_named_after_the_source() {
+        let source = Path::new("/areas/FR/12.pmtiles");
+        assert_eq!(
+            compressed_path(source, Compression::Gzip).unwrap(),
+            Path::new("/areas/FR/12.pmtiles.gz")
+        );
+        assert_eq!(
+            compressed_path(source, Compression::Zstd).unwrap(),
+            Path::new("/areas/FR/12.pmtiles.zst")
+        );
+        assert!(compressed_path(source, Compression::None).is_none());
+    }
+
+    #[tokio::test]
+    async fn gzip_copy_decompresses_to_the_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("1.pmtiles");
+        let content = b"PMTiles".repeat(1000);
+        std::fs::write(&source, &content).unwrap();
+
+        let compressed = compress_file(&source, Compression::Gzip, "zstd").await.unwrap();
+        assert_eq!(compressed, dir.path().join("1.pmtiles.gz"));
+        assert!(std::fs::metadata(&compressed).unwrap().len() < content.len() as u64);
+
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&compressed).unwrap())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, content);
+    }
+
+    #[tokio::test]
+    async fn gzip_copy_is_detected_and_decompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("cids.db");
+        std::fs::write(&source, b"SQLite format 3").unwrap();
+        assert_eq!(detect_compression(&source).unwrap(), Compression::None);
+
+        let compressed = compress_file(&source, Compression::Gzip, "zstd")
+            .await
+            .unwrap();
+        assert_eq!(detect_compression(&compressed).unwrap(), Compression::Gzip);
+
+        let restored = dir.path().join("restored.db");
+        decompress_file(&compressed, &restored, Compression::Gzip, "zstd")
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read(&restored).unwrap(), b"SQLite format 3");
+    }
+
+    #[tokio::test]
+    async fn uncompressed_upload_uses_the_source() {
+        let source = Path::new("/areas/FR/12.pmtiles");
+        let path = compress_file(source, Compression::None, "zstd").await.unwrap();
+        assert_eq!(path, source);
+    }
+
+    #[tokio::test]
+    async fn failed_zstd_command_leaves_no_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("1.pmtiles");
+        std::fs::write(&source, b"PMTiles").unwrap();
+
+        let result = compress_file(&source, Compression::Zstd, "false").await;
+        assert!(result.is_err());
+        assert!(!dir.path().join("1.pmtiles.zst").exists());
+    }
+}
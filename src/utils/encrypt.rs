@@ -0,0 +1,337 @@
+use hmac::{Hmac, Mac};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Plaintext bytes sealed per chunk. Each chunk is followed by its 16-byte tag.
+pub const ENCRYPTION_CHUNK_SIZE: usize = 1024 * 1024;
+
+const TAG_LEN: usize = 16;
+const NONCE_PREFIX_LEN: usize = NONCE_LEN - 4;
+
+#[derive(Error, Debug)]
+pub enum EncryptError {
+    #[error("Invalid encryption key, expected 64 hex characters (32 bytes)")]
+    InvalidKey,
+    #[error("Content was encrypted with key {expected}, the configured key is {actual}")]
+    WrongKey { expected: String, actual: String },
+    #[error("Encrypted content is corrupt or truncated")]
+    Corrupt,
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// AES-256-GCM key for private deployments. Only its ID, a hash prefix, is ever stored.
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// Short fingerprint recorded with each upload, to tell which key decrypts it
+    pub fn id(&self) -> String {
+        hex::encode(&Sha256::digest(self.0)[..8])
+    }
+
+    /// Encryption parameters of the content identified by `content_id`. The nonce prefix
+    /// is derived from the key and the content, so identical extracts encrypt to identical
+    /// ciphertext and keep deduplicating, while distinct extracts never share nonces.
+    pub fn info_for(&self, content_id: &str) -> EncryptionInfo {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts any key size");
+        mac.update(b"anynode-nonce:");
+        mac.update(content_id.as_bytes());
+        let digest = mac.finalize().into_bytes();
+
+        EncryptionInfo {
+            key_id: self.id(),
+            nonce_prefix: hex::encode(&digest[..NONCE_PREFIX_LEN]),
+        }
+    }
+
+    fn sealing_key(&self) -> LessSafeKey {
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &self.0).expect("32-byte AES-256 key"))
+    }
+}
+
+impl FromStr for EncryptionKey {
+    type Err = EncryptError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let bytes = hex::decode(value.trim()).map_err(|_| EncryptError::InvalidKey)?;
+        let key = bytes.try_into().map_err(|_| EncryptError::InvalidKey)?;
+        Ok(Self(key))
+    }
+}
+
+impl fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptionKey({})", self.id())
+    }
+}
+
+/// What a client needs besides the key to decrypt an upload: the key it was sealed with
+/// and the nonce prefix. Chunk `n` uses the prefix followed by `n` as a big-endian u32, and
+/// the last chunk is sealed with the additional data `[1]`, every other one with `[0]`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionInfo {
+    pub key_id: String,
+    pub nonce_prefix: String,
+}
+
+/// Path of the encrypted copy of `source`, `<source>.enc`
+pub fn encrypted_path(source: &Path) -> PathBuf {
+    let mut name = source.file_name().unwrap_or_default().to_os_string();
+    name.push(".enc");
+    source.with_file_name(name)
+}
+
+/// Write an encrypted copy of `source` next to it, see `EncryptionInfo` for the format
+pub async fn encrypt_file(
+    source: &Path,
+    key: &EncryptionKey,
+    content_id: &str,
+) -> Result<(PathBuf, EncryptionInfo), EncryptError> {
+    let info = key.info_for(content_id);
+    let destination = encrypted_path(source);
+
+    let result = {
+        let (source, destination) = (source.to_path_buf(), destination.clone());
+        let (key, info) = (key.clone(), info.clone());
+        tokio::task::spawn_blocking(move || {
+            let input = std::io::BufReader::new(std::fs::File::open(&source)?);
+            let output = std::io::BufWriter::new(std::fs::File::create(&destination)?);
+            seal_stream(input, output, &key, &info)
+        })
+        .await
+        .map_err(std::io::Error::other)?
+    };
+
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&destination).await;
+        return Err(e);
+    }
+    Ok((destination, info))
+}
+
+/// Decrypt `source` into `destination`
+pub async fn decrypt_file(
+    source: &Path,
+    destination: &Path,
+    key: &EncryptionKey,
+    info: &EncryptionInfo,
+) -> Result<(), EncryptError> {
+    if info.key_id != key.id() {
+        return Err(EncryptError::WrongKey {
+            expected: info.key_id.clone(),
+            actual: key.id(),
+        });
+    }
+
+    let (source, destination) = (source.to_path_buf(), destination.to_path_buf());
+    let (key, info) = (key.clone(), info.clone());
+    tokio::task::spawn_blocking(move || {
+        let input = std::io::BufReader::new(std::fs::File::open(&source)?);
+        let output = std::io::BufWriter::new(std::fs::File::create(&destination)?);
+        open_stream(input, output, &key, &info)
+    })
+    .await
+    .map_err(std::io::Error::other)?
+}
+
+fn seal_stream(
+    mut input: impl Read,
+    mut output: impl Write,
+    key: &EncryptionKey,
+    info: &EncryptionInfo,
+) -> Result<(), EncryptError> {
+    let sealing_key = key.sealing_key();
+    let prefix = nonce_prefix(info)?;
+
+    // One chunk of lookahead tells whether the current chunk is the last
+    let mut chunk = vec![0; ENCRYPTION_CHUNK_SIZE];
+    let mut next = vec![0; ENCRYPTION_CHUNK_SIZE];
+    let mut len = read_full(&mut input, &mut chunk)?;
+    let mut counter = 0u32;
+    loop {
+        let next_len = if len == ENCRYPTION_CHUNK_SIZE {
+            read_full(&mut input, &mut next)?
+        } else {
+            0
+        };
+        let last = next_len == 0;
+
+        let mut sealed = chunk[..len].to_vec();
+        sealing_key
+            .seal_in_place_append_tag(chunk_nonce(&prefix, counter), chunk_aad(last), &mut sealed)
+            .map_err(|_| EncryptError::Corrupt)?;
+        output.write_all(&sealed)?;
+
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+        counter = counter.checked_add(1).ok_or(EncryptError::Corrupt)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+fn open_stream(
+    mut input: impl Read,
+    mut output: impl Write,
+    key: &EncryptionKey,
+    info: &EncryptionInfo,
+) -> Result<(), EncryptError> {
+    let opening_key = key.sealing_key();
+    let prefix = nonce_prefix(info)?;
+
+    let sealed_size = ENCRYPTION_CHUNK_SIZE + TAG_LEN;
+    let mut chunk = vec![0; sealed_size];
+    let mut next = vec![0; sealed_size];
+    let mut len = read_full(&mut input, &mut chunk)?;
+    let mut counter = 0u32;
+    loop {
+        let next_len = if len == sealed_size {
+            read_full(&mut input, &mut next)?
+        } else {
+            0
+        };
+        let last = next_len == 0;
+
+        let plaintext = opening_key
+            .open_in_place(chunk_nonce(&prefix, counter), chunk_aad(last), &mut chunk[..len])
+            .map_err(|_| EncryptError::Corrupt)?;
+        output.write_all(plaintext)?;
+
+        if last {
+            break;
+        }
+        std::mem::swap(&mut chunk, &mut next);
+        len = next_len;
+        counter = counter.checked_add(1).ok_or(EncryptError::Corrupt)?;
+    }
+
+    output.flush()?;
+    Ok(())
+}
+
+fn nonce_prefix(info: &EncryptionInfo) -> Result<[u8; NONCE_PREFIX_LEN], EncryptError> {
+    hex::decode(&info.nonce_prefix)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or(EncryptError::Corrupt)
+}
+
+fn chunk_nonce(prefix: &[u8; NONCE_PREFIX_LEN], counter: u32) -> Nonce {
+    let mut nonce = [0; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..].copy_from_slice(&counter.to_be_bytes());
+    Nonce::assume_unique_for_key(nonce)
+}
+
+fn chunk_aad(last: bool) -> Aad<[u8; 1]> {
+    Aad::from([last as u8])
+}
+
+/// Fill `buf` as far as the reader allows, returning fewer bytes only at the end
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> EncryptionKey {
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+            .parse()
+            .unwrap()
+    }
+
+    fn round_trip(plaintext: &[u8]) -> Vec<u8> {
+        let info = key().info_for("content");
+        let mut sealed = Vec::new();
+        seal_stream(plaintext, &mut sealed, &key(), &info).unwrap();
+        let chunks = plaintext.len().div_ceil(ENCRYPTION_CHUNK_SIZE).max(1);
+        assert_eq!(sealed.len(), plaintext.len() + chunks * TAG_LEN);
+
+        let mut opened = Vec::new();
+        open_stream(sealed.as_slice(), &mut opened, &key(), &info).unwrap();
+        opened
+    }
+
+    #[test]
+    fn round_trips_across_chunk_boundaries() {
+        let chunk = ENCRYPTION_CHUNK_SIZE;
+        for len in [0, 1, chunk, chunk + 1, 2 * chunk] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            assert_eq!(round_trip(&plaintext), plaintext, "length {}", len);
+        }
+    }
+
+    #[test]
+    fn truncated_or_tampered_content_is_rejected() {
+        let info = key().info_for("content");
+        let plaintext = vec![7; ENCRYPTION_CHUNK_SIZE + 10];
+        let mut sealed = Vec::new();
+        seal_stream(plaintext.as_slice(), &mut sealed, &key(), &info).unwrap();
+
+        // Dropping the last chunk leaves a first chunk that was not sealed as the last one
+        let truncated = &sealed[..ENCRYPTION_CHUNK_SIZE + TAG_LEN];
+        let result = open_stream(truncated, &mut Vec::new(), &key(), &info);
+        assert!(matches!(result, Err(EncryptError::Corrupt)));
+
+        let mut tampered = sealed.clone();
+        tampered[5] ^= 1;
+        let result = open_stream(tampered.as_slice(), &mut Vec::new(), &key(), &info);
+        assert!(matches!(result, Err(EncryptError::Corrupt)));
+    }
+
+    #[test]
+    fn nonces_depend_on_content_and_key() {
+        let other_key: EncryptionKey = "ff".repeat(32).parse().unwrap();
+        assert_eq!(key().info_for("a"), key().info_for("a"));
+        assert_ne!(key().info_for("a").nonce_prefix, key().info_for("b").nonce_prefix);
+        assert_ne!(key().info_for("a").nonce_prefix, other_key.info_for("a").nonce_prefix);
+        assert_ne!(key().id(), other_key.id());
+    }
+
+    #[test]
+    fn keys_must_be_32_hex_bytes() {
+        assert!("00".repeat(31).parse::<EncryptionKey>().is_err());
+        assert!("zz".repeat(32).parse::<EncryptionKey>().is_err());
+        assert!(format!(" {} ", "ab".repeat(32)).parse::<EncryptionKey>().is_ok());
+    }
+
+    #[tokio::test]
+    async fn decrypting_with_another_key_fails_early() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("1.pmtiles");
+        std::fs::write(&source, b"PMTiles").unwrap();
+
+        let (encrypted, info) = encrypt_file(&source, &key(), "content").await.unwrap();
+        assert_eq!(encrypted, dir.path().join("1.pmtiles.enc"));
+
+        let other_key: EncryptionKey = "ff".repeat(32).parse().unwrap();
+        let output = dir.path().join("out");
+        let result = decrypt_file(&encrypted, &output, &other_key, &info).await;
+        assert!(matches!(result, Err(EncryptError::WrongKey { .. })));
+
+        decrypt_file(&encrypted, &output, &key(), &info).await.unwrap();
+        assert_eq!(std::fs::read(&output).unwrap(), b"PMTiles");
+    }
+}
@@ -1,17 +1,27 @@
 use futures::StreamExt;
 use indicatif::{ProgressBar, ProgressStyle};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION};
+use std::fmt;
+use std::io::SeekFrom;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use thiserror::Error;
 use tokio::fs::{File, OpenOptions};
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
 use tracing::{info, warn};
 
+use super::format_bytes;
+use super::throttle::RateLimiter;
+
 #[derive(Error, Debug)]
 pub enum FileError {
     #[error("Download failed: {0}")]
     DownloadFailed(String),
     #[error("IO error: {0}")]
     IoError(String),
+    #[error("Invalid header '{0}', expected Name: value")]
+    InvalidHeader(String),
     #[error("Reqwest error: {0}")]
     ReqwestError(#[from] reqwest::Error),
     #[error("Tokio IO error: {0}")]
@@ -20,21 +30,148 @@ pub enum FileError {
 
 const MAX_RETRIES: u32 = 5;
 const RETRY_DELAY_SECS: u64 = 5;
+/// Files smaller than this are downloaded over a single connection
+const MIN_SEGMENTED_SIZE: u64 = 64 * 1024 * 1024;
+/// Bytes a segment downloads between saves of the segmented download's progress
+const SEGMENT_SAVE_INTERVAL: u64 = 64 * 1024 * 1024;
+
+/// HTTP client for outbound requests, sending `headers` with each of them. Without an
+/// explicit proxy the HTTP_PROXY, HTTPS_PROXY and NO_PROXY variables apply, with one every
+/// request goes through it except to the hosts listed in NO_PROXY.
+pub fn http_client(
+    proxy_url: Option<&str>,
+    headers: &HeaderMap,
+) -> Result<reqwest::Client, FileError> {
+    let builder = reqwest::Client::builder().default_headers(headers.clone());
+    let builder = match proxy_url {
+        Some(url) => {
+            builder.proxy(reqwest::Proxy::all(url)?.no_proxy(reqwest::NoProxy::from_env()))
+        }
+        None => builder,
+    };
+    Ok(builder.build()?)
+}
+
+/// Parse `Name: value` headers separated by `;`. Values are marked sensitive so tokens they
+/// carry stay out of debug output.
+pub fn parse_headers(value: &str) -> Result<HeaderMap, FileError> {
+    let mut headers = HeaderMap::new();
+    for header in value.split(';').map(str::trim).filter(|h| !h.is_empty()) {
+        let invalid = || FileError::InvalidHeader(header.to_string());
+        let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+        let name = HeaderName::from_bytes(name.trim().as_bytes()).map_err(|_| invalid())?;
+        let mut value = HeaderValue::from_str(value.trim()).map_err(|_| invalid())?;
+        value.set_sensitive(true);
+        headers.append(name, value);
+    }
+    Ok(headers)
+}
+
+/// `Authorization` header carrying a bearer token
+pub fn bearer_header(token: &str) -> Result<(HeaderName, HeaderValue), FileError> {
+    let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+        .map_err(|_| FileError::InvalidHeader("Authorization: Bearer <token>".to_string()))?;
+    value.set_sensitive(true);
+    Ok((AUTHORIZATION, value))
+}
 
-/// Download a file with progress reporting, retry logic, and resume support.
+/// Download a file with progress reporting, retry logic, resume support and mirror failover.
 /// Downloads to a `.part` temporary file and only renames to final destination when complete.
-/// If a `.part` file exists, it will attempt to resume the download using HTTP Range headers.
-pub async fn download_file_with_progress(url: &str, destination: &Path) -> Result<(), FileError> {
-    let client = reqwest::Client::new();
+/// Mirrors are tried in order, moving to the next one once retries are exhausted. The mirror
+/// that produced a `.part` file is remembered so that a later attempt resumes against it using
+/// HTTP Range headers instead of starting over, one left without that record resumes against
+/// the first mirror. When a rate limiter is given the transfer is paced to stay within it.
+/// With more than one connection a large file is split into byte ranges downloaded in
+/// parallel, when the mirror supports ranges.
+pub async fn download_file_with_progress(
+    client: &reqwest::Client,
+    mirrors: &[String],
+    destination: &Path,
+    rate_limiter: Option<&RateLimiter>,
+    connections: usize,
+) -> Result<(), FileError> {
     let temp_path = get_temp_path(destination);
+    let marker_path = get_mirror_marker_path(&temp_path);
+    let segments_path = get_segments_path(&temp_path);
 
-    for attempt in 1..=MAX_RETRIES {
-        match download_attempt(&client, url, &temp_path).await {
+    let mut last_error = FileError::DownloadFailed("No download URL configured".to_string());
+
+    for url in order_mirrors(mirrors, &marker_path).await {
+        // A partial file can only be resumed against the mirror that produced it. One without
+        // a marker predates mirror failover and came from the primary URL.
+        let partial_mirror = match tokio::fs::read_to_string(&marker_path).await {
+            Ok(mirror) => Some(mirror),
+            Err(_) => mirrors.first().cloned(),
+        };
+        if partial_mirror.as_deref() != Some(url.as_str()) {
+            let _ = tokio::fs::remove_file(&temp_path).await;
+            let _ = tokio::fs::remove_file(&segments_path).await;
+        }
+
+        let plan = plan_segments(client, &url, &temp_path, &segments_path, connections).await;
+        let result = match plan {
+            Some(download) => {
+                download_segmented(
+                    client,
+                    &url,
+                    &temp_path,
+                    &marker_path,
+                    &segments_path,
+                    download,
+                    rate_limiter,
+                )
+                .await
+            }
+            None => {
+                download_from_mirror(client, &url, &temp_path, &marker_path, rate_limiter).await
+            }
+        };
+        match result {
             Ok(()) => {
                 // Download complete, rename temp file to final destination
                 tokio::fs::rename(&temp_path, destination).await?;
+                let _ = tokio::fs::remove_file(&marker_path).await;
+                let _ = tokio::fs::remove_file(&segments_path).await;
                 return Ok(());
             }
+            Err(e) => {
+                warn!(
+                    "Mirror {} failed after {} attempts: {}",
+                    url, MAX_RETRIES, e
+                );
+                last_error = e;
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Order mirrors so that the one a partial download came from is tried first
+async fn order_mirrors(mirrors: &[String], marker_path: &Path) -> Vec<String> {
+    let mut ordered = mirrors.to_vec();
+
+    if let Ok(partial_mirror) = tokio::fs::read_to_string(marker_path).await {
+        if let Some(index) = ordered.iter().position(|url| *url == partial_mirror) {
+            let url = ordered.remove(index);
+            info!("Resuming partial download from mirror: {}", url);
+            ordered.insert(0, url);
+        }
+    }
+
+    ordered
+}
+
+async fn download_from_mirror(
+    client: &reqwest::Client,
+    url: &str,
+    temp_path: &Path,
+    marker_path: &Path,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<(), FileError> {
+    for attempt in 1..=MAX_RETRIES {
+        match download_attempt(client, url, temp_path, marker_path, rate_limiter).await {
+            Ok(()) => return Ok(()),
             Err(e) => {
                 if attempt < MAX_RETRIES {
                     warn!(
@@ -43,8 +180,6 @@ pub async fn download_file_with_progress(url: &str, destination: &Path) -> Resul
                     );
                     tokio::time::sleep(tokio::time::Duration::from_secs(RETRY_DELAY_SECS)).await;
                 } else {
-                    // Clean up temp file on final failure
-                    let _ = tokio::fs::remove_file(&temp_path).await;
                     return Err(e);
                 }
             }
@@ -57,6 +192,268 @@ pub async fn download_file_with_progress(url: &str, destination: &Path) -> Resul
     )))
 }
 
+/// Download a bzip2 file and decompress it as it arrives, so only the decompressed
+/// `destination` is written, through a `.part` file renamed once complete. Mirrors are tried
+/// in order as `download_file_with_progress` does, but an interrupted transfer starts over
+/// since the decoder's state can't be resumed. Returns the decompressed size.
+pub async fn download_bzip2_decompressed(
+    client: &reqwest::Client,
+    mirrors: &[String],
+    destination: &Path,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<u64, FileError> {
+    let temp_path = get_temp_path(destination);
+    let mut last_error = FileError::DownloadFailed("No download URL configured".to_string());
+
+    for url in mirrors {
+        for attempt in 1..=MAX_RETRIES {
+            match decompress_attempt(client, url, &temp_path, rate_limiter).await {
+                Ok(written) => {
+                    tokio::fs::rename(&temp_path, destination).await?;
+                    return Ok(written);
+                }
+                Err(e) => {
+                    let _ = tokio::fs::remove_file(&temp_path).await;
+                    if attempt < MAX_RETRIES {
+                        warn!(
+                            "Download attempt {}/{} failed: {}. Retrying in {} seconds...",
+                            attempt, MAX_RETRIES, e, RETRY_DELAY_SECS
+                        );
+                        tokio::time::sleep(tokio::time::Duration::from_secs(RETRY_DELAY_SECS))
+                            .await;
+                    } else {
+                        warn!(
+                            "Mirror {} failed after {} attempts: {}",
+                            url, MAX_RETRIES, e
+                        );
+                        last_error = e;
+                    }
+                }
+            }
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Stream one download through the decoder, which runs on a blocking thread fed over a
+/// channel so a slow disk holds back the transfer instead of filling memory
+async fn decompress_attempt(
+    client: &reqwest::Client,
+    url: &str,
+    temp_path: &Path,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<u64, FileError> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(FileError::DownloadFailed(format!(
+            "HTTP error: {}",
+            response.status()
+        )));
+    }
+    let total_size = response.content_length().unwrap_or(0);
+
+    let (sender, receiver) = tokio::sync::mpsc::channel(16);
+    let output = temp_path.to_path_buf();
+    let decoder = tokio::task::spawn_blocking(move || -> std::io::Result<u64> {
+        use std::io::Write;
+
+        let reader = ChunkReader {
+            receiver,
+            chunk: Default::default(),
+            offset: 0,
+        };
+        let mut decoder = bzip2::read::MultiBzDecoder::new(reader);
+        let mut writer = std::io::BufWriter::new(std::fs::File::create(&output)?);
+        let written = std::io::copy(&mut decoder, &mut writer)?;
+        writer.flush()?;
+        Ok(written)
+    });
+
+    let pb = create_progress_bar(total_size);
+    let mut stream = response.bytes_stream();
+    let mut downloaded = 0;
+    let mut transfer = Ok(());
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                transfer = Err(e);
+                break;
+            }
+        };
+        if let Some(limiter) = rate_limiter {
+            limiter.acquire(chunk.len()).await;
+        }
+        downloaded += chunk.len() as u64;
+        pb.set_position(downloaded);
+        // The decoder only hangs up when it failed, its result tells why
+        if sender.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(sender);
+    pb.finish_and_clear();
+
+    let decoded = decoder
+        .await
+        .map_err(|e| FileError::IoError(e.to_string()))?;
+    transfer?;
+    let written = decoded?;
+    if total_size > 0 && downloaded < total_size {
+        return Err(FileError::DownloadFailed(format!(
+            "Incomplete download: got {} of {} bytes",
+            downloaded, total_size
+        )));
+    }
+
+    info!(
+        "Downloaded and decompressed {} to {}",
+        format_bytes(downloaded),
+        format_bytes(written)
+    );
+    Ok(written)
+}
+
+/// Blocking reader over the chunks of a download
+struct ChunkReader<T> {
+    receiver: tokio::sync::mpsc::Receiver<T>,
+    chunk: T,
+    offset: usize,
+}
+
+impl<T: AsRef<[u8]>> std::io::Read for ChunkReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.offset == self.chunk.as_ref().len() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => (self.chunk, self.offset) = (chunk, 0),
+                None => return Ok(0),
+            }
+        }
+        let chunk = &self.chunk.as_ref()[self.offset..];
+        let count = buf.len().min(chunk.len());
+        buf[..count].copy_from_slice(&chunk[..count]);
+        self.offset += count;
+        Ok(count)
+    }
+}
+
+/// Headers advertised by a remote server for a file, used to identify its build
+#[derive(Debug, Clone, Default)]
+pub struct RemoteFileInfo {
+    pub content_length: Option<u64>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub accepts_ranges: bool,
+}
+
+/// Issue a HEAD request for a remote file and collect its identifying headers
+pub async fn probe_remote_file(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<RemoteFileInfo, FileError> {
+    let response = client.head(url).send().await?;
+
+    if !response.status().is_success() {
+        return Err(FileError::DownloadFailed(format!(
+            "HTTP error: {}",
+            response.status()
+        )));
+    }
+
+    let headers = response.headers();
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    };
+
+    Ok(RemoteFileInfo {
+        content_length: header("content-length").and_then(|s| s.parse::<u64>().ok()),
+        etag: header("etag"),
+        last_modified: header("last-modified"),
+        accepts_ranges: header("accept-ranges").is_some_and(|s| s.contains("bytes")),
+    })
+}
+
+/// Free space available to this process on the volume holding `path`. The path does not
+/// need to exist yet, the nearest existing ancestor is queried instead.
+pub fn available_space(path: &Path) -> std::io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+    fs2::available_space(existing)
+}
+
+/// Hex SHA-256 digest of a file's contents, read in chunks
+pub async fn sha256_file(path: &Path) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncReadExt;
+
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Device identifier of the volume holding `path`, used to detect shared volumes
+#[cfg(unix)]
+pub fn volume_id(path: &Path) -> std::io::Result<Option<u64>> {
+    use std::os::unix::fs::MetadataExt;
+    let existing = path
+        .ancestors()
+        .find(|p| p.exists())
+        .unwrap_or_else(|| Path::new("."));
+    Ok(Some(std::fs::metadata(existing)?.dev()))
+}
+
+/// Volumes cannot be told apart here, so every path is treated as its own volume
+#[cfg(not(unix))]
+pub fn volume_id(_path: &Path) -> std::io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// Decompress a `.bz2` file to `destination` with a progress bar of the compressed bytes
+/// read. Concatenated streams, as parallel compressors write, are read as one. The output
+/// goes to a `.part` file renamed once complete, and the source is left.
+pub fn decompress_bzip2_file(source: &Path, destination: &Path) -> std::io::Result<u64> {
+    use std::io::Write;
+
+    let file = std::fs::File::open(source)?;
+    let pb = create_progress_bar(file.metadata()?.len());
+    let mut decoder = bzip2::read::MultiBzDecoder::new(std::io::BufReader::new(pb.wrap_read(file)));
+
+    let partial = get_temp_path(destination);
+    let mut writer = std::io::BufWriter::new(std::fs::File::create(&partial)?);
+    let result = std::io::copy(&mut decoder, &mut writer).and_then(|written| {
+        writer.flush()?;
+        Ok(written)
+    });
+    pb.finish_and_clear();
+
+    match result {
+        Ok(written) => {
+            std::fs::rename(&partial, destination)?;
+            Ok(written)
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial);
+            Err(e)
+        }
+    }
+}
+
 /// Generate a temporary file path for partial downloads
 fn get_temp_path(destination: &Path) -> PathBuf {
     let mut temp_path = destination.to_path_buf();
@@ -69,6 +466,28 @@ fn get_temp_path(destination: &Path) -> PathBuf {
     temp_path
 }
 
+/// Path of the file recording which mirror a partial download came from
+fn get_mirror_marker_path(temp_path: &Path) -> PathBuf {
+    let mut marker_path = temp_path.to_path_buf();
+    let file_name = marker_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("download.part");
+    marker_path.set_file_name(format!("{}.mirror", file_name));
+    marker_path
+}
+
+/// Path of the file recording the progress of a segmented download
+fn get_segments_path(temp_path: &Path) -> PathBuf {
+    let mut segments_path = temp_path.to_path_buf();
+    let file_name = segments_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("download.part");
+    segments_path.set_file_name(format!("{}.segments", file_name));
+    segments_path
+}
+
 /// Create a progress bar with standard styling
 fn create_progress_bar(total_size: u64) -> ProgressBar {
     let pb = ProgressBar::new(total_size);
@@ -86,6 +505,8 @@ async fn download_attempt(
     client: &reqwest::Client,
     url: &str,
     temp_path: &Path,
+    marker_path: &Path,
+    rate_limiter: Option<&RateLimiter>,
 ) -> Result<(), FileError> {
     // Check if we have a partial file to resume from
     let existing_size = if temp_path.exists() {
@@ -125,7 +546,7 @@ async fn download_attempt(
             // Parse total size from "bytes start-end/total"
             let total = content_range
                 .split('/')
-                .last()
+                .next_back()
                 .and_then(|s| s.parse::<u64>().ok())
                 .unwrap_or(existing_size);
 
@@ -162,6 +583,7 @@ async fn download_attempt(
     } else {
         File::create(temp_path).await?
     };
+    tokio::fs::write(marker_path, url).await?;
 
     // Create progress bar
     let pb = create_progress_bar(total_size);
@@ -172,6 +594,9 @@ async fn download_attempt(
 
     while let Some(chunk) = stream.next().await {
         let chunk = chunk?;
+        if let Some(limiter) = rate_limiter {
+            limiter.acquire(chunk.len()).await;
+        }
         file.write_all(&chunk).await?;
         downloaded += chunk.len() as u64;
         pb.set_position(downloaded);
@@ -194,3 +619,530 @@ async fn download_attempt(
     info!("Download completed: {}", temp_path.display());
     Ok(())
 }
+
+/// Byte range of a segmented download, `end` excluded, with the bytes of it on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Segment {
+    start: u64,
+    end: u64,
+    written: u64,
+}
+
+impl Segment {
+    fn is_complete(&self) -> bool {
+        self.start + self.written >= self.end
+    }
+}
+
+/// Progress of a download split into byte ranges, saved next to its `.part` file as a line
+/// with the size and ETag of the remote file followed by a line per segment
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SegmentedDownload {
+    total_size: u64,
+    etag: Option<String>,
+    segments: Vec<Segment>,
+}
+
+impl SegmentedDownload {
+    fn new(total_size: u64, etag: Option<String>, connections: usize) -> Self {
+        let count = (connections as u64).clamp(1, total_size.max(1));
+        let segment_size = total_size.div_ceil(count);
+        let segments = (0..count)
+            .map(|index| Segment {
+                start: index * segment_size,
+                end: ((index + 1) * segment_size).min(total_size),
+                written: 0,
+            })
+            .filter(|segment| segment.start < segment.end)
+            .collect();
+        Self {
+            total_size,
+            etag,
+            segments,
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        let mut lines = value.lines();
+        let (total_size, etag) = lines.next()?.split_once(' ')?;
+        let segments = lines
+            .map(|line| {
+                let mut fields = line.split(' ').map(|field| field.parse::<u64>().ok());
+                let segment = Segment {
+                    start: fields.next()??,
+                    end: fields.next()??,
+                    written: fields.next()??,
+                };
+                (segment.start + segment.written <= segment.end).then_some(segment)
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self {
+            total_size: total_size.parse().ok()?,
+            etag: Some(etag).filter(|etag| *etag != "-").map(str::to_string),
+            segments,
+        })
+    }
+
+    fn written(&self) -> u64 {
+        self.segments.iter().map(|segment| segment.written).sum()
+    }
+}
+
+impl fmt::Display for SegmentedDownload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.total_size,
+            self.etag.as_deref().unwrap_or("-")
+        )?;
+        for segment in &self.segments {
+            write!(f, "\n{} {} {}", segment.start, segment.end, segment.written)?;
+        }
+        Ok(())
+    }
+}
+
+/// The segments to download a file from a mirror with, resuming a saved segmented download
+/// of the same remote file. `None` when the file is downloaded over a single connection: a
+/// small file, a mirror without range support, or a partial single-connection download.
+async fn plan_segments(
+    client: &reqwest::Client,
+    url: &str,
+    temp_path: &Path,
+    segments_path: &Path,
+    connections: usize,
+) -> Option<SegmentedDownload> {
+    if connections < 2 {
+        return None;
+    }
+    let saved = tokio::fs::read_to_string(segments_path)
+        .await
+        .ok()
+        .and_then(|saved| SegmentedDownload::parse(&saved));
+    if saved.is_none() && temp_path.exists() {
+        return None;
+    }
+
+    let info = match probe_remote_file(client, url).await {
+        Ok(info) => info,
+        Err(e) => {
+            warn!("Could not probe {} for a segmented download: {}", url, e);
+            return None;
+        }
+    };
+    let total_size = info.content_length.filter(|_| info.accepts_ranges)?;
+    if total_size < MIN_SEGMENTED_SIZE {
+        return None;
+    }
+
+    match saved {
+        Some(saved) if saved.total_size == total_size && saved.etag == info.etag => {
+            info!(
+                "Resuming segmented download from {:.2} MB",
+                saved.written() as f64 / 1_048_576.0
+            );
+            Some(saved)
+        }
+        saved => {
+            if saved.is_some() {
+                info!("Remote file changed since the partial download, starting over");
+            }
+            let _ = tokio::fs::remove_file(temp_path).await;
+            Some(SegmentedDownload::new(total_size, info.etag, connections))
+        }
+    }
+}
+
+/// Bytes written per segment of a running segmented download, saved while it runs
+struct SegmentProgress<'a> {
+    download: &'a SegmentedDownload,
+    written: Vec<AtomicU64>,
+    segments_path: &'a Path,
+    save_lock: Mutex<()>,
+}
+
+impl SegmentProgress<'_> {
+    fn snapshot(&self) -> SegmentedDownload {
+        let mut download = self.download.clone();
+        for (segment, written) in download.segments.iter_mut().zip(&self.written) {
+            segment.written = written.load(Ordering::Relaxed);
+        }
+        download
+    }
+
+    async fn save(&self) -> Result<(), FileError> {
+        let _guard = self.save_lock.lock().await;
+        tokio::fs::write(self.segments_path, self.snapshot().to_string()).await?;
+        Ok(())
+    }
+}
+
+/// Download the segments of a file in parallel into a sparse `.part` file, then check every
+/// byte range arrived before it is renamed
+async fn download_segmented(
+    client: &reqwest::Client,
+    url: &str,
+    temp_path: &Path,
+    marker_path: &Path,
+    segments_path: &Path,
+    download: SegmentedDownload,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<(), FileError> {
+    if !temp_path.exists() {
+        File::create(temp_path)
+            .await?
+            .set_len(download.total_size)
+            .await?;
+    }
+    tokio::fs::write(marker_path, url).await?;
+    info!(
+        "Downloading {:.2} MB over {} connections",
+        download.total_size as f64 / 1_048_576.0,
+        download.segments.len()
+    );
+
+    let progress = SegmentProgress {
+        download: &download,
+        written: download
+            .segments
+            .iter()
+            .map(|segment| AtomicU64::new(segment.written))
+            .collect(),
+        segments_path,
+        save_lock: Mutex::new(()),
+    };
+    progress.save().await?;
+
+    let pb = create_progress_bar(download.total_size);
+    pb.set_position(download.written());
+
+    let results = futures::future::join_all(download.segments.iter().enumerate().map(
+        |(index, segment)| {
+            download_segment(
+                client,
+                url,
+                temp_path,
+                segment,
+                &progress,
+                index,
+                &pb,
+                rate_limiter,
+            )
+        },
+    ))
+    .await;
+    progress.save().await?;
+    results.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+    let finished = progress.snapshot();
+    let file_size = tokio::fs::metadata(temp_path).await?.len();
+    if !finished.segments.iter().all(Segment::is_complete) || file_size != finished.total_size {
+        return Err(FileError::DownloadFailed(format!(
+            "Incomplete segmented download: got {} of {} bytes",
+            finished.written(),
+            finished.total_size
+        )));
+    }
+    pb.finish_with_message("Download complete");
+
+    info!("Download completed: {}", temp_path.display());
+    Ok(())
+}
+
+/// Download one segment, retrying from where the previous attempt stopped
+#[allow(clippy::too_many_arguments)]
+async fn download_segment(
+    client: &reqwest::Client,
+    url: &str,
+    temp_path: &Path,
+    segment: &Segment,
+    progress: &SegmentProgress<'_>,
+    index: usize,
+    pb: &ProgressBar,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<(), FileError> {
+    for attempt in 1..=MAX_RETRIES {
+        match download_segment_attempt(
+            client,
+            url,
+            temp_path,
+            segment,
+            progress,
+            index,
+            pb,
+            rate_limiter,
+        )
+        .await
+        {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt < MAX_RETRIES => {
+                warn!(
+                    "Segment {} attempt {}/{} failed: {}. Retrying in {} seconds...",
+                    index + 1,
+                    attempt,
+                    MAX_RETRIES,
+                    e,
+                    RETRY_DELAY_SECS
+                );
+                tokio::time::sleep(tokio::time::Duration::from_secs(RETRY_DELAY_SECS)).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(FileError::DownloadFailed(format!(
+        "Failed after {} attempts",
+        MAX_RETRIES
+    )))
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn download_segment_attempt(
+    client: &reqwest::Client,
+    url: &str,
+    temp_path: &Path,
+    segment: &Segment,
+    progress: &SegmentProgress<'_>,
+    index: usize,
+    pb: &ProgressBar,
+    rate_limiter: Option<&RateLimiter>,
+) -> Result<(), FileError> {
+    let written = &progress.written[index];
+    let mut position = segment.start + written.load(Ordering::Relaxed);
+    if position >= segment.end {
+        return Ok(());
+    }
+
+    let response = client
+        .get(url)
+        .header("Range", format!("bytes={}-{}", position, segment.end - 1))
+        .send()
+        .await?;
+    if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(FileError::DownloadFailed(format!(
+            "Range request answered with {}",
+            response.status()
+        )));
+    }
+
+    let mut file = OpenOptions::new().write(true).open(temp_path).await?;
+    file.seek(SeekFrom::Start(position)).await?;
+
+    let mut stream = response.bytes_stream();
+    let mut unsaved = 0;
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk?;
+        // A server sending past the requested range must not overwrite the next segment
+        let len = chunk.len().min((segment.end - position) as usize);
+        if let Some(limiter) = rate_limiter {
+            limiter.acquire(len).await;
+        }
+        file.write_all(&chunk[..len]).await?;
+        position += len as u64;
+        written.fetch_add(len as u64, Ordering::Relaxed);
+        pb.inc(len as u64);
+
+        unsaved += len as u64;
+        if unsaved >= SEGMENT_SAVE_INTERVAL {
+            file.flush().await?;
+            progress.save().await?;
+            unsaved = 0;
+        }
+        if position >= segment.end {
+            break;
+        }
+    }
+    file.flush().await?;
+
+    if position < segment.end {
+        return Err(FileError::DownloadFailed(format!(
+            "Incomplete segment: got {} of {} bytes",
+            position - segment.start,
+            segment.end - segment.start
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `Paris, Lyon, Marseille` followed by 300 zeros and a newline, from `bzip2 -9`
+    const CITIES: [u8; 76] = [
+        0x42, 0x5a, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0x5e, 0x18, 0x40, 0xd1, 0x00,
+        0x00, 0x08, 0x5f, 0x80, 0x80, 0x10, 0x40, 0x24, 0x40, 0x00, 0x00, 0x06, 0x40, 0x00, 0x22,
+        0x25, 0x98, 0x20, 0x00, 0x08, 0x20, 0x00, 0x22, 0x13, 0x20, 0xd1, 0xa6, 0x99, 0xa8, 0x53,
+        0x4c, 0x8c, 0x4c, 0x4c, 0x44, 0x52, 0xbc, 0xc2, 0x18, 0xea, 0x1b, 0x13, 0x3b, 0xfb, 0x7a,
+        0xa5, 0x9a, 0x00, 0x97, 0x02, 0x3e, 0x2e, 0xe4, 0x8a, 0x70, 0xa1, 0x20, 0xbc, 0x30, 0x81,
+        0xa2,
+    ];
+    /// `Nice`
+    const NICE: [u8; 42] = [
+        0x42, 0x5a, 0x68, 0x39, 0x31, 0x41, 0x59, 0x26, 0x53, 0x59, 0x3a, 0xa0, 0x9f, 0xdd, 0x00,
+        0x00, 0x00, 0x05, 0x00, 0x00, 0x01, 0x0a, 0x20, 0x20, 0x00, 0x21, 0x9a, 0x68, 0x33, 0x4d,
+        0x32, 0xbc, 0x5d, 0xc9, 0x14, 0xe1, 0x42, 0x40, 0xea, 0x82, 0x7f, 0x74,
+    ];
+
+    #[test]
+    fn decompresses_concatenated_streams() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("cities.db.bz2");
+        let destination = dir.path().join("cities.db");
+        std::fs::write(&source, [CITIES.as_slice(), NICE.as_slice()].concat()).unwrap();
+
+        let written = decompress_bzip2_file(&source, &destination).unwrap();
+
+        let expected = format!("Paris, Lyon, Marseille{}\nNice", "0".repeat(300));
+        assert_eq!(written, expected.len() as u64);
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), expected);
+        assert!(!get_temp_path(&destination).exists());
+    }
+
+    #[test]
+    fn rejects_corrupted_bzip2_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("cities.db");
+        let mut corrupted = CITIES;
+        corrupted[10] ^= 0xff;
+
+        for (name, data) in [
+            ("corrupted.bz2", &corrupted[..]),
+            ("truncated.bz2", &CITIES[..40]),
+        ] {
+            let source = dir.path().join(name);
+            std::fs::write(&source, data).unwrap();
+            assert!(decompress_bzip2_file(&source, &destination).is_err());
+            assert!(!destination.exists());
+            assert!(!get_temp_path(&destination).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_an_unmarked_partial_against_the_primary_mirror() {
+        use axum::http::{header, HeaderMap, StatusCode};
+        use axum::response::IntoResponse;
+
+        let ranges = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = ranges.clone();
+        let app = axum::Router::new().route(
+            "/whosonfirst.db",
+            axum::routing::get(move |headers: HeaderMap| async move {
+                let range = headers
+                    .get(header::RANGE)
+                    .map(|v| v.to_str().unwrap().to_string());
+                seen.lock().unwrap().push(range.clone());
+                match range.as_deref() {
+                    Some("bytes=5-") => (
+                        StatusCode::PARTIAL_CONTENT,
+                        [(header::CONTENT_RANGE, "bytes 5-9/10")],
+                        "56789",
+                    )
+                        .into_response(),
+                    _ => "0123456789".into_response(),
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let destination = dir.path().join("whosonfirst.db");
+        // Left by a version without mirror markers
+        std::fs::write(get_temp_path(&destination), "01234").unwrap();
+
+        let mirrors = vec![
+            format!("http://{}/whosonfirst.db", addr),
+            "http://127.0.0.1:9/whosonfirst.db".to_string(),
+        ];
+        download_file_with_progress(&reqwest::Client::new(), &mirrors, &destination, None, 1)
+            .await
+            .unwrap();
+
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "0123456789");
+        assert_eq!(*ranges.lock().unwrap(), vec![Some("bytes=5-".to_string())]);
+    }
+
+    #[test]
+    fn splits_a_download_into_even_segments() {
+        let download = SegmentedDownload::new(10, None, 4);
+
+        let ranges: Vec<_> = download
+            .segments
+            .iter()
+            .map(|segment| (segment.start, segment.end))
+            .collect();
+        assert_eq!(ranges, vec![(0, 3), (3, 6), (6, 9), (9, 10)]);
+        assert_eq!(SegmentedDownload::new(2, None, 4).segments.len(), 2);
+    }
+
+    #[test]
+    fn saved_progress_round_trips() {
+        let mut download = SegmentedDownload::new(100, Some("\"abc 1\"".to_string()), 2);
+        download.segments[0].written = 20;
+
+        let saved = download.to_string();
+        assert_eq!(SegmentedDownload::parse(&saved), Some(download));
+        assert_eq!(
+            SegmentedDownload::parse("100 -\n0 50 60"),
+            None,
+            "more written than the segment holds"
+        );
+        assert!(SegmentedDownload::parse("100 -\n0 50 10")
+            .is_some_and(|download| download.etag.is_none() && download.written() == 10));
+    }
+
+    #[test]
+    fn streams_chunks_to_a_blocking_reader() {
+        use std::io::Read;
+
+        let (sender, receiver) = tokio::sync::mpsc::channel(1);
+        let reader = std::thread::spawn(move || {
+            let mut reader = ChunkReader {
+                receiver,
+                chunk: Vec::new(),
+                offset: 0,
+            };
+            let mut read = String::new();
+            reader.read_to_string(&mut read).unwrap();
+            read
+        });
+        for chunk in ["Paris, ", "", "Lyon"] {
+            sender.blocking_send(chunk.as_bytes().to_vec()).unwrap();
+        }
+        drop(sender);
+
+        assert_eq!(reader.join().unwrap(), "Paris, Lyon");
+    }
+
+    #[test]
+    fn builds_clients_for_valid_proxies_only() {
+        let headers = HeaderMap::new();
+        assert!(http_client(None, &headers).is_ok());
+        assert!(http_client(Some("http://proxy.internal:3128"), &headers).is_ok());
+        assert!(http_client(Some("not a proxy"), &headers).is_err());
+    }
+
+    #[test]
+    fn parses_request_headers() {
+        let headers = parse_headers("X-Api-Key: abc; X-Team:maps ;").unwrap();
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers["x-api-key"], "abc");
+        assert_eq!(headers["x-team"], "maps");
+        assert!(headers["x-api-key"].is_sensitive());
+        assert!(matches!(
+            parse_headers("X-Api-Key abc"),
+            Err(FileError::InvalidHeader(_))
+        ));
+        assert!(parse_headers("Bad Name: abc").is_err());
+
+        let (name, value) = bearer_header("secret").unwrap();
+        assert_eq!(name, AUTHORIZATION);
+        assert_eq!(value, "Bearer secret");
+    }
+}
@@ -0,0 +1,188 @@
+use crate::types::BoundingBox;
+use serde_json::{json, Value};
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum GeoJsonError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid JSON: {0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("expected a FeatureCollection or a Feature, found {0}")]
+    NotFeatures(String),
+    #[error("feature {index}: {reason}")]
+    InvalidFeature { index: usize, reason: String },
+}
+
+/// A named polygon of a GeoJSON file, to be extracted as a custom region
+#[derive(Debug, Clone)]
+pub struct Boundary {
+    pub name: String,
+    /// The Polygon or MultiPolygon geometry of the feature, as read
+    pub geometry: Value,
+    pub bbox: BoundingBox,
+}
+
+impl Boundary {
+    /// The boundary as a GeoJSON feature on its own, for `pmtiles extract --region`
+    pub fn to_geojson(&self) -> String {
+        json!({
+            "type": "Feature",
+            "properties": { "name": self.name },
+            "geometry": self.geometry,
+        })
+        .to_string()
+    }
+}
+
+/// Parse the Polygon and MultiPolygon features of a FeatureCollection, or a single Feature.
+/// A feature is named after its `name` property, by its position without one.
+pub fn parse_boundaries(text: &str) -> Result<Vec<Boundary>, GeoJsonError> {
+    let document: Value = serde_json::from_str(text)?;
+    let features = match document["type"].as_str() {
+        Some("FeatureCollection") => document["features"]
+            .as_array()
+            .cloned()
+            .ok_or_else(|| GeoJsonError::NotFeatures("a collection without features".into()))?,
+        Some("Feature") => vec![document],
+        other => {
+            return Err(GeoJsonError::NotFeatures(
+                other.unwrap_or("no type").to_string(),
+            ))
+        }
+    };
+
+    features
+        .into_iter()
+        .enumerate()
+        .map(|(index, feature)| {
+            let invalid = |reason: &str| GeoJsonError::InvalidFeature {
+                index: index + 1,
+                reason: reason.to_string(),
+            };
+            let geometry = feature["geometry"].clone();
+            if !matches!(geometry["type"].as_str(), Some("Polygon" | "MultiPolygon")) {
+                return Err(invalid("geometry is not a Polygon or MultiPolygon"));
+            }
+            let bbox = geometry_bbox(&geometry["coordinates"])
+                .ok_or_else(|| invalid("coordinates are missing or out of range"))?;
+            let name = match feature["properties"]["name"].as_str() {
+                Some(name) if !name.trim().is_empty() => name.trim().to_string(),
+                _ => format!("Feature {}", index + 1),
+            };
+            Ok(Boundary {
+                name,
+                geometry,
+                bbox,
+            })
+        })
+        .collect()
+}
+
+/// Read a GeoJSON file of boundaries, see `parse_boundaries`
+pub fn read_boundaries_file(path: &Path) -> Result<Vec<Boundary>, GeoJsonError> {
+    parse_boundaries(&std::fs::read_to_string(path)?)
+}
+
+/// Bounding box of every position nested in `coordinates`, `None` without any or with one
+/// outside WGS84 bounds
+fn geometry_bbox(coordinates: &Value) -> Option<BoundingBox> {
+    let mut positions = Vec::new();
+    collect_positions(coordinates, &mut positions);
+    let (first, rest) = positions.split_first()?;
+
+    let start = [first.0, first.1, first.0, first.1];
+    let [min_longitude, min_latitude, max_longitude, max_latitude] =
+        rest.iter().fold(start, |bbox, &(x, y)| {
+            [
+                bbox[0].min(x),
+                bbox[1].min(y),
+                bbox[2].max(x),
+                bbox[3].max(y),
+            ]
+        });
+    let bbox = BoundingBox {
+        min_longitude,
+        min_latitude,
+        max_longitude,
+        max_latitude,
+    };
+    // Parsing the box back applies the range checks of the command line
+    bbox.to_string().parse().ok()
+}
+
+fn collect_positions(value: &Value, positions: &mut Vec<(f64, f64)>) {
+    let Some(items) = value.as_array() else {
+        return;
+    };
+    match (
+        items.first().and_then(Value::as_f64),
+        items.get(1).and_then(Value::as_f64),
+    ) {
+        (Some(x), Some(y)) => positions.push((x, y)),
+        _ => items
+            .iter()
+            .for_each(|item| collect_positions(item, positions)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERVICE_AREAS: &str = r#"{
+        "type": "FeatureCollection",
+        "features": [
+            {
+                "type": "Feature",
+                "properties": { "name": "North depot" },
+                "geometry": {
+                    "type": "Polygon",
+                    "coordinates": [[[2.2, 48.8], [2.5, 48.8], [2.4, 48.95], [2.2, 48.8]]]
+                }
+            },
+            {
+                "type": "Feature",
+                "properties": {},
+                "geometry": {
+                    "type": "MultiPolygon",
+                    "coordinates": [
+                        [[[4.7, 45.7], [4.9, 45.7], [4.9, 45.8], [4.7, 45.7]]],
+                        [[[5.0, 45.1], [5.2, 45.1], [5.2, 45.3], [5.0, 45.1]]]
+                    ]
+                }
+            }
+        ]
+    }"#;
+
+    #[test]
+    fn reads_named_polygons_with_their_bounds() {
+        let boundaries = parse_boundaries(SERVICE_AREAS).unwrap();
+
+        assert_eq!(boundaries.len(), 2);
+        assert_eq!(boundaries[0].name, "North depot");
+        assert_eq!(boundaries[0].bbox.to_string(), "2.2,48.8,2.5,48.95");
+        assert_eq!(boundaries[1].name, "Feature 2");
+        assert_eq!(boundaries[1].bbox.to_string(), "4.7,45.1,5.2,45.8");
+
+        let feature: Value = serde_json::from_str(&boundaries[1].to_geojson()).unwrap();
+        assert_eq!(feature["geometry"]["type"], "MultiPolygon");
+    }
+
+    #[test]
+    fn rejects_features_without_polygons() {
+        let point = r#"{"type": "Feature", "geometry": {"type": "Point", "coordinates": [1, 2]}}"#;
+        assert!(matches!(
+            parse_boundaries(point),
+            Err(GeoJsonError::InvalidFeature { index: 1, .. })
+        ));
+        let outside = r#"{"type": "Feature", "geometry": {"type": "Polygon",
+            "coordinates": [[[0, 0], [200, 0], [0, 1], [0, 0]]]}}"#;
+        assert!(parse_boundaries(outside).is_err());
+        assert!(matches!(
+            parse_boundaries(r#"{"type": "Point"}"#),
+            Err(GeoJsonError::NotFeatures(_))
+        ));
+    }
+}
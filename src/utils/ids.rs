@@ -0,0 +1,58 @@
+use std::collections::HashSet;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum AreaIdsError {
+    #[error("IO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid area ID '{value}' on line {line}")]
+    InvalidId { line: usize, value: String },
+}
+
+/// Parse a list of area IDs separated by commas, spaces or newlines. `#` starts a comment
+/// running to the end of its line and repeated IDs are kept once, in first-seen order.
+pub fn parse_area_ids(text: &str) -> Result<Vec<u32>, AreaIdsError> {
+    let mut seen = HashSet::new();
+    let mut ids = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let content = line.split('#').next().unwrap_or_default();
+        for value in content
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|value| !value.is_empty())
+        {
+            let id = value.parse::<u32>().map_err(|_| AreaIdsError::InvalidId {
+                line: index + 1,
+                value: value.to_string(),
+            })?;
+            if seen.insert(id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+/// Read a curated list of area IDs, see `parse_area_ids` for the format
+pub fn read_area_ids_file(path: &Path) -> Result<Vec<u32>, AreaIdsError> {
+    parse_area_ids(&std::fs::read_to_string(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ids_across_lines_with_comments() {
+        let text = "# Alpine regions\n101, 102\n103 104 # Tyrol\n\n102\n";
+        assert_eq!(parse_area_ids(text).unwrap(), vec![101, 102, 103, 104]);
+    }
+
+    #[test]
+    fn reports_the_line_of_an_invalid_id() {
+        let error = parse_area_ids("101\n102,abc\n").unwrap_err();
+        assert!(matches!(error, AreaIdsError::InvalidId { line: 2, ref value } if value == "abc"));
+    }
+}
@@ -1,5 +1,46 @@
 pub mod cmd;
+pub mod compress;
+pub mod duration;
+pub mod encrypt;
 pub mod file;
+pub mod geojson;
+pub mod ids;
+pub mod parquet;
+pub mod payload;
+pub mod pmtiles;
+pub mod s3;
+pub mod size;
+pub mod smtp;
+pub mod spr;
+pub mod throttle;
 
-pub use cmd::{ensure_tools_are_present, is_tool_available, run_command, CmdError, CommandOutput};
-pub use file::{download_file_with_progress, FileError};
+pub use cmd::{
+    ensure_pmtiles_version, ensure_tools_are_present, is_tool_available, run_command, CmdError,
+    CommandOutput, ToolVersion, MIN_PMTILES_VERSION,
+};
+pub use compress::{
+    compress_file, compressed_path, decompress_file, detect_compression, CompressError,
+};
+pub use duration::format_duration;
+pub use encrypt::{
+    decrypt_file, encrypt_file, EncryptError, EncryptionInfo, EncryptionKey, ENCRYPTION_CHUNK_SIZE,
+};
+pub use file::{
+    available_space, bearer_header, decompress_bzip2_file, download_bzip2_decompressed,
+    download_file_with_progress, http_client, parse_headers, probe_remote_file, sha256_file,
+    volume_id, FileError, RemoteFileInfo,
+};
+pub use geojson::{parse_boundaries, read_boundaries_file, Boundary, GeoJsonError};
+pub use ids::{parse_area_ids, read_area_ids_file, AreaIdsError};
+pub use parquet::{encode_parquet, ColumnValues, ParquetColumn, ParquetError};
+pub use payload::{payload_cache_key, prepare_payload, Payload, PayloadError};
+pub use pmtiles::{
+    fetch_pmtiles_header, write_sample_pmtiles, DirectoryCache, PmtilesError, PmtilesHeader,
+    PMTILES_HEADER_LEN,
+};
+pub use s3::{parse_s3_location, presign_list_url, presign_url, S3Credentials, S3Error};
+pub use size::{format_bytes, parse_size, SizeError};
+pub use smtp::{send_email, Email, SmtpError, SmtpServer};
+pub(crate) use spr::abbreviate;
+pub use spr::{decode_spr, SignedPeerRecord, SprError};
+pub use throttle::{spawn_throttled_proxy, RateLimiter, ThrottledProxy};
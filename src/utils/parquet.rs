@@ -0,0 +1,241 @@
+use parquet::basic::{ConvertedType, Repetition, Type as PhysicalType};
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::metadata::KeyValue;
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::types::Type;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ParquetError {
+    #[error("column {0} has {1} values, expected {2}")]
+    ColumnLength(String, usize, usize),
+    #[error("Parquet encoding failed: {0}")]
+    Encoding(#[from] parquet::errors::ParquetError),
+}
+
+/// Values of one column, `None` for nulls. A column without nulls is written as required.
+pub enum ColumnValues {
+    Int64(Vec<Option<i64>>),
+    Double(Vec<Option<f64>>),
+    Utf8(Vec<Option<String>>),
+    Binary(Vec<Option<Vec<u8>>>),
+    /// Milliseconds since the Unix epoch, UTC
+    TimestampMillis(Vec<Option<i64>>),
+}
+
+impl ColumnValues {
+    fn len(&self) -> usize {
+        match self {
+            Self::Int64(values) | Self::TimestampMillis(values) => values.len(),
+            Self::Double(values) => values.len(),
+            Self::Utf8(values) => values.len(),
+            Self::Binary(values) => values.len(),
+        }
+    }
+
+    /// Definition levels, 1 for a value and 0 for a null
+    fn definition_levels(&self) -> Vec<i16> {
+        let levels = |present: bool| present as i16;
+        match self {
+            Self::Int64(values) | Self::TimestampMillis(values) => {
+                values.iter().map(|v| levels(v.is_some())).collect()
+            }
+            Self::Double(values) => values.iter().map(|v| levels(v.is_some())).collect(),
+            Self::Utf8(values) => values.iter().map(|v| levels(v.is_some())).collect(),
+            Self::Binary(values) => values.iter().map(|v| levels(v.is_some())).collect(),
+        }
+    }
+
+    fn schema_field(&self, name: &str, required: bool) -> Result<Type, ParquetError> {
+        let (physical_type, converted_type) = match self {
+            Self::Int64(_) => (PhysicalType::INT64, ConvertedType::NONE),
+            Self::TimestampMillis(_) => (PhysicalType::INT64, ConvertedType::TIMESTAMP_MILLIS),
+            Self::Double(_) => (PhysicalType::DOUBLE, ConvertedType::NONE),
+            Self::Utf8(_) => (PhysicalType::BYTE_ARRAY, ConvertedType::UTF8),
+            Self::Binary(_) => (PhysicalType::BYTE_ARRAY, ConvertedType::NONE),
+        };
+        let repetition = match required {
+            true => Repetition::REQUIRED,
+            false => Repetition::OPTIONAL,
+        };
+        Ok(Type::primitive_type_builder(name, physical_type)
+            .with_repetition(repetition)
+            .with_converted_type(converted_type)
+            .build()?)
+    }
+}
+
+pub struct ParquetColumn {
+    pub name: String,
+    pub values: ColumnValues,
+}
+
+impl ParquetColumn {
+    pub fn new(name: &str, values: ColumnValues) -> Self {
+        Self {
+            name: name.to_string(),
+            values,
+        }
+    }
+}
+
+/// Encode columns of equal length as a Parquet file with a single uncompressed row group
+/// and `metadata` as the footer's key-value metadata
+pub fn encode_parquet(
+    columns: &[ParquetColumn],
+    metadata: &[(&str, String)],
+) -> Result<Vec<u8>, ParquetError> {
+    let num_rows = columns.first().map_or(0, |column| column.values.len());
+    for column in columns {
+        if column.values.len() != num_rows {
+            return Err(ParquetError::ColumnLength(
+                column.name.clone(),
+                column.values.len(),
+                num_rows,
+            ));
+        }
+    }
+
+    // A column without nulls is written as required, without definition levels
+    let levels: Vec<Option<Vec<i16>>> = columns
+        .iter()
+        .map(|column| {
+            let levels = column.values.definition_levels();
+            levels.contains(&0).then_some(levels)
+        })
+        .collect();
+    let fields = columns
+        .iter()
+        .zip(&levels)
+        .map(|(column, levels)| {
+            let field = column.values.schema_field(&column.name, levels.is_none())?;
+            Ok(Arc::new(field))
+        })
+        .collect::<Result<Vec<_>, ParquetError>>()?;
+    let schema = Type::group_type_builder("schema")
+        .with_fields(fields)
+        .build()?;
+
+    let key_values = metadata
+        .iter()
+        .map(|(key, value)| KeyValue::new(key.to_string(), value.clone()))
+        .collect();
+    let properties = WriterProperties::builder()
+        .set_created_by(concat!("anynode version ", env!("CARGO_PKG_VERSION")).to_string())
+        .set_key_value_metadata(Some(key_values))
+        .build();
+
+    let mut writer = SerializedFileWriter::new(Vec::new(), Arc::new(schema), Arc::new(properties))?;
+    let mut row_group = writer.next_row_group()?;
+    for (column, levels) in columns.iter().zip(&levels) {
+        let mut column_writer = row_group
+            .next_column()?
+            .expect("the schema has a field per column");
+        let levels = levels.as_deref();
+        match &column.values {
+            ColumnValues::Int64(values) | ColumnValues::TimestampMillis(values) => {
+                let values: Vec<i64> = values.iter().flatten().copied().collect();
+                column_writer
+                    .typed::<Int64Type>()
+                    .write_batch(&values, levels, None)?;
+            }
+            ColumnValues::Double(values) => {
+                let values: Vec<f64> = values.iter().flatten().copied().collect();
+                column_writer
+                    .typed::<DoubleType>()
+                    .write_batch(&values, levels, None)?;
+            }
+            ColumnValues::Utf8(values) => {
+                let values: Vec<ByteArray> =
+                    values.iter().flatten().map(|v| v.as_str().into()).collect();
+                column_writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, levels, None)?;
+            }
+            ColumnValues::Binary(values) => {
+                let values: Vec<ByteArray> =
+                    values.iter().flatten().map(|v| v.clone().into()).collect();
+                column_writer
+                    .typed::<ByteArrayType>()
+                    .write_batch(&values, levels, None)?;
+            }
+        }
+        column_writer.close()?;
+    }
+    row_group.close()?;
+    Ok(writer.into_inner()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Field;
+
+    #[test]
+    fn round_trips_through_a_reader() {
+        let columns = [
+            ParquetColumn::new("id", ColumnValues::Int64(vec![Some(1), Some(2)])),
+            ParquetColumn::new(
+                "name",
+                ColumnValues::Utf8(vec![Some("Paris".to_string()), None]),
+            ),
+            ParquetColumn::new("area", ColumnValues::Double(vec![None, Some(2.5)])),
+            ParquetColumn::new(
+                "geometry",
+                ColumnValues::Binary(vec![Some(vec![1, 2]), Some(vec![])]),
+            ),
+            ParquetColumn::new(
+                "updated",
+                ColumnValues::TimestampMillis(vec![Some(1_700_000_000_000), None]),
+            ),
+        ];
+        let file = encode_parquet(&columns, &[("geo", "{}".to_string())]).unwrap();
+
+        let mut temp = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp, &file).unwrap();
+        let reader = SerializedFileReader::new(temp.reopen().unwrap()).unwrap();
+        let metadata = reader.metadata().file_metadata();
+        assert_eq!(metadata.num_rows(), 2);
+        let key_values = metadata.key_value_metadata().unwrap();
+        assert_eq!(key_values[0].key, "geo");
+        assert_eq!(key_values[0].value.as_deref(), Some("{}"));
+        let fields = metadata.schema_descr().columns();
+        assert!(fields[0].self_type().is_primitive());
+        assert_eq!(fields[1].converted_type(), ConvertedType::UTF8);
+        assert_eq!(fields[4].converted_type(), ConvertedType::TIMESTAMP_MILLIS);
+
+        let rows: Vec<Vec<Field>> = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(|row| {
+                row.unwrap()
+                    .get_column_iter()
+                    .map(|(_, field)| field.clone())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][0], Field::Long(1));
+        assert_eq!(rows[0][1], Field::Str("Paris".to_string()));
+        assert_eq!(rows[0][2], Field::Null);
+        assert_eq!(rows[0][4], Field::TimestampMillis(1_700_000_000_000));
+        assert_eq!(rows[1][1], Field::Null);
+        assert_eq!(rows[1][2], Field::Double(2.5));
+        assert_eq!(rows[1][3], Field::Bytes(vec![].into()));
+    }
+
+    #[test]
+    fn rejects_ragged_columns() {
+        let columns = [
+            ParquetColumn::new("a", ColumnValues::Int64(vec![Some(1)])),
+            ParquetColumn::new("b", ColumnValues::Double(vec![])),
+        ];
+        assert!(matches!(
+            encode_parquet(&columns, &[]),
+            Err(ParquetError::ColumnLength(name, 0, 1)) if name == "b"
+        ));
+    }
+}
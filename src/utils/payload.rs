@@ -0,0 +1,76 @@
+use crate::types::Compression;
+use crate::utils::{
+    compress_file, encrypt_file, CompressError, EncryptError, EncryptionInfo, EncryptionKey,
+};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum PayloadError {
+    #[error("Compression failed: {0}")]
+    Compression(#[from] CompressError),
+    #[error("Encryption failed: {0}")]
+    Encryption(#[from] EncryptError),
+}
+
+/// File handed to the storage node for an extract: the extract itself, or a compressed
+/// and/or encrypted copy next to it
+pub struct Payload {
+    pub path: PathBuf,
+    pub encryption: Option<EncryptionInfo>,
+    source: PathBuf,
+}
+
+impl Payload {
+    /// Delete the copy made for the upload, leaving the extract in place
+    pub async fn remove(self) {
+        if self.path != self.source {
+            let _ = tokio::fs::remove_file(&self.path).await;
+        }
+    }
+}
+
+/// Key of an extract in the extract cache, and the content ID its encryption nonce is
+/// derived from. Uploads with another codec or key are cached apart, since their CIDs differ.
+pub fn payload_cache_key(
+    content_hash: &str,
+    compression: Compression,
+    key: Option<&EncryptionKey>,
+) -> String {
+    let cache_key = compression.cache_key(content_hash);
+    match key {
+        Some(key) => format!("aes-{}:{}", key.id(), cache_key),
+        None => cache_key,
+    }
+}
+
+/// Compress `source` with `compression`, then encrypt it when a key is given. `content_id`
+/// identifies the content for the encryption nonce, see `EncryptionKey::info_for`.
+pub async fn prepare_payload(
+    source: &Path,
+    compression: Compression,
+    key: Option<&EncryptionKey>,
+    content_id: &str,
+    zstd_cmd: &str,
+) -> Result<Payload, PayloadError> {
+    let compressed = compress_file(source, compression, zstd_cmd).await?;
+    let Some(key) = key else {
+        return Ok(Payload {
+            path: compressed,
+            encryption: None,
+            source: source.to_path_buf(),
+        });
+    };
+
+    let encrypted = encrypt_file(&compressed, key, content_id).await;
+    if compressed != source {
+        let _ = tokio::fs::remove_file(&compressed).await;
+    }
+    let (path, info) = encrypted?;
+
+    Ok(Payload {
+        path,
+        encryption: Some(info),
+        source: source.to_path_buf(),
+    })
+}
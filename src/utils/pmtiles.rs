@@ -0,0 +1,463 @@
+use sha2::{Digest, Sha256};
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use tokio::sync::Mutex;
+
+/// Length of a PMTiles v3 header
+pub const PMTILES_HEADER_LEN: usize = 127;
+
+const PMTILES_MAGIC: &[u8] = b"PMTiles";
+const PMTILES_VERSION: u8 = 3;
+
+#[derive(Debug, Error)]
+pub enum PmtilesError {
+    #[error("Request failed: {0}")]
+    RequestFailed(#[from] reqwest::Error),
+    #[error("HTTP error: {0}")]
+    HttpError(reqwest::StatusCode),
+    #[error("Server does not support range requests, answered a range request with {0}")]
+    RangesUnsupported(reqwest::StatusCode),
+    #[error("Server reported no file size in its Content-Range header")]
+    UnknownSize,
+    #[error("Not a PMTiles file, the header does not start with the PMTiles magic bytes")]
+    NotPmtiles,
+    #[error("Unsupported PMTiles version {0}, only version 3 is supported")]
+    UnsupportedVersion(u8),
+    #[error("Truncated PMTiles file: {0}")]
+    Truncated(String),
+}
+
+/// Sections of a PMTiles v3 archive, as offsets and lengths in bytes from the start of the
+/// file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PmtilesHeader {
+    pub root_dir_offset: u64,
+    pub root_dir_length: u64,
+    pub metadata_offset: u64,
+    pub metadata_length: u64,
+    pub leaf_dirs_offset: u64,
+    pub leaf_dirs_length: u64,
+    pub tile_data_offset: u64,
+    pub tile_data_length: u64,
+    pub min_zoom: u8,
+    pub max_zoom: u8,
+}
+
+impl PmtilesHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, PmtilesError> {
+        if !bytes.starts_with(PMTILES_MAGIC) {
+            return Err(PmtilesError::NotPmtiles);
+        }
+        if bytes.len() < PMTILES_HEADER_LEN {
+            return Err(PmtilesError::Truncated(format!(
+                "header is {} of {} bytes",
+                bytes.len(),
+                PMTILES_HEADER_LEN
+            )));
+        }
+        if bytes[7] != PMTILES_VERSION {
+            return Err(PmtilesError::UnsupportedVersion(bytes[7]));
+        }
+
+        let u64_at = |offset: usize| {
+            u64::from_le_bytes(bytes[offset..offset + 8].try_into().expect("8 bytes"))
+        };
+        Ok(Self {
+            root_dir_offset: u64_at(8),
+            root_dir_length: u64_at(16),
+            metadata_offset: u64_at(24),
+            metadata_length: u64_at(32),
+            leaf_dirs_offset: u64_at(40),
+            leaf_dirs_length: u64_at(48),
+            tile_data_offset: u64_at(56),
+            tile_data_length: u64_at(64),
+            min_zoom: bytes[100],
+            max_zoom: bytes[101],
+        })
+    }
+
+    /// End of the last section, the smallest size a complete archive can have
+    pub fn end_offset(&self) -> u64 {
+        [
+            (self.root_dir_offset, self.root_dir_length),
+            (self.metadata_offset, self.metadata_length),
+            (self.leaf_dirs_offset, self.leaf_dirs_length),
+            (self.tile_data_offset, self.tile_data_length),
+        ]
+        .iter()
+        .map(|(offset, length)| offset.saturating_add(*length))
+        .max()
+        .unwrap_or(0)
+    }
+
+    /// End of the directories at the start of the archive: header, root directory,
+    /// metadata and leaf directories. Only the header and root directory when other
+    /// sections follow the tile data.
+    pub fn directories_end(&self) -> u64 {
+        let root_end = self.root_dir_offset.saturating_add(self.root_dir_length);
+        let end = [
+            root_end,
+            self.metadata_offset.saturating_add(self.metadata_length),
+            self.leaf_dirs_offset.saturating_add(self.leaf_dirs_length),
+        ]
+        .into_iter()
+        .max()
+        .unwrap_or(root_end);
+        match end <= self.tile_data_offset {
+            true => end,
+            false => root_end,
+        }
+    }
+
+    /// Check the sections fit a file of `file_size` bytes and that it holds tiles
+    pub fn check_size(&self, file_size: u64) -> Result<(), PmtilesError> {
+        if self.end_offset() > file_size {
+            return Err(PmtilesError::Truncated(format!(
+                "sections end at byte {} but the file has {}",
+                self.end_offset(),
+                file_size
+            )));
+        }
+        if self.tile_data_length == 0 {
+            return Err(PmtilesError::Truncated(
+                "the archive holds no tiles".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Write a small but valid PMTiles v3 archive at `path`, holding a single uncompressed
+/// z0 tile of `tile_size` bytes and declaring `bbox` (`[min_lon, min_lat, max_lon, max_lat]`)
+/// as its bounds
+pub fn write_sample_pmtiles(path: &Path, bbox: [f64; 4], tile_size: usize) -> std::io::Result<()> {
+    // One entry: tile 0, run length 1, `tile_size` bytes at offset 0 (stored as offset + 1)
+    let mut directory = Vec::new();
+    for value in [1, 0, 1, tile_size as u64, 1] {
+        write_varint(&mut directory, value);
+    }
+    let metadata = br#"{"name":"anynode sample"}"#;
+    let tile = vec![0x1a; tile_size];
+
+    let directory_offset = PMTILES_HEADER_LEN as u64;
+    let metadata_offset = directory_offset + directory.len() as u64;
+    let tile_data_offset = metadata_offset + metadata.len() as u64;
+    let e7 = |degrees: f64| ((degrees * 1e7) as i32).to_le_bytes();
+    let [min_lon, min_lat, max_lon, max_lat] = bbox;
+
+    let mut archive = Vec::with_capacity(tile_data_offset as usize + tile_size);
+    archive.extend_from_slice(PMTILES_MAGIC);
+    archive.push(PMTILES_VERSION);
+    for value in [
+        directory_offset,
+        directory.len() as u64,
+        metadata_offset,
+        metadata.len() as u64,
+        tile_data_offset,
+        0,
+        tile_data_offset,
+        tile_size as u64,
+        1,
+        1,
+        1,
+    ] {
+        archive.extend_from_slice(&value.to_le_bytes());
+    }
+    // Clustered, no internal compression, no tile compression, MVT tiles, zooms 0 to 0
+    archive.extend_from_slice(&[1, 1, 1, 1, 0, 0]);
+    archive.extend_from_slice(&e7(min_lon));
+    archive.extend_from_slice(&e7(min_lat));
+    archive.extend_from_slice(&e7(max_lon));
+    archive.extend_from_slice(&e7(max_lat));
+    archive.push(0);
+    archive.extend_from_slice(&e7((min_lon + max_lon) / 2.0));
+    archive.extend_from_slice(&e7((min_lat + max_lat) / 2.0));
+    debug_assert_eq!(archive.len(), PMTILES_HEADER_LEN);
+
+    archive.extend_from_slice(&directory);
+    archive.extend_from_slice(metadata);
+    archive.extend_from_slice(&tile);
+    std::fs::write(path, archive)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Read the header of a remote archive with a range request, returning it with the size
+/// of the file the server reports
+pub async fn fetch_pmtiles_header(
+    client: &reqwest::Client,
+    url: &str,
+) -> Result<(PmtilesHeader, u64), PmtilesError> {
+    let response = client
+        .get(url)
+        .header("Range", format!("bytes=0-{}", PMTILES_HEADER_LEN - 1))
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(PmtilesError::HttpError(status));
+    }
+    if status != reqwest::StatusCode::PARTIAL_CONTENT {
+        return Err(PmtilesError::RangesUnsupported(status));
+    }
+
+    // "bytes 0-126/<size>", the size is `*` when the server does not know it
+    let file_size = response
+        .headers()
+        .get("content-range")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.rsplit('/').next())
+        .and_then(|size| size.parse::<u64>().ok())
+        .ok_or(PmtilesError::UnknownSize)?;
+    let header = PmtilesHeader::parse(&response.bytes().await?)?;
+    Ok((header, file_size))
+}
+
+/// Directory bytes of a remote archive kept on disk, so extractions read its header and
+/// directories from one local copy instead of fetching them again for every area. Ranges
+/// are cached as they are first read, into a sparse file next to an index of the cached
+/// ranges, and the cache starts over when the archive's version changes.
+pub struct DirectoryCache {
+    data_path: PathBuf,
+    index_path: PathBuf,
+    version: String,
+    etag: Option<String>,
+    /// Directory bytes end here, tile data after it is never cached
+    end: u64,
+    file_size: u64,
+    /// Cached ranges, `end` excluded, sorted and merged
+    ranges: Mutex<Vec<(u64, u64)>>,
+}
+
+impl DirectoryCache {
+    /// Open the cache of the archive at `location` in `dir`, dropping what was cached for
+    /// another `version` of it
+    pub async fn open(
+        dir: &Path,
+        location: &str,
+        version: &str,
+        etag: Option<String>,
+        header: &PmtilesHeader,
+        file_size: u64,
+    ) -> std::io::Result<Self> {
+        tokio::fs::create_dir_all(dir).await?;
+        let key = &hex::encode(Sha256::digest(location.as_bytes()))[..16];
+        let data_path = dir.join(format!("{}.dirs", key));
+        let index_path = dir.join(format!("{}.index", key));
+
+        let saved = tokio::fs::read_to_string(&index_path).await.ok();
+        let ranges = match saved
+            .as_deref()
+            .and_then(|saved| parse_index(saved, version))
+        {
+            Some(ranges) => ranges,
+            None => {
+                let _ = tokio::fs::remove_file(&data_path).await;
+                let _ = tokio::fs::remove_file(&index_path).await;
+                Vec::new()
+            }
+        };
+
+        Ok(Self {
+            data_path,
+            index_path,
+            version: version.to_string(),
+            etag,
+            end: header.directories_end().min(file_size),
+            file_size,
+            ranges: Mutex::new(ranges),
+        })
+    }
+
+    /// Whether `start..end` lies in the directories, whether cached yet or not
+    pub fn holds(&self, start: u64, end: u64) -> bool {
+        start < end && end <= self.end
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.file_size
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.etag.as_deref()
+    }
+
+    /// Bytes `start..end` of the archive, `None` unless all of them are cached
+    pub async fn read(&self, start: u64, end: u64) -> std::io::Result<Option<Vec<u8>>> {
+        let ranges = self.ranges.lock().await;
+        if !ranges.iter().any(|(from, to)| *from <= start && end <= *to) {
+            return Ok(None);
+        }
+
+        let mut file = tokio::fs::File::open(&self.data_path).await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        let mut bytes = vec![0u8; (end - start) as usize];
+        file.read_exact(&mut bytes).await?;
+        Ok(Some(bytes))
+    }
+
+    /// Keep bytes read from the archive at `start`, when they are directory bytes
+    pub async fn write(&self, start: u64, bytes: &[u8]) -> std::io::Result<()> {
+        let end = start + bytes.len() as u64;
+        if !self.holds(start, end) {
+            return Ok(());
+        }
+
+        let mut ranges = self.ranges.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&self.data_path)
+            .await?;
+        file.seek(SeekFrom::Start(start)).await?;
+        file.write_all(bytes).await?;
+        file.flush().await?;
+
+        insert_range(&mut ranges, start, end);
+        let mut index = self.version.clone();
+        for (from, to) in ranges.iter() {
+            index.push_str(&format!("\n{} {}", from, to));
+        }
+        tokio::fs::write(&self.index_path, index).await
+    }
+}
+
+/// Cached ranges of an index written for `version`, `None` for another version
+fn parse_index(index: &str, version: &str) -> Option<Vec<(u64, u64)>> {
+    let mut lines = index.lines();
+    if lines.next()? != version {
+        return None;
+    }
+    lines
+        .map(|line| {
+            let (start, end) = line.split_once(' ')?;
+            Some((start.parse().ok()?, end.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Add `start..end` to sorted ranges, merging it with those it overlaps or touches
+fn insert_range(ranges: &mut Vec<(u64, u64)>, start: u64, end: u64) {
+    ranges.push((start, end));
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for &(from, to) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if from <= last.1 => last.1 = last.1.max(to),
+            _ => merged.push((from, to)),
+        }
+    }
+    *ranges = merged;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_bytes(sections: [(u64, u64); 4]) -> Vec<u8> {
+        let mut bytes = vec![0u8; PMTILES_HEADER_LEN];
+        bytes[..7].copy_from_slice(PMTILES_MAGIC);
+        bytes[7] = PMTILES_VERSION;
+        for (index, (offset, length)) in sections.iter().enumerate() {
+            let at = 8 + index * 16;
+            bytes[at..at + 8].copy_from_slice(&offset.to_le_bytes());
+            bytes[at + 8..at + 16].copy_from_slice(&length.to_le_bytes());
+        }
+        bytes[101] = 14;
+        bytes
+    }
+
+    #[test]
+    fn parses_the_sections_of_a_header() {
+        let bytes = header_bytes([(127, 400), (527, 100), (627, 0), (627, 5000)]);
+        let header = PmtilesHeader::parse(&bytes).unwrap();
+
+        assert_eq!(header.root_dir_length, 400);
+        assert_eq!(header.tile_data_offset, 627);
+        assert_eq!(header.max_zoom, 14);
+        assert_eq!(header.end_offset(), 5627);
+        assert!(header.check_size(5627).is_ok());
+        assert!(matches!(
+            header.check_size(4000),
+            Err(PmtilesError::Truncated(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_files_that_are_not_pmtiles() {
+        assert!(matches!(
+            PmtilesHeader::parse(b"<html>Not found</html>"),
+            Err(PmtilesError::NotPmtiles)
+        ));
+
+        let mut bytes = header_bytes([(127, 1), (128, 1), (129, 0), (129, 1)]);
+        bytes[7] = 2;
+        assert!(matches!(
+            PmtilesHeader::parse(&bytes),
+            Err(PmtilesError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            PmtilesHeader::parse(&bytes[..50]),
+            Err(PmtilesError::Truncated(_))
+        ));
+
+        let empty = PmtilesHeader::parse(&header_bytes([(127, 1), (128, 1), (129, 0), (129, 0)]));
+        assert!(empty.unwrap().check_size(1000).is_err());
+    }
+
+    #[test]
+    fn directories_end_before_the_tile_data() {
+        let bytes = header_bytes([(127, 400), (527, 100), (627, 3000), (3627, 5000)]);
+        assert_eq!(
+            PmtilesHeader::parse(&bytes).unwrap().directories_end(),
+            3627
+        );
+
+        // Leaf directories after the tile data are not cached
+        let bytes = header_bytes([(127, 400), (527, 100), (6000, 3000), (627, 5000)]);
+        assert_eq!(PmtilesHeader::parse(&bytes).unwrap().directories_end(), 527);
+    }
+
+    #[tokio::test]
+    async fn caches_directory_ranges_per_version() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = PmtilesHeader::parse(&header_bytes([
+            (127, 400),
+            (527, 100),
+            (627, 0),
+            (627, 5000),
+        ]))
+        .unwrap();
+        let location = "https://example.com/planet.pmtiles";
+
+        let cache = DirectoryCache::open(dir.path(), location, "v1", None, &header, 5627)
+            .await
+            .unwrap();
+        assert!(cache.holds(0, 627));
+        assert!(!cache.holds(600, 700));
+        cache.write(0, &[1; 100]).await.unwrap();
+        cache.write(100, &[2; 100]).await.unwrap();
+        cache.write(1000, &[3; 10]).await.unwrap();
+        assert_eq!(cache.read(90, 110).await.unwrap().unwrap()[9..11], [1, 2]);
+        assert!(cache.read(150, 250).await.unwrap().is_none());
+
+        let reopened = DirectoryCache::open(dir.path(), location, "v1", None, &header, 5627)
+            .await
+            .unwrap();
+        assert!(reopened.read(0, 200).await.unwrap().is_some());
+
+        let changed = DirectoryCache::open(dir.path(), location, "v2", None, &header, 5627)
+            .await
+            .unwrap();
+        assert!(changed.read(0, 200).await.unwrap().is_none());
+    }
+}
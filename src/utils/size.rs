@@ -0,0 +1,137 @@
+use thiserror::Error;
+
+const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB", "PB"];
+
+/// Format a byte count with a binary-scaled unit, e.g. `1.50 GB`
+pub fn format_bytes(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum SizeError {
+    #[error("invalid size '{0}', expected a number with an optional unit like 500GB, 1.5TB or 750GiB")]
+    InvalidSize(String),
+    #[error("size '{0}' is too large")]
+    TooLarge(String),
+}
+
+/// Parse a human-friendly size into bytes. Decimal units (`KB`, `MB`, `GB`, `TB`, `PB`)
+/// are powers of 1000 and binary units (`KiB`, `MiB`, `GiB`, `TiB`, `PiB`) powers of 1024.
+/// Units are case-insensitive, the trailing `B` is optional and a bare number is bytes.
+pub fn parse_size(value: &str) -> Result<u64, SizeError> {
+    let trimmed = value.trim();
+    let split = trimmed
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(trimmed.len());
+    let (number, unit) = trimmed.split_at(split);
+
+    let number: f64 = number
+        .parse()
+        .map_err(|_| SizeError::InvalidSize(value.to_string()))?;
+
+    let multiplier: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "p" | "pb" => 1_000_000_000_000_000,
+        "ki" | "kib" => 1 << 10,
+        "mi" | "mib" => 1 << 20,
+        "gi" | "gib" => 1 << 30,
+        "ti" | "tib" => 1 << 40,
+        "pi" | "pib" => 1 << 50,
+        _ => return Err(SizeError::InvalidSize(value.to_string())),
+    };
+
+    let bytes = number * multiplier as f64;
+    if !bytes.is_finite() || bytes >= u64::MAX as f64 {
+        return Err(SizeError::TooLarge(value.to_string()));
+    }
+
+    Ok(bytes.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_bare_bytes() {
+        assert_eq!(parse_size("0").unwrap(), 0);
+        assert_eq!(parse_size("1234").unwrap(), 1234);
+        assert_eq!(parse_size(" 42 B ").unwrap(), 42);
+    }
+
+    #[test]
+    fn parses_decimal_and_binary_units() {
+        assert_eq!(parse_size("500KB").unwrap(), 500_000);
+        assert_eq!(parse_size("2MB").unwrap(), 2_000_000);
+        assert_eq!(parse_size("500GB").unwrap(), 500_000_000_000);
+        assert_eq!(parse_size("3TB").unwrap(), 3_000_000_000_000);
+        assert_eq!(parse_size("1PB").unwrap(), 1_000_000_000_000_000);
+        assert_eq!(parse_size("1KiB").unwrap(), 1024);
+        assert_eq!(parse_size("1MiB").unwrap(), 1 << 20);
+        assert_eq!(parse_size("750GiB").unwrap(), 750 << 30);
+        assert_eq!(parse_size("2TiB").unwrap(), 2 << 40);
+        assert_eq!(parse_size("1PiB").unwrap(), 1 << 50);
+    }
+
+    #[test]
+    fn ignores_case_and_optional_suffix() {
+        assert_eq!(parse_size("10gb").unwrap(), 10_000_000_000);
+        assert_eq!(parse_size("10Gb").unwrap(), 10_000_000_000);
+        assert_eq!(parse_size("10g").unwrap(), 10_000_000_000);
+        assert_eq!(parse_size("10GIB").unwrap(), 10 << 30);
+        assert_eq!(parse_size("10gi").unwrap(), 10 << 30);
+        assert_eq!(parse_size("10 MiB").unwrap(), 10 << 20);
+    }
+
+    #[test]
+    fn parses_decimals() {
+        assert_eq!(parse_size("1.5TB").unwrap(), 1_500_000_000_000);
+        assert_eq!(parse_size("0.5KiB").unwrap(), 512);
+        assert_eq!(parse_size(".25MB").unwrap(), 250_000);
+        assert_eq!(parse_size("1.0005KB").unwrap(), 1001);
+    }
+
+    #[test]
+    fn rejects_oversized_values() {
+        assert!(matches!(parse_size("20000PB"), Err(SizeError::TooLarge(_))));
+        assert!(matches!(parse_size("16384PiB"), Err(SizeError::TooLarge(_))));
+        assert!(matches!(
+            parse_size("18446744073709551616"),
+            Err(SizeError::TooLarge(_))
+        ));
+        assert_eq!(parse_size("16383PiB").unwrap(), 16383 << 50);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        for value in ["", "GB", "abc", "-1GB", "1.2.3GB", "1e3", "10XB", "10 G B", "NaN", "inf"] {
+            assert!(
+                matches!(parse_size(value), Err(SizeError::InvalidSize(_))),
+                "{:?} should be rejected",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn formats_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.50 KB");
+        assert_eq!(format_bytes(3 << 30), "3.00 GB");
+    }
+}
@@ -0,0 +1,386 @@
+use base64::alphabet;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use base64::Engine;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use thiserror::Error;
+use tracing::debug;
+
+#[derive(Error, Debug)]
+pub enum SprError {
+    #[error("Signed peer record must start with 'spr:'")]
+    MissingPrefix,
+    #[error("Invalid signed peer record encoding: {0}")]
+    InvalidEncoding(String),
+    #[error("Invalid signed peer record: {0}")]
+    InvalidRecord(String),
+    #[error("Unsupported multiaddress protocol code: {0}")]
+    UnsupportedProtocol(u64),
+}
+
+/// Contents of a signed peer record. The envelope signature is not verified, the record
+/// is only used to find out where a peer can be dialed.
+#[derive(Debug, Clone)]
+pub struct SignedPeerRecord {
+    pub peer_id: String,
+    pub seq: u64,
+    pub addresses: Vec<String>,
+}
+
+const BASE64_URL: GeneralPurpose = GeneralPurpose::new(
+    &alphabet::URL_SAFE,
+    GeneralPurposeConfig::new().with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+/// Decode an `spr:` URI into the peer ID and multiaddresses it advertises
+pub fn decode_spr(uri: &str) -> Result<SignedPeerRecord, SprError> {
+    let encoded = uri.trim().strip_prefix("spr:").ok_or(SprError::MissingPrefix)?;
+    let envelope = BASE64_URL
+        .decode(encoded)
+        .map_err(|e| SprError::InvalidEncoding(e.to_string()))?;
+
+    // Envelope: public_key = 1, payload_type = 2, payload = 3, signature = 5
+    let mut payload = None;
+    for field in ProtoFields::new(&envelope) {
+        if let (3, FieldValue::Bytes(bytes)) = field? {
+            payload = Some(bytes);
+        }
+    }
+    let payload = payload.ok_or_else(|| SprError::InvalidRecord("missing payload".to_string()))?;
+
+    // PeerRecord: peer_id = 1, seq = 2, addresses = 3 (AddressInfo { multiaddr = 1 })
+    let mut peer_id = None;
+    let mut seq = 0;
+    let mut addresses = Vec::new();
+    for field in ProtoFields::new(payload) {
+        match field? {
+            (1, FieldValue::Bytes(bytes)) => peer_id = Some(bs58::encode(bytes).into_string()),
+            (2, FieldValue::Varint(value)) => seq = value,
+            (3, FieldValue::Bytes(address_info)) => {
+                for field in ProtoFields::new(address_info) {
+                    if let (1, FieldValue::Bytes(multiaddr)) = field? {
+                        // Addresses this decoder cannot render are dropped, the rest of the
+                        // record is still usable
+                        match multiaddr_to_string(multiaddr) {
+                            Ok(address) => addresses.push(address),
+                            Err(e) => debug!("Skipping signed peer record address: {}", e),
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(SignedPeerRecord {
+        peer_id: peer_id.ok_or_else(|| SprError::InvalidRecord("missing peer ID".to_string()))?,
+        seq,
+        addresses,
+    })
+}
+
+/// The start of a long `spr:` URI, enough to tell records apart in messages
+pub(crate) fn abbreviate(value: &str) -> String {
+    const MAX_LEN: usize = 48;
+    if value.chars().count() > MAX_LEN {
+        format!("{}...", value.chars().take(MAX_LEN).collect::<String>())
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render a binary multiaddress in its textual form
+fn multiaddr_to_string(bytes: &[u8]) -> Result<String, SprError> {
+    let mut reader = ByteReader::new(bytes);
+    let mut address = String::new();
+
+    while !reader.is_empty() {
+        let code = reader.varint()?;
+        match code {
+            4 => {
+                let ip: [u8; 4] = reader.take(4)?.try_into().unwrap();
+                address.push_str(&format!("/ip4/{}", Ipv4Addr::from(ip)));
+            }
+            41 => {
+                let ip: [u8; 16] = reader.take(16)?.try_into().unwrap();
+                address.push_str(&format!("/ip6/{}", Ipv6Addr::from(ip)));
+            }
+            6 | 273 => {
+                let port = reader.take(2)?;
+                let name = if code == 6 { "tcp" } else { "udp" };
+                address.push_str(&format!("/{}/{}", name, u16::from_be_bytes([port[0], port[1]])));
+            }
+            53..=56 => {
+                let name = match code {
+                    53 => "dns",
+                    54 => "dns4",
+                    55 => "dns6",
+                    _ => "dnsaddr",
+                };
+                let len = reader.varint()? as usize;
+                let host = String::from_utf8_lossy(reader.take(len)?);
+                address.push_str(&format!("/{}/{}", name, host));
+            }
+            421 => {
+                let len = reader.varint()? as usize;
+                address.push_str(&format!("/p2p/{}", bs58::encode(reader.take(len)?).into_string()));
+            }
+            460 => address.push_str("/quic"),
+            461 => address.push_str("/quic-v1"),
+            477 => address.push_str("/ws"),
+            478 => address.push_str("/wss"),
+            other => return Err(SprError::UnsupportedProtocol(other)),
+        }
+    }
+
+    Ok(address)
+}
+
+enum FieldValue<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Minimal protobuf field iterator, enough to walk the peer record messages
+struct ProtoFields<'a> {
+    reader: ByteReader<'a>,
+}
+
+impl<'a> ProtoFields<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            reader: ByteReader::new(bytes),
+        }
+    }
+
+    fn read_field(&mut self) -> Result<(u64, FieldValue<'a>), SprError> {
+        let key = self.reader.varint()?;
+        let value = match key & 0x7 {
+            0 => FieldValue::Varint(self.reader.varint()?),
+            1 => {
+                self.reader.take(8)?;
+                FieldValue::Fixed
+            }
+            2 => {
+                let len = self.reader.varint()? as usize;
+                FieldValue::Bytes(self.reader.take(len)?)
+            }
+            5 => {
+                self.reader.take(4)?;
+                FieldValue::Fixed
+            }
+            wire_type => {
+                return Err(SprError::InvalidRecord(format!(
+                    "unsupported wire type {}",
+                    wire_type
+                )))
+            }
+        };
+        Ok((key >> 3, value))
+    }
+}
+
+impl<'a> Iterator for ProtoFields<'a> {
+    type Item = Result<(u64, FieldValue<'a>), SprError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.reader.is_empty() {
+            return None;
+        }
+        let field = self.read_field();
+        if field.is_err() {
+            // Stop after the first error instead of reading garbage
+            self.reader.bytes = &[];
+        }
+        Some(field)
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SprError> {
+        if self.bytes.len() < len {
+            return Err(SprError::InvalidRecord("truncated record".to_string()));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn varint(&mut self) -> Result<u64, SprError> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            // The tenth byte only has room for the top bit of a u64
+            if shift == 63 && byte > 1 {
+                break;
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SprError::InvalidRecord("varint overflow".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Record for peer 16Uiu2HAkuVcAdoCkdw9kxjn3NCjmrTUufY81iXV6hLwfzupWD7ju, seq 7, advertising
+    /// a UDP and a TCP address
+    const SPR: &str = "spr:CiUIAhIhAgECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gEgIDARpECicAJQgCEiECAQIDBAUGBwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHyAQBxoLCgkEwKgBCpECH5oaCgoIBAoAAAEGH4YqQAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA";
+
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(byte);
+                return bytes;
+            }
+            bytes.push(byte | 0x80);
+        }
+    }
+
+    fn bytes_field(number: u64, value: &[u8]) -> Vec<u8> {
+        let mut field = varint(number << 3 | 2);
+        field.extend(varint(value.len() as u64));
+        field.extend_from_slice(value);
+        field
+    }
+
+    fn encode_spr(peer_id: &[u8], seq: u64, multiaddrs: &[Vec<u8>]) -> String {
+        let mut record = bytes_field(1, peer_id);
+        record.extend(varint(2 << 3));
+        record.extend(varint(seq));
+        for multiaddr in multiaddrs {
+            record.extend(bytes_field(3, &bytes_field(1, multiaddr)));
+        }
+
+        let mut envelope = bytes_field(2, &[0x03, 0x01]);
+        envelope.extend(bytes_field(3, &record));
+        envelope.extend(bytes_field(5, &[0; 64]));
+        format!("spr:{}", BASE64_URL.encode(envelope))
+    }
+
+    fn ip4_udp(ip: [u8; 4], port: u16) -> Vec<u8> {
+        let mut multiaddr = varint(4);
+        multiaddr.extend_from_slice(&ip);
+        multiaddr.extend(varint(273));
+        multiaddr.extend_from_slice(&port.to_be_bytes());
+        multiaddr
+    }
+
+    #[test]
+    fn decodes_known_record() {
+        let record = decode_spr(SPR).unwrap();
+        assert_eq!(
+            record.peer_id,
+            "16Uiu2HAkuVcAdoCkdw9kxjn3NCjmrTUufY81iXV6hLwfzupWD7ju"
+        );
+        assert_eq!(record.seq, 7);
+        assert_eq!(
+            record.addresses,
+            vec!["/ip4/192.168.1.10/udp/8090", "/ip4/10.0.0.1/tcp/8070"]
+        );
+    }
+
+    #[test]
+    fn round_trips_encoded_records() {
+        let peer_id = bs58::decode("16Uiu2HAkuVcAdoCkdw9kxjn3NCjmrTUufY81iXV6hLwfzupWD7ju")
+            .into_vec()
+            .unwrap();
+        let mut quic = ip4_udp([127, 0, 0, 1], 4001);
+        quic.extend(varint(461));
+        let mut dns = varint(54);
+        dns.extend(varint(16));
+        dns.extend_from_slice(b"boot.example.org");
+        dns.extend(varint(6));
+        dns.extend_from_slice(&443u16.to_be_bytes());
+        dns.extend(varint(478));
+
+        let uri = encode_spr(&peer_id, u64::MAX, &[quic, dns]);
+        let record = decode_spr(&uri).unwrap();
+
+        assert_eq!(bs58::decode(&record.peer_id).into_vec().unwrap(), peer_id);
+        assert_eq!(record.seq, u64::MAX);
+        assert_eq!(
+            record.addresses,
+            vec![
+                "/ip4/127.0.0.1/udp/4001/quic-v1",
+                "/dns4/boot.example.org/tcp/443/wss"
+            ]
+        );
+    }
+
+    #[test]
+    fn skips_addresses_with_unknown_protocols() {
+        let mut webtransport = ip4_udp([10, 0, 0, 2], 4001);
+        webtransport.extend(varint(461));
+        webtransport.extend(varint(465));
+
+        let uri = encode_spr(b"peer", 1, &[webtransport, ip4_udp([10, 0, 0, 3], 8090)]);
+        let record = decode_spr(&uri).unwrap();
+
+        assert_eq!(record.addresses, vec!["/ip4/10.0.0.3/udp/8090"]);
+    }
+
+    #[test]
+    fn rejects_malformed_records() {
+        assert!(matches!(
+            decode_spr("CiUIAhIh"),
+            Err(SprError::MissingPrefix)
+        ));
+        assert!(matches!(
+            decode_spr("spr:not base64!"),
+            Err(SprError::InvalidEncoding(_))
+        ));
+
+        let envelope = BASE64_URL.decode(&SPR[4..]).unwrap();
+        let truncated = format!("spr:{}", BASE64_URL.encode(&envelope[..envelope.len() / 2]));
+        assert!(matches!(
+            decode_spr(&truncated),
+            Err(SprError::InvalidRecord(_))
+        ));
+
+        let missing_payload = format!("spr:{}", BASE64_URL.encode(bytes_field(2, &[0x03, 0x01])));
+        assert!(matches!(
+            decode_spr(&missing_payload),
+            Err(SprError::InvalidRecord(_))
+        ));
+    }
+
+    #[test]
+    fn reads_varints() {
+        assert_eq!(ByteReader::new(&[0x00]).varint().unwrap(), 0);
+        assert_eq!(ByteReader::new(&[0xac, 0x02]).varint().unwrap(), 300);
+        assert_eq!(ByteReader::new(&varint(u64::MAX)).varint().unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn rejects_truncated_and_overflowing_varints() {
+        assert!(ByteReader::new(&[]).varint().is_err());
+        assert!(ByteReader::new(&[0x80]).varint().is_err());
+        assert!(ByteReader::new(&[0xff, 0xff]).varint().is_err());
+
+        // Eleven continuation bytes, and ten bytes whose last one exceeds 64 bits
+        assert!(ByteReader::new(&[0xff; 11]).varint().is_err());
+        let mut too_large = vec![0xff; 9];
+        too_large.push(0x02);
+        assert!(ByteReader::new(&too_large).varint().is_err());
+    }
+}
@@ -0,0 +1,307 @@
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::Router;
+use futures::StreamExt;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::pmtiles::DirectoryCache;
+
+/// Paces byte transfers to a maximum rate, shared through an `Arc` by every transfer it limits
+#[derive(Debug)]
+pub struct RateLimiter {
+    bytes_per_sec: u64,
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            bytes_per_sec: bytes_per_sec.max(1),
+            next_free: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Wait until `bytes` can be transferred without exceeding the configured rate
+    pub async fn acquire(&self, bytes: usize) {
+        let cost = Duration::from_secs_f64(bytes as f64 / self.bytes_per_sec as f64);
+        let ready_at = {
+            let mut next_free = self.next_free.lock().await;
+            let start = (*next_free).max(Instant::now());
+            *next_free = start + cost;
+            *next_free
+        };
+        tokio::time::sleep_until(ready_at).await;
+    }
+}
+
+/// Local HTTP endpoint forwarding GET and HEAD requests to an upstream file, at a limited
+/// rate when given a limiter and serving directory ranges from a cache when given one. The
+/// proxy stops when the handle is dropped.
+pub struct ThrottledProxy {
+    url: String,
+    task: JoinHandle<()>,
+}
+
+impl ThrottledProxy {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+}
+
+impl Drop for ThrottledProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct ProxyState {
+    client: reqwest::Client,
+    upstream: String,
+    limiter: Option<Arc<RateLimiter>>,
+    cache: Option<DirectoryCache>,
+}
+
+const FORWARDED_REQUEST_HEADERS: &[header::HeaderName] = &[
+    header::RANGE,
+    header::IF_RANGE,
+    header::IF_MATCH,
+    header::IF_NONE_MATCH,
+    header::IF_MODIFIED_SINCE,
+];
+
+const FORWARDED_RESPONSE_HEADERS: &[header::HeaderName] = &[
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::CONTENT_TYPE,
+    header::ACCEPT_RANGES,
+    header::ETAG,
+    header::LAST_MODIFIED,
+];
+
+/// Start a proxy on a random loopback port serving `upstream` through `limiter` and
+/// `cache`, fetched with `client` and the headers it sends
+pub async fn spawn_throttled_proxy(
+    client: reqwest::Client,
+    upstream: String,
+    limiter: Option<Arc<RateLimiter>>,
+    cache: Option<DirectoryCache>,
+) -> std::io::Result<ThrottledProxy> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let file_name = upstream
+        .split('?')
+        .next()
+        .and_then(|path| path.rsplit('/').next())
+        .filter(|name| !name.is_empty())
+        .unwrap_or("planet.pmtiles")
+        .to_string();
+
+    let state = Arc::new(ProxyState {
+        client,
+        upstream,
+        limiter,
+        cache,
+    });
+    let app = Router::new().fallback(forward).with_state(state);
+
+    let task = tokio::spawn(async move {
+        let _ = axum::serve(listener, app).await;
+    });
+
+    let url = format!("http://{}/{}", addr, file_name);
+    info!("Planet proxy listening on {}", url);
+
+    Ok(ThrottledProxy { url, task })
+}
+
+async fn forward(
+    State(proxy): State<Arc<ProxyState>>,
+    method: Method,
+    headers: HeaderMap,
+) -> Response {
+    // Presigned upstreams are signed for GET, so HEAD is answered from a GET whose body is
+    // dropped and anything else could never match the signature
+    let head_only = match method {
+        Method::GET => false,
+        Method::HEAD => true,
+        _ => {
+            return (
+                StatusCode::METHOD_NOT_ALLOWED,
+                [(header::ALLOW, "GET, HEAD")],
+            )
+                .into_response()
+        }
+    };
+
+    if let (false, Some(cache)) = (head_only, &proxy.cache) {
+        if let Some((start, end)) = requested_range(&headers) {
+            if cache.holds(start, end) && matches_etag(&headers, cache.etag()) {
+                return serve_cached(&proxy, cache, &headers, start, end).await;
+            }
+        }
+    }
+
+    let mut request = proxy.client.get(&proxy.upstream);
+    for name in FORWARDED_REQUEST_HEADERS {
+        if let Some(value) = headers.get(name) {
+            request = request.header(name, value);
+        }
+    }
+
+    let upstream_response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => return (StatusCode::BAD_GATEWAY, e.to_string()).into_response(),
+    };
+
+    let mut response = Response::builder().status(upstream_response.status());
+    for name in FORWARDED_RESPONSE_HEADERS {
+        if let Some(value) = upstream_response.headers().get(name) {
+            response = response.header(name, value);
+        }
+    }
+
+    if head_only {
+        return response
+            .body(Body::empty())
+            .unwrap_or_else(|e| (StatusCode::BAD_GATEWAY, e.to_string()).into_response());
+    }
+
+    let limiter = proxy.limiter.clone();
+    let body = upstream_response.bytes_stream().then(move |chunk| {
+        let limiter = limiter.clone();
+        async move {
+            if let (Ok(bytes), Some(limiter)) = (&chunk, &limiter) {
+                limiter.acquire(bytes.len()).await;
+            }
+            chunk
+        }
+    });
+
+    response
+        .body(Body::from_stream(body))
+        .unwrap_or_else(|e| (StatusCode::BAD_GATEWAY, e.to_string()).into_response())
+}
+
+/// Single `bytes=start-last` range of a request, as `start..end` with `end` excluded
+fn requested_range(headers: &HeaderMap) -> Option<(u64, u64)> {
+    let range = headers.get(header::RANGE)?.to_str().ok()?;
+    let (start, last) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let (start, last) = (start.parse::<u64>().ok()?, last.parse::<u64>().ok()?);
+    (start <= last).then_some((start, last + 1))
+}
+
+/// Whether the request's preconditions hold for the cached version, a request without any
+/// does not care which version it reads
+fn matches_etag(headers: &HeaderMap, etag: Option<&str>) -> bool {
+    [header::IF_MATCH, header::IF_RANGE]
+        .iter()
+        .filter_map(|name| headers.get(name))
+        .all(|value| Some(value.as_bytes()) == etag.map(str::as_bytes))
+        && !headers.contains_key(header::IF_NONE_MATCH)
+        && !headers.contains_key(header::IF_MODIFIED_SINCE)
+}
+
+/// Answer a directory range from the cache, reading it from upstream into the cache first
+/// when it is not cached yet
+async fn serve_cached(
+    proxy: &ProxyState,
+    cache: &DirectoryCache,
+    headers: &HeaderMap,
+    start: u64,
+    end: u64,
+) -> Response {
+    let bytes = match cache.read(start, end).await {
+        Ok(Some(bytes)) => bytes,
+        Ok(None) => match fetch_range(proxy, headers).await {
+            Ok(bytes) => {
+                if let Err(e) = cache.write(start, &bytes).await {
+                    warn!("Failed to cache planet directory bytes: {}", e);
+                }
+                bytes
+            }
+            Err(response) => return response,
+        },
+        Err(e) => {
+            warn!("Failed to read cached planet directory bytes: {}", e);
+            match fetch_range(proxy, headers).await {
+                Ok(bytes) => bytes,
+                Err(response) => return response,
+            }
+        }
+    };
+
+    let mut response = Response::builder()
+        .status(StatusCode::PARTIAL_CONTENT)
+        .header(header::CONTENT_LENGTH, bytes.len())
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end - 1, cache.file_size()),
+        )
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(etag) = cache.etag() {
+        response = response.header(header::ETAG, etag);
+    }
+    response
+        .body(Body::from(bytes))
+        .unwrap_or_else(|e| (StatusCode::BAD_GATEWAY, e.to_string()).into_response())
+}
+
+/// Read the requested range from upstream in full, answering with the upstream response
+/// when it is not the requested range
+async fn fetch_range(proxy: &ProxyState, headers: &HeaderMap) -> Result<Vec<u8>, Response> {
+    let bad_gateway = |e: reqwest::Error| (StatusCode::BAD_GATEWAY, e.to_string()).into_response();
+    let mut request = proxy.client.get(&proxy.upstream);
+    if let Some(range) = headers.get(header::RANGE) {
+        request = request.header(header::RANGE, range);
+    }
+    let response = request.send().await.map_err(bad_gateway)?;
+    if response.status() != StatusCode::PARTIAL_CONTENT {
+        let status = response.status();
+        return Err((status, response.text().await.unwrap_or_default()).into_response());
+    }
+
+    let bytes = response.bytes().await.map_err(bad_gateway)?;
+    if let Some(limiter) = &proxy.limiter {
+        limiter.acquire(bytes.len()).await;
+    }
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    #[test]
+    fn reads_single_byte_ranges() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(requested_range(&headers), None);
+
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-16383"));
+        assert_eq!(requested_range(&headers), Some((0, 16384)));
+
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=100-"));
+        assert_eq!(requested_range(&headers), None);
+        headers.insert(header::RANGE, HeaderValue::from_static("bytes=0-10,20-30"));
+        assert_eq!(requested_range(&headers), None);
+    }
+
+    #[test]
+    fn serves_cached_bytes_only_for_the_cached_version() {
+        let mut headers = HeaderMap::new();
+        assert!(matches_etag(&headers, Some("\"v1\"")));
+
+        headers.insert(header::IF_MATCH, HeaderValue::from_static("\"v1\""));
+        assert!(matches_etag(&headers, Some("\"v1\"")));
+        assert!(!matches_etag(&headers, Some("\"v2\"")));
+        assert!(!matches_etag(&headers, None));
+    }
+}
@@ -0,0 +1,90 @@
+//! Cancels a run spawned in the background while it extracts and checks it stops there:
+//! no area is extracted once `RunHandle::await_completion` returns.
+
+mod common;
+
+use anynode::app::ExitStatus;
+use anynode::testing::{fixture_area, MockStorageBackend};
+use anynode::{AnyNodeBuilder, Config, StorageBackend, StorageStatus};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const REGIONS: i64 = 8;
+
+/// Stand-in for `pmtiles` logging each extract it starts to `log`, then taking half a second
+fn write_slow_pmtiles_cmd(dir: &Path, log: &Path) -> PathBuf {
+    let path = dir.join("pmtiles");
+    std::fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\n\
+             case \"$1\" in\n\
+             extract) echo \"$3\" >> {} ; sleep 0.5 ; cp \"$2\" \"$3\" ;;\n\
+             version) echo \"pmtiles 1.22.1, commit fake, built at unknown\" ;;\n\
+             *) exit 0 ;;\n\
+             esac\n",
+            log.display()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path
+}
+
+fn started_extracts(log: &Path) -> usize {
+    std::fs::read_to_string(log).map_or(0, |log| log.lines().count())
+}
+
+#[tokio::test]
+async fn cancelled_run_extracts_nothing_more() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("extracts.log");
+    let pmtiles_cmd = write_slow_pmtiles_cmd(dir.path(), &log);
+    let regions: Vec<_> = (1..=REGIONS)
+        .map(|id| {
+            let west = id as f64 - 5.0;
+            fixture_area(
+                id,
+                &format!("Region {}", id),
+                "FR",
+                "region",
+                [west, 45.0, west + 0.5, 46.0],
+            )
+        })
+        .collect();
+    common::write_fixtures(dir.path(), &regions).await;
+    common::configure(dir.path(), &pmtiles_cmd, 0);
+
+    let storage = Arc::new(MockStorageBackend::new());
+    let node = AnyNodeBuilder::new(Config::load().unwrap())
+        .with_storage_service(storage.clone())
+        .build()
+        .await
+        .unwrap();
+    let handle = node.runner.spawn();
+
+    for _ in 0..100 {
+        if started_extracts(&log) > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert!(started_extracts(&log) > 0, "extraction never started");
+    let progress = handle.progress().await;
+    assert_eq!(progress.stats.total_failed, 0);
+    assert!(!handle.is_finished());
+
+    handle.cancel();
+    assert_eq!(
+        handle.await_completion().await.unwrap(),
+        ExitStatus::Cancelled
+    );
+    assert_eq!(storage.get_status().await, StorageStatus::Initialized);
+
+    let started = started_extracts(&log);
+    assert!(started < REGIONS as usize);
+    tokio::time::sleep(Duration::from_millis(1500)).await;
+    assert_eq!(started_extracts(&log), started);
+}
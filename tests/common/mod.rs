@@ -0,0 +1,49 @@
+//! Setup shared by the integration tests: a node configured in a scratch directory against
+//! the fixtures of `anynode::testing`
+
+use anynode::testing::{fixture_area, write_fake_pmtiles, write_whosonfirst_fixture};
+use anynode::types::AdministrativeArea;
+use std::path::Path;
+
+pub const FRANCE: [f64; 4] = [-5.0, 41.0, 10.0, 51.0];
+
+/// Write the fake planet and a WhosOnFirst fixture holding France and `regions`
+pub async fn write_fixtures(dir: &Path, regions: &[AdministrativeArea]) {
+    write_fake_pmtiles(&dir.join("planet.pmtiles"), FRANCE, 256).unwrap();
+    let mut areas = vec![fixture_area(85633147, "France", "FR", "country", FRANCE)];
+    areas.extend_from_slice(regions);
+    write_whosonfirst_fixture(&dir.join("whosonfirst.db"), &areas)
+        .await
+        .unwrap();
+}
+
+/// Point the config at `dir`, the only environment the test binary reads it from. Each
+/// test binary holds a single test, so nothing else sets these variables meanwhile.
+pub fn configure(dir: &Path, pmtiles_cmd: &Path, plan_samples: usize) {
+    let path = |name: &str| dir.join(name).to_string_lossy().to_string();
+    let vars = [
+        ("STORAGE_BACKEND", "local".to_string()),
+        ("STORAGE_DATA_DIR", path("storage")),
+        ("STORAGE_QUOTA", "1GB".to_string()),
+        ("STORAGE_DISCOVERY_PORT", "0".to_string()),
+        ("STORAGE_MAX_PEERS", "1".to_string()),
+        ("STORAGE_NAT", "any".to_string()),
+        ("STORAGE_LISTEN_ADDRS", "/ip4/127.0.0.1/tcp/0".to_string()),
+        ("WHOSONFIRST_DB_PATH", path("whosonfirst.db")),
+        (
+            "WHOSONFIRST_DB_URL",
+            "http://127.0.0.1:9/unused.db.bz2".to_string(),
+        ),
+        ("CID_DB_PATH", path("cids.db")),
+        ("AREAS_DIR", path("areas")),
+        ("PLANET_PMTILES_LOCATION", path("planet.pmtiles")),
+        ("PMTILES_CMD", pmtiles_cmd.to_string_lossy().to_string()),
+        ("TARGET_COUNTRIES", "FR".to_string()),
+        ("MAX_CONCURRENT_EXTRACTIONS", "2".to_string()),
+        ("PLAN_SAMPLES", plan_samples.to_string()),
+        ("UPLOAD_COMPRESSION", "none".to_string()),
+    ];
+    for (var, value) in vars {
+        std::env::set_var(var, value);
+    }
+}
@@ -0,0 +1,77 @@
+//! Runs the whole pipeline, plan, extract, upload, run statistics and dataset index, against
+//! the fixtures of `anynode::testing`: a WhosOnFirst fixture database, a fake planet, the
+//! stand-in `pmtiles` tool and the in-memory storage backend.
+
+mod common;
+
+use anynode::app::ExitStatus;
+use anynode::testing::{fixture_area, write_fake_pmtiles_cmd, MockStorageBackend};
+use anynode::types::DatasetIndex;
+use anynode::{AnyNodeBuilder, Config, DatabaseService};
+use std::sync::Arc;
+
+#[tokio::test]
+async fn pipeline_uploads_extracts_and_publishes_the_index() {
+    let dir = tempfile::tempdir().unwrap();
+    let pmtiles_cmd = write_fake_pmtiles_cmd(dir.path()).unwrap();
+    common::write_fixtures(
+        dir.path(),
+        &[
+            fixture_area(1, "Alsace", "FR", "region", [7.0, 47.5, 8.2, 49.1]),
+            fixture_area(2, "Bretagne", "FR", "region", [-5.0, 47.3, -1.0, 48.9]),
+        ],
+    )
+    .await;
+    common::configure(dir.path(), &pmtiles_cmd, 1);
+
+    let storage = Arc::new(MockStorageBackend::new());
+    let node = AnyNodeBuilder::new(Config::load().unwrap())
+        .with_storage_service(storage.clone())
+        .build()
+        .await
+        .unwrap();
+
+    let status = node.runner.run().await.unwrap();
+    assert_eq!(status, ExitStatus::Success);
+    node.runner.shutdown().await.unwrap();
+
+    let mut mapped: Vec<_> = node
+        .cid_db
+        .get_cid_mappings(Some("FR"))
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|mapping| {
+            assert!(storage.content(&mapping.cid).is_some());
+            mapping.area_id
+        })
+        .collect();
+    mapped.sort_unstable();
+    assert_eq!(mapped, vec![1, 2]);
+
+    let (runs, lifetime) = node.cid_db.get_lifetime_stats().await.unwrap();
+    assert_eq!(runs, 1);
+    // The fake pmtiles copies the whole planet, the second identical extract reuses a CID
+    assert_eq!((lifetime.total_uploaded, lifetime.total_reused), (1, 1));
+    assert_eq!(lifetime.total_failed, 0);
+
+    let published = node
+        .cid_db
+        .get_latest_published_index()
+        .await
+        .unwrap()
+        .expect("an index is published");
+    assert_eq!((published.country_count, published.area_count), (1, 2));
+    let index: DatasetIndex =
+        serde_json::from_slice(&storage.content(&published.root_cid).unwrap()).unwrap();
+    assert_eq!(index.countries["FR"].area_count, 2);
+    assert!(storage
+        .content(&index.countries["FR"].manifest_cid)
+        .is_some());
+
+    // The CID database is the one the node reopens on its next run
+    let reopened = DatabaseService::new(&dir.path().join("cids.db").to_string_lossy(), true)
+        .await
+        .unwrap();
+    assert_eq!(reopened.get_cid_mappings(None).await.unwrap().len(), 2);
+}